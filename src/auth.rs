@@ -0,0 +1,201 @@
+//! API tokens for integrations, kept out of the repo and shell history.
+//!
+//! Tokens live in the OS keychain: `security` on macOS, `secret-tool`
+//! (libsecret) elsewhere. Environment variables take precedence so CI can
+//! inject tokens without a keychain.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Keychain service name every token is filed under
+const SERVICE: &str = "tk";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Provider {
+    Github,
+    Gitlab,
+    Linear,
+}
+
+impl Provider {
+    pub const ALL: [Provider; 3] = [Provider::Github, Provider::Gitlab, Provider::Linear];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::Github => "github",
+            Provider::Gitlab => "gitlab",
+            Provider::Linear => "linear",
+        }
+    }
+
+    /// Environment variables checked before the keychain, in order
+    pub fn env_vars(self) -> &'static [&'static str] {
+        match self {
+            Provider::Github => &["TK_GITHUB_TOKEN", "GITHUB_TOKEN", "GH_TOKEN"],
+            Provider::Gitlab => &["TK_GITLAB_TOKEN", "GITLAB_TOKEN"],
+            Provider::Linear => &["TK_LINEAR_TOKEN", "LINEAR_API_KEY"],
+        }
+    }
+}
+
+impl std::fmt::Display for Provider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Where a token was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Env(&'static str),
+    Keychain,
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Env(var) => write!(f, "${}", var),
+            Source::Keychain => write!(f, "keychain"),
+        }
+    }
+}
+
+/// Look up a provider's token: environment first, then the keychain
+pub fn lookup(provider: Provider) -> Option<(String, Source)> {
+    for var in provider.env_vars() {
+        if let Ok(token) = std::env::var(var) {
+            if !token.trim().is_empty() {
+                return Some((token.trim().to_string(), Source::Env(var)));
+            }
+        }
+    }
+    keychain_get(provider).map(|t| (t, Source::Keychain))
+}
+
+pub fn store(provider: Provider, token: &str) -> Result<()> {
+    keychain_set(provider, token).with_context(|| {
+        format!(
+            "Could not store the token in the OS keychain; set {} instead",
+            provider.env_vars()[0]
+        )
+    })
+}
+
+/// Whether the OS keychain holds a token for `provider`
+pub fn is_stored(provider: Provider) -> bool {
+    keychain_get(provider).is_some()
+}
+
+/// Remove a stored token. Returns false if there was none.
+pub fn remove(provider: Provider) -> Result<bool> {
+    if !is_stored(provider) {
+        return Ok(false);
+    }
+    keychain_delete(provider)?;
+    Ok(true)
+}
+
+#[cfg(target_os = "macos")]
+fn keychain_get(provider: Provider) -> Option<String> {
+    let output = Command::new("security")
+        .args([
+            "find-generic-password",
+            "-s",
+            SERVICE,
+            "-a",
+            provider.name(),
+            "-w",
+        ])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !token.is_empty()).then_some(token)
+}
+
+#[cfg(target_os = "macos")]
+fn keychain_set(provider: Provider, token: &str) -> Result<()> {
+    // `security -i` reads commands from stdin, keeping the token out of argv
+    let command = format!(
+        "add-generic-password -U -s {} -a {} -w \"{}\"\n",
+        SERVICE,
+        provider.name(),
+        token.replace('\\', "\\\\").replace('"', "\\\"")
+    );
+    run_with_stdin("security", &["-i"], &command)
+}
+
+#[cfg(target_os = "macos")]
+fn keychain_delete(provider: Provider) -> Result<()> {
+    run_with_stdin(
+        "security",
+        &[
+            "delete-generic-password",
+            "-s",
+            SERVICE,
+            "-a",
+            provider.name(),
+        ],
+        "",
+    )
+}
+
+#[cfg(not(target_os = "macos"))]
+fn keychain_get(provider: Provider) -> Option<String> {
+    let output = Command::new("secret-tool")
+        .args(["lookup", "service", SERVICE, "account", provider.name()])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !token.is_empty()).then_some(token)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn keychain_set(provider: Provider, token: &str) -> Result<()> {
+    let label = format!("--label=tk {} token", provider.name());
+    // secret-tool reads the secret from stdin
+    run_with_stdin(
+        "secret-tool",
+        &[
+            "store",
+            &label,
+            "service",
+            SERVICE,
+            "account",
+            provider.name(),
+        ],
+        token,
+    )
+}
+
+#[cfg(not(target_os = "macos"))]
+fn keychain_delete(provider: Provider) -> Result<()> {
+    run_with_stdin(
+        "secret-tool",
+        &["clear", "service", SERVICE, "account", provider.name()],
+        "",
+    )
+}
+
+fn run_with_stdin(program: &str, args: &[&str], input: &str) -> Result<()> {
+    tracing::debug!("running {} {}", program, args.first().unwrap_or(&""));
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", program))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        anyhow::bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
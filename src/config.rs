@@ -0,0 +1,750 @@
+//! Per-repo settings in `.tickets/config.yaml`. Every key is optional; a
+//! missing file means defaults.
+
+use crate::error::TkError;
+use crate::id::IdScheme;
+use crate::lint;
+use crate::storage::{FrontmatterFormat, StoreLayout};
+use crate::sync::ConflictPolicy;
+use crate::types::{Status, Ticket, TicketType, TitleStyle};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+pub const CONFIG_FILE: &str = "config.yaml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// `hex` (default) or `ulid`
+    #[serde(default)]
+    pub id_scheme: IdScheme,
+    /// How ticket files are written: `yaml` (default) or `toml`
+    #[serde(default)]
+    pub frontmatter: FrontmatterFormat,
+    /// `flat` (default) or `sharded`: each ticket in a subdirectory named
+    /// after two characters of its ID
+    #[serde(default)]
+    pub layout: StoreLayout,
+    /// Keep `.tickets/.manifest`, a hash of every ticket file as tk last
+    /// wrote it, for `tk doctor --verify`
+    #[serde(default)]
+    pub manifest: bool,
+    /// Stop with an error on a ticket file that doesn't parse, as with
+    /// `--strict`, rather than skip it with a warning
+    #[serde(default)]
+    pub strict: bool,
+    /// Where new tickets keep their title: `heading` (default) or
+    /// `frontmatter`. Files that already have one keep theirs.
+    #[serde(default)]
+    pub title: TitlePlacement,
+    /// `create --parent` also makes the parent depend on the new child, as
+    /// if `--link-dep` were given
+    #[serde(default)]
+    pub children_block_parent: bool,
+    /// Lowercase tags whenever a ticket is saved, so `Backend` and `backend`
+    /// can't drift apart
+    #[serde(default)]
+    pub lowercase_tags: bool,
+    /// Keep at most this many history events per ticket, dropping the
+    /// oldest on save (the `created` event always stays)
+    #[serde(default)]
+    pub max_events: Option<usize>,
+    /// Move closed tickets into `.tickets/archive/`: `true` as they're
+    /// closed, or a number of days after closing, on the next `tk gc`
+    #[serde(default)]
+    pub archive_on_close: ArchiveOnClose,
+    /// Editor that `tk note` and `tk edit` open when `--editor` isn't given;
+    /// beats `$VISUAL` and `$EDITOR`
+    #[serde(default)]
+    pub editor: Option<String>,
+    #[serde(default)]
+    pub summarize: Option<SummarizeConfig>,
+    /// Per-type settings, keyed by type name (`fix`, `feat`, ...)
+    #[serde(default)]
+    pub types: HashMap<TicketType, TypeConfig>,
+    #[serde(default)]
+    pub lint: LintConfig,
+    #[serde(default)]
+    pub list: ListConfig,
+    /// Named listings for `tk list --view`, each with any of `list`'s keys;
+    /// the ones a view leaves out come from `list`
+    #[serde(default)]
+    pub views: BTreeMap<String, ListConfig>,
+    #[serde(default)]
+    pub ready: ReadyConfig,
+    #[serde(default)]
+    pub close: CloseConfig,
+    #[serde(default)]
+    pub score: ScoreConfig,
+    #[serde(default)]
+    pub suggest: SuggestConfig,
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    pub escalate: EscalateConfig,
+    #[serde(default)]
+    pub sync: SyncConfig,
+    #[serde(default)]
+    pub hyperlinks: HyperlinksConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveOnClose {
+    Now(bool),
+    AfterDays(u32),
+}
+
+// By hand rather than `untagged`, so a bad value is reported at its line
+impl<'de> Deserialize<'de> for ArchiveOnClose {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = ArchiveOnClose;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("true, false or a number of days")
+            }
+
+            fn visit_bool<E: serde::de::Error>(self, v: bool) -> Result<Self::Value, E> {
+                Ok(ArchiveOnClose::Now(v))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                u32::try_from(v)
+                    .map(ArchiveOnClose::AfterDays)
+                    .map_err(|_| E::invalid_value(serde::de::Unexpected::Unsigned(v), &self))
+            }
+        }
+        d.deserialize_any(Visitor)
+    }
+}
+
+impl Default for ArchiveOnClose {
+    fn default() -> Self {
+        ArchiveOnClose::Now(false)
+    }
+}
+
+impl ArchiveOnClose {
+    /// How long after closing a ticket goes to the archive, if it does
+    pub fn delay(self) -> Option<chrono::Duration> {
+        match self {
+            ArchiveOnClose::Now(true) => Some(chrono::Duration::zero()),
+            ArchiveOnClose::Now(false) => None,
+            ArchiveOnClose::AfterDays(days) => Some(chrono::Duration::days(days.into())),
+        }
+    }
+
+    /// Whether `tk gc` archives `ticket` at `now`: it's closed, still loose
+    /// in the store, and was closed at least the delay ago
+    pub fn due(self, ticket: &Ticket, now: DateTime<Utc>) -> bool {
+        let m = &ticket.meta;
+        let closed = m.closed.or(m.updated).unwrap_or(m.created);
+        !ticket.archived && !ticket.is_open() && self.delay().is_some_and(|d| closed <= now - d)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TitlePlacement {
+    /// A `# Title` line above the body
+    #[default]
+    Heading,
+    /// A `title:` key in the frontmatter, leaving the body as it is
+    Frontmatter,
+}
+
+impl TitlePlacement {
+    pub fn style(self) -> TitleStyle {
+        match self {
+            TitlePlacement::Heading => TitleStyle::Heading,
+            TitlePlacement::Frontmatter => TitleStyle::Frontmatter,
+        }
+    }
+}
+
+/// Clickable ticket IDs in `tk list` and `tk show` (OSC 8 terminal links)
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HyperlinksConfig {
+    /// `auto` (default: only when printing to a terminal), `always` or `never`
+    #[serde(default)]
+    pub enabled: HyperlinkMode,
+    /// Where an ID links to, with `{id}` standing for it; the ticket's file
+    /// when unset
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HyperlinkMode {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// `tk sync` settings
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyncConfig {
+    /// `remote-wins` (default), `local-wins`, `newest-wins` or `manual`
+    #[serde(default)]
+    pub conflicts: ConflictPolicy,
+}
+
+/// What happens the moment a ticket is created at, or raised to, a critical
+/// priority. Each gets the same JSON as `hooks`, with action `escalated`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EscalateConfig {
+    /// Priorities at or above this (numerically at or below) escalate
+    #[serde(default)]
+    pub priority: u8,
+    /// Shell commands to run
+    #[serde(default)]
+    pub run: Vec<String>,
+    /// URLs to POST the JSON to
+    #[serde(default)]
+    pub webhooks: Vec<String>,
+}
+
+impl EscalateConfig {
+    /// Whether a ticket at `priority` is critical: at `priority` in the
+    /// config or more urgent (P0 unless set)
+    pub fn is_critical(&self, priority: u8) -> bool {
+        priority <= self.priority
+    }
+}
+
+/// Shell commands run after tk changes a ticket, with the change as JSON on
+/// stdin
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HooksConfig {
+    /// After creating a ticket or changing its text, status, priority,
+    /// assignee, notes or watchers
+    #[serde(default)]
+    pub on_change: Vec<String>,
+}
+
+/// What `tk list` shows when its flags don't say
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListConfig {
+    /// Statuses shown without `--status` (`--status all` shows every one);
+    /// empty shows all
+    #[serde(default)]
+    pub status: Vec<Status>,
+    /// Order without `--sort` (default: priority)
+    #[serde(default)]
+    pub sort: Option<ListSort>,
+    /// Fields of each text line, in order, aligned as a table (`--columns`
+    /// overrides it); empty is the usual `[status] id [priority] title`
+    #[serde(default)]
+    pub columns: Vec<ListColumn>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ListSort {
+    /// Most urgent first, then oldest
+    #[default]
+    Priority,
+    /// Oldest first
+    Created,
+    /// Most recently changed first
+    Updated,
+    Id,
+    Title,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ListColumn {
+    Status,
+    Id,
+    Priority,
+    Type,
+    Title,
+    Assignee,
+    Tags,
+    Estimate,
+    Due,
+    Milestone,
+}
+
+/// Extra conditions, all off by default, a ticket must meet to be listed by
+/// `tk ready` on top of being open with no open deps, and how it lists them
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReadyConfig {
+    /// Someone must be assigned
+    #[serde(default)]
+    pub require_assignee: bool,
+    /// Skip tickets whose `snoozed_until` date hasn't arrived
+    #[serde(default)]
+    pub exclude_snoozed: bool,
+    /// Children are only ready once their parent is in progress
+    #[serde(default)]
+    pub require_parent_in_progress: bool,
+    /// The ticket must have an estimate
+    #[serde(default)]
+    pub require_estimate: bool,
+    /// List in overdue, due soon, due later and no due date sections, as
+    /// with `--by-due`
+    #[serde(default)]
+    pub by_due: bool,
+    /// Days ahead of its due date that a ticket counts as due soon
+    #[serde(default = "default_due_soon")]
+    pub due_soon: u32,
+}
+
+impl Default for ReadyConfig {
+    fn default() -> Self {
+        Self {
+            require_assignee: false,
+            exclude_snoozed: false,
+            require_parent_in_progress: false,
+            require_estimate: false,
+            by_due: false,
+            due_soon: default_due_soon(),
+        }
+    }
+}
+
+fn default_due_soon() -> u32 {
+    7
+}
+
+/// What `tk close` checks on top of required sections; `--force` skips all
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CloseConfig {
+    /// A `--resolution` must be given
+    #[serde(default)]
+    pub require_resolution: bool,
+    /// Every `- [ ]` checklist item in the body must be ticked
+    #[serde(default)]
+    pub require_checklist: bool,
+    /// The ticket must have at least one note
+    #[serde(default)]
+    pub require_note: bool,
+    /// Every child must be closed first
+    #[serde(default)]
+    pub require_children_closed: bool,
+}
+
+/// Weights of the score `tk ready --sort score` and `tk next` rank by. Each
+/// is multiplied by its factor and the products summed; negative weights
+/// count against a ticket. The defaults rank by priority alone.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScoreConfig {
+    /// Per step of priority above P4, so a P0 gets four times this
+    #[serde(default = "default_priority_weight")]
+    pub priority: f64,
+    /// Per day since the ticket was created
+    #[serde(default)]
+    pub age: f64,
+    /// Per day inside `due_window` of the due date, and per day overdue
+    #[serde(default)]
+    pub due: f64,
+    /// Days before its due date that a ticket starts to gain on it
+    #[serde(default = "default_due_window")]
+    pub due_window: u32,
+    /// Per unit of estimate; make it negative to favor small tickets
+    #[serde(default)]
+    pub estimate: f64,
+}
+
+impl Default for ScoreConfig {
+    fn default() -> Self {
+        Self {
+            priority: default_priority_weight(),
+            age: 0.0,
+            due: 0.0,
+            due_window: default_due_window(),
+            estimate: 0.0,
+        }
+    }
+}
+
+fn default_priority_weight() -> f64 {
+    1.0
+}
+
+fn default_due_window() -> u32 {
+    14
+}
+
+/// What `tk suggest --good-first` offers newcomers: ready, unassigned
+/// tickets with no deps at all, carrying the tag and no bigger than this
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SuggestConfig {
+    #[serde(default = "default_good_first_tag")]
+    pub good_first_tag: String,
+    /// Largest estimate; tickets without one are left out
+    #[serde(default = "default_good_first_estimate")]
+    pub max_estimate: u32,
+}
+
+impl Default for SuggestConfig {
+    fn default() -> Self {
+        Self {
+            good_first_tag: default_good_first_tag(),
+            max_estimate: default_good_first_estimate(),
+        }
+    }
+}
+
+fn default_good_first_tag() -> String {
+    "good-first-issue".to_string()
+}
+
+fn default_good_first_estimate() -> u32 {
+    2
+}
+
+/// Settings for `tk lint`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LintConfig {
+    /// How titles relate to the ticket type (off unless set)
+    #[serde(default)]
+    pub title: Option<TitleRule>,
+    /// Longest title, in characters, before lint complains; 0 turns the
+    /// check off
+    #[serde(default = "default_max_title_length")]
+    pub max_title_length: usize,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            title: None,
+            max_title_length: default_max_title_length(),
+        }
+    }
+}
+
+fn default_max_title_length() -> usize {
+    80
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TitleRule {
+    /// Titles must not repeat the type, e.g. "fix: crash on save"
+    NoTypePrefix,
+    /// Titles must read `<type>: [<scope>: ]<summary>` with the ticket's type
+    Conventional,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TypeConfig {
+    /// `## Heading` sections the body must have, with content, before the
+    /// ticket can be closed. Added as empty headings on create.
+    #[serde(default)]
+    pub sections: Vec<String>,
+    /// Body new tickets of this type start with when they're given none
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+/// `tk summarize`: the ticket is piped to `command` (run by the shell) and
+/// its stdout is the summary
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SummarizeConfig {
+    pub command: String,
+}
+
+impl Config {
+    /// The body a new ticket of `ticket_type` is saved with: `body`, or the
+    /// type's template if that's blank, with its required sections added
+    pub fn new_body(&self, body: &str, ticket_type: TicketType) -> String {
+        let template = self
+            .types
+            .get(&ticket_type)
+            .and_then(|t| t.template.as_deref());
+        let body = match template {
+            Some(template) if body.trim().is_empty() => template,
+            _ => body,
+        };
+        lint::apply_template(body, self.required_sections(ticket_type))
+    }
+
+    /// What `tk list` shows without flags: `list`, overridden by the saved
+    /// view `name` if given
+    pub fn list_view(&self, name: Option<&str>) -> Result<ListConfig> {
+        let Some(name) = name else {
+            return Ok(self.list.clone());
+        };
+        let Some(view) = self.views.get(name) else {
+            let known: Vec<&str> = self.views.keys().map(String::as_str).collect();
+            let hint = match known.is_empty() {
+                true => "no views are set in config.yaml".to_string(),
+                false => format!("known views: {}", known.join(", ")),
+            };
+            return Err(TkError::NotFound(format!("View '{}' not found ({})", name, hint)).into());
+        };
+        fn pick<T: Clone>(own: &[T], list: &[T]) -> Vec<T> {
+            match own.is_empty() {
+                true => list.to_vec(),
+                false => own.to_vec(),
+            }
+        }
+        Ok(ListConfig {
+            status: pick(&view.status, &self.list.status),
+            sort: view.sort.or(self.list.sort),
+            columns: pick(&view.columns, &self.list.columns),
+        })
+    }
+
+    pub fn required_sections(&self, ticket_type: TicketType) -> &[String] {
+        self.types
+            .get(&ticket_type)
+            .map(|t| t.sections.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn load(tickets_dir: &Path) -> Result<Self> {
+        let path = tickets_dir.join(CONFIG_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(content) => Self::parse(&content, &path.display().to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Settings from config.yaml text; `origin` names it in errors
+    pub fn parse(content: &str, origin: &str) -> Result<Self> {
+        if content.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_yaml::from_str(content)
+            .map_err(|e| TkError::Parse(format!("{}: {}", origin, e)).into())
+    }
+}
+
+/// Starting configs for `tk init --preset`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// Continuous flow: list what's open or moving, close with a resolution
+    Kanban,
+    /// Sprints: estimates before work is ready, acceptance criteria on
+    /// features, children closed before their parent
+    Scrum,
+    /// One person: recent work first, snoozing, tidy tags
+    Solo,
+}
+
+impl Preset {
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Kanban => "kanban",
+            Preset::Scrum => "scrum",
+            Preset::Solo => "solo",
+        }
+    }
+
+    /// The config.yaml it writes
+    pub fn yaml(self) -> &'static str {
+        match self {
+            Preset::Kanban => {
+                "\
+# Kanban preset (tk init --preset kanban)
+list:
+  status: [open, in-progress]
+  columns: [status, id, priority, assignee, title]
+close:
+  require_resolution: true
+score:
+  priority: 1
+  age: 0.1
+views:
+  wip:
+    status: [in-progress]
+    sort: updated
+  done:
+    status: [closed]
+    sort: updated
+types:
+  fix:
+    sections: [Steps to reproduce]
+    template: |
+      What happens, and what should happen instead.
+"
+            }
+            Preset::Scrum => {
+                "\
+# Scrum preset (tk init --preset scrum)
+list:
+  status: [open, in-progress]
+  columns: [status, id, priority, estimate, assignee, milestone, title]
+ready:
+  require_estimate: true
+  require_parent_in_progress: true
+close:
+  require_checklist: true
+  require_children_closed: true
+score:
+  priority: 1
+  due: 5
+views:
+  backlog:
+    status: [open]
+    columns: [id, priority, estimate, milestone, title]
+  sprint:
+    status: [open, in-progress]
+    sort: priority
+    columns: [status, id, estimate, assignee, title]
+types:
+  feat:
+    sections: [Acceptance criteria]
+    template: |
+      As a <who>, I want <what> so that <why>.
+  fix:
+    sections: [Steps to reproduce]
+    template: |
+      What happens, and what should happen instead.
+"
+            }
+            Preset::Solo => {
+                "\
+# Solo preset (tk init --preset solo)
+lowercase_tags: true
+list:
+  status: [open, in-progress]
+  sort: updated
+ready:
+  exclude_snoozed: true
+score:
+  priority: 1
+  age: 0.05
+views:
+  doing:
+    status: [in-progress]
+  everything:
+    status: [open, in-progress, closed]
+    sort: created
+types:
+  fix:
+    template: |
+      What happens, and what should happen instead.
+"
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive_on_close(yaml: &str) -> Result<ArchiveOnClose, serde_yaml::Error> {
+        serde_yaml::from_str::<Config>(&format!("archive_on_close: {}", yaml))
+            .map(|c| c.archive_on_close)
+    }
+
+    #[test]
+    fn archive_on_close_takes_a_flag_or_days() {
+        assert_eq!(archive_on_close("true").unwrap(), ArchiveOnClose::Now(true));
+        assert_eq!(
+            archive_on_close("false").unwrap(),
+            ArchiveOnClose::Now(false)
+        );
+        assert_eq!(
+            archive_on_close("30").unwrap(),
+            ArchiveOnClose::AfterDays(30)
+        );
+        assert_eq!(
+            Config::default().archive_on_close,
+            ArchiveOnClose::Now(false)
+        );
+
+        for bad in ["-1", "soon", "1.5", "99999999999"] {
+            let err = archive_on_close(bad).unwrap_err().to_string();
+            assert!(
+                err.contains("true, false or a number of days"),
+                "{}: {}",
+                bad,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn gc_archives_tickets_closed_long_enough_ago() {
+        let now: DateTime<Utc> = "2026-03-31T12:00:00Z".parse().unwrap();
+        let closed = |days_ago: i64| {
+            let mut t = Ticket::new("tk-a1b2".to_string(), "Done".to_string());
+            t.meta.status = Status::Closed;
+            t.meta.closed = Some(now - chrono::Duration::days(days_ago));
+            t
+        };
+        let week = ArchiveOnClose::AfterDays(7);
+        assert!(week.due(&closed(7), now));
+        assert!(week.due(&closed(30), now));
+        assert!(!week.due(&closed(6), now));
+        assert!(ArchiveOnClose::Now(true).due(&closed(0), now));
+        assert!(!ArchiveOnClose::Now(false).due(&closed(300), now));
+
+        let mut reopened = closed(30);
+        reopened.meta.status = Status::Open;
+        assert!(!week.due(&reopened, now));
+        let mut archived = closed(30);
+        archived.archived = true;
+        assert!(!week.due(&archived, now));
+    }
+
+    #[test]
+    fn every_preset_parses_with_views_and_templates() {
+        for preset in Preset::value_variants() {
+            let config = Config::parse(preset.yaml(), preset.name()).unwrap();
+            assert!(!config.views.is_empty(), "{} has no views", preset.name());
+            assert!(
+                config.types.values().any(|t| t.template.is_some()),
+                "{} has no templates",
+                preset.name()
+            );
+            for name in config.views.keys() {
+                config.list_view(Some(name)).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn views_fall_back_to_list_settings() {
+        let config = Config::parse(
+            "list:\n  status: [open]\n  sort: created\n  columns: [id, title]\n\
+             views:\n  wip:\n    status: [in-progress]\n",
+            "test",
+        )
+        .unwrap();
+        let wip = config.list_view(Some("wip")).unwrap();
+        assert_eq!(wip.status, vec![Status::InProgress]);
+        assert_eq!(wip.sort, Some(ListSort::Created));
+        assert_eq!(wip.columns, vec![ListColumn::Id, ListColumn::Title]);
+        assert_eq!(config.list_view(None).unwrap().status, vec![Status::Open]);
+        assert!(config.list_view(Some("nope")).is_err());
+    }
+
+    #[test]
+    fn templates_fill_blank_bodies_only() {
+        let config = Config::parse(Preset::Scrum.yaml(), "scrum").unwrap();
+        let body = config.new_body("", TicketType::Feat);
+        assert!(body.starts_with("As a <who>"));
+        assert!(body.ends_with("## Acceptance criteria\n"));
+        let given = config.new_body("Ship it.", TicketType::Feat);
+        assert_eq!(given, "Ship it.\n\n## Acceptance criteria\n");
+    }
+}
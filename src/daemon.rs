@@ -0,0 +1,355 @@
+//! `tk daemon`: keep a parsed index of `.tickets/` in memory and serve it over
+//! a unix socket at `.tickets/.tk.sock`.
+//!
+//! Before answering each request the daemon re-stats the ticket directories
+//! and re-parses only files whose size or mtime changed, so answers are never
+//! staler than the files on disk. `Storage::load_all` asks the daemon first
+//! and silently falls back to reading files when nothing answers.
+//!
+//! With `--metrics`, a second thread answers Prometheus scrapes from the same
+//! index.
+
+use crate::error::TkError;
+use crate::metrics;
+use crate::storage::{Scope, Storage, StoreLayout};
+use crate::types::Ticket;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const SOCKET_NAME: &str = ".tk.sock";
+
+/// How long the daemon waits on a client to send its request or take the
+/// answer. Clients are served one at a time, so one that stalls must not
+/// hold up the rest for longer.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize)]
+struct Request {
+    scope: Scope,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Response {
+    #[serde(default)]
+    tickets: Vec<Ticket>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Ask a running daemon for the tickets in scope. Returns None when there is
+/// no daemon or it fails to answer, in which case callers read the files.
+pub fn query(socket: &Path, scope: Scope) -> Option<Vec<Ticket>> {
+    if std::env::var_os("TK_NO_DAEMON").is_some() || !socket.exists() {
+        return None;
+    }
+
+    let mut stream = UnixStream::connect(socket).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+    stream
+        .set_write_timeout(Some(Duration::from_secs(2)))
+        .ok()?;
+
+    let mut request = serde_json::to_string(&Request { scope }).ok()?;
+    request.push('\n');
+    stream.write_all(request.as_bytes()).ok()?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).ok()?;
+    let response: Response = serde_json::from_str(&line).ok()?;
+    if let Some(err) = response.error {
+        tracing::warn!("daemon error, reading files instead: {}", err);
+        return None;
+    }
+
+    tracing::debug!("daemon served {} tickets", response.tickets.len());
+    Some(response.tickets)
+}
+
+/// Run the daemon in the foreground until killed. With `metrics_addr`, also
+/// serve Prometheus metrics at `http://<addr>/metrics`.
+pub fn serve(storage: &Storage, metrics_addr: Option<&str>) -> anyhow::Result<()> {
+    let socket = storage.socket_path();
+    if UnixStream::connect(&socket).is_ok() {
+        return Err(TkError::validation(format!(
+            "A daemon is already serving {}",
+            socket.display()
+        ))
+        .into());
+    }
+    // Left behind by a daemon that didn't shut down cleanly
+    let _ = std::fs::remove_file(&socket);
+
+    let listener = UnixListener::bind(&socket)?;
+    let index = Arc::new(Mutex::new(Index::new(storage)));
+    let count = {
+        let mut index = index.lock().unwrap();
+        index.refresh();
+        index.entries.len()
+    };
+    eprintln!(
+        "Serving {} tickets on {} (Ctrl-C to stop)",
+        count,
+        socket.display()
+    );
+
+    if let Some(addr) = metrics_addr {
+        let http = TcpListener::bind(addr)?;
+        eprintln!("Metrics at http://{}/metrics", http.local_addr()?);
+        let index = Arc::clone(&index);
+        std::thread::spawn(move || serve_metrics(http, index));
+    }
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle_client(stream, &index) {
+                    tracing::warn!("request failed: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("accept failed: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Answer one `query`: a JSON request line in, a JSON response line out
+fn handle_client(mut stream: UnixStream, index: &Mutex<Index>) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut line = String::new();
+    // Connect-and-close, e.g. another `tk daemon` checking for us
+    if BufReader::new(&stream).read_line(&mut line)? == 0 {
+        return Ok(());
+    }
+
+    let response = match serde_json::from_str::<Request>(&line) {
+        Ok(req) => {
+            let mut index = index.lock().unwrap();
+            index.refresh();
+            Response {
+                tickets: index.tickets(req.scope),
+                error: None,
+            }
+        }
+        Err(e) => Response {
+            tickets: Vec::new(),
+            error: Some(format!("bad request: {}", e)),
+        },
+    };
+
+    let mut out = serde_json::to_string(&response)?;
+    out.push('\n');
+    stream.write_all(out.as_bytes())?;
+    Ok(())
+}
+
+fn serve_metrics(listener: TcpListener, index: Arc<Mutex<Index>>) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = handle_http(stream, &index) {
+                    tracing::warn!("metrics request failed: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("accept failed: {}", e),
+        }
+    }
+}
+
+/// Just enough HTTP/1.1 for a Prometheus scraper: `GET /metrics`, one
+/// response per connection
+fn handle_http(mut stream: TcpStream, index: &Mutex<Index>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain headers
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    let (status, content_type, body) = if path == "/metrics" {
+        let tickets = {
+            let mut index = index.lock().unwrap();
+            index.refresh();
+            index.tickets(Scope::All)
+        };
+        (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            metrics::render(&tickets, Utc::now()),
+        )
+    } else {
+        ("404 Not Found", "text/plain", "Not found\n".to_string())
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+struct Entry {
+    len: u64,
+    modified: SystemTime,
+    ticket: Ticket,
+}
+
+/// A yearly bundle of archived tickets, re-read whole when it changes
+struct Bundle {
+    len: u64,
+    modified: SystemTime,
+    tickets: Vec<Ticket>,
+}
+
+struct Index {
+    live_dir: PathBuf,
+    archive_dir: PathBuf,
+    bundles_dir: PathBuf,
+    /// Where a ticket filed both flat and in its shard counts
+    layout: StoreLayout,
+    entries: HashMap<PathBuf, Entry>,
+    bundles: HashMap<PathBuf, Bundle>,
+}
+
+impl Index {
+    fn new(storage: &Storage) -> Self {
+        Self {
+            live_dir: storage.tickets_dir().to_path_buf(),
+            archive_dir: storage.archive_dir(),
+            bundles_dir: storage.bundles_dir(),
+            layout: storage.layout(),
+            entries: HashMap::new(),
+            bundles: HashMap::new(),
+        }
+    }
+
+    /// Re-parse files that changed since the last refresh and forget deleted ones
+    fn refresh(&mut self) {
+        let mut seen = HashSet::new();
+
+        for (dir, archived) in [
+            (self.live_dir.clone(), false),
+            (self.archive_dir.clone(), true),
+        ] {
+            let Ok(paths) = Storage::ticket_files(&dir) else {
+                continue;
+            };
+            let stray = Storage::stray_copies(&dir, &paths, self.layout);
+            for path in paths {
+                if let Some((kept, _)) = stray.iter().find(|(_, s)| *s == path) {
+                    tracing::warn!("skipping {}, a copy of {}", path.display(), kept.display());
+                    continue;
+                }
+                let Ok(meta) = std::fs::metadata(&path) else {
+                    continue;
+                };
+                let (len, modified) = (meta.len(), meta.modified().ok());
+                seen.insert(path.clone());
+
+                if let Some(existing) = self.entries.get(&path) {
+                    if Some(existing.modified) == modified && existing.len == len {
+                        continue;
+                    }
+                }
+
+                match std::fs::read_to_string(&path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|c| Storage::parse_ticket(&c))
+                {
+                    Ok(mut ticket) => {
+                        tracing::debug!("indexed {}", path.display());
+                        ticket.archived = archived;
+                        self.entries.insert(
+                            path,
+                            Entry {
+                                len,
+                                modified: modified.unwrap_or(UNIX_EPOCH),
+                                ticket,
+                            },
+                        );
+                    }
+                    Err(e) => {
+                        eprintln!("Warning: Failed to parse {}: {}", path.display(), e);
+                        self.entries.remove(&path);
+                    }
+                }
+            }
+        }
+
+        self.entries.retain(|path, _| seen.contains(path));
+        self.refresh_bundles();
+    }
+
+    fn refresh_bundles(&mut self) {
+        let mut seen = HashSet::new();
+        if let Ok(read) = std::fs::read_dir(&self.bundles_dir) {
+            for entry in read.flatten() {
+                let path = entry.path();
+                if path.extension().is_none_or(|e| e != "md") {
+                    continue;
+                }
+                let Ok(meta) = entry.metadata() else {
+                    continue;
+                };
+                let (len, modified) = (meta.len(), meta.modified().ok());
+                seen.insert(path.clone());
+
+                if let Some(existing) = self.bundles.get(&path) {
+                    if Some(existing.modified) == modified && existing.len == len {
+                        continue;
+                    }
+                }
+                match std::fs::read_to_string(&path) {
+                    Ok(content) => {
+                        tracing::debug!("indexed {}", path.display());
+                        let tickets = Storage::parse_bundle(&content, &path);
+                        self.bundles.insert(
+                            path,
+                            Bundle {
+                                len,
+                                modified: modified.unwrap_or(UNIX_EPOCH),
+                                tickets,
+                            },
+                        );
+                    }
+                    Err(e) => {
+                        eprintln!("Warning: Failed to read {}: {}", path.display(), e);
+                        self.bundles.remove(&path);
+                    }
+                }
+            }
+        }
+        self.bundles.retain(|path, _| seen.contains(path));
+    }
+
+    fn tickets(&self, scope: Scope) -> Vec<Ticket> {
+        self.entries
+            .values()
+            .map(|e| &e.ticket)
+            .chain(self.bundles.values().flat_map(|b| &b.tickets))
+            .filter(|t| match scope {
+                Scope::Live => !t.archived,
+                Scope::Archived => t.archived,
+                Scope::All => true,
+            })
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,231 @@
+//! Dates typed on the command line.
+//!
+//! Every date flag (`--due`, `--created-since`, `--closed-since`, `stats
+//! --since`, ...) goes through `parse`, so they all accept the same words:
+//!
+//! - `2024-06-01`
+//! - `today`, `tomorrow`, `yesterday`
+//! - `friday`, `next friday` (the first one after today), `this friday`
+//!   (today if it is Friday), `last friday` (the latest one before today);
+//!   weekdays may be cut to three letters
+//! - `+3d`, `-2w`, `+1m` (days, weeks, calendar months from today)
+//! - `3 days ago`, `2 weeks ago`, `in 3 days`
+//!
+//! Words are English whatever the system locale, and "today" is the local
+//! date. Tickets always store plain `YYYY-MM-DD`.
+//!
+//! Claim leases (`start --lease`) are lengths rather than dates: `30m`,
+//! `2h`, `1d`.
+
+use crate::error::TkError;
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, Days, Duration, Local, Months, NaiveDate, Weekday};
+
+/// A date flag's value, for clap's `value_parser`
+pub fn arg(s: &str) -> std::result::Result<NaiveDate, String> {
+    parse(s, Local::now().date_naive()).map_err(|e| e.to_string())
+}
+
+/// `s` as a date, with relative dates counted from `today`
+pub fn parse(s: &str, today: NaiveDate) -> Result<NaiveDate> {
+    let text = s.trim().to_ascii_lowercase();
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let date = match words.as_slice() {
+        [] => None,
+        ["today"] | ["now"] => Some(today),
+        ["tomorrow"] => today.succ_opt(),
+        ["yesterday"] => today.pred_opt(),
+        [day] if weekday(day).is_some() => weekday(day).map(|w| after(today, w)),
+        ["next", day] => weekday(day).map(|w| after(today, w)),
+        ["this", day] => weekday(day).map(|w| {
+            if today.weekday() == w {
+                today
+            } else {
+                after(today, w)
+            }
+        }),
+        ["last", day] => weekday(day).map(|w| before(today, w)),
+        [offset] if offset.starts_with(['+', '-']) => {
+            let (sign, rest) = offset.split_at(1);
+            split_unit(rest)
+                .and_then(|(n, unit)| Some((n.parse().ok()?, unit)))
+                .and_then(|(n, unit)| shift(today, sign == "-", n, unit))
+        }
+        [n, unit, "ago"] => n.parse().ok().and_then(|n| shift(today, true, n, unit)),
+        ["in", n, unit] => n.parse().ok().and_then(|n| shift(today, false, n, unit)),
+        [iso] => NaiveDate::parse_from_str(iso, "%Y-%m-%d").ok(),
+        _ => None,
+    };
+    date.ok_or_else(|| {
+        anyhow!(
+            "Invalid date '{}': use YYYY-MM-DD, today, tomorrow, next friday, +2w or 3 days ago",
+            s.trim()
+        )
+    })
+}
+
+/// A lease length: a whole number of minutes, hours or days (`30m`, `2h`, `1d`)
+pub fn lease(s: &str) -> Result<Duration> {
+    let invalid = || TkError::validation(format!("Invalid lease '{}': use e.g. 30m, 2h or 1d", s));
+    let (n, unit) = split_unit(s.trim()).ok_or_else(invalid)?;
+    let n: i64 = n.parse().map_err(|_| invalid())?;
+    let lease = match unit {
+        "m" => Duration::try_minutes(n),
+        "h" => Duration::try_hours(n),
+        "d" => Duration::try_days(n),
+        _ => None,
+    };
+    Ok(lease
+        .filter(|d| *d > Duration::zero())
+        .ok_or_else(invalid)?)
+}
+
+/// `3d` as `("3", "d")`: everything but the last character, and the last
+fn split_unit(s: &str) -> Option<(&str, &str)> {
+    let (i, _) = s.char_indices().last()?;
+    Some(s.split_at(i))
+}
+
+fn weekday(word: &str) -> Option<Weekday> {
+    const DAYS: [(&str, Weekday); 7] = [
+        ("monday", Weekday::Mon),
+        ("tuesday", Weekday::Tue),
+        ("wednesday", Weekday::Wed),
+        ("thursday", Weekday::Thu),
+        ("friday", Weekday::Fri),
+        ("saturday", Weekday::Sat),
+        ("sunday", Weekday::Sun),
+    ];
+    DAYS.iter()
+        .find(|(name, _)| word.len() >= 3 && name.starts_with(word))
+        .map(|&(_, day)| day)
+}
+
+/// The first `day` strictly after `today`
+fn after(today: NaiveDate, day: Weekday) -> NaiveDate {
+    let ahead = (day.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+    today + Days::new(if ahead == 0 { 7 } else { ahead.into() })
+}
+
+/// The latest `day` strictly before `today`
+fn before(today: NaiveDate, day: Weekday) -> NaiveDate {
+    let back = (today.weekday().num_days_from_monday() + 7 - day.num_days_from_monday()) % 7;
+    today - Days::new(if back == 0 { 7 } else { back.into() })
+}
+
+/// `today` moved `n` days, weeks or months; None for an unknown unit or a
+/// date out of range
+fn shift(today: NaiveDate, back: bool, n: u32, unit: &str) -> Option<NaiveDate> {
+    match unit.trim_end_matches('s') {
+        "d" | "day" => {
+            let days = Days::new(n.into());
+            if back {
+                today.checked_sub_days(days)
+            } else {
+                today.checked_add_days(days)
+            }
+        }
+        "w" | "week" => {
+            let days = Days::new(u64::from(n) * 7);
+            if back {
+                today.checked_sub_days(days)
+            } else {
+                today.checked_add_days(days)
+            }
+        }
+        "m" | "month" => {
+            let months = Months::new(n);
+            if back {
+                today.checked_sub_months(months)
+            } else {
+                today.checked_add_months(months)
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Wednesday
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, 15).unwrap()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        parse(s, today()).unwrap()
+    }
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn iso_dates() {
+        assert_eq!(date("2024-06-01"), ymd(2024, 6, 1));
+        assert_eq!(date(" 2024-06-01 "), ymd(2024, 6, 1));
+        assert!(parse("2024-02-30", today()).is_err());
+        assert!(parse("06/01/2024", today()).is_err());
+    }
+
+    #[test]
+    fn named_days() {
+        assert_eq!(date("today"), today());
+        assert_eq!(date("Tomorrow"), ymd(2024, 5, 16));
+        assert_eq!(date("YESTERDAY"), ymd(2024, 5, 14));
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(date("friday"), ymd(2024, 5, 17));
+        assert_eq!(date("next friday"), ymd(2024, 5, 17));
+        assert_eq!(date("next wed"), ymd(2024, 5, 22));
+        assert_eq!(date("this wednesday"), today());
+        assert_eq!(date("this mon"), ymd(2024, 5, 20));
+        assert_eq!(date("last monday"), ymd(2024, 5, 13));
+        assert_eq!(date("last wednesday"), ymd(2024, 5, 8));
+        assert!(parse("next fr", today()).is_err());
+        assert!(parse("next", today()).is_err());
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(date("+3d"), ymd(2024, 5, 18));
+        assert_eq!(date("-3d"), ymd(2024, 5, 12));
+        assert_eq!(date("+2w"), ymd(2024, 5, 29));
+        assert_eq!(date("+1m"), ymd(2024, 6, 15));
+        assert_eq!(date("3 days ago"), ymd(2024, 5, 12));
+        assert_eq!(date("1 week ago"), ymd(2024, 5, 8));
+        assert_eq!(date("in 2 months"), ymd(2024, 7, 15));
+        assert!(parse("+3y", today()).is_err());
+        assert!(parse("+d", today()).is_err());
+    }
+
+    #[test]
+    fn month_ends_clamp() {
+        let jan31 = ymd(2024, 1, 31);
+        assert_eq!(parse("+1m", jan31).unwrap(), ymd(2024, 2, 29));
+    }
+
+    #[test]
+    fn rejects_nonsense() {
+        let err = parse("someday", today()).unwrap_err().to_string();
+        assert!(err.contains("Invalid date 'someday'"));
+        assert!(parse("", today()).is_err());
+        for bad in ["+3é", "-é", "+", "+3"] {
+            assert!(parse(bad, today()).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn leases() {
+        assert_eq!(lease("30m").unwrap(), Duration::minutes(30));
+        assert_eq!(lease(" 2h ").unwrap(), Duration::hours(2));
+        assert_eq!(lease("1d").unwrap(), Duration::days(1));
+        for bad in ["", "m", "0h", "-1d", "2w", "2é", "é", "1.5h", "h2"] {
+            assert!(lease(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Graphviz DOT rendering of the dependency graph for `tk graph`.
+//!
+//! Edges point from a dep to the ticket it blocks, so the graph reads in the
+//! order work has to happen. Clustering draws related tickets inside a
+//! labelled box; a DOT node can sit in only one cluster, so a ticket with
+//! several tags goes under its first.
+
+use crate::types::Ticket;
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ClusterBy {
+    Tag,
+    Parent,
+    Milestone,
+}
+
+/// Render `tickets` and the deps between them as a DOT digraph. Deps on
+/// tickets outside the set are left out.
+pub fn render(tickets: &[&Ticket], cluster_by: Option<ClusterBy>) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "digraph tickets {{");
+    let _ = writeln!(out, "  rankdir=LR;");
+    let _ = writeln!(out, "  node [shape=box, fontname=\"sans-serif\"];");
+
+    let mut clusters: BTreeMap<String, Vec<&Ticket>> = BTreeMap::new();
+    let mut loose = Vec::new();
+    for t in tickets {
+        match cluster_by.and_then(|c| cluster_key(t, c)) {
+            Some(key) => clusters.entry(key).or_default().push(t),
+            None => loose.push(*t),
+        }
+    }
+
+    for (i, (key, members)) in clusters.iter().enumerate() {
+        let label = match cluster_by {
+            Some(ClusterBy::Parent) => tickets
+                .iter()
+                .find(|t| t.id() == key)
+                .map_or_else(|| key.clone(), |p| format!("{} - {}", key, p.title)),
+            _ => key.clone(),
+        };
+        let _ = writeln!(out, "  subgraph cluster_{} {{", i);
+        let _ = writeln!(out, "    label=\"{}\";", escape(&label));
+        let _ = writeln!(out, "    style=rounded;");
+        for t in members {
+            node(&mut out, t, "    ");
+        }
+        let _ = writeln!(out, "  }}");
+    }
+    for t in loose {
+        node(&mut out, t, "  ");
+    }
+
+    for t in tickets {
+        for dep in &t.meta.deps {
+            if tickets.iter().any(|d| d.id() == dep) {
+                let _ = writeln!(out, "  \"{}\" -> \"{}\";", escape(dep), escape(t.id()));
+            }
+        }
+    }
+
+    let _ = writeln!(out, "}}");
+    out
+}
+
+fn cluster_key(ticket: &Ticket, cluster_by: ClusterBy) -> Option<String> {
+    match cluster_by {
+        ClusterBy::Tag => ticket.meta.tags.first().cloned(),
+        ClusterBy::Parent => ticket.meta.parent.clone(),
+        ClusterBy::Milestone => ticket.meta.milestone.clone(),
+    }
+}
+
+fn node(out: &mut String, ticket: &Ticket, indent: &str) {
+    let style = if ticket.is_open() {
+        ""
+    } else {
+        ", style=dashed, fontcolor=gray50"
+    };
+    let _ = writeln!(
+        out,
+        "{}\"{}\" [label=\"{}\\n{}\"{}];",
+        indent,
+        escape(ticket.id()),
+        escape(ticket.id()),
+        escape(&ticket.title),
+        style
+    );
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
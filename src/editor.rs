@@ -0,0 +1,78 @@
+//! Launching the user's editor for `tk note` and `tk edit`.
+//!
+//! The editor is the first of `--editor`, `editor` in config.yaml, `$VISUAL`
+//! and `$EDITOR` that is set, falling back to vi (notepad on Windows). Each is
+//! a command line rather than a program name, so `code --wait` or
+//! `emacsclient -t` work: it runs through the platform shell with the file
+//! as its last argument.
+
+use crate::config::Config;
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// The editor command line to use, given an `--editor` flag
+pub fn resolve(flag: Option<String>, config: &Config) -> String {
+    flag.or_else(|| config.editor.clone())
+        .or_else(|| env("VISUAL"))
+        .or_else(|| env("EDITOR"))
+        .unwrap_or_else(|| default().to_string())
+}
+
+/// Let the user edit `initial` in a temp file named after `name`; returns
+/// what they saved
+pub fn compose(editor: &str, name: &str, initial: &str) -> Result<String> {
+    let temp = std::env::temp_dir().join(format!("tk-{}.md", name));
+    std::fs::write(&temp, initial)?;
+
+    tracing::debug!("running editor: {} {}", editor, temp.display());
+    let status = command(editor, &temp)
+        .status()
+        .with_context(|| format!("Failed to run editor {}", editor))?;
+    if !status.success() {
+        anyhow::bail!("Editor exited with error");
+    }
+
+    let content = std::fs::read_to_string(&temp)?;
+    // Windows refuses to delete a file an editor still has open; a leftover
+    // temp file isn't worth failing over
+    if let Err(e) = std::fs::remove_file(&temp) {
+        tracing::warn!("could not remove {}: {}", temp.display(), e);
+    }
+    Ok(content)
+}
+
+fn env(var: &str) -> Option<String> {
+    std::env::var(var).ok().filter(|v| !v.trim().is_empty())
+}
+
+fn default() -> &'static str {
+    if cfg!(windows) {
+        "notepad"
+    } else {
+        "vi"
+    }
+}
+
+/// `sh -c '<editor> "$@"' sh <path>` keeps the path out of the shell's word
+/// splitting while the editor's own arguments are split as usual
+#[cfg(not(windows))]
+fn command(editor: &str, path: &Path) -> Command {
+    let mut c = Command::new("sh");
+    c.arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg("sh")
+        .arg(path);
+    c
+}
+
+/// Through `cmd` so `.cmd` shims like `code.cmd` resolve. Passed raw, since
+/// cmd doesn't follow the quoting rules `Command` applies to arguments.
+#[cfg(windows)]
+fn command(editor: &str, path: &Path) -> Command {
+    use std::os::windows::process::CommandExt;
+    let mut c = Command::new("cmd");
+    c.arg("/C")
+        .raw_arg(format!("{} \"{}\"", editor, path.display()));
+    c
+}
@@ -0,0 +1,201 @@
+use crate::output::{ErrorBody, ErrorOutput, SCHEMA_VERSION};
+use crate::types::{Status, Ticket};
+
+/// Errors that scripts may want to branch on. Anything else is reported as a
+/// generic failure.
+///
+/// Exit codes:
+///   1  general failure (including checks like dep-cycle finding problems)
+///   2  usage error (bad arguments)
+///   3  not found
+///   4  ambiguous ID prefix or title
+///   5  validation error (bad input or a rejected change)
+///   6  parse error (malformed ticket file)
+#[derive(Debug)]
+pub enum TkError {
+    NotFound(String),
+    AmbiguousPrefix {
+        prefix: String,
+        matches: Vec<Candidate>,
+    },
+    AmbiguousTitle {
+        title: String,
+        matches: Vec<Candidate>,
+    },
+    Validation(String),
+    Parse(String),
+    /// A check found problems, already reported on stdout
+    Check(String),
+}
+
+impl TkError {
+    /// "<what> '<id>' not found", e.g. "Ticket 'tk-a1' not found"
+    pub fn not_found(what: &str, id: &str) -> Self {
+        TkError::NotFound(format!("{} '{}' not found", what, id))
+    }
+
+    pub fn validation(msg: impl Into<String>) -> Self {
+        TkError::Validation(msg.into())
+    }
+
+    /// `prefix` could mean any of `matches`
+    pub fn ambiguous(prefix: &str, matches: &[Ticket]) -> Self {
+        TkError::AmbiguousPrefix {
+            prefix: prefix.to_string(),
+            matches: Candidate::all(matches),
+        }
+    }
+
+    /// Every one of `matches` has `title` in its title
+    pub fn ambiguous_title(title: &str, matches: &[Ticket]) -> Self {
+        TkError::AmbiguousTitle {
+            title: title.to_string(),
+            matches: Candidate::all(matches),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            TkError::NotFound(_) => "not_found",
+            TkError::AmbiguousPrefix { .. } => "ambiguous_prefix",
+            TkError::AmbiguousTitle { .. } => "ambiguous_title",
+            TkError::Validation(_) => "validation",
+            TkError::Parse(_) => "parse_error",
+            TkError::Check(_) => "check_failed",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TkError::NotFound(_) => 3,
+            TkError::AmbiguousPrefix { .. } | TkError::AmbiguousTitle { .. } => 4,
+            TkError::Validation(_) => 5,
+            TkError::Parse(_) => 6,
+            TkError::Check(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+impl std::fmt::Display for TkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TkError::NotFound(msg)
+            | TkError::Validation(msg)
+            | TkError::Parse(msg)
+            | TkError::Check(msg) => write!(f, "{}", msg),
+            TkError::AmbiguousPrefix { prefix, matches } => {
+                write!(
+                    f,
+                    "Ambiguous prefix '{}': matches {} tickets. Use more of the ID:",
+                    prefix,
+                    matches.len()
+                )?;
+                Candidate::list(f, matches)
+            }
+            TkError::AmbiguousTitle { title, matches } => {
+                write!(
+                    f,
+                    "{} ticket titles contain '{}'. Use more of the title, or the ID:",
+                    matches.len(),
+                    title
+                )?;
+                Candidate::list(f, matches)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TkError {}
+
+/// A ticket an ambiguous prefix could mean
+#[derive(Debug)]
+pub struct Candidate {
+    pub id: String,
+    pub status: Status,
+    pub title: String,
+}
+
+impl Candidate {
+    fn all(tickets: &[Ticket]) -> Vec<Self> {
+        tickets
+            .iter()
+            .map(|t| Candidate {
+                id: t.id().to_string(),
+                status: t.meta.status,
+                title: t.title.clone(),
+            })
+            .collect()
+    }
+
+    /// One indented `id [status] title` line each
+    fn list(f: &mut std::fmt::Formatter<'_>, candidates: &[Self]) -> std::fmt::Result {
+        for c in candidates {
+            write!(f, "\n  {} [{}] {}", c.id, c.status, c.title)?;
+        }
+        Ok(())
+    }
+}
+
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+
+/// Print an error to stderr (as JSON when requested) and return the exit code
+pub fn report(err: &anyhow::Error, json: bool) -> i32 {
+    let tk_err = err.chain().find_map(|e| e.downcast_ref::<TkError>());
+    let (code, exit) = match tk_err {
+        Some(e) => (e.code(), e.exit_code()),
+        None => ("error", EXIT_FAILURE),
+    };
+
+    if json {
+        let candidates = match tk_err {
+            Some(
+                TkError::AmbiguousPrefix { matches, .. } | TkError::AmbiguousTitle { matches, .. },
+            ) => Some(matches.iter().map(|c| c.id.clone()).collect()),
+            _ => None,
+        };
+        let out = ErrorOutput {
+            schema: SCHEMA_VERSION,
+            error: ErrorBody {
+                code: code.to_string(),
+                message: format!("{:#}", err),
+                candidates,
+            },
+        };
+        eprintln!("{}", serde_json::to_string(&out).unwrap_or_default());
+    } else {
+        eprintln!("Error: {:?}", err);
+    }
+
+    exit
+}
+
+/// Report a command-line parsing failure. Help and version requests are
+/// printed normally.
+pub fn report_usage(err: clap::Error, json: bool) -> ! {
+    use clap::error::ErrorKind;
+
+    if !json
+        || matches!(
+            err.kind(),
+            ErrorKind::DisplayHelp
+                | ErrorKind::DisplayVersion
+                | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+        )
+    {
+        err.exit();
+    }
+
+    let message = err.render().to_string();
+    let message = message.trim().trim_start_matches("error: ");
+    let out = ErrorOutput {
+        schema: SCHEMA_VERSION,
+        error: ErrorBody {
+            code: "usage".to_string(),
+            message: message.to_string(),
+            candidates: None,
+        },
+    };
+    eprintln!("{}", serde_json::to_string(&out).unwrap_or_default());
+    std::process::exit(EXIT_USAGE);
+}
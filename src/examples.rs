@@ -0,0 +1,114 @@
+//! What `tk init --with-examples` adds: a handful of demo tickets showing
+//! parents, deps, tags and notes, and a `.tickets/README.md` on the workflow.
+
+use crate::config::Config;
+use crate::lint;
+use crate::types::{EventAction, Note, Status, Ticket, TicketType};
+
+/// `.tickets/README.md`
+pub const README: &str = "\
+# Tickets
+
+Each `tk-*.md` file here is one ticket: YAML frontmatter (status, priority,
+type, tags, deps, parent) and a Markdown body, with notes appended at the end.
+Edit them with `tk`, or by hand and check with `tk lint`.
+
+## Workflow
+
+1. `echo \"# Title\" | tk create -t fix -p 1` files a ticket. Priorities run
+   from 0 (critical) to 4 (backlog).
+2. `tk ready` lists open tickets whose deps are all closed; `tk next` picks the
+   most urgent one.
+3. `tk start <id>` marks it in progress, and `tk note <id> \"...\"` records
+   decisions and findings as you go.
+4. `tk close <id>` when it's done. Tickets that depend on it become ready.
+
+## Conventions
+
+- Break big work into children with `tk create --parent <id>`; `tk tree`
+  shows the hierarchy.
+- `tk dep <id> <other>` says `<id>` can't start before `<other>` is closed;
+  `tk blocked` and `tk why-blocked` show what is waiting on what.
+- Tags group tickets across the tree (`tk list -t backend`, `tk tags`).
+- Ticket files are meant to be committed along with the code they describe.
+
+Settings live in `config.yaml` next to this file. The example tickets are
+tagged `example`; remove them with `tk list -t example` and `tk delete`.
+";
+
+/// The demo tickets, saved parents first. `epic` and `bug` are the IDs for
+/// the two top-level ones; the epic's children are numbered under it.
+pub fn tickets(epic: &str, bug: &str, config: &Config) -> Vec<Ticket> {
+    let child = |n: u32| format!("{}.{}", epic, n);
+    let mut tickets = vec![
+        ticket(
+            epic,
+            "Adopt tk for tracking work",
+            TicketType::Feat,
+            2,
+            &["example", "onboarding"],
+            "Move the team's open work into `.tickets/` and agree on how we use it.\n\n\
+             Its children are the steps; `tk tree` shows them under this ticket.",
+        ),
+        ticket(
+            &child(1),
+            "Write down how we triage new tickets",
+            TicketType::Docs,
+            2,
+            &["example", "onboarding"],
+            "Agree on what each priority means and who picks up new bugs.",
+        ),
+        ticket(
+            &child(2),
+            "Move the open bugs into tk",
+            TicketType::Chore,
+            3,
+            &["example"],
+            "Waits on the triage guide (see `deps`), so `tk ready` won't offer \
+             it until that one is closed.",
+        ),
+        ticket(
+            bug,
+            "Login form accepts an empty password",
+            TicketType::Fix,
+            1,
+            &["example", "auth"],
+            "A bug report on its own, outside the tree. P1, so `tk ready` lists \
+             it before the P2 and P3 work.",
+        ),
+    ];
+
+    for t in &mut tickets {
+        t.body = lint::apply_template(&t.body, config.required_sections(t.meta.ticket_type));
+    }
+    tickets[0].set_status(Status::InProgress);
+    tickets[1].meta.parent = Some(epic.to_string());
+    tickets[2].meta.parent = Some(epic.to_string());
+    tickets[2].meta.deps = vec![child(1)];
+    for (t, estimate) in tickets.iter_mut().skip(1).zip([2, 3, 1]) {
+        t.meta.estimate = Some(estimate);
+    }
+    tickets[1].add_note(&Note::new(
+        "Started a draft: P0 means someone drops everything, P1 this week, \
+         the rest whenever the queue allows."
+            .to_string(),
+    ));
+    tickets
+}
+
+fn ticket(
+    id: &str,
+    title: &str,
+    ticket_type: TicketType,
+    priority: u8,
+    tags: &[&str],
+    body: &str,
+) -> Ticket {
+    let mut ticket = Ticket::new(id.to_string(), title.to_string());
+    ticket.meta.ticket_type = ticket_type;
+    ticket.meta.priority = priority;
+    ticket.meta.tags = tags.iter().map(|t| t.to_string()).collect();
+    ticket.body = body.to_string();
+    ticket.record(EventAction::Created);
+    ticket
+}
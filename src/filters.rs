@@ -0,0 +1,296 @@
+//! Filter flags shared by the listing commands, and what they match.
+//!
+//! Every listing (`list`, `ready`, `blocked`, `search`, `count`, `export
+//! cards`) flattens `FilterArgs` into its flags and keeps the tickets its
+//! `FilterSet` matches, so a filter added here works everywhere at once.
+
+use crate::dates;
+use crate::error::TkError;
+use crate::types::{Status, Ticket, TicketType};
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use clap::Args;
+
+/// A `-t a,b` value as the tags a ticket must all carry
+pub fn tags(tag: Option<String>) -> Vec<String> {
+    tag.map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// Which tickets a listing keeps
+#[derive(Args)]
+pub struct FilterArgs {
+    /// Only these statuses (comma-separated), or `all`
+    #[arg(short, long, value_name = "STATUS")]
+    pub status: Option<String>,
+    /// Only tickets with every one of these tags (comma-separated)
+    #[arg(short = 't', long)]
+    pub tag: Option<String>,
+    /// Only these types (comma-separated, any of them)
+    #[arg(long = "type", value_name = "TYPE", value_delimiter = ',')]
+    pub types: Vec<TicketType>,
+    #[command(flatten)]
+    pub priority: PriorityArgs,
+    /// Only tickets assigned to this person
+    #[arg(short, long, conflicts_with = "unassigned")]
+    pub assignee: Option<String>,
+    /// Only tickets nobody is assigned to
+    #[arg(long)]
+    pub unassigned: bool,
+    /// Only tickets created on or after this date (2024-06-01, last monday, -2w, ...)
+    #[arg(long, value_name = "DATE", value_parser = dates::arg)]
+    pub created_since: Option<NaiveDate>,
+    /// Only tickets created before this date
+    #[arg(long, value_name = "DATE", value_parser = dates::arg)]
+    pub created_before: Option<NaiveDate>,
+    /// Only tickets changed on or after this date
+    #[arg(long, value_name = "DATE", value_parser = dates::arg)]
+    pub updated_since: Option<NaiveDate>,
+    /// Only tickets unchanged since before this date
+    #[arg(long, value_name = "DATE", value_parser = dates::arg)]
+    pub updated_before: Option<NaiveDate>,
+    /// Only tickets closed on or after this date
+    #[arg(long, value_name = "DATE", value_parser = dates::arg)]
+    pub closed_since: Option<NaiveDate>,
+    /// Only tickets closed before this date
+    #[arg(long, value_name = "DATE", value_parser = dates::arg)]
+    pub closed_before: Option<NaiveDate>,
+    /// Only tickets due on or before this date
+    #[arg(long, value_name = "DATE", value_parser = dates::arg)]
+    pub due_by: Option<NaiveDate>,
+    /// Only tickets whose title or body contains this (any case)
+    #[arg(long)]
+    pub text: Option<String>,
+    #[command(flatten)]
+    pub exclude: ExcludeArgs,
+}
+
+impl FilterArgs {
+    /// Parse and check the flags
+    pub fn build(&self) -> Result<FilterSet> {
+        let statuses = match self.status.as_deref() {
+            None => None,
+            Some("all") => Some(Vec::new()),
+            Some(s) => Some(
+                s.split(',')
+                    .map(|s| s.trim().parse())
+                    .collect::<Result<Vec<Status>>>()?,
+            ),
+        };
+        Ok(FilterSet {
+            statuses,
+            tags: tags(self.tag.clone()),
+            types: self.types.clone(),
+            priority: self.priority.range()?,
+            assignee: match (&self.assignee, self.unassigned) {
+                (Some(a), _) => Assignee::Is(a.clone()),
+                (None, true) => Assignee::Nobody,
+                (None, false) => Assignee::Anyone,
+            },
+            created: DateRange::new(self.created_since, self.created_before)?,
+            updated: DateRange::new(self.updated_since, self.updated_before)?,
+            closed: DateRange::new(self.closed_since, self.closed_before)?,
+            due_by: self.due_by,
+            text: self.text.as_ref().map(|t| t.to_lowercase()),
+            not_tags: self.exclude.not_tag.clone(),
+            not_statuses: self.exclude.not_status.clone(),
+            not_types: self.exclude.not_type.clone(),
+            no_parent: self.exclude.no_parent,
+        })
+    }
+}
+
+/// Parsed `FilterArgs`; the default matches every ticket
+#[derive(Default)]
+pub struct FilterSet {
+    /// None when `--status` wasn't given, empty for `all`
+    statuses: Option<Vec<Status>>,
+    tags: Vec<String>,
+    types: Vec<TicketType>,
+    priority: PriorityRange,
+    assignee: Assignee,
+    created: DateRange,
+    updated: DateRange,
+    closed: DateRange,
+    due_by: Option<NaiveDate>,
+    text: Option<String>,
+    not_tags: Vec<String>,
+    not_statuses: Vec<Status>,
+    not_types: Vec<TicketType>,
+    no_parent: bool,
+}
+
+#[derive(Default)]
+enum Assignee {
+    #[default]
+    Anyone,
+    Nobody,
+    Is(String),
+}
+
+impl FilterSet {
+    /// Only tickets with every one of the `-t` tags
+    pub fn tagged(tag: Option<String>) -> Self {
+        Self {
+            tags: tags(tag),
+            ..Self::default()
+        }
+    }
+
+    /// One of the tags every match must have, if there are any
+    pub fn first_tag(&self) -> Option<&str> {
+        self.tags.first().map(String::as_str)
+    }
+
+    /// Statuses to show when `--status` wasn't given (list.status in config)
+    pub fn default_statuses(&mut self, statuses: &[Status]) {
+        self.statuses.get_or_insert_with(|| statuses.to_vec());
+    }
+
+    pub fn matches(&self, t: &Ticket) -> bool {
+        let m = &t.meta;
+        let kept = self
+            .statuses
+            .as_ref()
+            .is_none_or(|s| s.is_empty() || s.contains(&m.status))
+            && self.tags.iter().all(|tag| m.tags.contains(tag))
+            && (self.types.is_empty() || self.types.contains(&m.ticket_type))
+            && self.priority.matches(t)
+            && match &self.assignee {
+                Assignee::Anyone => true,
+                Assignee::Nobody => m.assignee.is_none(),
+                Assignee::Is(a) => m.assignee.as_ref() == Some(a),
+            }
+            && self.created.contains(m.created)
+            && self.updated.contains(m.updated.unwrap_or(m.created))
+            && self.closed.matches(m.closed)
+            && self
+                .due_by
+                .is_none_or(|by| m.due.is_some_and(|due| due <= by))
+            && self.text.as_ref().is_none_or(|text| {
+                t.title.to_lowercase().contains(text) || t.body.to_lowercase().contains(text)
+            })
+            && !m.tags.iter().any(|tag| self.not_tags.contains(tag))
+            && !self.not_statuses.contains(&m.status)
+            && !self.not_types.contains(&m.ticket_type)
+            && !(self.no_parent && m.parent.is_some());
+        if !kept {
+            tracing::trace!("{} filtered out", t.id());
+        }
+        kept
+    }
+}
+
+/// Priorities to keep. Lower numbers are more urgent, so `--max-priority 1`
+/// means P0 and P1.
+#[derive(Args)]
+pub struct PriorityArgs {
+    /// Only this priority
+    #[arg(short, long, conflicts_with_all = ["min_priority", "max_priority"])]
+    pub priority: Option<u8>,
+    /// Only this priority number or higher (this urgent or less)
+    #[arg(long, value_name = "N")]
+    pub min_priority: Option<u8>,
+    /// Only this priority number or lower (this urgent or more)
+    #[arg(long, value_name = "N")]
+    pub max_priority: Option<u8>,
+}
+
+impl PriorityArgs {
+    /// The inclusive range the flags allow
+    fn range(&self) -> Result<PriorityRange> {
+        let (min, max) = match self.priority {
+            Some(p) => (p, p),
+            None => (
+                self.min_priority.unwrap_or(0),
+                self.max_priority.unwrap_or(u8::MAX),
+            ),
+        };
+        if min > max {
+            return Err(TkError::validation(format!(
+                "--min-priority {} is above --max-priority {}; nothing could match",
+                min, max
+            ))
+            .into());
+        }
+        Ok(PriorityRange { min, max })
+    }
+}
+
+#[derive(Clone, Copy)]
+struct PriorityRange {
+    min: u8,
+    max: u8,
+}
+
+impl Default for PriorityRange {
+    fn default() -> Self {
+        Self {
+            min: 0,
+            max: u8::MAX,
+        }
+    }
+}
+
+impl PriorityRange {
+    fn matches(&self, t: &Ticket) -> bool {
+        (self.min..=self.max).contains(&t.meta.priority)
+    }
+}
+
+/// Days from `since` (inclusive) to `before` (exclusive), in local time
+#[derive(Default)]
+struct DateRange {
+    since: Option<NaiveDate>,
+    before: Option<NaiveDate>,
+}
+
+impl DateRange {
+    fn new(since: Option<NaiveDate>, before: Option<NaiveDate>) -> Result<Self> {
+        if let (Some(s), Some(b)) = (since, before) {
+            if s >= b {
+                return Err(TkError::validation(format!(
+                    "No day is both on or after {} and before {}",
+                    s, b
+                ))
+                .into());
+            }
+        }
+        Ok(Self { since, before })
+    }
+
+    fn is_set(&self) -> bool {
+        self.since.is_some() || self.before.is_some()
+    }
+
+    /// Like `contains`, for a time that may not be set: an unset time only
+    /// matches when the range is
+    fn matches(&self, at: Option<DateTime<Utc>>) -> bool {
+        match at {
+            Some(at) => self.contains(at),
+            None => !self.is_set(),
+        }
+    }
+
+    fn contains(&self, at: DateTime<Utc>) -> bool {
+        let day = at.with_timezone(&Local).date_naive();
+        self.since.is_none_or(|s| day >= s) && self.before.is_none_or(|b| day < b)
+    }
+}
+
+/// Filters that leave tickets out
+#[derive(Args)]
+pub struct ExcludeArgs {
+    /// Leave out tickets with any of these tags (comma-separated)
+    #[arg(long, value_name = "TAGS", value_delimiter = ',')]
+    pub not_tag: Vec<String>,
+    /// Leave out tickets in any of these statuses (comma-separated)
+    #[arg(long, value_name = "STATUS", value_delimiter = ',')]
+    pub not_status: Vec<Status>,
+    /// Leave out tickets of any of these types (comma-separated)
+    #[arg(long, value_name = "TYPE", value_delimiter = ',')]
+    pub not_type: Vec<TicketType>,
+    /// Leave out children: only tickets without a parent
+    #[arg(long)]
+    pub no_parent: bool,
+}
@@ -0,0 +1,202 @@
+//! Saving YAML frontmatter without rewriting all of it.
+//!
+//! Serializing `Frontmatter` afresh puts every key in tk's order with
+//! serde_yaml's quoting, so saving a hand-edited ticket turned a one-field
+//! change into a diff of the whole block. `patch` keeps each top-level key's
+//! original lines, comments above it included, while its value is unchanged,
+//! rewrites only the keys whose value changed, drops the ones that went away
+//! and slots new ones in after the key that comes before them in tk's order.
+
+use crate::types::Frontmatter;
+use serde_yaml::{Mapping, Value};
+
+/// One top-level key of the original text
+struct Entry {
+    key: Value,
+    value: Value,
+    /// Comment and blank lines just above the key
+    comments: String,
+    /// The key's line and its value's continuation lines
+    text: String,
+}
+
+/// `original` (YAML without its `---` fences) updated to say `meta`, or
+/// None when it can't be patched safely and should be written afresh
+pub fn patch(original: &str, meta: &Frontmatter) -> Option<String> {
+    let Value::Mapping(new) = serde_yaml::to_value(meta).ok()? else {
+        return None;
+    };
+    let (entries, trailer) = entries(original)?;
+
+    let mut kept: Vec<(Value, String)> = Vec::new();
+    for entry in entries {
+        let Some(value) = new.get(&entry.key) else {
+            continue;
+        };
+        let text = if *value == entry.value {
+            entry.text
+        } else {
+            render(&entry.key, value)?
+        };
+        kept.push((entry.key, entry.comments + &text));
+    }
+
+    let keys: Vec<&Value> = new.keys().collect();
+    for (i, (key, value)) in new.iter().enumerate() {
+        if kept.iter().any(|(k, _)| k == key) {
+            continue;
+        }
+        let at = keys[..i]
+            .iter()
+            .rev()
+            .find_map(|prev| kept.iter().position(|(k, _)| k == *prev))
+            .map_or(0, |pos| pos + 1);
+        kept.insert(at, (key.clone(), render(key, value)?));
+    }
+
+    let mut yaml: String = kept.into_iter().map(|(_, text)| text).collect();
+    yaml.push_str(&trailer);
+
+    // Whatever the original looked like, the result must say exactly `meta`
+    let reparsed: Mapping = serde_yaml::from_str(&yaml).ok()?;
+    (reparsed == new).then_some(yaml)
+}
+
+/// The top-level keys of `yaml` in order, and the comments after the last.
+/// None if it isn't a plain block mapping with one key per line.
+fn entries(yaml: &str) -> Option<(Vec<Entry>, String)> {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut pending = String::new();
+    for line in yaml.split_inclusive('\n') {
+        let line = if line.ends_with('\n') {
+            line.to_string()
+        } else {
+            format!("{}\n", line)
+        };
+        let trimmed = line.trim();
+        let top_level = !line.starts_with([' ', '\t', '-']);
+        if trimmed.is_empty() || (top_level && trimmed.starts_with('#')) {
+            pending.push_str(&line);
+        } else if top_level {
+            entries.push(Entry {
+                key: Value::Null,
+                value: Value::Null,
+                comments: std::mem::take(&mut pending),
+                text: line,
+            });
+        } else {
+            // A continuation line: comments between it and the key are
+            // part of the value
+            let entry = entries.last_mut()?;
+            entry.text.push_str(&std::mem::take(&mut pending));
+            entry.text.push_str(&line);
+        }
+    }
+
+    for entry in &mut entries {
+        let mapping: Mapping = serde_yaml::from_str(&entry.text).ok()?;
+        let mut pairs = mapping.into_iter();
+        let (key, value) = pairs.next()?;
+        if pairs.next().is_some() {
+            return None;
+        }
+        entry.key = key;
+        entry.value = value;
+    }
+    (!entries.is_empty()).then_some((entries, pending))
+}
+
+/// `key: value` as serde_yaml writes it
+fn render(key: &Value, value: &Value) -> Option<String> {
+    let mut mapping = Mapping::new();
+    mapping.insert(key.clone(), value.clone());
+    serde_yaml::to_string(&mapping).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn meta(yaml: &str) -> Frontmatter {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn unchanged_keys_keep_their_comments_and_quoting() {
+        let original = "\
+# Filed from the standup
+id: 'tk-a1b2'
+status: open
+created: \"2026-01-05T09:00:00Z\"
+type: fix
+priority: 2
+# Keep in sync with the tracker
+tags: [ui, login]
+";
+        let mut changed = meta(original);
+        changed.priority = 1;
+        let patched = patch(original, &changed).unwrap();
+        assert_eq!(patched, original.replace("priority: 2", "priority: 1"));
+    }
+
+    #[test]
+    fn block_sequences_are_rewritten_whole_when_they_change() {
+        let original = "\
+id: tk-a1b2
+status: open
+created: 2026-01-05T09:00:00Z
+type: fix
+priority: 2
+tags:
+  # the team's
+  - ui
+  - login
+";
+        let unchanged = patch(original, &meta(original)).unwrap();
+        assert_eq!(unchanged, original);
+
+        let mut changed = meta(original);
+        changed.tags.push("auth".to_string());
+        let patched = patch(original, &changed).unwrap();
+        assert!(patched.ends_with("tags:\n- ui\n- login\n- auth\n"));
+        assert!(patched.starts_with("id: tk-a1b2\nstatus: open\n"));
+    }
+
+    #[test]
+    fn new_keys_follow_the_nearest_key_before_them_in_tk_order() {
+        let original = "\
+priority: 2
+id: tk-a1b2
+status: open
+created: 2026-01-05T09:00:00Z
+type: fix
+assignee: ana
+";
+        let mut changed = meta(original);
+        changed.assignee = None;
+        changed.parent = Some("tk-c3d4".to_string());
+        changed.due = NaiveDate::from_ymd_opt(2026, 2, 1);
+        let patched = patch(original, &changed).unwrap();
+        assert_eq!(
+            patched,
+            "\
+priority: 2
+due: 2026-02-01
+id: tk-a1b2
+status: open
+parent: tk-c3d4
+created: 2026-01-05T09:00:00Z
+type: fix
+"
+        );
+    }
+
+    #[test]
+    fn flow_mappings_spanning_keys_are_written_afresh() {
+        let original = "{id: tk-a1b2, status: open, created: 2026-01-05T09:00:00Z}\n";
+        let mut changed = meta(original);
+        changed.priority = 1;
+        assert_eq!(patch(original, &changed), None);
+    }
+}
@@ -0,0 +1,151 @@
+//! Setting a repo up to keep its tickets in git (`tk init --git`): ignore
+//! rules for per-clone files, merge attributes and driver, and the first commit.
+
+use crate::storage::Storage;
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// Per-clone and runtime files under .tickets/ that never belong in a
+/// commit, `tk daemon`'s socket among them
+const IGNORED: [&str; 5] = [".local/", ".cache/", ".lock", ".manifest.lock", ".tk.sock"];
+
+/// Ticket files and archive bundles merge through `tk merge-file`. A clone
+/// that hasn't registered the driver merges them as plain text.
+const ATTRIBUTES: [&str; 1] = [".tickets/**/*.md merge=tk"];
+
+/// Attributes earlier versions of tk set, dropped when the file is updated.
+/// Union merges could duplicate a ticket bundled on both sides, or
+/// interleave two edits of one.
+const RETIRED: [&str; 1] = [".tickets/archive/bundles/*.md merge=union"];
+
+/// Make sure .tickets/.gitignore lists every per-clone file. Returns
+/// whether it changed.
+pub fn ignore_local_files(storage: &Storage) -> Result<bool> {
+    let path = storage.tickets_dir().join(".gitignore");
+    let existing = read_or_empty(&path)?;
+    match with_lines(&existing, "# Per-clone state, not shared", &IGNORED) {
+        Some(content) => {
+            storage.save_file(".gitignore", &content)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Make sure the repo's .gitattributes sets tk's merge rules. Returns
+/// whether it changed.
+pub fn set_attributes(storage: &Storage) -> Result<bool> {
+    let path = storage.root_dir().join(".gitattributes");
+    let read = read_or_empty(&path)?;
+    let existing: String = read
+        .split_inclusive('\n')
+        .filter(|line| !RETIRED.contains(&line.trim()))
+        .collect();
+    match with_lines(&existing, "# tk tickets", &ATTRIBUTES) {
+        None if existing != read => {
+            storage.save_root_file(".gitattributes", &existing)?;
+            Ok(true)
+        }
+        Some(content) => {
+            storage.save_root_file(".gitattributes", &content)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Tell git how to run the `merge=tk` driver. Git keeps this in the
+/// clone's own config, not in the repo, so each clone runs `tk init --git`
+/// once to get it.
+pub fn register_merge_driver(root: &Path) -> Result<()> {
+    run(root, &["config", "merge.tk.name", "tk ticket merge"])?;
+    run(
+        root,
+        &["config", "merge.tk.driver", "tk merge-file %O %A %B %P"],
+    )?;
+    Ok(())
+}
+
+/// Commit `paths` (and nothing else that's staged) with `message`, running
+/// `git init` first outside a repo. Returns the new commit's short hash, or
+/// None when there was nothing to commit.
+pub fn commit(root: &Path, paths: &[&str], message: &str) -> Result<Option<String>> {
+    if !git(root, &["rev-parse", "--is-inside-work-tree"])?
+        .status
+        .success()
+    {
+        run(root, &["init", "--quiet"])?;
+    }
+    let mut add = vec!["add", "--"];
+    add.extend(paths);
+    run(root, &add)?;
+
+    let mut diff = vec!["diff", "--cached", "--quiet", "--"];
+    diff.extend(paths);
+    if git(root, &diff)?.status.success() {
+        return Ok(None);
+    }
+    let mut commit = vec!["commit", "--quiet", "-m", message, "--"];
+    commit.extend(paths);
+    run(root, &commit)?;
+    let head = run(root, &["rev-parse", "--short", "HEAD"])?;
+    Ok(Some(head))
+}
+
+/// `existing` with any missing `lines` appended under `header`; None if
+/// nothing is missing
+fn with_lines(existing: &str, header: &str, lines: &[&str]) -> Option<String> {
+    let missing: Vec<&str> = lines
+        .iter()
+        .copied()
+        .filter(|line| !existing.lines().any(|l| l.trim() == *line))
+        .collect();
+    if missing.is_empty() {
+        return None;
+    }
+    let mut content = existing.to_string();
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    if !content.is_empty() {
+        content.push('\n');
+    }
+    content.push_str(header);
+    content.push('\n');
+    for line in missing {
+        content.push_str(line);
+        content.push('\n');
+    }
+    Some(content)
+}
+
+fn read_or_empty(path: &Path) -> Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn git(root: &Path, args: &[&str]) -> Result<std::process::Output> {
+    tracing::debug!("running git {}", args.join(" "));
+    Command::new("git")
+        .current_dir(root)
+        .args(args)
+        .output()
+        .context("Failed to run git")
+}
+
+/// Run git, failing with its stderr if it does; returns its trimmed stdout
+fn run(root: &Path, args: &[&str]) -> Result<String> {
+    let output = git(root, args)?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,496 @@
+//! Algorithms over the ticket graph: deps (a ticket waits on its deps) and
+//! parents (a ticket sits under its parent). They work on a `View`, so
+//! every step is a lookup by ID instead of a scan of the whole list.
+//!
+//! IDs that a dep or parent names but no ticket has are leaves: nothing
+//! hangs off them.
+
+use crate::types::Ticket;
+use crate::view::View;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Every dep cycle, each as the IDs along it starting where the search
+/// entered it. A ticket that depends on itself is a cycle of one.
+pub fn cycles(view: &View) -> Vec<Vec<String>> {
+    let mut cycles = Vec::new();
+    let mut visited = HashSet::new();
+    let mut on_path = HashSet::new();
+    let mut path = Vec::new();
+    for ticket in view.tickets() {
+        if !visited.contains(ticket.id()) {
+            find_cycles(
+                view,
+                ticket.id(),
+                &mut visited,
+                &mut on_path,
+                &mut path,
+                &mut cycles,
+            );
+        }
+    }
+    cycles
+}
+
+fn find_cycles(
+    view: &View,
+    id: &str,
+    visited: &mut HashSet<String>,
+    on_path: &mut HashSet<String>,
+    path: &mut Vec<String>,
+    cycles: &mut Vec<Vec<String>>,
+) {
+    visited.insert(id.to_string());
+    on_path.insert(id.to_string());
+    path.push(id.to_string());
+
+    if let Some(ticket) = view.get(id) {
+        for dep in &ticket.meta.deps {
+            if !visited.contains(dep) {
+                find_cycles(view, dep, visited, on_path, path, cycles);
+            } else if on_path.contains(dep) {
+                if let Some(start) = path.iter().position(|x| x == dep) {
+                    cycles.push(path[start..].to_vec());
+                }
+            }
+        }
+    }
+
+    path.pop();
+    on_path.remove(id);
+}
+
+/// Everything `id` depends on, directly or through other deps. `id` is
+/// only in it if it's on a cycle.
+pub fn transitive_deps(view: &View, id: &str) -> BTreeSet<String> {
+    let mut seen = BTreeSet::new();
+    let mut stack = vec![id.to_string()];
+    while let Some(current) = stack.pop() {
+        let Some(ticket) = view.get(&current) else {
+            continue;
+        };
+        for dep in &ticket.meta.deps {
+            if seen.insert(dep.clone()) {
+                stack.push(dep.clone());
+            }
+        }
+    }
+    seen
+}
+
+/// `id`'s parent, its parent's parent and so on up to the top, stopping
+/// short of repeating itself if the parents loop
+pub fn ancestors(view: &View, id: &str) -> Vec<String> {
+    let mut chain: Vec<String> = Vec::new();
+    let mut cursor = view.get(id).and_then(|t| t.meta.parent.clone());
+    while let Some(parent) = cursor {
+        if parent == id || chain.contains(&parent) {
+            break;
+        }
+        cursor = view.get(&parent).and_then(|t| t.meta.parent.clone());
+        chain.push(parent);
+    }
+    chain
+}
+
+/// Every path from `ticket` through open deps down to one with no open
+/// deps of its own, `ticket` first. A path that runs into a cycle ends
+/// just before repeating itself.
+pub fn blocker_chains(view: &View, ticket: &Ticket) -> Vec<Vec<String>> {
+    let mut chains = Vec::new();
+    let mut path = vec![ticket.id().to_string()];
+    walk_blockers(view, ticket, &mut path, &mut chains);
+    chains
+}
+
+fn walk_blockers(
+    view: &View,
+    ticket: &Ticket,
+    path: &mut Vec<String>,
+    chains: &mut Vec<Vec<String>>,
+) {
+    let open_deps: Vec<&Ticket> = ticket
+        .meta
+        .deps
+        .iter()
+        .filter_map(|d| view.get(d))
+        .filter(|d| d.is_open())
+        .collect();
+
+    if open_deps.is_empty() {
+        if path.len() > 1 {
+            chains.push(path.clone());
+        }
+        return;
+    }
+
+    for dep in open_deps {
+        if path.iter().any(|p| p == dep.id()) {
+            tracing::debug!("cycle through {}, ending chain", dep.id());
+            chains.push(path.clone());
+            continue;
+        }
+        path.push(dep.id().to_string());
+        walk_blockers(view, dep, path, chains);
+        path.pop();
+    }
+}
+
+/// The tickets `include` accepts, each after all of its deps among them.
+/// Ties go in ID order. Tickets on a dep cycle, or waiting on one, are left
+/// out.
+pub fn topological(view: &View, include: impl Fn(&Ticket) -> bool) -> Vec<&Ticket> {
+    let mut nodes: BTreeMap<&str, &Ticket> = BTreeMap::new();
+    for t in view.tickets().iter().filter(|t| include(t)) {
+        nodes.entry(t.id()).or_insert(t);
+    }
+
+    let mut waiting: HashMap<&str, usize> = HashMap::new();
+    let mut unlocks: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (&id, t) in &nodes {
+        let deps: BTreeSet<&str> = t
+            .meta
+            .deps
+            .iter()
+            .map(String::as_str)
+            .filter(|d| nodes.contains_key(d))
+            .collect();
+        waiting.insert(id, deps.len());
+        for dep in deps {
+            unlocks.entry(dep).or_default().push(id);
+        }
+    }
+
+    let mut ready: BTreeSet<&str> = waiting
+        .iter()
+        .filter(|(_, &n)| n == 0)
+        .map(|(&id, _)| id)
+        .collect();
+    let mut order = Vec::new();
+    while let Some(id) = ready.pop_first() {
+        order.push(nodes[id]);
+        for &next in unlocks.get(id).into_iter().flatten() {
+            let n = waiting.get_mut(next).expect("every node has a count");
+            *n -= 1;
+            if *n == 0 {
+                ready.insert(next);
+            }
+        }
+    }
+    order
+}
+
+/// How much work a ticket is on the critical path: its estimate, or 1
+/// without one
+pub fn weight(ticket: &Ticket) -> u64 {
+    ticket.meta.estimate.map_or(1, u64::from)
+}
+
+/// The chain of open live tickets, each depending on the one before, with
+/// the most work in it by `weight`: however the work is split up, it can't
+/// finish sooner. Ties go to lower IDs. Empty without open tickets.
+pub fn critical_path(view: &View) -> Vec<&Ticket> {
+    let order = topological(view, |t| t.is_open() && !t.archived);
+
+    // The heaviest chain ending at each ticket: its total, and the dep
+    // it continues
+    let mut best: HashMap<&str, (u64, Option<&str>)> = HashMap::new();
+    for t in &order {
+        let (total, via) = t
+            .meta
+            .deps
+            .iter()
+            .filter_map(|d| best.get(d.as_str()).map(|&(total, _)| (total, d.as_str())))
+            .max_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(a.1)))
+            .map_or((0, None), |(total, d)| (total, Some(d)));
+        best.insert(t.id(), (total + weight(t), via));
+    }
+
+    let Some(end) = order.iter().max_by(|a, b| {
+        best[a.id()]
+            .0
+            .cmp(&best[b.id()].0)
+            .then_with(|| b.id().cmp(a.id()))
+    }) else {
+        return Vec::new();
+    };
+    let mut path = vec![*end];
+    while let Some(prev) = best[path[path.len() - 1].id()].1 {
+        path.push(view.get(prev).expect("deps on the path exist"));
+    }
+    path.reverse();
+    path
+}
+
+/// Everything under `id`: its children, their children and so on, in ID
+/// order, without `id` itself even if the parents loop
+pub fn descendants<'a>(view: &'a View, id: &str) -> Vec<&'a Ticket> {
+    let mut seen: BTreeMap<&str, &Ticket> = BTreeMap::new();
+    let mut stack = vec![id];
+    while let Some(current) = stack.pop() {
+        for child in view.children(current) {
+            if child.id() != id && seen.insert(child.id(), child).is_none() {
+                stack.push(child.id());
+            }
+        }
+    }
+    seen.into_values().collect()
+}
+
+/// Estimates summed over a set of tickets
+#[derive(Debug, Default, PartialEq)]
+pub struct Rollup {
+    /// Tickets counted: those without children in the set
+    pub tickets: usize,
+    pub open: usize,
+    /// The counted tickets' estimates
+    pub estimate: u32,
+    /// The open counted tickets' estimates
+    pub remaining: u32,
+    /// Open counted tickets without an estimate
+    pub unestimated: usize,
+}
+
+/// Sum the estimates of `tickets`. A ticket with children among them counts
+/// as those children, so an epic's own estimate isn't added to its stories'.
+pub fn rollup(tickets: &[&Ticket]) -> Rollup {
+    let parents: HashSet<&str> = tickets
+        .iter()
+        .filter_map(|t| t.meta.parent.as_deref())
+        .collect();
+    let mut sum = Rollup::default();
+    for t in tickets.iter().filter(|t| !parents.contains(t.id())) {
+        let estimate = t.meta.estimate.unwrap_or(0);
+        sum.tickets += 1;
+        sum.estimate += estimate;
+        if t.is_open() {
+            sum.open += 1;
+            sum.remaining += estimate;
+            if t.meta.estimate.is_none() {
+                sum.unestimated += 1;
+            }
+        }
+    }
+    sum
+}
+
+/// A ticket and the tickets under it
+pub struct Tree<'a> {
+    pub ticket: &'a Ticket,
+    pub children: Vec<Tree<'a>>,
+}
+
+/// Nest `tickets` under their parents, keeping their order among siblings.
+/// Tickets whose parent isn't among them are roots. A ticket is placed
+/// once, so parents that loop can't nest forever.
+pub fn forest<'a>(tickets: &[&'a Ticket]) -> Vec<Tree<'a>> {
+    let ids: HashSet<&str> = tickets.iter().map(|t| t.id()).collect();
+    let mut children: HashMap<&str, Vec<&'a Ticket>> = HashMap::new();
+    let mut roots = Vec::new();
+    for t in tickets {
+        match t.meta.parent.as_deref() {
+            Some(p) if ids.contains(p) && p != t.id() => children.entry(p).or_default().push(t),
+            _ => roots.push(*t),
+        }
+    }
+
+    fn build<'a>(
+        t: &'a Ticket,
+        children: &HashMap<&str, Vec<&'a Ticket>>,
+        seen: &mut HashSet<String>,
+    ) -> Option<Tree<'a>> {
+        if !seen.insert(t.id().to_string()) {
+            return None;
+        }
+        Some(Tree {
+            ticket: t,
+            children: children
+                .get(t.id())
+                .into_iter()
+                .flatten()
+                .filter_map(|c| build(c, children, seen))
+                .collect(),
+        })
+    }
+
+    let mut seen = HashSet::new();
+    roots
+        .into_iter()
+        .filter_map(|r| build(r, &children, &mut seen))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Status;
+
+    fn ticket(id: &str, deps: &[&str]) -> Ticket {
+        let mut t = Ticket::new(id.to_string(), id.to_string());
+        t.meta.deps = deps.iter().map(|d| d.to_string()).collect();
+        t
+    }
+
+    fn ids(tickets: &[&Ticket]) -> Vec<String> {
+        tickets.iter().map(|t| t.id().to_string()).collect()
+    }
+
+    #[test]
+    fn finds_each_cycle_once() {
+        let view = View::new(vec![
+            ticket("a", &["b"]),
+            ticket("b", &["c"]),
+            ticket("c", &["a"]),
+            ticket("d", &["d"]),
+            ticket("e", &["a"]),
+        ]);
+        assert_eq!(
+            cycles(&view),
+            vec![
+                vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                vec!["d".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn acyclic_graph_has_no_cycles() {
+        let view = View::new(vec![
+            ticket("a", &["b", "c"]),
+            ticket("b", &["c"]),
+            ticket("c", &["missing"]),
+        ]);
+        assert!(cycles(&view).is_empty());
+    }
+
+    #[test]
+    fn transitive_deps_follow_every_branch() {
+        let view = View::new(vec![
+            ticket("a", &["b", "c"]),
+            ticket("b", &["d"]),
+            ticket("c", &["d"]),
+            ticket("d", &[]),
+            ticket("e", &["a"]),
+        ]);
+        let deps: Vec<String> = transitive_deps(&view, "a").into_iter().collect();
+        assert_eq!(deps, ["b", "c", "d"]);
+        assert!(transitive_deps(&view, "e").contains("d"));
+        assert!(!transitive_deps(&view, "a").contains("a"));
+    }
+
+    #[test]
+    fn ancestors_stop_at_a_loop() {
+        let mut a = ticket("a", &[]);
+        let mut b = ticket("b", &[]);
+        let mut c = ticket("c", &[]);
+        a.meta.parent = Some("b".to_string());
+        b.meta.parent = Some("c".to_string());
+        c.meta.parent = Some("a".to_string());
+        let view = View::new(vec![a, b, c]);
+        assert_eq!(ancestors(&view, "a"), ["b", "c"]);
+    }
+
+    #[test]
+    fn blocker_chains_skip_closed_deps() {
+        let mut done = ticket("done", &[]);
+        done.meta.status = Status::Closed;
+        let view = View::new(vec![
+            ticket("a", &["b", "done"]),
+            ticket("b", &["c"]),
+            ticket("c", &[]),
+            done,
+        ]);
+        let a = view.get("a").unwrap();
+        assert_eq!(blocker_chains(&view, a), vec![vec!["a", "b", "c"]]);
+    }
+
+    #[test]
+    fn topological_order_puts_deps_first_and_drops_cycles() {
+        let view = View::new(vec![
+            ticket("a", &["c"]),
+            ticket("b", &[]),
+            ticket("c", &["b"]),
+            ticket("x", &["y"]),
+            ticket("y", &["x"]),
+            ticket("z", &["x"]),
+        ]);
+        assert_eq!(ids(&topological(&view, |_| true)), ["b", "c", "a"]);
+        assert_eq!(ids(&topological(&view, |t| t.id() != "c")), ["a", "b"]);
+    }
+
+    #[test]
+    fn critical_path_follows_the_most_work() {
+        let mut big = ticket("big", &["start"]);
+        big.meta.estimate = Some(5);
+        let mut closed = ticket("closed", &[]);
+        closed.meta.status = Status::Closed;
+        let view = View::new(vec![
+            ticket("start", &["closed"]),
+            ticket("small1", &["start"]),
+            ticket("small2", &["small1"]),
+            big,
+            ticket("end", &["big", "small2"]),
+            closed,
+        ]);
+        assert_eq!(ids(&critical_path(&view)), ["start", "big", "end"]);
+    }
+
+    #[test]
+    fn critical_path_is_empty_without_open_tickets() {
+        let mut t = ticket("a", &[]);
+        t.meta.status = Status::Closed;
+        assert!(critical_path(&View::new(vec![t])).is_empty());
+    }
+
+    #[test]
+    fn forest_nests_children_and_keeps_orphans_as_roots() {
+        let mut child = ticket("a.1", &[]);
+        child.meta.parent = Some("a".to_string());
+        let mut orphan = ticket("b.1", &[]);
+        orphan.meta.parent = Some("b".to_string());
+        let a = ticket("a", &[]);
+        let tickets = vec![&child, &a, &orphan];
+
+        let trees = forest(&tickets);
+        let roots: Vec<&str> = trees.iter().map(|t| t.ticket.id()).collect();
+        assert_eq!(roots, ["a", "b.1"]);
+        assert_eq!(trees[0].children.len(), 1);
+        assert_eq!(trees[0].children[0].ticket.id(), "a.1");
+    }
+
+    #[test]
+    fn rollup_counts_parents_as_their_children() {
+        let sized = |id: &str, parent: Option<&str>, estimate: u32, open: bool| {
+            let mut t = ticket(id, &[]);
+            t.meta.parent = parent.map(str::to_string);
+            t.meta.estimate = Some(estimate);
+            if !open {
+                t.meta.status = Status::Closed;
+            }
+            t
+        };
+        let mut bare = ticket("f", &[]);
+        bare.meta.parent = Some("b".to_string());
+        let view = View::new(vec![
+            sized("a", None, 40, true),
+            sized("b", Some("a"), 10, true),
+            sized("c", Some("a"), 3, false),
+            sized("d", Some("b"), 5, true),
+            sized("e", Some("b"), 2, false),
+            bare,
+        ]);
+
+        let under = descendants(&view, "a");
+        assert_eq!(ids(&under), ["b", "c", "d", "e", "f"]);
+        assert_eq!(
+            rollup(&under),
+            Rollup {
+                tickets: 4,
+                open: 2,
+                estimate: 10,
+                remaining: 5,
+                unestimated: 1,
+            }
+        );
+        assert!(descendants(&view, "d").is_empty());
+    }
+}
@@ -0,0 +1,98 @@
+//! Commands from `hooks` in config.yaml, run after tk changes a ticket.
+//!
+//! Each hook gets a JSON description of the change on stdin (see
+//! `tk schema hook`), including who should hear about it: the ticket's
+//! watchers plus anyone `@mentioned` in the new text, minus whoever made the
+//! change. How to reach them (mail, chat) is up to the hook. A failing hook
+//! is reported but doesn't undo or fail the change.
+//!
+//! `escalate` in config.yaml reuses the same payload for tickets that just
+//! became critical, and can also POST it to webhooks.
+
+use crate::identity;
+use crate::output::{HookPayload, SCHEMA_VERSION};
+use crate::types::Ticket;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// `@name` mentions in `text`, in order of first appearance. A mention
+/// starts after whitespace or punctuation (so `a@b.com` isn't one) and
+/// runs over letters, digits, `_`, `-` and inner dots.
+pub fn mentions(text: &str) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+    let mut prev = ' ';
+    for (i, c) in text.char_indices() {
+        if c == '@' && !prev.is_alphanumeric() && prev != '@' {
+            let name: String = text[i + 1..]
+                .chars()
+                .take_while(|&c| c.is_alphanumeric() || "_-.".contains(c))
+                .collect();
+            let name = name.trim_end_matches('.');
+            if !name.is_empty() && !found.iter().any(|f| f == name) {
+                found.push(name.to_string());
+            }
+        }
+        prev = c;
+    }
+    found
+}
+
+/// Describe a change just made to `ticket`. `text` is the new prose (a note,
+/// or the body on create and edit) to look for mentions in.
+pub fn payload(ticket: &Ticket, action: &str, text: Option<&str>) -> HookPayload {
+    let by = identity::current();
+    let mentions = text.map(mentions).unwrap_or_default();
+    let mut notify: Vec<String> = Vec::new();
+    for name in ticket.meta.watchers.iter().chain(&mentions) {
+        if Some(name) != by.as_ref() && !notify.contains(name) {
+            notify.push(name.clone());
+        }
+    }
+    HookPayload {
+        schema: SCHEMA_VERSION,
+        action: action.to_string(),
+        id: ticket.id().to_string(),
+        title: ticket.title.clone(),
+        status: ticket.meta.status.to_string(),
+        priority: ticket.meta.priority,
+        by,
+        watchers: ticket.meta.watchers.clone(),
+        mentions,
+        notify,
+    }
+}
+
+/// POST the payload to a webhook, warning rather than failing
+pub fn post(url: &str, payload: &HookPayload) {
+    tracing::debug!("posting {} to {}", payload.action, url);
+    let result = ureq::post(url)
+        .set("User-Agent", concat!("tk/", env!("CARGO_PKG_VERSION")))
+        .send_json(payload);
+    if let Err(e) = result {
+        eprintln!("Warning: webhook {} for {} failed: {}", url, payload.id, e);
+    }
+}
+
+/// Run one hook with the payload on stdin, warning rather than failing
+pub fn run(mut command: Command, payload: &HookPayload) {
+    tracing::debug!("running hook {:?}", command);
+    let result = (|| -> anyhow::Result<()> {
+        // Hook output goes to stderr so it can't corrupt --json or --porcelain
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(std::io::stderr())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // A hook that ignores stdin may exit before reading it
+            let _ = stdin.write_all(serde_json::to_string(payload)?.as_bytes());
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            anyhow::bail!("exited with {}", status);
+        }
+        Ok(())
+    })();
+    if let Err(e) = result {
+        eprintln!("Warning: hook for {} failed: {}", payload.id, e);
+    }
+}
@@ -0,0 +1,126 @@
+//! HTML rendering of tickets, for `tk export cards`.
+//!
+//! The page is self-contained (inline CSS, no scripts) so it can be mailed or
+//! opened offline. Cards are sized for 5x3 inch index cards when printed; the
+//! browser's "Save as PDF" gives the PDF.
+
+use crate::types::Ticket;
+use chrono::Utc;
+
+/// Longest card summary, in characters; the rest is on the ticket
+const SUMMARY_LEN: usize = 240;
+
+const STYLE: &str = "\
+@page { size: letter; margin: 0.5in; }
+body { font-family: -apple-system, 'Segoe UI', Helvetica, Arial, sans-serif; margin: 1em; color: #222; }
+header { margin-bottom: 1em; color: #666; font-size: 0.9em; }
+.cards { display: flex; flex-wrap: wrap; gap: 0.25in; }
+.card { box-sizing: border-box; width: 5in; height: 3in; padding: 0.2in; border: 1px solid #999; border-radius: 4px; overflow: hidden; display: flex; flex-direction: column; break-inside: avoid; page-break-inside: avoid; }
+.card .top { display: flex; justify-content: space-between; font-size: 0.8em; color: #555; }
+.card .id { font-family: monospace; font-weight: bold; }
+.card h2 { font-size: 1.15em; margin: 0.3em 0; }
+.card .summary { font-size: 0.85em; flex: 1; overflow: hidden; margin: 0; }
+.card .meta { font-size: 0.75em; color: #555; }
+.tag { display: inline-block; padding: 0 0.4em; margin-right: 0.3em; border: 1px solid #bbb; border-radius: 3px; }
+.p0 { border-left: 6px solid #c0392b; }
+.p1 { border-left: 6px solid #e67e22; }
+.p2 { border-left: 6px solid #f1c40f; }
+.p3, .p4 { border-left: 6px solid #95a5a6; }
+@media print { header { display: none; } body { margin: 0; } }
+";
+
+/// A page of index cards, one per ticket, in the given order
+pub fn cards(tickets: &[Ticket]) -> String {
+    let mut page = String::new();
+    page.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    page.push_str("<title>Ticket cards</title>\n<style>\n");
+    page.push_str(STYLE);
+    page.push_str("</style>\n</head>\n<body>\n");
+    page.push_str(&format!(
+        "<header>{} tickets, exported {}</header>\n<main class=\"cards\">\n",
+        tickets.len(),
+        Utc::now().format("%Y-%m-%d %H:%M UTC")
+    ));
+    for t in tickets {
+        page.push_str(&card(t));
+    }
+    page.push_str("</main>\n</body>\n</html>\n");
+    page
+}
+
+fn card(t: &Ticket) -> String {
+    let m = &t.meta;
+    let mut meta = Vec::new();
+    if let Some(a) = &m.assignee {
+        meta.push(format!("@{}", escape(a)));
+    }
+    if let Some(e) = &m.estimate {
+        meta.push(format!("est. {}", e));
+    }
+    if let Some(ms) = &m.milestone {
+        meta.push(format!("milestone {}", escape(ms)));
+    }
+    if !m.deps.is_empty() {
+        meta.push(format!("needs {}", escape(&m.deps.join(", "))));
+    }
+    let tags: String = m
+        .tags
+        .iter()
+        .map(|tag| format!("<span class=\"tag\">{}</span>", escape(tag)))
+        .collect();
+
+    format!(
+        "<section class=\"card p{}\">\n\
+         <div class=\"top\"><span class=\"id\">{}</span><span>{} &middot; P{} &middot; {}</span></div>\n\
+         <h2>{}</h2>\n\
+         <p class=\"summary\">{}</p>\n\
+         <div class=\"meta\">{}{}</div>\n\
+         </section>\n",
+        m.priority.min(4),
+        escape(t.id()),
+        m.ticket_type,
+        m.priority,
+        m.status,
+        escape(&t.title),
+        escape(&summary(&t.body)),
+        tags,
+        meta.join(" &middot; ")
+    )
+}
+
+/// The first paragraph of the description: no headings, no notes
+fn summary(body: &str) -> String {
+    let mut text = String::new();
+    for line in body.lines().map(str::trim) {
+        if line.is_empty() {
+            if text.is_empty() {
+                continue;
+            }
+            break;
+        }
+        if line.starts_with('#') || line.starts_with('[') {
+            if text.is_empty() {
+                continue;
+            }
+            break;
+        }
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(line);
+    }
+    if text.chars().count() > SUMMARY_LEN {
+        text = text.chars().take(SUMMARY_LEN - 1).collect();
+        text.truncate(text.trim_end().len());
+        text.push('…');
+    }
+    text
+}
+
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -1,14 +1,41 @@
+use crate::error::TkError;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+const PREFIX: &str = "tk";
+
+/// Crockford base32, lowercased to match the rest of the ID
+const ULID_ALPHABET: &[u8; 32] = b"0123456789abcdefghjkmnpqrstvwxyz";
+const ULID_LEN: usize = 26;
+
+/// How new ticket IDs are made (`id_scheme` in config.yaml)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdScheme {
+    /// Short random hex: `tk-a1b2`
+    #[default]
+    Hex,
+    /// Time-sortable: `tk-01jabcd...`, so sorting by name sorts by creation
+    Ulid,
+}
+
+/// Generate a new top-level ticket ID
+pub fn generate(existing: &[String], scheme: IdScheme) -> String {
+    match scheme {
+        IdScheme::Hex => generate_hex(existing),
+        IdScheme::Ulid => generate_ulid(existing, now_millis()),
+    }
+}
+
 /// Generate a short ticket ID like "tk-a1b2"
 /// Uses prefix + random hex chars
-pub fn generate(existing: &[String]) -> String {
-    let prefix = "tk";
-
+fn generate_hex(existing: &[String]) -> String {
     for hex_len in 4..=8 {
         for _ in 0..100 {
             let mut bytes = [0u8; 8];
             getrandom::getrandom(&mut bytes).expect("failed to get random bytes");
             let hex = hex::encode(bytes);
-            let id = format!("{}-{}", prefix, &hex[..hex_len]);
+            let id = format!("{}-{}", PREFIX, &hex[..hex_len]);
 
             if !existing.contains(&id) {
                 return id;
@@ -19,5 +46,221 @@ pub fn generate(existing: &[String]) -> String {
     // Fallback with longer hex
     let mut bytes = [0u8; 8];
     getrandom::getrandom(&mut bytes).expect("failed to get random bytes");
-    format!("{}-{}", prefix, hex::encode(bytes))
+    format!("{}-{}", PREFIX, hex::encode(bytes))
+}
+
+/// Generate a ULID-based ID. IDs made in the same millisecond as an
+/// existing one increment it, so order is kept within a burst too.
+fn generate_ulid(existing: &[String], millis: u64) -> String {
+    let mut chars = [0u8; ULID_LEN];
+
+    let mut ts = millis;
+    for c in chars[..10].iter_mut().rev() {
+        *c = ULID_ALPHABET[(ts % 32) as usize];
+        ts /= 32;
+    }
+
+    let mut random = [0u8; 16];
+    getrandom::getrandom(&mut random).expect("failed to get random bytes");
+    for (c, r) in chars[10..].iter_mut().zip(random) {
+        *c = ULID_ALPHABET[(r % 32) as usize];
+    }
+
+    let candidate = format!("{}-{}", PREFIX, String::from_utf8_lossy(&chars));
+    let same_ms = &candidate[..PREFIX.len() + 1 + 10];
+    match existing
+        .iter()
+        .filter(|id| id.starts_with(same_ms) && is_ulid(id))
+        .max()
+    {
+        Some(latest) if latest.as_str() >= candidate.as_str() => increment(latest),
+        _ => candidate,
+    }
+}
+
+/// The next ULID after `id` in sort order
+fn increment(id: &str) -> String {
+    let mut chars: Vec<u8> = id.bytes().collect();
+    for c in chars.iter_mut().rev() {
+        let pos = ULID_ALPHABET.iter().position(|a| a == c).unwrap_or(0);
+        if pos + 1 < ULID_ALPHABET.len() {
+            *c = ULID_ALPHABET[pos + 1];
+            break;
+        }
+        *c = ULID_ALPHABET[0];
+    }
+    String::from_utf8_lossy(&chars).into_owned()
+}
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Whether `s` has the shape of a ticket ID (`tk-a1b2`, `tk-a1b2.3`, or a
+/// ULID ID), whether or not such a ticket exists
+pub fn looks_like_id(s: &str) -> bool {
+    let mut parts = s.split('.');
+    let root = parts.next().unwrap_or_default();
+    let Some(rest) = root.strip_prefix(PREFIX).and_then(|r| r.strip_prefix('-')) else {
+        return false;
+    };
+    let root_ok = (rest.len() >= 4 && rest.bytes().all(|b| b.is_ascii_hexdigit())) || is_ulid(root);
+    root_ok && parts.all(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// `text` with every whole mention of an ID in `renamed` replaced by its new
+/// ID (so renaming `tk-a1b2` leaves `tk-a1b2.1` alone), or None if it
+/// mentions none of them
+pub fn rename_refs(text: &str, renamed: &HashMap<&str, &str>) -> Option<String> {
+    let is_id_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '.';
+    let mut out = String::new();
+    let mut copied = 0;
+    let mut start = None;
+    for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        match start {
+            None if is_id_char(c) => start = Some(i),
+            Some(s) if !is_id_char(c) => {
+                let token = text[s..i].trim_end_matches('.');
+                if let Some(to) = renamed.get(token) {
+                    out.push_str(&text[copied..s]);
+                    out.push_str(to);
+                    copied = s + token.len();
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if copied == 0 {
+        return None;
+    }
+    out.push_str(&text[copied..]);
+    Some(out)
+}
+
+/// Whether a top-level ID (or the root of a child ID) is a ULID
+pub fn is_ulid(id: &str) -> bool {
+    let root = id.split('.').next().unwrap_or(id);
+    root.strip_prefix(PREFIX)
+        .and_then(|r| r.strip_prefix('-'))
+        .is_some_and(|u| u.len() == ULID_LEN && u.bytes().all(|b| ULID_ALPHABET.contains(&b)))
+}
+
+/// The directory a ticket goes in under the sharded layout: two characters
+/// of its root ID, from the random part (the start of a hex ID, the end of a
+/// ULID, whose start is the time), so children sit with their parent and
+/// tickets spread evenly
+pub fn shard(id: &str) -> String {
+    let root = id.split('.').next().unwrap_or(id);
+    let rest = root
+        .strip_prefix(PREFIX)
+        .and_then(|r| r.strip_prefix('-'))
+        .unwrap_or(root);
+    let chars: Vec<char> = rest.chars().collect();
+    let pick = if is_ulid(root) {
+        &chars[chars.len() - 2..]
+    } else {
+        &chars[..chars.len().min(2)]
+    };
+    pick.iter().collect::<String>().to_ascii_lowercase()
+}
+
+/// Generate the next child ID under a parent, e.g. "tk-a1b2" -> "tk-a1b2.3"
+/// Picks one past the highest existing direct child suffix
+/// Under ULID parents the suffix is zero-padded so that name order stays
+/// creation order (`.001`, `.002`, ... `.010`).
+pub fn generate_child(parent: &str, existing: &[String]) -> String {
+    let prefix = format!("{}.", parent);
+    let max = existing
+        .iter()
+        .filter_map(|id| id.strip_prefix(&prefix))
+        .filter_map(|suffix| suffix.parse::<u32>().ok())
+        .max()
+        .unwrap_or(0);
+
+    if is_ulid(parent) {
+        format!("{}{:03}", prefix, max + 1)
+    } else {
+        format!("{}{}", prefix, max + 1)
+    }
+}
+
+/// Longest generated slug, in characters
+const SLUG_LEN: usize = 40;
+
+/// A readable alias for a ticket made from its title (`login-timeout`),
+/// numbered (`login-timeout-2`) when `taken` already has it. None when the
+/// title has nothing to make one from.
+pub fn slug(title: &str, taken: &[String]) -> Option<String> {
+    let mut base = String::new();
+    for word in title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+    {
+        if !base.is_empty() && base.chars().count() + 1 + word.chars().count() > SLUG_LEN {
+            break;
+        }
+        if !base.is_empty() {
+            base.push('-');
+        }
+        base.extend(word.chars().flat_map(char::to_lowercase).take(SLUG_LEN));
+    }
+    if base.is_empty() || looks_like_id(&base) {
+        return None;
+    }
+    if !taken.contains(&base) {
+        return Some(base);
+    }
+    (2..)
+        .map(|n| format!("{}-{}", base, n))
+        .find(|s| !taken.contains(s))
+}
+
+/// Check a slug given by hand: lowercase words joined by hyphens, and
+/// nothing that could be read as a ticket ID
+pub fn check_slug(slug: &str) -> anyhow::Result<()> {
+    let words_ok = slug
+        .split('-')
+        .all(|w| !w.is_empty() && w.chars().all(|c| c.is_alphanumeric() && !c.is_uppercase()));
+    if !words_ok {
+        return Err(TkError::validation(format!(
+            "Invalid slug '{}': use lowercase words joined by hyphens, like login-timeout",
+            slug
+        ))
+        .into());
+    }
+    if looks_like_id(slug) {
+        return Err(TkError::validation(format!(
+            "Invalid slug '{}': it looks like a ticket ID",
+            slug
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rename_refs_replaces_whole_ids_only() {
+        let renamed = HashMap::from([("tk-a1b2", "tk-c3d4.2"), ("tk-a1b2.1", "tk-c3d4.2.1")]);
+        assert_eq!(
+            rename_refs(
+                "tk-a1b2 waits on tk-a1b2.1. Not tk-a1b2.10 or xtk-a1b2",
+                &renamed
+            )
+            .as_deref(),
+            Some("tk-c3d4.2 waits on tk-c3d4.2.1. Not tk-a1b2.10 or xtk-a1b2")
+        );
+        assert_eq!(
+            rename_refs("(see tk-a1b2)", &renamed).as_deref(),
+            Some("(see tk-c3d4.2)")
+        );
+        assert_eq!(rename_refs("nothing to see, tk-a1b2c", &renamed), None);
+    }
 }
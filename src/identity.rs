@@ -0,0 +1,46 @@
+//! Who is running tk, for note authors and ticket history.
+
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// `$TK_AUTHOR`, so bots and agents can say who they are; otherwise the git
+/// `user.name` of the current repo, or the login name if git has none.
+/// Looked up once per run.
+pub fn current() -> Option<String> {
+    static NAME: OnceLock<Option<String>> = OnceLock::new();
+    NAME.get_or_init(|| override_name().or_else(git_user).or_else(login_name))
+        .clone()
+}
+
+fn override_name() -> Option<String> {
+    std::env::var("TK_AUTHOR")
+        .ok()
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+}
+
+fn git_user() -> Option<String> {
+    let output = Command::new("git")
+        .args(["config", "user.name"])
+        .output()
+        .ok()?;
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !name.is_empty()).then_some(name)
+}
+
+fn login_name() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .filter(|n| !n.is_empty())
+        .or_else(whoami)
+}
+
+/// For shells that export neither variable (services, some Windows setups).
+/// Windows prints `DOMAIN\user`; only the user part is kept.
+fn whoami() -> Option<String> {
+    let output = Command::new("whoami").output().ok()?;
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let name = name.rsplit('\\').next().unwrap_or_default().to_string();
+    (output.status.success() && !name.is_empty()).then_some(name)
+}
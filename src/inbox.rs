@@ -0,0 +1,71 @@
+//! Per-user read state for `tk inbox`.
+//!
+//! `tk show` records when each user last looked at a ticket in
+//! `.tickets/.local/seen.json`, which is git-ignored: it describes this
+//! clone's readers, not the project. A ticket is unread when it changed after
+//! the user last showed it, or after they last ran `tk inbox --mark-read`.
+
+use crate::error::TkError;
+use crate::types::Ticket;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Relative to `.tickets/.local/`
+pub const STATE_FILE: &str = "seen.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UserSeen {
+    /// Everything changed before this counts as read (`--mark-read`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<DateTime<Utc>>,
+    /// When each ticket was last shown
+    #[serde(default)]
+    pub tickets: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl UserSeen {
+    /// When the user last caught up with `ticket`, if ever
+    pub fn seen(&self, ticket: &Ticket) -> Option<DateTime<Utc>> {
+        let shown = self.tickets.get(ticket.id()).copied();
+        shown.max(self.since)
+    }
+
+    pub fn is_unread(&self, ticket: &Ticket) -> bool {
+        let changed = ticket.meta.updated.unwrap_or(ticket.meta.created);
+        self.seen(ticket).is_none_or(|seen| changed > seen)
+    }
+
+    pub fn mark_all_read(&mut self, now: DateTime<Utc>) {
+        self.since = Some(now);
+        self.tickets.clear();
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SeenState {
+    #[serde(default)]
+    pub users: BTreeMap<String, UserSeen>,
+}
+
+impl SeenState {
+    pub fn path(local_dir: &Path) -> PathBuf {
+        local_dir.join(STATE_FILE)
+    }
+
+    pub fn load(local_dir: &Path) -> Result<Self> {
+        let path = Self::path(local_dir);
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| TkError::Parse(format!("{}: {}", path.display(), e)).into()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::process::Command;
+
+/// A reference to source code found in a ticket body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceRef {
+    /// The reference exactly as written in the body
+    pub raw: String,
+    /// Repository-relative file path
+    pub path: String,
+    /// First referenced line (1-based)
+    pub line: usize,
+    /// Last referenced line for ranges like `foo.rs:10-20`
+    pub end_line: Option<usize>,
+    /// Column, as in `foo.rs:12:5`
+    pub col: Option<usize>,
+    /// Pinned revision for permalinks (`.../blob/<rev>/...`)
+    pub rev: Option<String>,
+}
+
+/// Extract `path:line` and forge permalink references from a ticket body,
+/// each once
+pub fn extract_refs(body: &str) -> Vec<SourceRef> {
+    let mut refs = Vec::new();
+    for (_, r) in locate_refs(body) {
+        if !refs.contains(&r) {
+            refs.push(r);
+        }
+    }
+    refs
+}
+
+/// Every reference in `body` with the byte range it was written at
+pub fn locate_refs(body: &str) -> Vec<(Range<usize>, SourceRef)> {
+    let mut refs = Vec::new();
+
+    for token in body.split_whitespace() {
+        let token = token.trim_matches(|c: char| "()[]<>`'\",;".contains(c));
+        let token = token.trim_end_matches(['.', ':']);
+
+        let parsed = if token.contains("://") {
+            parse_permalink(token)
+        } else {
+            parse_path_line(token)
+        };
+
+        if let Some(r) = parsed {
+            let start = token.as_ptr() as usize - body.as_ptr() as usize;
+            refs.push((start..start + token.len(), r));
+        }
+    }
+
+    refs
+}
+
+/// `body` with each plain reference in `moved` (by its raw text) pointed at
+/// the new line, keeping any range length and column. A reference is only
+/// rewritten where it is written whole, so moving `foo.rs:1` leaves
+/// `foo.rs:12` alone.
+pub fn rewrite(body: &str, moved: &HashMap<String, usize>) -> String {
+    let mut out = body.to_string();
+    for (span, r) in locate_refs(body).into_iter().rev() {
+        if let Some(&line) = moved.get(&r.raw).filter(|_| r.rev.is_none()) {
+            out.replace_range(span, &r.at_line(line));
+        }
+    }
+    out
+}
+
+impl SourceRef {
+    /// This plain reference written against `line` instead
+    pub fn at_line(&self, line: usize) -> String {
+        let mut raw = format!("{}:{}", self.path, line);
+        if let Some(end) = self.end_line {
+            raw.push_str(&format!("-{}", end + line - self.line));
+        }
+        if let Some(col) = self.col {
+            raw.push_str(&format!(":{}", col));
+        }
+        raw
+    }
+}
+
+/// Parse `src/foo.rs:12`, `src/foo.rs:12-20` or `src/foo.rs:12:5`
+fn parse_path_line(token: &str) -> Option<SourceRef> {
+    let mut parts = token.splitn(3, ':');
+    let path = parts.next()?;
+    let lines = parts.next()?;
+    let col = parts.next().and_then(|c| c.parse().ok());
+
+    // Only things that look like files: has an extension or a directory, and
+    // doesn't start with a digit (rules out timestamps like 10:45)
+    if path.is_empty()
+        || path.starts_with(|c: char| c.is_ascii_digit())
+        || !(path.contains('/') || path.contains('.'))
+    {
+        return None;
+    }
+
+    let (line, end_line) = parse_line_range(lines, "-")?;
+
+    Some(SourceRef {
+        raw: token.to_string(),
+        // Written on Windows as src\foo.rs; git only takes forward slashes
+        path: path.replace('\\', "/"),
+        line,
+        end_line,
+        col,
+        rev: None,
+    })
+}
+
+/// Parse GitHub/GitLab style permalinks:
+/// `https://github.com/o/r/blob/<rev>/path#L10-L20`
+/// `https://gitlab.com/o/r/-/blob/<rev>/path#L10-20`
+fn parse_permalink(token: &str) -> Option<SourceRef> {
+    let (url, fragment) = token.split_once('#')?;
+    let lines = fragment.strip_prefix('L')?;
+    let lines = lines.replace("-L", "-");
+    let (line, end_line) = parse_line_range(&lines, "-")?;
+
+    let (_, after_blob) = url.split_once("/blob/")?;
+    let (rev, path) = after_blob.split_once('/')?;
+    if rev.is_empty() || path.is_empty() {
+        return None;
+    }
+
+    Some(SourceRef {
+        raw: token.to_string(),
+        path: path.to_string(),
+        line,
+        end_line,
+        col: None,
+        rev: Some(rev.to_string()),
+    })
+}
+
+fn parse_line_range(s: &str, sep: &str) -> Option<(usize, Option<usize>)> {
+    match s.split_once(sep) {
+        Some((start, end)) => {
+            let start: usize = start.parse().ok()?;
+            let end: usize = end.parse().ok()?;
+            (start > 0 && end >= start).then_some((start, Some(end)))
+        }
+        None => {
+            let line: usize = s.parse().ok()?;
+            (line > 0).then_some((line, None))
+        }
+    }
+}
+
+/// Check that a reference still points at something. Returns a description of
+/// the problem, or None if the reference is fine.
+pub fn check_ref(root: &Path, r: &SourceRef) -> Option<String> {
+    let content = match &r.rev {
+        Some(rev) => match git_show(root, rev, &r.path) {
+            Some(c) => c,
+            None => return Some(format!("{} not found at revision {}", r.path, rev)),
+        },
+        None => match std::fs::read_to_string(root.join(&r.path)) {
+            Ok(c) => c,
+            Err(_) => return Some(format!("{} does not exist", r.path)),
+        },
+    };
+
+    let line_count = content.lines().count();
+    let last = r.end_line.unwrap_or(r.line);
+    if last > line_count {
+        return Some(format!(
+            "line {} is past end of {} ({} lines)",
+            last, r.path, line_count
+        ));
+    }
+
+    None
+}
+
+/// Find where a referenced line lives today using `git blame`.
+///
+/// Blames the line at `rev` to learn which commit introduced it and at which
+/// original line number, then blames the working tree and looks for the same
+/// (commit, original line) pair. Returns the new line number if the line
+/// survived unchanged.
+pub fn reanchor(root: &Path, rev: &str, path: &str, line: usize) -> Option<usize> {
+    tracing::debug!("re-anchoring {}:{} from {}", path, line, rev);
+    let range = format!("{},{}", line, line);
+    let output = Command::new("git")
+        .current_dir(root)
+        .args(["blame", "--porcelain", "-L", &range, rev, "--", path])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let blame = String::from_utf8_lossy(&output.stdout);
+    let (commit, orig_line) = parse_blame_header(blame.lines().next()?)?;
+
+    let output = Command::new("git")
+        .current_dir(root)
+        .args(["blame", "--porcelain", "--", path])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let blame = String::from_utf8_lossy(&output.stdout);
+
+    for header in blame.lines().filter(|l| is_blame_header(l)) {
+        let mut fields = header.split(' ');
+        let (Some(c), Some(orig), Some(current)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        if c == commit && orig.parse::<usize>().ok() == Some(orig_line) {
+            return current.parse().ok();
+        }
+    }
+
+    None
+}
+
+/// The revision that was current when the ticket was last touched, used as
+/// the baseline for re-anchoring plain `path:line` references.
+pub fn rev_at(root: &Path, time: chrono::DateTime<chrono::Utc>) -> Option<String> {
+    let before = format!("--before={}", time.to_rfc3339());
+    let output = Command::new("git")
+        .current_dir(root)
+        .args(["rev-list", "-1", &before, "HEAD"])
+        .output()
+        .ok()?;
+    let rev = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !rev.is_empty()).then_some(rev)
+}
+
+fn git_show(root: &Path, rev: &str, path: &str) -> Option<String> {
+    let output = Command::new("git")
+        .current_dir(root)
+        .args(["show", &format!("{}:{}", rev, path)])
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Porcelain blame headers look like `<40-hex sha> <orig> <final> [<count>]`
+fn is_blame_header(line: &str) -> bool {
+    line.split(' ')
+        .next()
+        .is_some_and(|sha| sha.len() == 40 && sha.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn parse_blame_header(line: &str) -> Option<(String, usize)> {
+    if !is_blame_header(line) {
+        return None;
+    }
+    let mut fields = line.split(' ');
+    let commit = fields.next()?.to_string();
+    let orig = fields.next()?.parse().ok()?;
+    Some((commit, orig))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_refs_where_they_are_written() {
+        let body = "See (src/foo.rs:12:5), and `src/bar.rs:3-9` but not 10:45.";
+        let located = locate_refs(body);
+        let spans: Vec<&str> = located.iter().map(|(s, _)| &body[s.clone()]).collect();
+        assert_eq!(spans, ["src/foo.rs:12:5", "src/bar.rs:3-9"]);
+        assert_eq!(located[0].1.col, Some(5));
+        assert_eq!(located[1].1.end_line, Some(9));
+    }
+
+    #[test]
+    fn rewrite_moves_only_whole_refs() {
+        let body = "src/foo.rs:1 then src/foo.rs:12 and src/foo.rs:1:7, src/foo.rs:1-3";
+        let moved = HashMap::from([
+            ("src/foo.rs:1".to_string(), 4),
+            ("src/foo.rs:1:7".to_string(), 4),
+        ]);
+        assert_eq!(
+            rewrite(body, &moved),
+            "src/foo.rs:4 then src/foo.rs:12 and src/foo.rs:4:7, src/foo.rs:1-3"
+        );
+
+        let moved = HashMap::from([("src/foo.rs:1-3".to_string(), 10)]);
+        assert!(rewrite(body, &moved).ends_with("src/foo.rs:10-12"));
+    }
+
+    #[test]
+    fn permalinks_are_left_alone() {
+        let body = "https://github.com/o/r/blob/abc/src/foo.rs#L1";
+        let raw = extract_refs(body)[0].raw.clone();
+        assert_eq!(rewrite(body, &HashMap::from([(raw, 9)])), body);
+    }
+}
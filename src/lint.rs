@@ -0,0 +1,520 @@
+//! Ticket quality rules for `tk lint`, driven by `.tickets/config.yaml`.
+
+use crate::config::{Config, TitleRule};
+use crate::id;
+use crate::sections;
+use crate::storage::Storage;
+use crate::types::{Ticket, TicketType};
+use std::collections::HashSet;
+
+/// One rule violation
+pub struct Problem {
+    pub id: String,
+    /// Short rule name, e.g. "required-sections"
+    pub rule: &'static str,
+    pub message: String,
+    /// Line of the body (from 1) the problem is on; None when it's about
+    /// the whole ticket
+    pub line: Option<usize>,
+}
+
+/// Check a ticket against every rule. `known_ids` is every ticket in the
+/// store, live and archived, for resolving references in the body.
+pub fn check(ticket: &Ticket, config: &Config, known_ids: &HashSet<String>) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut report = |rule, line, message| {
+        problems.push(Problem {
+            id: ticket.id().to_string(),
+            rule,
+            message,
+            line,
+        })
+    };
+
+    // Closed tickets either passed this at close time or were closed with
+    // --force on purpose
+    let missing = if ticket.is_open() {
+        missing_sections(ticket, config)
+    } else {
+        Vec::new()
+    };
+    if !missing.is_empty() {
+        report(
+            "required-sections",
+            None,
+            format!("{} needs: {}", ticket.meta.ticket_type, missing.join(", ")),
+        );
+    }
+
+    if let Some(message) = title_problem(&ticket.title, ticket.meta.ticket_type, config) {
+        report("title", None, message);
+    }
+
+    let max = config.lint.max_title_length;
+    let len = ticket.title.chars().count();
+    if max > 0 && len > max {
+        report(
+            "title-length",
+            None,
+            format!("title is {} characters, over the limit of {}", len, max),
+        );
+    }
+
+    let mut reported = HashSet::new();
+    for (n, r) in ticket_refs(&ticket.body) {
+        if !known_ids.contains(r) && is_surely_id(r) && reported.insert(r) {
+            report("broken-ref", Some(n), format!("{} doesn't exist", r));
+        }
+    }
+
+    for (n, line) in ticket.body.lines().enumerate() {
+        if fix_checklist_item(line).is_some() {
+            report(
+                "checklist",
+                Some(n + 1),
+                format!(
+                    "malformed checklist item on body line {}: {}",
+                    n + 1,
+                    line.trim()
+                ),
+            );
+        }
+    }
+
+    if let Some((n, heading)) = heading_after_notes(&ticket.body) {
+        report(
+            "notes-position",
+            Some(n),
+            format!("notes come before \"{}\"; they belong at the end", heading),
+        );
+    }
+
+    problems
+}
+
+/// Ticket IDs mentioned in a body, outside code blocks, in order, each with
+/// the body line (from 1) it's on
+fn ticket_refs(body: &str) -> Vec<(usize, &str)> {
+    let mut refs = Vec::new();
+    let mut in_code = false;
+    for (n, line) in body.lines().enumerate() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        for token in line.split(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '.')) {
+            let token = token.trim_end_matches('.');
+            if id::looks_like_id(token) {
+                refs.push((n + 1, token));
+            }
+        }
+    }
+    refs
+}
+
+/// Whether a missing reference is worth reporting. A root of hex letters
+/// alone, like `tk-cafe` or `tk-deadbeef`, is as likely a word as an ID, so
+/// only one that names a real ticket counts.
+fn is_surely_id(r: &str) -> bool {
+    let root = r.split('.').next().unwrap_or_default();
+    id::is_ulid(root) || root.bytes().any(|b| b.is_ascii_digit())
+}
+
+/// The corrected line if `line` is a checklist item in a shape Markdown
+/// renderers don't recognize, like `-[ ]`, `- []` or `- [x]done`
+fn fix_checklist_item(line: &str) -> Option<String> {
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let rest = line.trim_start();
+    let marker = rest
+        .chars()
+        .next()
+        .filter(|c| matches!(c, '-' | '*' | '+'))?;
+    let after_marker = &rest[1..];
+    let spaced = after_marker.trim_start();
+    let inner_end = spaced.strip_prefix('[')?.find(']')?;
+    let inner = &spaced[1..1 + inner_end];
+    if inner.len() > 3 || !inner.chars().all(|c| matches!(c, ' ' | 'x' | 'X')) {
+        return None;
+    }
+    let text = &spaced[inner_end + 2..];
+    // `- [x](url)` and `- [x][ref]` are links, not checkboxes
+    if text.starts_with(['(', '[']) {
+        return None;
+    }
+
+    let checked = inner.contains(['x', 'X']);
+    let well_formed = after_marker.starts_with(' ')
+        && !after_marker.starts_with("  ")
+        && matches!(inner, " " | "x" | "X")
+        && (text.is_empty() || text.starts_with(' '));
+    if well_formed {
+        return None;
+    }
+    let text = text.trim_start();
+    let fixed = format!(
+        "{}{} [{}]{}{}",
+        indent,
+        marker,
+        if checked { "x" } else { " " },
+        if text.is_empty() { "" } else { " " },
+        text
+    );
+    Some(fixed)
+}
+
+/// The first heading that follows a note, and its body line. Notes are
+/// appended at the end of the body, so a heading after one means content
+/// was added below them.
+fn heading_after_notes(body: &str) -> Option<(usize, &str)> {
+    body.lines()
+        .enumerate()
+        .skip_while(|(_, l)| !Storage::is_note_line(l))
+        .find(|(_, l)| Storage::is_heading(l))
+        .map(|(n, l)| (n + 1, l.trim()))
+}
+
+/// The body with mechanical problems fixed: malformed checklist items are
+/// normalized and notes stranded above later sections are moved to the end,
+/// each whole as `Storage::parse_notes` reads it. None if nothing changed.
+pub fn fix_body(body: &str) -> Option<String> {
+    let fix_line = |l: &str| fix_checklist_item(l).unwrap_or_else(|| l.to_string());
+
+    let lines: Vec<String> = if heading_after_notes(body).is_some() {
+        let (text, notes) = Storage::split_notes(body);
+        let mut lines: Vec<String> = Vec::with_capacity(text.len());
+        // Drop the blank lines left where notes were cut out
+        for line in text {
+            let blank = line.trim().is_empty();
+            if !(blank && lines.last().is_some_and(|l| l.trim().is_empty())) {
+                lines.push(fix_line(line));
+            }
+        }
+        while lines.last().is_some_and(|l| l.trim().is_empty()) {
+            lines.pop();
+        }
+        for mut note in notes {
+            while note.last().is_some_and(|l| l.trim().is_empty()) {
+                note.pop();
+            }
+            lines.push(String::new());
+            lines.extend(note.into_iter().map(fix_line));
+        }
+        lines
+    } else {
+        body.lines().map(fix_line).collect()
+    };
+
+    let fixed = lines.join("\n");
+    (fixed.trim_end() != body.trim_end()).then_some(fixed)
+}
+
+/// Why a title breaks the configured title rule, if it does
+pub fn title_problem(title: &str, ticket_type: TicketType, config: &Config) -> Option<String> {
+    let prefix = split_type_prefix(title);
+    match (config.lint.title?, prefix.map(|p| (p.ticket_type, p.rest))) {
+        (TitleRule::NoTypePrefix, Some(_)) => Some(format!(
+            "title repeats the type prefix; the type is already {}",
+            ticket_type
+        )),
+        (TitleRule::Conventional, None) => {
+            Some(format!("title should start with \"{}: \"", ticket_type))
+        }
+        (TitleRule::Conventional, Some((t, _))) if t != ticket_type => Some(format!(
+            "title prefix is {} but the ticket type is {}",
+            t, ticket_type
+        )),
+        (TitleRule::Conventional, Some((_, ""))) => {
+            Some("title has a type prefix but no summary".to_string())
+        }
+        _ => None,
+    }
+}
+
+/// The title rewritten to satisfy the title rule, or None if it already does
+/// or can't be fixed mechanically
+pub fn fix_title(title: &str, ticket_type: TicketType, config: &Config) -> Option<String> {
+    title_problem(title, ticket_type, config)?;
+    let prefix = split_type_prefix(title);
+    let rest = prefix.as_ref().map_or(title, |p| p.rest);
+    if rest.is_empty() {
+        return None;
+    }
+    match config.lint.title? {
+        TitleRule::NoTypePrefix => Some(rest.to_string()),
+        TitleRule::Conventional => {
+            let qualifier = prefix.map_or("", |p| p.qualifier);
+            Some(format!("{}{}: {}", ticket_type, qualifier, rest))
+        }
+    }
+}
+
+struct TypePrefix<'a> {
+    ticket_type: TicketType,
+    /// `(scope)` and/or `!` between the type and the colon
+    qualifier: &'a str,
+    rest: &'a str,
+}
+
+/// Split `fix: summary`, `fix(scope): summary`, `fix!: summary` or
+/// `[fix] summary` into the type and the rest
+fn split_type_prefix(title: &str) -> Option<TypePrefix<'_>> {
+    let title = title.trim();
+    let (word, qualifier, rest) = if let Some(inner) = title.strip_prefix('[') {
+        let (word, rest) = inner.split_once(']')?;
+        (word, "", rest)
+    } else {
+        let (head, rest) = title.split_once(':')?;
+        let end = head.find(['(', '!']).unwrap_or(head.len());
+        (&head[..end], &head[end..], rest)
+    };
+    if word.is_empty() || !word.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    Some(TypePrefix {
+        ticket_type: word.parse().ok()?,
+        qualifier,
+        rest: rest.trim(),
+    })
+}
+/// Required sections for the ticket's type that are absent or empty
+pub fn missing_sections(ticket: &Ticket, config: &Config) -> Vec<String> {
+    config
+        .required_sections(ticket.meta.ticket_type)
+        .iter()
+        .filter(|name| sections::content(&ticket.body, name).is_none_or(|c| c.trim().is_empty()))
+        .cloned()
+        .collect()
+}
+
+/// Why `ticket` can't be closed yet under the `close` rules and required
+/// sections in `config`; `tickets` is only needed for
+/// `require_children_closed`. Empty when it can be.
+pub fn close_blockers(ticket: &Ticket, tickets: &[Ticket], config: &Config) -> Vec<String> {
+    let rules = &config.close;
+    let mut blockers = Vec::new();
+    let missing = missing_sections(ticket, config);
+    if !missing.is_empty() {
+        blockers.push(format!("missing required sections: {}", missing.join(", ")));
+    }
+    if rules.require_resolution && ticket.meta.resolution.is_none() {
+        blockers.push("no resolution (pass --resolution)".to_string());
+    }
+    if rules.require_checklist {
+        let unchecked = unchecked_items(&ticket.body);
+        if unchecked > 0 {
+            blockers.push(format!("unchecked checklist items: {}", unchecked));
+        }
+    }
+    if rules.require_note && Storage::parse_notes(&ticket.body).is_empty() {
+        blockers.push("no notes".to_string());
+    }
+    if rules.require_children_closed {
+        let open: Vec<&str> = tickets
+            .iter()
+            .filter(|t| t.meta.parent.as_deref() == Some(ticket.id()) && t.is_open())
+            .map(|t| t.id())
+            .collect();
+        if !open.is_empty() {
+            blockers.push(format!("open children: {}", open.join(", ")));
+        }
+    }
+    blockers
+}
+
+/// `- [ ]` items in the body above the notes
+fn unchecked_items(body: &str) -> usize {
+    let text = match Storage::notes_section(body) {
+        Some(notes) => &body[..body.len() - notes.len()],
+        None => body,
+    };
+    text.lines()
+        .filter(|line| {
+            let line = line.trim_start();
+            ["- [ ]", "* [ ]", "+ [ ]"]
+                .iter()
+                .any(|item| line.starts_with(item))
+        })
+        .count()
+}
+
+/// Add a `## Name` heading for every required section the body lacks
+pub fn apply_template(body: &str, sections: &[String]) -> String {
+    let mut body = body.trim_end().to_string();
+    for name in sections {
+        if sections::content(&body, name).is_some() {
+            continue;
+        }
+        if !body.is_empty() {
+            body.push_str("\n\n");
+        }
+        body.push_str(&format!("## {}\n", name));
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Status;
+
+    fn config(yaml: &str) -> Config {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn ticket(title: &str, body: &str) -> Ticket {
+        let mut ticket = Ticket::new("tk-a1b2".to_string(), title.to_string());
+        ticket.body = body.to_string();
+        ticket
+    }
+
+    /// The rules `check` reports for `ticket`, in order
+    fn rules(ticket: &Ticket, config: &Config) -> Vec<&'static str> {
+        let known: HashSet<String> = ["tk-a1b2", "tk-c3d4"].map(String::from).into();
+        check(ticket, config, &known)
+            .into_iter()
+            .map(|p| p.rule)
+            .collect()
+    }
+
+    #[test]
+    fn required_sections() {
+        let config = config("types:\n  feat:\n    sections: [Why]\n");
+        let mut t = ticket("Add it", "");
+        t.meta.ticket_type = TicketType::Feat;
+        assert_eq!(rules(&t, &config), ["required-sections"]);
+        t.body = "## Why\n\nBecause.".to_string();
+        assert!(rules(&t, &config).is_empty());
+    }
+
+    #[test]
+    fn titles() {
+        let config = config("lint:\n  title: conventional\n  max_title_length: 10\n");
+        let mut t = ticket("feat: short", "");
+        t.meta.ticket_type = TicketType::Feat;
+        assert_eq!(rules(&t, &config), ["title-length"]);
+        t.title = "no prefix".to_string();
+        assert_eq!(rules(&t, &config), ["title"]);
+        assert_eq!(
+            fix_title(&t.title, t.meta.ticket_type, &config).as_deref(),
+            Some("feat: no prefix")
+        );
+    }
+
+    #[test]
+    fn broken_refs() {
+        let config = Config::default();
+        let t = ticket("T", "After tk-c3d4 and tk-9f9f.\n\n```\ntk-1234\n```");
+        assert_eq!(rules(&t, &config), ["broken-ref"]);
+        // Words made of hex letters aren't taken for missing tickets
+        let t = ticket("T", "Serve it from tk-cafe, see tk-c3d4.1");
+        assert_eq!(rules(&t, &config), ["broken-ref"]);
+        let t = ticket("T", "Serve it from tk-cafe or tk-deadbeef");
+        assert!(rules(&t, &config).is_empty());
+    }
+
+    #[test]
+    fn checklists() {
+        assert_eq!(fix_checklist_item("-[ ] a").as_deref(), Some("- [ ] a"));
+        assert_eq!(fix_checklist_item("  * []").as_deref(), Some("  * [ ]"));
+        assert_eq!(
+            fix_checklist_item("- [X]done").as_deref(),
+            Some("- [x] done")
+        );
+        assert_eq!(fix_checklist_item("- [x] done"), None);
+        assert_eq!(fix_checklist_item("- [x](https://example.com)"), None);
+        assert_eq!(fix_checklist_item("- [ ][ref]"), None);
+        assert_eq!(fix_checklist_item("- [later] not a box"), None);
+        let t = ticket("T", "- [x](https://example.com) is a link\n-[ ] a box");
+        assert_eq!(rules(&t, &Config::default()), ["checklist"]);
+    }
+
+    #[test]
+    fn notes_are_moved_whole() {
+        let body = "Intro\n\n\
+                    [2024-05-01 10:00 ann] First paragraph.\n\n\
+                    Second paragraph.\n\n\
+                    [2024-05-02 10:00 bob] Short.\n\n\
+                    ## Later\n\n\
+                    -[ ] added below the notes";
+        let t = ticket("T", body);
+        assert_eq!(
+            rules(&t, &Config::default()),
+            ["checklist", "notes-position"]
+        );
+
+        let fixed = fix_body(body).unwrap();
+        assert_eq!(
+            fixed,
+            "Intro\n\n\
+             ## Later\n\n\
+             - [ ] added below the notes\n\n\
+             [2024-05-01 10:00 ann] First paragraph.\n\n\
+             Second paragraph.\n\n\
+             [2024-05-02 10:00 bob] Short."
+        );
+        let before: Vec<String> = Storage::parse_notes(body)
+            .into_iter()
+            .map(|n| n.content)
+            .collect();
+        let after: Vec<String> = Storage::parse_notes(&fixed)
+            .into_iter()
+            .map(|n| n.content)
+            .collect();
+        assert_eq!(before, after);
+        assert_eq!(fix_body(&fixed), None);
+    }
+
+    #[test]
+    fn problems_on_a_line_say_which() {
+        let t = ticket(
+            "T",
+            "Intro\n\nsee tk-9f9f\n-[ ] box\n\n[2026-03-02 09:00 ana] Note\n\n## Later\n",
+        );
+        let config = config("types:\n  feat:\n    sections: [Why]\n");
+        let known: HashSet<String> = HashSet::new();
+        let lines: Vec<(&str, Option<usize>)> = check(&t, &config, &known)
+            .into_iter()
+            .map(|p| (p.rule, p.line))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                ("required-sections", None),
+                ("broken-ref", Some(3)),
+                ("checklist", Some(4)),
+                ("notes-position", Some(8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn close_rules_each_block_until_met() {
+        let config = config(
+            "close:\n  require_resolution: true\n  require_checklist: true\n  \
+             require_note: true\n  require_children_closed: true\n",
+        );
+        let mut t = ticket("T", "- [x] done\n- [ ] not yet\n");
+        let mut child = Ticket::new("tk-a1b2.1".to_string(), "Child".to_string());
+        child.meta.parent = Some("tk-a1b2".to_string());
+        let blockers = close_blockers(&t, std::slice::from_ref(&child), &config);
+        assert_eq!(
+            blockers,
+            [
+                "no resolution (pass --resolution)",
+                "unchecked checklist items: 1",
+                "no notes",
+                "open children: tk-a1b2.1",
+            ]
+        );
+
+        t.meta.resolution = Some("fixed".to_string());
+        // Checklists in notes don't count against the body
+        t.body = "- [x] done\n\n[2026-03-02 09:00 ana] - [ ] someday".to_string();
+        child.set_status(Status::Closed);
+        assert!(close_blockers(&t, &[child], &config).is_empty());
+        assert!(close_blockers(&ticket("T", ""), &[], &Config::default()).is_empty());
+    }
+}
@@ -0,0 +1,22 @@
+use std::io::IsTerminal;
+use tracing_subscriber::EnvFilter;
+
+/// Set up stderr logging. `TK_LOG` (e.g. `TK_LOG=debug` or
+/// `TK_LOG=tk::storage=trace`) takes precedence over `-v` flags.
+pub fn init(verbosity: u8) {
+    let default = match verbosity {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+
+    let filter = EnvFilter::try_from_env("TK_LOG").unwrap_or_else(|_| EnvFilter::new(default));
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .without_time()
+        .init();
+}
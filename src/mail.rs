@@ -0,0 +1,495 @@
+//! Reading email for `tk import mbox` and `tk ingest --maildir`.
+//!
+//! Just enough of RFC 5322 and MIME to turn requests sent by mail into
+//! tickets: unfolded headers with encoded words decoded, and the first
+//! `text/plain` part of the body (or `text/html` with the tags stripped),
+//! undoing base64 and quoted-printable. Charsets other than UTF-8 and
+//! Latin-1 are read as UTF-8, lossily.
+
+use crate::types::{EventAction, ExternalRef, Ticket};
+use anyhow::{Context, Result};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// External ref system for tickets and notes that came from email; the ID
+/// is the message's `key`
+pub const SYSTEM: &str = "email";
+
+/// One email, reduced to what a ticket or note needs
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// Without the angle brackets
+    pub message_id: Option<String>,
+    /// `In-Reply-To` and `References`, nearest ancestor first
+    pub ancestors: Vec<String>,
+    pub subject: String,
+    /// Display name, or the address when there is none
+    pub from: Option<String>,
+    pub date: Option<DateTime<Utc>>,
+    pub body: String,
+}
+
+impl Message {
+    /// What identifies the message across imports: its Message-ID, or for
+    /// mail sent without one, a hash of who sent what when
+    pub fn key(&self) -> String {
+        if let Some(id) = &self.message_id {
+            return id.clone();
+        }
+        let mut hasher = Sha256::new();
+        for field in [
+            self.from.as_deref().unwrap_or_default(),
+            &self.date.map(|d| d.to_rfc3339()).unwrap_or_default(),
+            &self.subject,
+            &self.body,
+        ] {
+            hasher.update(field.as_bytes());
+            hasher.update([0]);
+        }
+        format!("sha256:{}", hex::encode(hasher.finalize()))
+    }
+
+    /// The body without the quoted message it replies to (`> ` lines and
+    /// the `On ... wrote:` line before them) and without a `-- ` signature
+    pub fn reply_text(&self) -> String {
+        let mut lines: Vec<&str> = Vec::new();
+        for line in self.body.lines() {
+            if line == "-- " {
+                break;
+            }
+            if line.starts_with('>') {
+                if lines
+                    .last()
+                    .is_some_and(|l| l.trim_end().ends_with("wrote:"))
+                {
+                    lines.pop();
+                }
+                continue;
+            }
+            lines.push(line);
+        }
+        lines.join("\n").trim().to_string()
+    }
+}
+
+/// A ticket for a message that starts a thread: subject as title, body as
+/// body, created when it was sent and by whoever sent it
+pub fn new_ticket(id: String, msg: &Message) -> Ticket {
+    let mut ticket = Ticket::new(id, title(&msg.subject));
+    ticket.body = msg.body.clone();
+    if let Some(date) = msg.date {
+        ticket.meta.created = date;
+    }
+    ticket.meta.external = vec![external(&msg.key())];
+    ticket.record(EventAction::Created);
+    if let Some(event) = ticket.meta.events.last_mut() {
+        event.at = ticket.meta.created;
+        event.by = msg.from.clone();
+    }
+    ticket
+}
+
+pub fn external(message_id: &str) -> ExternalRef {
+    ExternalRef {
+        system: SYSTEM.to_string(),
+        id: message_id.to_string(),
+        url: None,
+        synced: None,
+    }
+}
+
+/// Messages waiting in a maildir's `new/`, oldest file name first
+pub fn maildir_new(dir: &Path) -> Result<Vec<PathBuf>> {
+    let new = dir.join("new");
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(&new)
+        .with_context(|| format!("{} is not a maildir (no new/)", dir.display()))?
+    {
+        let path = entry?.path();
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Move a message from `new/` to `cur/` flagged seen, as mail readers do,
+/// so the next ingest leaves it alone
+pub fn mark_seen(dir: &Path, path: &Path) -> Result<()> {
+    let name = path
+        .file_name()
+        .context("maildir entry has no file name")?
+        .to_string_lossy();
+    let cur = dir.join("cur");
+    std::fs::create_dir_all(&cur)?;
+    std::fs::rename(path, cur.join(format!("{}:2,S", name)))
+        .with_context(|| format!("Failed to move {} to cur/", path.display()))
+}
+
+/// Split an mbox file into raw messages. Each starts with a `From ` line at
+/// the top of the file or after a blank line; `>From ` escapes are undone.
+pub fn split_mbox(content: &str) -> Vec<String> {
+    let content = content.replace("\r\n", "\n");
+    let mut messages = Vec::new();
+    let mut current: Option<String> = None;
+    let mut prev_blank = true;
+    for line in content.lines() {
+        if prev_blank && line.starts_with("From ") {
+            messages.extend(current.take());
+            current = Some(String::new());
+        } else if let Some(msg) = current.as_mut() {
+            let line = match line.strip_prefix('>') {
+                Some(rest) if rest.trim_start_matches('>').starts_with("From ") => rest,
+                _ => line,
+            };
+            msg.push_str(line);
+            msg.push('\n');
+        }
+        prev_blank = line.is_empty();
+    }
+    messages.extend(current);
+    messages
+}
+
+/// Parse one raw message
+pub fn parse(raw: &str) -> Message {
+    let raw = raw.replace("\r\n", "\n");
+    let (headers, body) = split_headers(&raw);
+
+    let message_id = header(&headers, "message-id").and_then(|v| ids(v).into_iter().next());
+    let mut ancestors = Vec::new();
+    for name in ["in-reply-to", "references"] {
+        let mut found = header(&headers, name).map(ids).unwrap_or_default();
+        // References lists the thread oldest first
+        found.reverse();
+        for id in found {
+            if !ancestors.contains(&id) {
+                ancestors.push(id);
+            }
+        }
+    }
+
+    Message {
+        message_id,
+        ancestors,
+        subject: header(&headers, "subject")
+            .map(|s| decode_words(s).trim().to_string())
+            .unwrap_or_default(),
+        from: header(&headers, "from").map(|f| sender(&decode_words(f))),
+        date: header(&headers, "date")
+            .and_then(|d| DateTime::parse_from_rfc2822(d.trim()).ok())
+            .map(|d| d.with_timezone(&Utc)),
+        body: text_body(&headers, body).trim().to_string(),
+    }
+}
+
+/// The subject without `Re:`/`Fwd:` prefixes, as a ticket title
+pub fn title(subject: &str) -> String {
+    let mut title = subject.trim();
+    loop {
+        let lower = title.to_ascii_lowercase();
+        let Some(prefix) = ["re:", "fwd:", "fw:", "aw:"]
+            .iter()
+            .find(|p| lower.starts_with(*p))
+        else {
+            break;
+        };
+        title = title[prefix.len()..].trim_start();
+    }
+    if title.is_empty() {
+        "(no subject)".to_string()
+    } else {
+        title.to_string()
+    }
+}
+
+type Headers = Vec<(String, String)>;
+
+/// Unfolded headers, and the body after the first blank line
+fn split_headers(raw: &str) -> (Headers, &str) {
+    let (head, body) = raw.split_once("\n\n").unwrap_or((raw, ""));
+    let mut headers: Headers = Vec::new();
+    for line in head.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    (headers, body)
+}
+
+fn header<'a>(headers: &'a Headers, name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// Message IDs in a header value: `<a@b> <c@d>`
+fn ids(value: &str) -> Vec<String> {
+    value
+        .split('<')
+        .skip(1)
+        .filter_map(|s| s.split_once('>'))
+        .map(|(id, _)| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect()
+}
+
+/// `Alice Smith <alice@example.com>` -> `Alice Smith`; a bare address stays
+fn sender(from: &str) -> String {
+    let from = from.trim();
+    let name = match from.split_once('<') {
+        Some((name, addr)) if name.trim().is_empty() => addr.trim_end_matches('>').trim(),
+        Some((name, _)) => name.trim().trim_matches('"').trim(),
+        None => from,
+    };
+    // Note headers are `[date author]`, so the name can't close the bracket
+    name.replace(']', ")").replace('[', "(")
+}
+
+/// The readable text of a body, given its headers
+fn text_body(headers: &Headers, body: &str) -> String {
+    let content_type = header(headers, "content-type").unwrap_or("text/plain");
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+
+    if mime.starts_with("multipart/") {
+        let Some(boundary) = param(content_type, "boundary") else {
+            return body.to_string();
+        };
+        let parts = multipart(body, &boundary);
+        let mut html = None;
+        for part in &parts {
+            let (headers, body) = split_headers(part);
+            let part_type = header(&headers, "content-type")
+                .unwrap_or("text/plain")
+                .to_ascii_lowercase();
+            if part_type.starts_with("text/plain") || part_type.starts_with("multipart/") {
+                let text = text_body(&headers, body);
+                if !text.trim().is_empty() {
+                    return text;
+                }
+            } else if part_type.starts_with("text/html") && html.is_none() {
+                html = Some(text_body(&headers, body));
+            }
+        }
+        return html.unwrap_or_default();
+    }
+
+    let encoding = header(headers, "content-transfer-encoding")
+        .unwrap_or("7bit")
+        .trim()
+        .to_ascii_lowercase();
+    let bytes = match encoding.as_str() {
+        "base64" => {
+            let compact: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+            base64::engine::general_purpose::STANDARD
+                .decode(compact)
+                .unwrap_or_else(|_| body.as_bytes().to_vec())
+        }
+        "quoted-printable" => quoted_printable(body, false),
+        _ => body.as_bytes().to_vec(),
+    };
+    let text = decode_charset(&bytes, param(content_type, "charset").as_deref());
+    if mime == "text/html" {
+        strip_html(&text)
+    } else {
+        text
+    }
+}
+
+/// A `name=value` parameter of a header like Content-Type
+fn param(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|p| {
+        let (k, v) = p.split_once('=')?;
+        k.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| v.trim().trim_matches('"').to_string())
+    })
+}
+
+fn multipart(body: &str, boundary: &str) -> Vec<String> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut current: Option<String> = None;
+    for line in body.lines() {
+        if line.starts_with(&delimiter) {
+            parts.extend(current.take());
+            if line[delimiter.len()..].starts_with("--") {
+                break;
+            }
+            current = Some(String::new());
+        } else if let Some(part) = current.as_mut() {
+            part.push_str(line);
+            part.push('\n');
+        }
+    }
+    parts.extend(current);
+    parts
+}
+
+fn quoted_printable(text: &str, underscores: bool) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'=' if bytes.get(i + 1) == Some(&b'\n') => i += 2,
+            b'=' => match text
+                .get(i + 1..i + 3)
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+            {
+                Some(b) => {
+                    out.push(b);
+                    i += 3;
+                }
+                None => {
+                    out.push(b'=');
+                    i += 1;
+                }
+            },
+            b'_' if underscores => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn decode_charset(bytes: &[u8], charset: Option<&str>) -> String {
+    let latin1 = charset.is_some_and(|c| {
+        matches!(
+            c.to_ascii_lowercase().as_str(),
+            "iso-8859-1" | "latin1" | "latin-1" | "windows-1252" | "us-ascii"
+        )
+    });
+    if latin1 {
+        bytes.iter().map(|&b| b as char).collect()
+    } else {
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
+
+/// Decode RFC 2047 encoded words (`=?UTF-8?B?...?=`). Whitespace between
+/// two encoded words is dropped, as the RFC says.
+fn decode_words(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut last_was_word = false;
+    while let Some(start) = rest.find("=?") {
+        let decoded = rest[start + 2..]
+            .split_once("?=")
+            .and_then(|(word, after)| {
+                let mut fields = word.splitn(3, '?');
+                let charset = fields.next()?;
+                let encoding = fields.next()?;
+                let text = fields.next()?;
+                let bytes = match encoding.to_ascii_uppercase().as_str() {
+                    "B" => base64::engine::general_purpose::STANDARD
+                        .decode(text)
+                        .ok()?,
+                    "Q" => quoted_printable(text, true),
+                    _ => return None,
+                };
+                Some((decode_charset(&bytes, Some(charset)), after))
+            });
+        let Some((text, after)) = decoded else {
+            out.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+            last_was_word = false;
+            continue;
+        };
+        let between = &rest[..start];
+        if !(last_was_word && between.trim().is_empty()) {
+            out.push_str(between);
+        }
+        out.push_str(&text);
+        rest = after;
+        last_was_word = true;
+    }
+    out.push_str(rest);
+    out
+}
+
+fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MBOX: &str = "\
+From alice@example.com Mon Mar  2 09:00:00 2026
+From: Alice <alice@example.com>
+Subject: Login fails
+Date: Mon, 2 Mar 2026 09:00:00 +0000
+
+It times out.
+
+From bob@example.com Mon Mar  2 10:00:00 2026
+From: bob@example.com
+Subject: Re: Login fails
+Date: Mon, 2 Mar 2026 10:00:00 +0000
+Message-ID: <b1@example.com>
+
+Same here.
+>From the logs: nothing.
+";
+
+    #[test]
+    fn messages_without_an_id_are_keyed_by_their_content() {
+        let messages: Vec<Message> = split_mbox(MBOX).iter().map(|m| parse(m)).collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].from.as_deref(), Some("Alice"));
+        assert_eq!(messages[1].body, "Same here.\nFrom the logs: nothing.");
+
+        assert_eq!(messages[1].key(), "b1@example.com");
+        let key = messages[0].key();
+        assert!(key.starts_with("sha256:"));
+        assert_eq!(parse(&split_mbox(MBOX)[0]).key(), key);
+
+        let mut edited = messages[0].clone();
+        edited.body.push_str(" Again.");
+        assert_ne!(edited.key(), key);
+        assert_eq!(
+            new_ticket("tk-a1b2".to_string(), &messages[0])
+                .meta
+                .external[0]
+                .id,
+            key
+        );
+    }
+}
@@ -1,14 +1,56 @@
+mod auth;
+mod config;
+#[cfg(unix)]
+mod daemon;
+mod dates;
+mod diff;
+mod dot;
+mod editor;
+mod error;
+mod examples;
+mod filters;
+mod frontmatter;
+mod git;
+mod graph;
+mod hooks;
+mod html;
 mod id;
+mod identity;
+mod inbox;
+mod links;
+mod lint;
+mod logging;
+mod mail;
+mod manifest;
+mod merge;
+#[cfg(unix)]
+mod metrics;
+mod outline;
+mod output;
+mod plan;
+mod prompt;
+mod rescue;
+mod score;
+mod sections;
+mod similarity;
+mod snapshot;
+mod stats;
 mod storage;
+mod sync;
+mod tags;
 mod types;
+mod view;
 
 use anyhow::{Context, Result};
 use chrono::Utc;
-use clap::{Parser, Subcommand};
-use std::collections::{HashMap, HashSet};
+use clap::{Args, Parser, Subcommand};
+use error::TkError;
+use filters::{FilterArgs, FilterSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use storage::Storage;
-use types::{Note, Status, Ticket, TicketType};
+use storage::{FrontmatterFormat, Scope, Storage, StoreLayout};
+use types::{EventAction, ExternalRef, Note, Status, Ticket, TicketType};
 
 #[derive(Parser)]
 #[command(name = "tk")]
@@ -16,10 +58,12 @@ use types::{Note, Status, Ticket, TicketType};
 #[command(
     long_about = "A lightweight, git-backed ticket tracker designed for simplicity.
 
-Tickets are stored as Markdown files with YAML frontmatter in .tickets/.
+Tickets are stored as Markdown files with YAML (or TOML) frontmatter in .tickets/.
 Each ticket is a separate file, making git diffs readable and merges easy.
 
 Searches parent directories for .tickets/ (override with TICKETS_DIR env var).
+Tickets moved to .tickets/archive/ are hidden from listings (see
+--include-archived) but still resolve by ID.
 
 Key concepts:
   - deps: blocking dependencies (must close dep before this is ready)
@@ -39,17 +83,227 @@ struct Cli {
     #[arg(long, global = true)]
     json: bool,
 
+    /// Show what would change without writing anything
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Log what tk is doing to stderr (-v info, -vv debug, -vvv trace; or set TK_LOG)
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Don't confirm changes; errors still go to stderr
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Confirm changes as stable tab-separated fields (e.g. the new ID)
+    #[arg(long, global = true, conflicts_with = "quiet")]
+    porcelain: bool,
+
+    /// Indent JSON output (the default on a terminal)
+    #[arg(long, global = true)]
+    pretty: bool,
+
+    /// Print JSON output on one line (the default when piped)
+    #[arg(long, global = true, conflicts_with = "pretty")]
+    compact: bool,
+
+    /// Fail on a ticket file that doesn't parse instead of skipping it with
+    /// a warning (or set strict: true in config.yaml)
+    #[arg(long, global = true)]
+    strict: bool,
+
+    /// Change frozen tickets anyway
+    #[arg(long, global = true)]
+    ignore_frozen: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Args)]
+struct InitArgs {
+    /// Start from a ready-made config.yaml
+    #[arg(long)]
+    preset: Option<config::Preset>,
+    /// Add a few demo tickets and a .tickets/README.md on the workflow
+    #[arg(long)]
+    with_examples: bool,
+    /// Ignore per-clone files, set merge rules in .gitattributes and commit
+    /// the result (running git init if needed)
+    #[arg(long)]
+    git: bool,
+}
+
+#[derive(Args)]
+struct CreateArgs {
+    /// Priority (0=critical, 4=backlog)
+    #[arg(short, long, default_value = "2")]
+    priority: u8,
+    /// Type: feat, fix, chore, docs, refactor, test
+    #[arg(short = 't', long, default_value = "feat")]
+    r#type: String,
+    /// Initial tags (comma-separated)
+    #[arg(long)]
+    tags: Option<String>,
+    /// Who is working on it
+    #[arg(short, long)]
+    assignee: Option<String>,
+    /// Size estimate (points, hours, ...)
+    #[arg(short, long, conflicts_with = "bulk")]
+    estimate: Option<u32>,
+    /// Milestone the ticket is scheduled for
+    #[arg(long)]
+    milestone: Option<String>,
+    /// Readable alias to refer to it by, instead of one made from the title
+    #[arg(long, conflicts_with = "bulk")]
+    slug: Option<String>,
+    /// Date it should be done by (2024-06-01, friday, +2w, ...)
+    #[arg(long, value_name = "DATE", value_parser = dates::arg)]
+    due: Option<chrono::NaiveDate>,
+    /// External links (comma-separated URLs)
+    #[arg(long, conflicts_with = "bulk")]
+    links: Option<String>,
+    /// Issue this ticket mirrors in another tracker (system#id, e.g. github#123)
+    #[arg(long, conflicts_with = "bulk")]
+    external: Option<String>,
+    /// Create as a child of this ticket (prefix match)
+    #[arg(long)]
+    parent: Option<String>,
+    /// Make the parent depend on the new ticket, so it isn't ready until
+    /// its children are closed (default: children_block_parent in config)
+    #[arg(long, requires = "parent")]
+    link_dep: bool,
+    /// Create one ticket per heading/list item of a Markdown outline;
+    /// nested items become children that their parent depends on
+    #[arg(long)]
+    bulk: bool,
+}
+
+/// What `tk migrate` converts the store to
+#[derive(Args)]
+#[group(required = true, multiple = true)]
+struct MigrateArgs {
+    /// Frontmatter format to rewrite every ticket in, written from now on
+    #[arg(long, value_enum)]
+    format: Option<FrontmatterFormat>,
+    /// Directory layout to move every ticket file into, used from now on
+    #[arg(long, value_enum)]
+    layout: Option<StoreLayout>,
+    /// Give each ticket without a slug one made from its title, as new
+    /// tickets get
+    #[arg(long)]
+    slugs: bool,
+}
+
+/// The ticket a command acts on: by ID, or by a piece of its title
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct TicketArg {
+    /// Ticket ID (prefix match)
+    id: Option<String>,
+    /// Find the ticket by part of its title instead (any case)
+    #[arg(long, value_name = "TEXT")]
+    title: Option<String>,
+}
+
+impl TicketArg {
+    /// The full ID of the ticket meant
+    fn resolve(&self, storage: &Storage) -> Result<String> {
+        let ticket = match (&self.id, &self.title) {
+            (_, Some(text)) => {
+                let matches = storage.find_by_title(text, Scope::All)?;
+                pick_ticket("Ticket", Lookup::Title(text), matches)?
+            }
+            (Some(id), None) => find_ticket(storage, "Ticket", id)?,
+            (None, None) => unreachable!("clap requires an ID or --title"),
+        };
+        Ok(ticket.id().to_string())
+    }
+
+    /// The one of `owner`'s drafts meant
+    fn resolve_draft(&self, storage: &Storage, owner: &str) -> Result<Ticket> {
+        match (&self.id, &self.title) {
+            (_, Some(text)) => pick_ticket(
+                "Draft",
+                Lookup::Title(text),
+                storage.find_draft_by_title(owner, text)?,
+            ),
+            (Some(id), None) => {
+                pick_ticket("Draft", Lookup::Prefix(id), storage.find_draft(owner, id)?)
+            }
+            (None, None) => unreachable!("clap requires an ID or --title"),
+        }
+    }
+}
+
+/// The ticket for commands that take more values after it, by ID or by a
+/// piece of its title. With `--title` there is no ID, so clap hands the
+/// first of those values to `id` (`tk status --title login closed`), and
+/// `shift` gives it back.
+#[derive(Args)]
+#[group(required = true, multiple = true)]
+struct LeadingTicketArg {
+    /// Ticket ID (prefix match)
+    id: Option<String>,
+    /// Find the ticket by part of its title instead (any case)
+    #[arg(long, value_name = "TEXT")]
+    title: Option<String>,
+}
+
+impl LeadingTicketArg {
+    /// The full ID of the ticket meant
+    fn resolve(&self, storage: &Storage) -> Result<String> {
+        TicketArg {
+            id: self.id.clone(),
+            title: self.title.clone(),
+        }
+        .resolve(storage)
+    }
+
+    /// With `--title`, the value clap put in `id`, which belongs to the
+    /// argument after it
+    fn shifted(&mut self) -> Option<String> {
+        self.title.as_ref()?;
+        self.id.take()
+    }
+
+    /// `next`, the argument after the ID, or with `--title` the value clap
+    /// gave `id` in its place, parsed as `next` would have been
+    fn shift<T: std::str::FromStr>(&mut self, what: &str, next: Option<T>) -> Result<Option<T>> {
+        let Some(value) = self.shifted() else {
+            return Ok(next);
+        };
+        if next.is_some() {
+            return Err(too_many_with_title());
+        }
+        let parsed = value
+            .parse()
+            .map_err(|_| TkError::validation(format!("Invalid {} '{}'", what, value)))?;
+        Ok(Some(parsed))
+    }
+}
+
+fn too_many_with_title() -> anyhow::Error {
+    TkError::validation("Too many arguments: --title takes the place of the ticket ID").into()
+}
+
+/// A required argument that clap couldn't insist on because `--title` moved
+/// it along
+fn missing(what: &str) -> TkError {
+    TkError::validation(format!("Missing {}", what))
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Initialize ticket tracking in current directory
-    Init,
+    Init(InitArgs),
 
     /// Create a new ticket from stdin (expects "# Title" on first line)
-    Create {
+    Create(CreateArgs),
+
+    /// Create a ticket in your drafts from stdin, kept out of list and ready
+    /// until you publish it
+    Draft {
         /// Priority (0=critical, 4=backlog)
         #[arg(short, long, default_value = "2")]
         priority: u8,
@@ -61,77 +315,340 @@ enum Commands {
         tags: Option<String>,
     },
 
+    /// Move one of your drafts into the shared store
+    Publish {
+        #[command(flatten)]
+        draft: TicketArg,
+    },
+
     /// List tickets
     #[command(alias = "ls")]
     List {
-        /// Filter by status
-        #[arg(short, long)]
-        status: Option<String>,
-        /// Filter by tag (comma-separated for multiple, AND logic)
-        #[arg(short = 't', long)]
-        tag: Option<String>,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
+        /// Only list archived tickets
+        #[arg(long, conflicts_with = "include_archived")]
+        archived: bool,
+        /// Indent children under their parents; closed tickets' children are
+        /// collapsed
+        #[arg(long)]
+        tree: bool,
+        /// With --tree, show children of closed tickets too
+        #[arg(long, requires = "tree")]
+        expand: bool,
+        /// Print `path:line:col: message` lines for editor quickfix lists
+        #[arg(long, conflicts_with = "tree")]
+        vimgrep: bool,
+        /// List your drafts instead of the store
+        #[arg(long, conflicts_with_all = ["include_archived", "archived", "vimgrep"])]
+        drafts: bool,
+        /// Start from this saved view (views.<name> in config) instead of
+        /// the list settings; flags still override it
+        #[arg(long)]
+        view: Option<String>,
+        /// Order of the listing (default: list.sort in config, or priority)
+        #[arg(long, value_enum)]
+        sort: Option<config::ListSort>,
+        /// Fields to show, in order, as aligned columns (default: list.columns
+        /// in config)
+        #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "tree")]
+        columns: Vec<config::ListColumn>,
     },
 
     /// Show a ticket
     Show {
-        /// Ticket ID (prefix match)
-        id: String,
+        #[command(flatten)]
+        ticket: TicketArg,
+        /// Print the ticket's history as a timeline instead
+        #[arg(long)]
+        history: bool,
     },
 
-    /// Replace ticket title + body from stdin (expects "# Title" on first line)
+    /// List tickets changed since you last showed them
+    Inbox {
+        /// Count everything as read from now on
+        #[arg(long)]
+        mark_read: bool,
+    },
+
+    /// Replace ticket title + body from stdin, or in your editor from a terminal
     Edit {
-        /// Ticket ID (prefix match)
-        id: String,
+        #[command(flatten)]
+        ticket: TicketArg,
+        /// Edit in this editor even if stdin isn't a terminal (default: editor
+        /// in config, $VISUAL, $EDITOR)
+        #[arg(long)]
+        editor: Option<String>,
+        /// Let the new text drop or change notes; the editor then shows them
+        /// too
+        #[arg(long)]
+        replace_notes: bool,
     },
 
     /// Change ticket status
     Status {
-        /// Ticket ID (prefix match)
-        id: String,
+        #[command(flatten)]
+        ticket: LeadingTicketArg,
         /// New status: open, in-progress, closed
-        status: String,
+        #[arg(required_unless_present = "title")]
+        status: Option<String>,
+        /// Close even if the close checks fail
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Mark a ticket in progress, or renew the lease on one that already is
+    Start {
+        #[command(flatten)]
+        ticket: TicketArg,
+        /// Hold the ticket for this long (e.g. 30m, 2h, 1d); `tk reap` reopens
+        /// it afterwards
+        #[arg(long)]
+        lease: Option<String>,
+    },
+
+    /// Change ticket priority
+    Priority {
+        #[command(flatten)]
+        ticket: LeadingTicketArg,
+        /// New priority (0=critical, 4=backlog)
+        #[arg(required_unless_present = "title")]
+        priority: Option<u8>,
+    },
+
+    /// Set or clear who is working on a ticket
+    Assign {
+        #[command(flatten)]
+        ticket: LeadingTicketArg,
+        /// Assignee
+        #[arg(required_unless_present_any = ["none", "title"])]
+        assignee: Option<String>,
+        /// Unassign
+        #[arg(long, conflicts_with = "assignee")]
+        none: bool,
+    },
+
+    /// Hand a ticket over to someone else, with a note on where it stands
+    Handoff {
+        #[command(flatten)]
+        ticket: LeadingTicketArg,
+        /// Who takes it over
+        #[arg(required_unless_present = "title")]
+        assignee: Option<String>,
+        /// Context for the new assignee, added to the handoff note
+        #[arg(long)]
+        note: Option<String>,
+        /// Put the ticket back to open for the new assignee to start
+        #[arg(long)]
+        reopen: bool,
+    },
+
+    /// Get told about a ticket's changes through the configured hooks
+    #[command(name = "watch-ticket")]
+    WatchTicket {
+        #[command(flatten)]
+        ticket: LeadingTicketArg,
+        /// Who to add (default: you, as in note authors)
+        name: Option<String>,
+        /// Stop watching instead
+        #[arg(long)]
+        remove: bool,
     },
 
     /// Close a ticket
     Close {
-        /// Ticket ID (prefix match)
-        id: String,
+        #[command(flatten)]
+        ticket: TicketArg,
+        /// How it ended: fixed, wontfix, duplicate of tk-a1b2, ...
+        #[arg(long, value_name = "TEXT")]
+        resolution: Option<String>,
+        /// Close even if required sections are missing or other close
+        /// checks fail
+        #[arg(short, long)]
+        force: bool,
     },
 
     /// Reopen a ticket
     Reopen {
-        /// Ticket ID (prefix match)
-        id: String,
+        #[command(flatten)]
+        ticket: TicketArg,
+    },
+
+    /// List closed tickets nobody has reviewed yet, oldest first
+    #[command(name = "review-queue")]
+    ReviewQueue {
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
+    },
+
+    /// Verify closed tickets before they go into a release
+    Review {
+        #[command(subcommand)]
+        command: ReviewCommand,
+    },
+
+    /// Delete a ticket file (asks for confirmation)
+    Delete {
+        #[command(flatten)]
+        ticket: TicketArg,
+        /// Don't ask for confirmation
+        #[arg(short, long, visible_alias = "force")]
+        yes: bool,
+        /// What to do with children and dependents of the ticket
+        #[arg(long, value_enum)]
+        orphans: Option<OrphanPolicy>,
+    },
+
+    /// Mark a ticket blocked by something outside the tracker, so it isn't
+    /// ready until unblocked
+    Block {
+        #[command(flatten)]
+        ticket: TicketArg,
+        /// What it's waiting for
+        #[arg(long, value_name = "TEXT")]
+        reason: Option<String>,
+    },
+
+    /// Clear what `tk block` set
+    Unblock {
+        #[command(flatten)]
+        ticket: TicketArg,
+    },
+
+    /// Make a ticket read-only: tk refuses to change it without --ignore-frozen
+    Freeze {
+        #[command(flatten)]
+        ticket: TicketArg,
+    },
+
+    /// Make a frozen ticket editable again
+    Unfreeze {
+        #[command(flatten)]
+        ticket: TicketArg,
     },
 
     /// Add a blocking dependency
     Dep {
         /// Ticket that is blocked
-        id: String,
+        #[command(flatten)]
+        ticket: LeadingTicketArg,
         /// Ticket that blocks (dependency)
-        dep_id: String,
+        #[arg(required_unless_present = "title")]
+        dep_id: Option<String>,
+    },
+
+    /// Move a ticket under a new parent (or to the top level with --none)
+    Adopt {
+        /// Ticket to move
+        #[command(flatten)]
+        ticket: LeadingTicketArg,
+        /// New parent (prefix match)
+        #[arg(required_unless_present_any = ["none", "title"])]
+        new_parent: Option<String>,
+        /// Make it a top-level ticket
+        #[arg(long, conflicts_with = "new_parent")]
+        none: bool,
+        /// Also re-ID it (and its descendants) into the new parent's child
+        /// namespace, rewriting parent, deps and body references to them
+        #[arg(long)]
+        rename: bool,
     },
 
     /// Remove a blocking dependency
     Undep {
         /// Ticket to remove dep from
-        id: String,
+        #[command(flatten)]
+        ticket: LeadingTicketArg,
         /// Dependency to remove
-        dep_id: String,
+        #[arg(required_unless_present = "title")]
+        dep_id: Option<String>,
     },
 
     /// List tickets ready to work on (open, no unresolved deps)
     Ready {
-        /// Filter by tag (comma-separated for multiple, AND logic)
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
+        /// Order by priority, or by the weighted score in config.yaml
+        #[arg(long, value_enum, default_value = "priority")]
+        sort: ReadySort,
+        /// List overdue tickets first, then due soon, due later and those
+        /// with no due date (or set ready.by_due in config.yaml)
+        #[arg(long)]
+        by_due: bool,
+    },
+
+    /// Show the ready ticket with the highest score, to pick up next
+    Next {
+        /// Only consider tickets with this tag (comma-separated for multiple)
         #[arg(short = 't', long)]
         tag: Option<String>,
     },
 
-    /// List blocked tickets (open, has unresolved deps)
-    Blocked {
-        /// Filter by tag (comma-separated for multiple, AND logic)
+    /// Pick a random ready, unassigned ticket and describe it
+    Suggest {
+        /// Only small, dependency-free tickets tagged for newcomers (see
+        /// suggest in config.yaml)
+        #[arg(long)]
+        good_first: bool,
+    },
+
+    /// Take the most urgent ready ticket: mark it in progress, assign it to you
+    /// and print it as JSON
+    Claim {
+        /// Only claim tickets with these tags (comma-separated, AND logic)
         #[arg(short = 't', long)]
         tag: Option<String>,
+        /// Claim for this name instead of you (as note authors are named)
+        #[arg(short, long)]
+        assignee: Option<String>,
+        /// Hold the ticket for this long (e.g. 30m, 2h, 1d); `tk reap` reopens
+        /// it afterwards
+        #[arg(long)]
+        lease: Option<String>,
+    },
+
+    /// Reopen in-progress tickets whose lease has run out, with a note
+    Reap,
+
+    /// List blocked tickets (open, has unresolved deps)
+    Blocked {
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
+        /// Also count deps on tickets that aren't in this store (another
+        /// repository's, or deleted ones) as blocking
+        #[arg(long)]
+        external: bool,
+    },
+
+    /// Show every chain of open deps down to the tickets to do first
+    #[command(name = "why-blocked")]
+    WhyBlocked {
+        #[command(flatten)]
+        ticket: TicketArg,
+    },
+
+    /// Split open tickets into waves that can be worked on in parallel
+    Plan {
+        /// People to spread unassigned tickets over (comma-separated)
+        #[arg(long)]
+        assignees: Option<String>,
+        /// Most tickets in progress at once (default: one per assignee)
+        #[arg(long)]
+        parallel: Option<usize>,
+        /// Instead, list one person's queue: their in-progress tickets, then
+        /// ready ones assigned to them, then unassigned ready ones with their tags
+        #[arg(long = "for", value_name = "ASSIGNEE", conflicts_with_all = ["assignees", "parallel"])]
+        who: Option<String>,
     },
 
     /// Detect dependency cycles
@@ -145,728 +662,6262 @@ enum Commands {
         /// Show full tree (include closed)
         #[arg(short, long)]
         full: bool,
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
+    },
+
+    /// Print the dependency graph in Graphviz DOT format
+    Graph {
+        /// Include closed tickets
+        #[arg(short, long)]
+        full: bool,
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
+        /// Group tickets into labelled clusters
+        #[arg(long, value_enum)]
+        cluster_by: Option<dot::ClusterBy>,
     },
 
     /// Add a timestamped note to a ticket
     Note {
-        /// Ticket ID (prefix match)
-        id: String,
-        /// Note content (opens $EDITOR if omitted)
+        #[command(flatten)]
+        ticket: LeadingTicketArg,
+        /// Note content (opens your editor if omitted)
         content: Option<String>,
+        /// Editor to write the note in (default: editor in config, $VISUAL, $EDITOR)
+        #[arg(long, conflicts_with = "content")]
+        editor: Option<String>,
+        /// Attribute the note to this name instead of $TK_AUTHOR, git user.name or $USER
+        #[arg(long)]
+        author: Option<String>,
+        /// Reply to this note (numbered as in `tk notes`)
+        #[arg(long, value_name = "N")]
+        reply: Option<usize>,
+    },
+
+    /// List a ticket's notes
+    Notes {
+        #[command(flatten)]
+        ticket: TicketArg,
+    },
+
+    /// Read or change one `## Section` of a ticket's body, leaving the rest
+    /// and the notes alone
+    Section {
+        #[command(flatten)]
+        ticket: LeadingTicketArg,
+        /// get (print the section's text), set (replace it, adding the
+        /// section if it's missing) or append (add lines at its end)
+        #[arg(required_unless_present = "title")]
+        action: Option<String>,
+        /// Section heading, without the `##` (any case)
+        #[arg(required_unless_present = "title")]
+        heading: Option<String>,
+        /// Text to set or append (default: stdin)
+        #[arg(allow_hyphen_values = true)]
+        text: Option<String>,
+    },
+
+    /// Digest a ticket and its notes with the command configured in config.yaml
+    Summarize {
+        #[command(flatten)]
+        ticket: TicketArg,
+        /// Append the summary to the ticket as a note instead of only printing it
+        #[arg(long)]
+        save: bool,
+    },
+
+    /// Count tickets, optionally grouped by a field
+    Count {
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// Group counts by this field
+        #[arg(long, value_enum)]
+        by: Option<CountBy>,
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
+    },
+
+    /// List tags with how many tickets use each
+    Tags {
+        /// Instead, list tags that look like spellings of the same tag
+        #[arg(long)]
+        suggest_merges: bool,
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
     },
 
     /// Query tickets as JSON (pipe to jq)
     Query {
         /// Optional jq-style filter (requires jq)
         filter: Option<String>,
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
     },
-}
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let storage = Storage::new();
+    /// Find the ticket mapped to an external issue
+    Find {
+        /// External reference (system#id, e.g. github#123)
+        #[arg(long)]
+        external: String,
+    },
 
-    match cli.command {
-        Commands::Init => cmd_init(&storage, cli.json),
-        Commands::Create {
-            priority,
-            r#type,
-            tags,
-        } => cmd_create(&storage, priority, &r#type, tags, cli.json),
-        Commands::List { status, tag } => cmd_list(&storage, status, tag, cli.json),
-        Commands::Show { id } => cmd_show(&storage, &id),
-        Commands::Edit { id } => cmd_edit(&storage, &id),
-        Commands::Status { id, status } => cmd_status(&storage, &id, &status, cli.json),
-        Commands::Close { id } => cmd_close(&storage, &id, cli.json),
-        Commands::Reopen { id } => cmd_status(&storage, &id, "open", cli.json),
-        Commands::Dep { id, dep_id } => cmd_dep(&storage, &id, &dep_id, cli.json),
-        Commands::Undep { id, dep_id } => cmd_undep(&storage, &id, &dep_id, cli.json),
-        Commands::Ready { tag } => cmd_ready(&storage, tag, cli.json),
-        Commands::Blocked { tag } => cmd_blocked(&storage, tag, cli.json),
-        Commands::DepCycle => cmd_dep_cycle(&storage, cli.json),
-        Commands::Tree { id, full } => cmd_tree(&storage, id.as_deref(), full, cli.json),
-        Commands::Note { id, content } => cmd_note(&storage, &id, content, cli.json),
-        Commands::Query { filter } => cmd_query(&storage, filter),
-    }
-}
+    /// Rank tickets by similarity to find related work and likely duplicates
+    Search {
+        /// Ticket ID (prefix match) or free text to compare against
+        #[arg(long, value_name = "ID|TEXT")]
+        similar: String,
+        /// Maximum number of results
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
+        /// Print `path:line:col: message` lines for editor quickfix lists
+        #[arg(long)]
+        vimgrep: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
 
-fn ensure_init(storage: &Storage) -> Result<()> {
-    if !storage.is_initialized() {
-        storage.init()?;
-        eprintln!("Initialized .tickets/");
-    }
-    Ok(())
-}
+    /// Open a ticket's external links in the browser
+    Browse {
+        #[command(flatten)]
+        ticket: LeadingTicketArg,
+        /// Which link to open (1-based); prompts when omitted and there are several
+        n: Option<usize>,
+        /// Open every link
+        #[arg(short, long)]
+        all: bool,
+    },
 
-fn cmd_init(storage: &Storage, json: bool) -> Result<()> {
-    if storage.is_initialized() {
-        if json {
-            println!(r#"{{"status":"already_initialized"}}"#);
-        } else {
-            println!("Already initialized.");
-        }
-        return Ok(());
-    }
+    /// Print JSON Schema documents for --json output
+    Schema {
+        /// Output to describe (e.g. list, query, error); omit for all
+        name: Option<String>,
+    },
 
-    storage.init()?;
+    /// Merge two versions of a ticket file; the git merge driver `tk init
+    /// --git` sets up
+    #[command(hide = true)]
+    MergeFile {
+        /// The common ancestor's version (git's %O)
+        base: PathBuf,
+        /// Our version, overwritten with the result (%A)
+        ours: PathBuf,
+        /// Their version (%B)
+        theirs: PathBuf,
+        /// The file's path in the repo (%P)
+        path: PathBuf,
+    },
 
-    if json {
-        println!(r#"{{"status":"initialized"}}"#);
-    } else {
-        println!("Initialized .tickets/");
-    }
-    Ok(())
-}
+    /// Serve a warm in-memory index of tickets to other tk commands
+    Daemon {
+        /// Also serve Prometheus metrics at http://ADDR/metrics (e.g. 127.0.0.1:9464)
+        #[arg(long, value_name = "ADDR")]
+        metrics: Option<String>,
+    },
 
-fn cmd_create(
-    storage: &Storage,
-    priority: u8,
-    type_str: &str,
-    tags: Option<String>,
-    json: bool,
-) -> Result<()> {
-    use std::io::Read;
+    /// Write tickets out for other tools
+    Export {
+        #[command(subcommand)]
+        command: ExportCommand,
+    },
 
-    ensure_init(storage)?;
+    /// Import and update tickets from GitHub or GitLab issues
+    Sync {
+        /// github or gitlab
+        provider: auth::Provider,
+        /// owner/name (default: parsed from the origin remote)
+        #[arg(long)]
+        repo: Option<String>,
+        /// Ignore the saved cursor and fetch every issue
+        #[arg(long)]
+        full: bool,
+        /// List every issue fetched and what became of it: created,
+        /// updated, skipped or conflict, with the reason
+        #[arg(long)]
+        report: bool,
+        /// How to settle issues changed both upstream and here (default:
+        /// sync.conflicts in config, or remote-wins)
+        #[arg(long, value_enum, value_name = "POLICY")]
+        on_conflict: Option<sync::ConflictPolicy>,
+    },
 
-    // Read from stdin
-    let mut buf = String::new();
-    std::io::stdin().read_to_string(&mut buf)?;
-    let input = buf.trim();
+    /// Turn emails into tickets and their replies into notes
+    Import {
+        #[command(subcommand)]
+        command: ImportCommand,
+    },
 
-    if input.is_empty() {
-        anyhow::bail!("No input provided. Expected: # Title\\n[body]");
-    }
+    /// Import new mail from a maildir, then mark it read
+    Ingest {
+        /// Maildir to read new/ from; imported messages move to cur/
+        #[arg(long, value_name = "DIR")]
+        maildir: PathBuf,
+        /// List every message and what became of it
+        #[arg(long)]
+        report: bool,
+    },
 
-    // Extract title from first # heading
-    let (title, body) = Storage::extract_title(input);
-    if title == "Untitled" && !input.starts_with("# ") {
-        anyhow::bail!("No title found. First line must be: # Your Title");
-    }
+    /// Manage API tokens for integrations
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommand,
+    },
 
-    let existing = storage.all_ids()?;
-    let id = id::generate(&existing);
+    /// Copy .tickets/config.yaml between repos
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
 
-    let ticket_type: TicketType = type_str.parse()?;
-    let tags: Vec<String> = tags
-        .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
-        .unwrap_or_default();
+    /// Reports on how work flows through the tracker
+    Stats {
+        #[command(subcommand)]
+        command: StatsCommand,
+    },
 
-    let mut ticket = Ticket::new(id.clone(), title.clone());
-    ticket.meta.priority = priority;
-    ticket.meta.ticket_type = ticket_type;
-    ticket.meta.tags = tags;
-    ticket.body = body.to_string();
+    /// Manage .tickets/archive/
+    Archive {
+        #[command(subcommand)]
+        command: ArchiveCommand,
+    },
 
-    storage.save(&ticket)?;
+    /// Trim ticket histories to the newest events, and archive closed tickets (archive_on_close)
+    Gc {
+        /// Events to keep per ticket (default: max_events in config, or 100)
+        #[arg(long, value_name = "N")]
+        keep: Option<usize>,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
 
-    if json {
-        println!(r#"{{"id":"{}","title":"{}"}}"#, id, title);
-    } else {
-        println!("Created {} - {}", id, title);
-    }
-    Ok(())
-}
+    /// Convert every ticket to another frontmatter format or directory layout, and make it the project's
+    Migrate(MigrateArgs),
 
-fn cmd_list(
-    storage: &Storage,
-    status: Option<String>,
-    tag: Option<String>,
-    json: bool,
-) -> Result<()> {
-    ensure_init(storage)?;
+    /// Check tickets against the rules in config.yaml
+    Lint {
+        /// Ticket ID (prefix match), or omit for all tickets
+        id: Option<String>,
+        /// Fix what can be fixed mechanically: titles that break the title
+        /// rule, malformed checklist items, and notes above later sections
+        #[arg(long)]
+        fix: bool,
+        /// Print `path:line:col: message` lines for editor quickfix lists
+        #[arg(long)]
+        vimgrep: bool,
+    },
 
-    let tickets = storage.load_all()?;
+    /// Write TICKETS_SUMMARY.md (counts, ready and blocked tickets) to commit
+    Snapshot {
+        /// Only check that the file is up to date; exit 5 if it isn't
+        #[arg(long)]
+        check: bool,
+    },
 
-    let status_filter: Option<Status> = status.map(|s| s.parse()).transpose()?;
-    let tags_filter: Vec<String> = tag
-        .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
-        .unwrap_or_default();
+    /// Exit non-zero if the tickets fail the given release checks (for CI)
+    Gate {
+        /// Fail if an open ticket has this priority or a more urgent one
+        #[arg(long, value_name = "N")]
+        max_open_priority: Option<u8>,
+        /// Fail on dependency cycles
+        #[arg(long)]
+        forbid_cycles: bool,
+        /// Fail on deps that point at tickets that don't exist
+        #[arg(long)]
+        forbid_dangling_deps: bool,
+    },
 
-    let mut filtered: Vec<_> = tickets
-        .iter()
-        .filter(|t| status_filter.is_none_or(|s| t.meta.status == s))
-        .filter(|t| {
-            tags_filter.is_empty() || tags_filter.iter().all(|tag| t.meta.tags.contains(tag))
-        })
-        .collect();
+    /// Check path:line and permalink references in ticket bodies
+    #[command(name = "lint-links")]
+    LintLinks {
+        /// Ticket ID (prefix match), or omit for all tickets
+        id: Option<String>,
+        /// Update moved path:line references using git blame
+        #[arg(long)]
+        reanchor: bool,
+    },
 
-    filtered.sort_by(|a, b| {
-        a.meta
-            .priority
-            .cmp(&b.meta.priority)
-            .then_with(|| a.meta.created.cmp(&b.meta.created))
-    });
+    /// Check that every ticket file parses, and with --verify that none changed outside tk
+    Doctor {
+        /// Also compare every file with the hash in .tickets/.manifest
+        #[arg(long)]
+        verify: bool,
+        /// Record the current hashes of every file that parses in
+        /// .tickets/.manifest, accepting edits made outside tk
+        #[arg(long)]
+        rehash: bool,
+    },
 
-    if json {
-        let items: Vec<_> = filtered
-            .iter()
+    /// Salvage a ticket file that doesn't parse, writing a fixed copy
+    Rescue {
+        /// The broken ticket file
+        file: PathBuf,
+        /// Replace the file rather than writing <file>.rescued next to it
+        #[arg(long)]
+        in_place: bool,
+    },
+}
+
+/// How listing commands print their results
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+    /// `path:line:col: message`, the format of `grep -n`/`rg --vimgrep` that
+    /// Vim's quickfix list and VS Code problem matchers read
+    Vimgrep,
+}
+
+impl Format {
+    fn new(json: bool, vimgrep: bool) -> Self {
+        match (json, vimgrep) {
+            (_, true) => Format::Vimgrep,
+            (true, false) => Format::Json,
+            (false, false) => Format::Text,
+        }
+    }
+}
+
+/// How `delete` treats tickets that point at the deleted one. Children always
+/// move up to the deleted ticket's parent.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OrphanPolicy {
+    /// Dependents forget the dep
+    Drop,
+    /// Dependents inherit the deleted ticket's own deps
+    Transfer,
+    /// Don't delete
+    Abort,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ReadySort {
+    Priority,
+    Score,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum SectionAction {
+    /// Print the section's text
+    Get,
+    /// Replace the section's text, adding the section if it's missing
+    Set,
+    /// Add lines at the end of the section
+    Append,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum CountBy {
+    Status,
+    Type,
+    Tag,
+    Priority,
+}
+
+#[derive(Subcommand)]
+enum StatsCommand {
+    /// Tickets that were closed and reopened repeatedly
+    Quality {
+        /// Only list tickets reopened at least this many times
+        #[arg(long, default_value_t = 2)]
+        min: usize,
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
+    },
+    /// In-progress tickets, longest in progress first
+    AgingWip {
+        /// Flag tickets in progress at least this many days
+        #[arg(long, value_name = "DAYS", default_value_t = 3)]
+        warn: u32,
+        /// Flag tickets in progress at least this many days as stalled
+        #[arg(long, value_name = "DAYS", default_value_t = 7)]
+        stale: u32,
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
+    },
+    /// How long tickets spend open, in progress and waiting for review
+    TimeInStatus {
+        /// Only this ticket, listing each stretch of time
+        #[arg(long, conflicts_with = "since")]
+        id: Option<String>,
+        /// Only time spent on or after this date (2024-06-01, last monday, -2w, ...)
+        #[arg(long, value_name = "DATE", value_parser = dates::arg)]
+        since: Option<chrono::NaiveDate>,
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
+    },
+    /// Tickets created and closed per week (or day, month), with sparklines
+    Throughput {
+        /// Interval to count per
+        #[arg(long, value_enum, default_value_t = stats::Interval::Week)]
+        by: stats::Interval,
+        /// Only tickets of this type
+        #[arg(long = "type", value_name = "TYPE")]
+        ticket_type: Option<String>,
+        /// Start from the interval this date is in (default: 12 intervals back)
+        #[arg(long, value_name = "DATE", value_parser = dates::arg)]
+        since: Option<chrono::NaiveDate>,
+        /// Print CSV, with a column pair per type, for a spreadsheet
+        #[arg(long)]
+        csv: bool,
+    },
+    /// Estimates still open, per milestone
+    Remaining {
+        /// Only this milestone
+        #[arg(long)]
+        milestone: Option<String>,
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExportCommand {
+    /// Full tickets with their notes as JSON Lines, one file per status plus
+    /// archived.ndjson
+    Ndjson {
+        /// Directory to write into (created if missing)
+        #[arg(long, value_name = "DIR")]
+        out: PathBuf,
+    },
+    /// A printable HTML page of index cards, one per ticket, for planning
+    /// boards or offline review (print to PDF from the browser)
+    Cards {
+        /// File to write
+        #[arg(long, value_name = "FILE")]
+        out: PathBuf,
+        #[command(flatten)]
+        filter: Box<FilterArgs>,
+        /// Include tickets in .tickets/archive/
+        #[arg(long)]
+        include_archived: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ImportCommand {
+    /// Every message in an mbox file: thread starters become tickets,
+    /// replies become notes on them
+    Mbox {
+        file: PathBuf,
+        /// List every message and what became of it
+        #[arg(long)]
+        report: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ArchiveCommand {
+    /// Roll archived tickets older than N years into one Markdown bundle per
+    /// year, so the archive holds a few files instead of thousands
+    Compact {
+        /// Only tickets closed (or, if never closed, last changed) at least
+        /// this many years ago
+        #[arg(long, value_name = "YEARS", default_value = "2")]
+        older_than: u32,
+    },
+}
+
+#[derive(Subcommand)]
+enum ReviewCommand {
+    /// Mark a closed ticket reviewed by you (someone other than who closed
+    /// it, unless --force)
+    Done {
+        #[command(flatten)]
+        ticket: TicketArg,
+        /// Review a ticket you closed yourself
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuthCommand {
+    /// Store a token in the OS keychain (read from a hidden prompt or stdin)
+    Login { provider: auth::Provider },
+    /// Remove a stored token
+    Logout { provider: auth::Provider },
+    /// Show which providers have a token and where it comes from
+    Status,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print config.yaml, or write it to a file
+    Export {
+        /// File to write instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Replace config.yaml with a file exported from another repo
+    Import {
+        /// File to read ("-" for stdin)
+        file: PathBuf,
+        /// Overwrite an existing config.yaml
+        #[arg(long)]
+        replace: bool,
+    },
+}
+
+fn main() {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => error::report_usage(e, std::env::args().any(|a| a == "--json")),
+    };
+
+    logging::init(cli.verbose);
+    output::set_confirmations(if cli.porcelain {
+        output::Confirmations::Porcelain
+    } else if cli.quiet {
+        output::Confirmations::Quiet
+    } else {
+        output::Confirmations::Prose
+    });
+
+    output::set_json_style(if cli.pretty {
+        output::JsonStyle::Pretty
+    } else if cli.compact {
+        output::JsonStyle::Compact
+    } else {
+        output::JsonStyle::Auto
+    });
+
+    let json = cli.json;
+    let dry_run = cli.dry_run;
+    if let Err(err) = run(cli) {
+        std::process::exit(error::report(&err, json));
+    }
+    if dry_run {
+        eprintln!("Dry run: nothing was written.");
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let storage = Storage::new()
+        .with_dry_run(cli.dry_run)
+        .with_strict(cli.strict)
+        .with_force(cli.ignore_frozen);
+
+    match cli.command {
+        Commands::Init(args) => cmd_init(&storage, &args, cli.json),
+        Commands::Create(args) => {
+            if args.bulk {
+                cmd_create_bulk(&storage, args, cli.json)
+            } else {
+                cmd_create(&storage, args, cli.json)
+            }
+        }
+        Commands::Draft {
+            priority,
+            r#type,
+            tags,
+        } => cmd_draft(&storage, priority, &r#type, tags, cli.json),
+        Commands::Publish { draft } => cmd_publish(&storage, &draft, cli.json),
+        Commands::List {
+            filter,
+            include_archived,
+            archived,
+            tree,
+            expand,
+            vimgrep,
+            drafts,
+            view: saved,
+            sort,
+            columns,
+        } => {
+            let scope = if drafts {
+                None
+            } else if archived {
+                Some(Scope::Archived)
+            } else {
+                Some(Scope::listing(include_archived))
+            };
+            let format = Format::new(cli.json, vimgrep);
+            let view = ListView {
+                saved,
+                sort,
+                columns,
+                tree,
+                expand,
+                format,
+            };
+            cmd_list(&storage, &filter, scope, view)
+        }
+        Commands::Show { ticket, history } => {
+            cmd_show(&storage, &ticket.resolve(&storage)?, history)
+        }
+        Commands::Inbox { mark_read } => cmd_inbox(&storage, mark_read, cli.json),
+        Commands::Edit {
+            ticket,
+            editor,
+            replace_notes,
+        } => cmd_edit(&storage, &ticket.resolve(&storage)?, editor, replace_notes),
+        Commands::Status {
+            mut ticket,
+            status,
+            force,
+        } => {
+            let status = ticket
+                .shift("status", status)?
+                .ok_or_else(|| missing("status"))?;
+            cmd_status(
+                &storage,
+                &ticket.resolve(&storage)?,
+                &status,
+                force,
+                cli.json,
+            )
+        }
+        Commands::Start { ticket, lease } => {
+            cmd_start(&storage, &ticket.resolve(&storage)?, lease, cli.json)
+        }
+        Commands::Priority {
+            mut ticket,
+            priority,
+        } => {
+            let priority = ticket
+                .shift("priority", priority)?
+                .ok_or_else(|| missing("priority"))?;
+            cmd_priority(&storage, &ticket.resolve(&storage)?, priority, cli.json)
+        }
+        Commands::Assign {
+            mut ticket,
+            assignee,
+            none,
+        } => {
+            let assignee = ticket.shift("assignee", assignee)?;
+            if assignee.is_some() == none {
+                return Err(missing("assignee (or --none)").into());
+            }
+            cmd_assign(&storage, &ticket.resolve(&storage)?, assignee, cli.json)
+        }
+        Commands::Handoff {
+            mut ticket,
+            assignee,
+            note,
+            reopen,
+        } => {
+            let assignee = ticket
+                .shift("assignee", assignee)?
+                .ok_or_else(|| missing("assignee"))?;
+            let id = ticket.resolve(&storage)?;
+            cmd_handoff(&storage, &id, assignee, note, reopen, cli.json)
+        }
+        Commands::WatchTicket {
+            mut ticket,
+            name,
+            remove,
+        } => {
+            let name = ticket.shift("name", name)?;
+            cmd_watch_ticket(&storage, &ticket.resolve(&storage)?, name, remove, cli.json)
+        }
+        Commands::Close {
+            ticket,
+            resolution,
+            force,
+        } => cmd_close(
+            &storage,
+            &ticket.resolve(&storage)?,
+            resolution,
+            force,
+            cli.json,
+        ),
+        Commands::Reopen { ticket } => cmd_status(
+            &storage,
+            &ticket.resolve(&storage)?,
+            "open",
+            false,
+            cli.json,
+        ),
+        Commands::Delete {
+            ticket,
+            yes,
+            orphans,
+        } => cmd_delete(&storage, &ticket.resolve(&storage)?, yes, orphans, cli.json),
+        Commands::ReviewQueue { include_archived } => {
+            cmd_review_queue(&storage, include_archived, cli.json)
+        }
+        Commands::Review { command } => match command {
+            ReviewCommand::Done { ticket, force } => {
+                cmd_review_done(&storage, &ticket.resolve(&storage)?, force, cli.json)
+            }
+        },
+        Commands::Block { ticket, reason } => {
+            cmd_block(&storage, &ticket.resolve(&storage)?, Some(reason), cli.json)
+        }
+        Commands::Unblock { ticket } => {
+            cmd_block(&storage, &ticket.resolve(&storage)?, None, cli.json)
+        }
+        Commands::Freeze { ticket } => {
+            cmd_freeze(&storage, &ticket.resolve(&storage)?, true, cli.json)
+        }
+        Commands::Unfreeze { ticket } => {
+            cmd_freeze(&storage, &ticket.resolve(&storage)?, false, cli.json)
+        }
+        Commands::Dep { mut ticket, dep_id } => {
+            let dep_id = ticket
+                .shift("dependency", dep_id)?
+                .ok_or_else(|| missing("dependency"))?;
+            cmd_dep(&storage, &ticket.resolve(&storage)?, &dep_id, cli.json)
+        }
+        Commands::Undep { mut ticket, dep_id } => {
+            let dep_id = ticket
+                .shift("dependency", dep_id)?
+                .ok_or_else(|| missing("dependency"))?;
+            cmd_undep(&storage, &ticket.resolve(&storage)?, &dep_id, cli.json)
+        }
+        Commands::Adopt {
+            mut ticket,
+            new_parent,
+            none,
+            rename,
+        } => {
+            let new_parent = ticket.shift("parent", new_parent)?;
+            if new_parent.is_some() == none {
+                return Err(missing("new parent (or --none)").into());
+            }
+            let id = ticket.resolve(&storage)?;
+            cmd_adopt(&storage, &id, new_parent.as_deref(), rename, cli.json)
+        }
+        Commands::Ready {
+            filter,
+            include_archived,
+            sort,
+            by_due,
+        } => cmd_ready(&storage, &filter, include_archived, sort, by_due, cli.json),
+        Commands::Next { tag } => cmd_next(&storage, tag, cli.json),
+        Commands::Suggest { good_first } => cmd_suggest(&storage, good_first, cli.json),
+        Commands::Claim {
+            tag,
+            assignee,
+            lease,
+        } => cmd_claim(&storage, tag, assignee, lease),
+        Commands::Reap => cmd_reap(&storage, cli.json),
+        Commands::Blocked {
+            filter,
+            include_archived,
+            external,
+        } => cmd_blocked(&storage, &filter, include_archived, external, cli.json),
+        Commands::WhyBlocked { ticket } => {
+            cmd_why_blocked(&storage, &ticket.resolve(&storage)?, cli.json)
+        }
+        Commands::Plan { who: Some(who), .. } => cmd_plan_for(&storage, &who, cli.json),
+        Commands::Plan {
+            assignees,
+            parallel,
+            who: None,
+        } => cmd_plan(&storage, assignees, parallel, cli.json),
+        Commands::DepCycle => cmd_dep_cycle(&storage, cli.json),
+        Commands::Tree {
+            id,
+            full,
+            include_archived,
+        } => cmd_tree(&storage, id.as_deref(), full, include_archived, cli.json),
+        Commands::Graph {
+            full,
+            include_archived,
+            cluster_by,
+        } => cmd_graph(&storage, full, include_archived, cluster_by),
+        Commands::Note {
+            mut ticket,
+            content,
+            editor,
+            author,
+            reply,
+        } => {
+            let content = ticket.shift("note", content)?;
+            if content.is_some() && editor.is_some() {
+                return Err(TkError::validation("--editor can't be used with note content").into());
+            }
+            let id = ticket.resolve(&storage)?;
+            cmd_note(&storage, &id, content, editor, author, reply, cli.json)
+        }
+        Commands::Notes { ticket } => cmd_notes(&storage, &ticket.resolve(&storage)?, cli.json),
+        Commands::Section {
+            mut ticket,
+            action,
+            heading,
+            text,
+        } => {
+            let (action, heading, text) = match ticket.shifted() {
+                Some(_) if text.is_some() => return Err(too_many_with_title()),
+                Some(first) => (Some(first), action, heading),
+                None => (action, heading, text),
+            };
+            let action = action.ok_or_else(|| missing("action"))?;
+            let action =
+                <SectionAction as clap::ValueEnum>::from_str(&action, true).map_err(|_| {
+                    TkError::validation(format!(
+                        "Invalid action '{}': use get, set or append",
+                        action
+                    ))
+                })?;
+            let heading = heading.ok_or_else(|| missing("heading"))?;
+            let id = ticket.resolve(&storage)?;
+            cmd_section(&storage, &id, action, &heading, text, cli.json)
+        }
+        Commands::Summarize { ticket, save } => {
+            cmd_summarize(&storage, &ticket.resolve(&storage)?, save, cli.json)
+        }
+        Commands::Count {
+            filter,
+            by,
+            include_archived,
+        } => cmd_count(&storage, &filter, by, include_archived, cli.json),
+        Commands::Tags {
+            suggest_merges,
+            include_archived,
+        } => cmd_tags(&storage, suggest_merges, include_archived, cli.json),
+        Commands::Query {
+            filter,
+            include_archived,
+        } => cmd_query(&storage, filter, include_archived),
+        Commands::Find { external } => cmd_find(&storage, &external, cli.json),
+        Commands::Search {
+            similar,
+            limit,
+            include_archived,
+            vimgrep,
+            filter,
+        } => cmd_search(
+            &storage,
+            &similar,
+            limit,
+            &filter,
+            Scope::listing(include_archived),
+            Format::new(cli.json, vimgrep),
+        ),
+        Commands::Browse { mut ticket, n, all } => {
+            let n = ticket.shift("link number", n)?;
+            cmd_browse(&storage, &ticket.resolve(&storage)?, n, all, cli.json)
+        }
+        Commands::Schema { name } => cmd_schema(name.as_deref()),
+        Commands::MergeFile {
+            base,
+            ours,
+            theirs,
+            path,
+        } => cmd_merge_file(&base, &ours, &theirs, &path),
+        Commands::Daemon { metrics } => cmd_daemon(&storage, metrics.as_deref()),
+        Commands::Sync {
+            provider,
+            repo,
+            full,
+            report,
+            on_conflict,
+        } => cmd_sync(
+            &storage,
+            provider,
+            repo.as_deref(),
+            full,
+            report,
+            on_conflict,
+            cli.json,
+        ),
+        Commands::Export { command } => match command {
+            ExportCommand::Ndjson { out } => cmd_export_ndjson(&storage, &out, cli.json),
+            ExportCommand::Cards {
+                out,
+                filter,
+                include_archived,
+            } => cmd_export_cards(&storage, &out, &filter, include_archived, cli.json),
+        },
+        Commands::Import { command } => match command {
+            ImportCommand::Mbox { file, report } => {
+                cmd_import_mbox(&storage, &file, report, cli.json)
+            }
+        },
+        Commands::Ingest { maildir, report } => cmd_ingest(&storage, &maildir, report, cli.json),
+        Commands::Auth { command } => match command {
+            AuthCommand::Login { provider } => cmd_auth_login(&storage, provider, cli.json),
+            AuthCommand::Logout { provider } => cmd_auth_logout(&storage, provider, cli.json),
+            AuthCommand::Status => cmd_auth_status(cli.json),
+        },
+        Commands::Config { command } => match command {
+            ConfigCommand::Export { output } => cmd_config_export(&storage, output, cli.json),
+            ConfigCommand::Import { file, replace } => {
+                cmd_config_import(&storage, &file, replace, cli.json)
+            }
+        },
+        Commands::Stats { command } => match command {
+            StatsCommand::Quality {
+                min,
+                include_archived,
+            } => cmd_stats_quality(&storage, min, include_archived, cli.json),
+            StatsCommand::AgingWip {
+                warn,
+                stale,
+                include_archived,
+            } => cmd_stats_aging_wip(&storage, warn, stale, include_archived, cli.json),
+            StatsCommand::TimeInStatus {
+                id,
+                since,
+                include_archived,
+            } => {
+                cmd_stats_time_in_status(&storage, id.as_deref(), since, include_archived, cli.json)
+            }
+            StatsCommand::Throughput {
+                by,
+                ticket_type,
+                since,
+                csv,
+            } => cmd_stats_throughput(&storage, by, ticket_type.as_deref(), since, csv, cli.json),
+            StatsCommand::Remaining {
+                milestone,
+                include_archived,
+            } => cmd_stats_remaining(&storage, milestone.as_deref(), include_archived, cli.json),
+        },
+        Commands::Archive { command } => match command {
+            ArchiveCommand::Compact { older_than } => {
+                cmd_archive_compact(&storage, older_than, cli.json)
+            }
+        },
+        Commands::Gc { keep, yes } => cmd_gc(&storage, keep, yes, cli.json),
+        Commands::Migrate(args) => cmd_migrate(&storage, args, cli.json),
+        Commands::Lint { id, fix, vimgrep } => {
+            cmd_lint(&storage, id.as_deref(), fix, Format::new(cli.json, vimgrep))
+        }
+        Commands::Snapshot { check } => cmd_snapshot(&storage, check, cli.json),
+        Commands::Gate {
+            max_open_priority,
+            forbid_cycles,
+            forbid_dangling_deps,
+        } => cmd_gate(
+            &storage,
+            max_open_priority,
+            forbid_cycles,
+            forbid_dangling_deps,
+            cli.json,
+        ),
+        Commands::LintLinks { id, reanchor } => {
+            cmd_lint_links(&storage, id.as_deref(), reanchor, cli.json)
+        }
+        Commands::Doctor { verify, rehash } => cmd_doctor(&storage, verify, rehash, cli.json),
+        Commands::Rescue { file, in_place } => cmd_rescue(&storage, &file, in_place, cli.json),
+    }
+}
+
+fn ensure_init(storage: &Storage) -> Result<()> {
+    if !storage.is_initialized() {
+        storage.init()?;
+        if !storage.is_dry_run() {
+            eprintln!("Initialized .tickets/");
+        }
+    }
+    Ok(())
+}
+
+/// The ticket `id` (a prefix of it is enough) refers to; `what` names it in
+/// the not-found error
+fn find_ticket(storage: &Storage, what: &str, id: &str) -> Result<Ticket> {
+    let matches = storage.find_by_prefix(id, Scope::All)?;
+    pick_ticket(what, Lookup::Prefix(id), matches)
+}
+
+/// How a ticket was asked for
+#[derive(Clone, Copy)]
+enum Lookup<'a> {
+    Prefix(&'a str),
+    Title(&'a str),
+}
+
+/// The one ticket of the ones a lookup matched. When it matched several, a
+/// person at a terminal picks; anyone else gets the candidates in the error.
+fn pick_ticket(what: &str, lookup: Lookup, mut matches: Vec<Ticket>) -> Result<Ticket> {
+    match (matches.len(), lookup) {
+        (0, Lookup::Prefix(id)) => Err(TkError::not_found(what, id).into()),
+        (0, Lookup::Title(text)) => Err(TkError::NotFound(format!(
+            "No {} title contains '{}'",
+            what.to_lowercase(),
+            text
+        ))
+        .into()),
+        (1, _) => Ok(matches.remove(0)),
+        (_, Lookup::Prefix(id)) if !prompt::interactive() => {
+            Err(TkError::ambiguous(id, &matches).into())
+        }
+        (_, Lookup::Title(text)) if !prompt::interactive() => {
+            Err(TkError::ambiguous_title(text, &matches).into())
+        }
+        (n, lookup) => {
+            let options: Vec<String> = matches
+                .iter()
+                .map(|t| format!("{} [{}] {}", t.id(), t.meta.status, t.title))
+                .collect();
+            let (question, hint) = match lookup {
+                Lookup::Prefix(id) => (
+                    format!("'{}' matches {} tickets; which one?", id, n),
+                    "more of the ID",
+                ),
+                Lookup::Title(text) => (
+                    format!("{} titles contain '{}'; which one?", n, text),
+                    "more of the title",
+                ),
+            };
+            let i = prompt::choose(&question, &options, hint)?;
+            Ok(matches.swap_remove(i))
+        }
+    }
+}
+
+/// `tk merge-file`: exit 1 (as git expects of a merge driver) when the
+/// file was left with conflict markers
+fn cmd_merge_file(base: &Path, ours: &Path, theirs: &Path, path: &Path) -> Result<()> {
+    if !merge::merge_file(base, ours, theirs, path)? {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn cmd_init(storage: &Storage, args: &InitArgs, json: bool) -> Result<()> {
+    let initialized = storage.is_initialized();
+    if initialized && args.preset.is_none() && !args.with_examples && !args.git {
+        if json {
+            output::print(&output::InitResult {
+                schema: output::SCHEMA_VERSION,
+                status: "already_initialized".into(),
+                preset: None,
+                examples: Vec::new(),
+                commit: None,
+            })?;
+        } else {
+            output::changed("Already initialized.", &["already_initialized"]);
+        }
+        return Ok(());
+    }
+    if args.preset.is_some() && storage.config_text()?.is_some() {
+        return Err(TkError::validation(
+            "config.yaml already exists; use tk config import --replace to swap it",
+        )
+        .into());
+    }
+    if args.with_examples && initialized && !storage.all_ids(Scope::All)?.is_empty() {
+        return Err(TkError::validation(
+            "Examples only go into an empty tracker, and this one has tickets",
+        )
+        .into());
+    }
+
+    storage.init()?;
+    if let Some(preset) = args.preset {
+        storage.write_config(preset.yaml(), "preset")?;
+    }
+    let examples = if args.with_examples {
+        add_examples(storage)?
+    } else {
+        Vec::new()
+    };
+    let commit = if args.git {
+        git::ignore_local_files(storage)?;
+        git::set_attributes(storage)?;
+        let paths = [".tickets", ".gitattributes"];
+        if storage.is_dry_run() {
+            eprintln!("Would commit {}", paths.join(" and "));
+            eprintln!("Would register tk's merge driver with git");
+            None
+        } else {
+            let commit = git::commit(
+                &storage.root_dir(),
+                &paths,
+                "Set up ticket tracking with tk",
+            )?;
+            git::register_merge_driver(&storage.root_dir())?;
+            commit
+        }
+    } else {
+        None
+    };
+
+    let status = if initialized {
+        "already_initialized"
+    } else {
+        "initialized"
+    };
+    let preset = args.preset.map(|p| p.name().to_string());
+    if json {
+        output::print(&output::InitResult {
+            schema: output::SCHEMA_VERSION,
+            status: status.into(),
+            preset,
+            examples,
+            commit,
+        })?;
+    } else {
+        let mut prose = vec![];
+        if !initialized {
+            prose.push("Initialized .tickets/".to_string());
+        }
+        if let Some(p) = preset {
+            prose.push(format!("Wrote the {} preset to .tickets/config.yaml", p));
+        }
+        if !examples.is_empty() {
+            prose.push(format!(
+                "Added {} example tickets (tagged example) and .tickets/README.md",
+                examples.len()
+            ));
+        }
+        if args.git && !storage.is_dry_run() {
+            prose.push(match &commit {
+                Some(hash) => format!("Committed .tickets/ and .gitattributes as {}", hash),
+                None => "Git rules in place; nothing new to commit".to_string(),
+            });
+        }
+        output::changed(prose.join("\n"), &[status, commit.as_deref().unwrap_or("")]);
+    }
+    Ok(())
+}
+
+/// Save the demo tickets and the workflow README; returns the new IDs
+fn add_examples(storage: &Storage) -> Result<Vec<String>> {
+    let config = storage.config()?;
+    let epic = id::generate(&[], config.id_scheme);
+    let bug = id::generate(std::slice::from_ref(&epic), config.id_scheme);
+    let mut taken = Vec::new();
+    let mut ids = Vec::new();
+    for mut ticket in examples::tickets(&epic, &bug, &config) {
+        ticket.meta.slug = id::slug(&ticket.title, &taken);
+        taken.extend(ticket.names().map(str::to_string));
+        storage.save(&ticket)?;
+        ids.push(ticket.id().to_string());
+    }
+    storage.write_readme(examples::README)?;
+    Ok(ids)
+}
+
+fn cmd_config_export(storage: &Storage, path: Option<PathBuf>, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let yaml = storage.config_text()?.unwrap_or_default();
+    if yaml.trim().is_empty() {
+        eprintln!("No config.yaml: every setting is at its default");
+    }
+    match &path {
+        Some(path) => {
+            if storage.is_dry_run() {
+                eprintln!("Would write {}", path.display());
+            } else {
+                std::fs::write(path, &yaml)?;
+            }
+        }
+        None if !json => print!("{}", yaml),
+        None => {}
+    }
+    if json {
+        output::print(&output::ConfigExport {
+            schema: output::SCHEMA_VERSION,
+            path: path.as_ref().map(|p| p.display().to_string()),
+            yaml,
+        })?;
+    } else if let Some(path) = path {
+        let path = path.display().to_string();
+        output::changed(format!("Wrote config to {}", path), &[&path]);
+    }
+    Ok(())
+}
+
+fn cmd_config_import(storage: &Storage, file: &Path, replace: bool, json: bool) -> Result<()> {
+    use std::io::Read;
+    ensure_init(storage)?;
+
+    let (yaml, origin) = if file == Path::new("-") {
+        let mut yaml = String::new();
+        std::io::stdin().read_to_string(&mut yaml)?;
+        (yaml, "stdin".to_string())
+    } else {
+        (std::fs::read_to_string(file)?, file.display().to_string())
+    };
+    let existing = storage.config_text()?;
+    if existing.as_ref().is_some_and(|e| e != &yaml) && !replace {
+        return Err(TkError::validation(
+            "config.yaml already exists; pass --replace to overwrite it",
+        )
+        .into());
+    }
+    let path = storage.write_config(&yaml, &origin)?;
+
+    if json {
+        output::print(&output::ConfigImported {
+            schema: output::SCHEMA_VERSION,
+            path: path.display().to_string(),
+            replaced: existing.is_some(),
+        })?;
+    } else {
+        let path = path.display().to_string();
+        output::changed(format!("Imported config from {}", origin), &[&path]);
+    }
+    Ok(())
+}
+
+fn cmd_create(storage: &Storage, args: CreateArgs, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let (title, body) = read_new_ticket()?;
+    let body = body.as_str();
+
+    let parent = match &args.parent {
+        Some(p) => Some(find_ticket(storage, "Parent", p)?),
+        None => None,
+    };
+
+    let config = storage.config()?;
+    let existing = storage.all_ids(Scope::All)?;
+    let id = match &parent {
+        Some(p) => id::generate_child(p.id(), &existing),
+        None => id::generate(&existing, config.id_scheme),
+    };
+
+    let ticket_type: TicketType = args.r#type.parse()?;
+    let tags: Vec<String> = args
+        .tags
+        .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+        .unwrap_or_default();
+    let links: Vec<String> = args
+        .links
+        .map(|l| l.split(',').map(|s| s.trim().to_string()).collect())
+        .unwrap_or_default();
+    let external: Option<ExternalRef> = args.external.map(|e| e.parse()).transpose()?;
+    if let Some(e) = &external {
+        if let Some(existing) = storage.find_by_external(&e.system, &e.id, Scope::All)? {
+            return Err(TkError::Validation(format!(
+                "{} is already mapped to {}",
+                e,
+                existing.id()
+            ))
+            .into());
+        }
+    }
+
+    check_title(&title, ticket_type, &config)?;
+    let slug = match args.slug {
+        Some(slug) => {
+            id::check_slug(&slug)?;
+            Some(slug)
+        }
+        None => id::slug(&title, &storage.taken_names()?),
+    };
+
+    let mut ticket = Ticket::new(id.clone(), title.clone());
+    ticket.meta.slug = slug;
+    ticket.meta.priority = args.priority;
+    ticket.meta.ticket_type = ticket_type;
+    ticket.meta.tags = tags;
+    ticket.meta.assignee = args.assignee;
+    ticket.meta.estimate = args.estimate;
+    ticket.meta.milestone = args.milestone;
+    ticket.meta.due = args.due;
+    ticket.meta.links = links;
+    ticket.meta.external = external.into_iter().collect();
+    ticket.meta.parent = parent.as_ref().map(|p| p.id().to_string());
+    ticket.body = config.new_body(body, ticket_type);
+    ticket.record(EventAction::Created);
+
+    let link_dep = args.link_dep || config.children_block_parent;
+    if let Some(parent) = parent.as_ref().filter(|_| link_dep) {
+        storage.check_unfrozen(parent)?;
+    }
+    storage.save(&ticket)?;
+
+    if let Some(mut parent) = parent {
+        if link_dep {
+            parent.meta.deps.push(id.clone());
+            parent.touch();
+            storage.save(&parent)?;
+        }
+    }
+    fire_hooks(storage, &ticket, "created", Some(&ticket.body))?;
+    escalate(storage, &ticket, None)?;
+
+    let path = storage.ticket_path(&id);
+    if json {
+        output::print(&output::Created::new(&ticket, &path))?;
+    } else {
+        let path = path.display().to_string();
+        output::changed(format!("Created {} - {}", id, title), &[&id, &path]);
+    }
+    Ok(())
+}
+
+/// Title and body of a new ticket from stdin (`# Title` on the first line)
+fn read_new_ticket() -> Result<(String, String)> {
+    use std::io::Read;
+
+    let mut buf = String::new();
+    std::io::stdin().read_to_string(&mut buf)?;
+    let input = buf.trim();
+
+    if input.is_empty() {
+        return Err(TkError::validation("No input provided. Expected: # Title\\n[body]").into());
+    }
+
+    // Extract title from first # heading
+    let (title, body) = Storage::extract_title(input);
+    if title == "Untitled" && !input.starts_with("# ") {
+        return Err(TkError::validation("No title found. First line must be: # Your Title").into());
+    }
+    Ok((title, body.to_string()))
+}
+
+fn cmd_draft(
+    storage: &Storage,
+    priority: u8,
+    ticket_type: &str,
+    tags: Option<String>,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+    let owner = draft_owner()?;
+    let (title, body) = read_new_ticket()?;
+
+    let config = storage.config()?;
+    let ticket_type: TicketType = ticket_type.parse()?;
+    check_title(&title, ticket_type, &config)?;
+
+    let mut existing = storage.all_ids(Scope::All)?;
+    existing.extend(
+        storage
+            .load_drafts(&owner)?
+            .iter()
+            .map(|t| t.id().to_string()),
+    );
+    let id = id::generate(&existing, config.id_scheme);
+
+    let mut ticket = Ticket::new(id.clone(), title.clone());
+    ticket.meta.priority = priority;
+    ticket.meta.ticket_type = ticket_type;
+    ticket.meta.tags = tags
+        .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+        .unwrap_or_default();
+    ticket.body = config.new_body(&body, ticket_type);
+    ticket.record(EventAction::Created);
+    storage.save_draft(&owner, &ticket)?;
+
+    let path = storage.drafts_dir(&owner).join(format!("{}.md", id));
+    if json {
+        output::print(&output::Created::new(&ticket, &path))?;
+    } else {
+        let path = path.display().to_string();
+        output::changed(format!("Drafted {} - {}", id, title), &[&id, &path]);
+    }
+    Ok(())
+}
+
+fn cmd_publish(storage: &Storage, draft: &TicketArg, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+    let owner = draft_owner()?;
+
+    let mut ticket = draft.resolve_draft(storage, &owner)?;
+
+    // Another clone may have published a ticket with the same ID since
+    let lock = storage.lock()?;
+    if storage
+        .all_ids(Scope::All)?
+        .iter()
+        .any(|i| i == ticket.id())
+    {
+        return Err(TkError::validation(format!(
+            "{} is already taken in the store; rename the draft file and its id to publish it",
+            ticket.id()
+        ))
+        .into());
+    }
+    if ticket.meta.slug.is_none() {
+        ticket.meta.slug = id::slug(&ticket.title, &storage.taken_names()?);
+    }
+    storage.save(&ticket)?;
+    storage.delete_draft(&owner, ticket.id())?;
+    drop(lock);
+
+    fire_hooks(storage, &ticket, "created", Some(&ticket.body))?;
+    escalate(storage, &ticket, None)?;
+
+    let path = storage.ticket_path(ticket.id());
+    if json {
+        output::print(&output::Created::new(&ticket, &path))?;
+    } else {
+        let path = path.display().to_string();
+        output::changed(
+            format!("Published {} - {}", ticket.id(), ticket.title),
+            &[ticket.id(), &path],
+        );
+    }
+    Ok(())
+}
+
+/// Whose drafts to use: you, as note authors are named. The name becomes a
+/// directory, so it can't contain path separators.
+fn draft_owner() -> Result<String> {
+    let owner = identity::current()
+        .ok_or_else(|| TkError::validation("Can't tell who you are; set TK_AUTHOR"))?;
+    if owner.contains(['/', '\\']) || owner.starts_with('.') {
+        return Err(TkError::validation(format!(
+            "Can't keep drafts for '{}'; set TK_AUTHOR to a name without slashes",
+            owner
+        ))
+        .into());
+    }
+    Ok(owner)
+}
+
+fn cmd_create_bulk(storage: &Storage, args: CreateArgs, json: bool) -> Result<()> {
+    use std::io::Read;
+
+    ensure_init(storage)?;
+
+    let mut buf = String::new();
+    std::io::stdin().read_to_string(&mut buf)?;
+
+    let items = outline::parse(&buf);
+    if items.is_empty() {
+        return Err(TkError::validation("No headings or list items found in outline").into());
+    }
+
+    let parent = match &args.parent {
+        Some(p) => Some(find_ticket(storage, "Parent", p)?),
+        None => None,
+    };
+
+    let ticket_type: TicketType = args.r#type.parse()?;
+    let tags: Vec<String> = args
+        .tags
+        .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+        .unwrap_or_default();
+
+    let config = storage.config()?;
+
+    // Assign IDs in document order so parents exist before their children
+    let mut existing = storage.all_ids(Scope::All)?;
+    let mut taken = storage.taken_names()?;
+    let mut tickets: Vec<Ticket> = Vec::with_capacity(items.len());
+    for item in &items {
+        let parent_id = match item.parent {
+            Some(i) => Some(tickets[i].id().to_string()),
+            None => parent.as_ref().map(|p| p.id().to_string()),
+        };
+        let id = match &parent_id {
+            Some(p) => id::generate_child(p, &existing),
+            None => id::generate(&existing, config.id_scheme),
+        };
+        existing.push(id.clone());
+
+        let mut ticket = Ticket::new(id, item.title.clone());
+        ticket.meta.slug = id::slug(&item.title, &taken);
+        taken.extend(ticket.meta.slug.clone());
+        ticket.meta.priority = args.priority;
+        ticket.meta.ticket_type = ticket_type;
+        ticket.meta.tags = tags.clone();
+        ticket.meta.assignee = args.assignee.clone();
+        ticket.meta.milestone = args.milestone.clone();
+        ticket.meta.due = args.due;
+        ticket.meta.parent = parent_id;
+        ticket.body = config.new_body(&item.body, ticket_type);
+        ticket.record(EventAction::Created);
+        tickets.push(ticket);
+    }
+
+    // Parents are blocked until their children are done
+    for (i, item) in items.iter().enumerate() {
+        if let Some(p) = item.parent {
+            let child_id = tickets[i].id().to_string();
+            tickets[p].meta.deps.push(child_id);
+        }
+    }
+
+    let link_dep = args.link_dep || config.children_block_parent;
+    if let Some(parent) = parent.as_ref().filter(|_| link_dep) {
+        storage.check_unfrozen(parent)?;
+    }
+    for ticket in &tickets {
+        storage.save(ticket)?;
+    }
+
+    // The --parent ticket only waits on the outline's top-level items when asked
+    if let Some(mut parent) = parent {
+        if link_dep {
+            for (item, t) in items.iter().zip(&tickets) {
+                if item.parent.is_none() {
+                    parent.meta.deps.push(t.id().to_string());
+                }
+            }
+            parent.touch();
+            storage.save(&parent)?;
+        }
+    }
+    for ticket in &tickets {
+        fire_hooks(storage, ticket, "created", Some(&ticket.body))?;
+        escalate(storage, ticket, None)?;
+    }
+
+    if json {
+        let created: Vec<_> = tickets
+            .iter()
+            .map(|t| output::Created::new(t, &storage.ticket_path(t.id())))
+            .collect();
+        output::print(&created)?;
+    } else {
+        let mut depths: Vec<usize> = Vec::with_capacity(items.len());
+        for (item, t) in items.iter().zip(&tickets) {
+            let depth = item.parent.map_or(0, |p| depths[p] + 1);
+            depths.push(depth);
+            let prose = format!("{}Created {} - {}", "  ".repeat(depth), t.id(), t.title);
+            let path = storage.ticket_path(t.id()).display().to_string();
+            output::changed(prose, &[t.id(), &path]);
+        }
+    }
+    Ok(())
+}
+
+/// How `tk list` lays out what it found
+struct ListView {
+    /// `--view`
+    saved: Option<String>,
+    sort: Option<config::ListSort>,
+    columns: Vec<config::ListColumn>,
+    tree: bool,
+    expand: bool,
+    format: Format,
+}
+
+/// Lists tickets in `scope`, or your drafts when it is `None`
+fn cmd_list(
+    storage: &Storage,
+    filter: &FilterArgs,
+    scope: Option<Scope>,
+    view: ListView,
+) -> Result<()> {
+    ensure_init(storage)?;
+    let mut filter = filter.build()?;
+    let ListView {
+        saved,
+        sort,
+        columns,
+        tree,
+        expand,
+        format,
+    } = view;
+
+    let tickets = match scope {
+        Some(scope) => storage.load_all(scope)?,
+        None => storage.load_drafts(&draft_owner()?)?,
+    };
+    let config = storage.config()?;
+    let defaults = config.list_view(saved.as_deref())?;
+    let critical = |t: &Ticket| config.escalate.is_critical(t.meta.priority);
+    filter.default_statuses(&defaults.status);
+
+    let mut filtered: Vec<_> = tickets.iter().filter(|t| filter.matches(t)).collect();
+
+    filtered.sort_by(|a, b| {
+        let order = match sort.or(defaults.sort).unwrap_or_default() {
+            config::ListSort::Priority => a
+                .meta
+                .priority
+                .cmp(&b.meta.priority)
+                .then_with(|| a.meta.created.cmp(&b.meta.created)),
+            config::ListSort::Created => a.meta.created.cmp(&b.meta.created),
+            config::ListSort::Updated => b.meta.updated.cmp(&a.meta.updated),
+            config::ListSort::Id => std::cmp::Ordering::Equal,
+            config::ListSort::Title => a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+        };
+        order.then_with(|| a.id().cmp(b.id()))
+    });
+    // Critical tickets can't be missed, whatever the order asked for
+    filtered.sort_by_key(|t| !critical(t));
+
+    if tree {
+        let view = storage.view()?;
+        let nodes = nest_by_parent(&filtered, &view, expand);
+        if format == Format::Json {
+            let items: Vec<_> = nodes.iter().map(ParentNode::to_json).collect();
+            output::print(&items)?;
+        } else if nodes.is_empty() {
+            println!("No tickets found.");
+        } else {
+            for node in &nodes {
+                node.print(0, &config.escalate);
+            }
+        }
+        return Ok(());
+    }
+
+    match format {
+        Format::Json => {
+            let items: Vec<_> = filtered
+                .iter()
+                .map(|t| output::TicketSummary::from(*t))
+                .collect();
+            output::print(&items)?;
+        }
+        Format::Vimgrep => {
+            for t in filtered {
+                println!("{}", vimgrep_line(storage, t.id(), None, &list_line(t)));
+            }
+        }
+        Format::Text if filtered.is_empty() => println!("No tickets found."),
+        Format::Text => {
+            let columns = if columns.is_empty() {
+                defaults.columns
+            } else {
+                columns
+            };
+            let lines: Vec<String> = if columns.is_empty() {
+                filtered.iter().map(|t| list_line(t)).collect()
+            } else {
+                let rows: Vec<Vec<String>> =
+                    filtered.iter().map(|t| list_columns(t, &columns)).collect();
+                align_columns(&rows)
+            };
+            let links = TicketLinks::new(storage)?;
+            for (t, line) in filtered.iter().zip(lines) {
+                let line = links.link_id(t.id(), line);
+                println!("{}", output::flag_critical(critical(t), line));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The `--columns` (or list.columns) fields of a `list` row
+fn list_columns(t: &Ticket, columns: &[config::ListColumn]) -> Vec<String> {
+    use config::ListColumn;
+
+    let or_dash = |s: Option<String>| s.unwrap_or_else(|| "-".to_string());
+    columns
+        .iter()
+        .map(|c| match c {
+            ListColumn::Status => format!("[{}]", status_marker(t.meta.status)),
+            ListColumn::Id => t.id().to_string(),
+            ListColumn::Priority => format!("[P{}]", t.meta.priority),
+            ListColumn::Type => t.meta.ticket_type.to_string(),
+            ListColumn::Title if t.archived => format!("{} (archived)", t.title),
+            ListColumn::Title => t.title.clone(),
+            ListColumn::Assignee => or_dash(t.meta.assignee.as_ref().map(|a| format!("@{}", a))),
+            ListColumn::Tags => or_dash((!t.meta.tags.is_empty()).then(|| t.meta.tags.join(","))),
+            ListColumn::Estimate => or_dash(t.meta.estimate.map(|e| e.to_string())),
+            ListColumn::Due => or_dash(t.meta.due.map(|d| d.to_string())),
+            ListColumn::Milestone => or_dash(t.meta.milestone.clone()),
+        })
+        .collect()
+}
+
+/// Rows padded so each column lines up; the last column isn't padded
+fn align_columns(rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        widths.resize(widths.len().max(row.len()), 0);
+        for (w, field) in widths.iter_mut().zip(row) {
+            *w = (*w).max(field.chars().count());
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            let last = row.len().saturating_sub(1);
+            row.iter()
+                .enumerate()
+                .map(|(i, field)| {
+                    if i == last {
+                        field.clone()
+                    } else {
+                        format!("{:<1$}", field, widths[i])
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("  ")
+        })
+        .collect()
+}
+
+/// `path:line:1: message` pointing at line `body_line` of a ticket's body,
+/// or its title line when that's None, with the path relative to the
+/// working directory when it's below it
+fn vimgrep_line(storage: &Storage, id: &str, body_line: Option<usize>, message: &str) -> String {
+    let path = storage.locate(id);
+    let content = std::fs::read_to_string(&path).ok();
+    let at_body = content
+        .as_deref()
+        .zip(body_line)
+        .and_then(|(content, n)| Storage::body_line(content).map(|start| start + n - 1));
+    let at_title = || {
+        content
+            .as_deref()
+            .and_then(|content| {
+                // A `title:` key if the frontmatter has one, else the first line
+                // after it: the title heading, if there is one
+                let fence = FrontmatterFormat::detect(content)?.fence();
+                let lines: Vec<&str> = content.lines().collect();
+                let close = lines.iter().skip(1).position(|l| l.trim_end() == fence)? + 1;
+                let key = lines[1..close]
+                    .iter()
+                    .position(|l| l.starts_with("title:") || l.starts_with("title ="));
+                match key {
+                    Some(i) => Some(i + 1),
+                    None => lines[close + 1..]
+                        .iter()
+                        .position(|l| !l.trim().is_empty())
+                        .map(|i| i + close + 1),
+                }
+            })
+            .map_or(1, |i| i + 1)
+    };
+    let line = at_body.unwrap_or_else(at_title);
+    let shown = std::env::current_dir()
+        .ok()
+        .and_then(|cwd| path.strip_prefix(cwd).ok().map(|p| p.to_path_buf()))
+        .unwrap_or(path);
+    format!("{}:{}:1: {}", shown.display(), line, message)
+}
+
+fn status_marker(status: Status) -> &'static str {
+    match status {
+        Status::Open => " ",
+        Status::InProgress => "-",
+        Status::Closed => "x",
+    }
+}
+
+fn list_line(t: &Ticket) -> String {
+    let marker = status_marker(t.meta.status);
+    let archived = if t.archived { " (archived)" } else { "" };
+    format!(
+        "[{}] {} [P{}] {}{}",
+        marker,
+        t.id(),
+        t.meta.priority,
+        t.title,
+        archived
+    )
+}
+
+/// A ticket with its children, for `list --tree`
+struct ParentNode<'a> {
+    ticket: &'a Ticket,
+    children: Vec<ParentNode<'a>>,
+    /// Descendants collapsed under a closed ticket
+    hidden: usize,
+    /// Estimates under the ticket, if it has children in the store
+    rollup: Option<graph::Rollup>,
+}
+
+impl<'a> ParentNode<'a> {
+    fn new(tree: graph::Tree<'a>, view: &view::View, expand: bool) -> Self {
+        let children: Vec<_> = tree
+            .children
+            .into_iter()
+            .map(|c| ParentNode::new(c, view, expand))
+            .collect();
+        let rollup = rollup_under(view, tree.ticket);
+        if expand || tree.ticket.is_open() {
+            ParentNode {
+                ticket: tree.ticket,
+                children,
+                hidden: 0,
+                rollup,
+            }
+        } else {
+            ParentNode {
+                ticket: tree.ticket,
+                hidden: children.iter().map(|n| 1 + n.descendants()).sum(),
+                children: Vec::new(),
+                rollup,
+            }
+        }
+    }
+
+    /// Everything below this node, shown or collapsed
+    fn descendants(&self) -> usize {
+        self.hidden
+            + self
+                .children
+                .iter()
+                .map(|c| 1 + c.descendants())
+                .sum::<usize>()
+    }
+
+    fn print(&self, depth: usize, escalate: &config::EscalateConfig) {
+        let hidden = match self.hidden {
+            0 => String::new(),
+            n => format!(" (+{} hidden)", n),
+        };
+        let size = self.rollup.as_ref().map(remaining).unwrap_or_default();
+        let line = format!("{}{}{}", list_line(self.ticket), size, hidden);
+        let line = output::flag_critical(escalate.is_critical(self.ticket.meta.priority), line);
+        println!("{}{}", "  ".repeat(depth), line);
+        for c in &self.children {
+            c.print(depth + 1, escalate);
+        }
+    }
+
+    fn to_json(&self) -> output::ListNode {
+        output::ListNode {
+            ticket: output::TicketSummary::from(self.ticket),
+            children: self.children.iter().map(ParentNode::to_json).collect(),
+            hidden: self.hidden,
+            rollup: self.rollup.as_ref().map(output::Rollup::from),
+        }
+    }
+}
+
+/// Nest tickets under their parents, keeping the incoming order among
+/// siblings. Tickets whose parent isn't in the list are roots. Unless
+/// `expand`, closed tickets keep their descendants only as a count.
+/// Parents are sized by their descendants in `view`, listed or not.
+fn nest_by_parent<'a>(
+    tickets: &[&'a Ticket],
+    view: &view::View,
+    expand: bool,
+) -> Vec<ParentNode<'a>> {
+    graph::forest(tickets)
+        .into_iter()
+        .map(|tree| ParentNode::new(tree, view, expand))
+        .collect()
+}
+
+fn cmd_show(storage: &Storage, id: &str, history: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let ticket = find_ticket(storage, "Ticket", id)?;
+    mark_seen(storage, ticket.id());
+
+    if history {
+        println!("{} - {}", ticket.id(), ticket.title);
+        if ticket.meta.events.is_empty() {
+            println!("No recorded history.");
+        }
+        for e in &ticket.meta.events {
+            let change = match (&e.from, &e.to) {
+                (Some(from), Some(to)) => format!(" {} -> {}", from, to),
+                (None, Some(to)) => format!(" -> {}", to),
+                (Some(from), None) => format!(" {} -> (none)", from),
+                (None, None) => String::new(),
+            };
+            println!(
+                "  {}  {:<12} {}{}",
+                e.at.format("%Y-%m-%d %H:%M"),
+                e.by.as_deref().unwrap_or("anonymous"),
+                e.action,
+                change
+            );
+        }
+        return Ok(());
+    }
+
+    // Bundled archive tickets have no file of their own to show
+    let content = if storage.is_loose(&ticket) {
+        std::fs::read_to_string(storage.locate(ticket.id()))
+            .context("Failed to read ticket file")?
+    } else {
+        Storage::serialize_ticket(&ticket, storage.config()?.frontmatter)?
+    };
+
+    let content = TicketLinks::new(storage)?.link_id(ticket.id(), content);
+
+    // Replies are appended like any note; show them under what they answer
+    let threaded = Storage::notes_section(&ticket.body).and_then(|section| {
+        let notes = Storage::parse_notes(section);
+        let pos = content.rfind(section.trim_end())?;
+        notes
+            .iter()
+            .any(|n| n.reply_to.is_some())
+            .then(|| format!("{}{}", &content[..pos], note_thread(&notes)))
+    });
+    print!("{}", threaded.unwrap_or(content));
+    print_children(storage, &ticket)
+}
+
+/// A parent's children after `show`, under their summed estimates. Only on
+/// a terminal: piped, `show` prints the file as it is, ready for `tk edit`.
+/// `tk tree` and `tk list --tree` show the sums either way, and in JSON.
+fn print_children(storage: &Storage, ticket: &Ticket) -> Result<()> {
+    use std::io::IsTerminal;
+    if !std::io::stdout().is_terminal() {
+        return Ok(());
+    }
+    let view = storage.view()?;
+    let Some(sum) = rollup_under(&view, ticket) else {
+        return Ok(());
+    };
+    let unestimated = match sum.unestimated {
+        0 => String::new(),
+        n => format!(", {} unestimated", n),
+    };
+    println!();
+    println!(
+        "Children: estimate {}, {} remaining ({} of {} tickets under it open{})",
+        sum.estimate, sum.remaining, sum.open, sum.tickets, unestimated
+    );
+    for c in view.children(ticket.id()) {
+        // A child with children of its own is sized by them
+        let size = match rollup_under(&view, c) {
+            None => c.meta.estimate.map(|e| format!(" ({})", e)),
+            Some(sum) => Some(remaining(&sum)),
+        };
+        println!("  {}{}", list_line(c), size.unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// Estimates summed over everything under `ticket`, if it has children
+fn rollup_under(view: &view::View, ticket: &Ticket) -> Option<graph::Rollup> {
+    (!view.children(ticket.id()).is_empty())
+        .then(|| graph::rollup(&graph::descendants(view, ticket.id())))
+}
+
+/// " (5 of 8 remaining)", a parent's size after its line; nothing when
+/// none of its descendants is estimated
+fn remaining(sum: &graph::Rollup) -> String {
+    if sum.estimate == 0 {
+        return String::new();
+    }
+    format!(" ({} of {} remaining)", sum.remaining, sum.estimate)
+}
+
+/// Where ticket IDs link to in terminal output (`hyperlinks` in config)
+struct TicketLinks<'a> {
+    storage: &'a Storage,
+    /// None when links are off
+    rule: Option<config::HyperlinksConfig>,
+}
+
+impl<'a> TicketLinks<'a> {
+    fn new(storage: &'a Storage) -> Result<Self> {
+        use std::io::IsTerminal;
+        let rule = storage.config()?.hyperlinks;
+        let on = match rule.enabled {
+            config::HyperlinkMode::Always => true,
+            config::HyperlinkMode::Never => false,
+            config::HyperlinkMode::Auto => {
+                std::io::stdout().is_terminal()
+                    && std::env::var("TERM").map_or(true, |t| t != "dumb")
+            }
+        };
+        Ok(Self {
+            storage,
+            rule: on.then_some(rule),
+        })
+    }
+
+    fn url(&self, id: &str) -> Option<String> {
+        let rule = self.rule.as_ref()?;
+        match &rule.url {
+            Some(template) => Some(template.replace("{id}", id)),
+            None => {
+                let path = std::fs::canonicalize(self.storage.locate(id)).ok()?;
+                Some(format!(
+                    "file://{}",
+                    path.display().to_string().replace(' ', "%20")
+                ))
+            }
+        }
+    }
+
+    /// `text` with the first mention of `id` made a link to the ticket
+    fn link_id(&self, id: &str, text: String) -> String {
+        match self.url(id) {
+            Some(url) => text.replacen(id, &output::hyperlink(&url, id), 1),
+            None => text,
+        }
+    }
+}
+
+/// Record that the current user has seen a ticket, for `tk inbox`. Viewing
+/// shouldn't fail over bookkeeping, so problems are only logged.
+fn mark_seen(storage: &Storage, id: &str) {
+    let Some(user) = identity::current() else {
+        return;
+    };
+    if storage.is_dry_run() {
+        return;
+    }
+    let result = inbox::SeenState::load(&storage.local_dir()).and_then(|mut state| {
+        let seen = state.users.entry(user).or_default();
+        seen.tickets.insert(id.to_string(), Utc::now());
+        storage.save_local_file(inbox::STATE_FILE, &state.to_json()?)
+    });
+    if let Err(e) = result {
+        tracing::warn!("could not record {} as seen: {}", id, e);
+    }
+}
+
+fn cmd_inbox(storage: &Storage, mark_read: bool, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let user = identity::current().ok_or_else(|| {
+        TkError::validation("Can't tell who you are; set TK_AUTHOR or git user.name")
+    })?;
+    let mut state = inbox::SeenState::load(&storage.local_dir())?;
+    let seen = state.users.entry(user).or_default();
+
+    let mut tickets = storage.load_all(Scope::Live)?;
+    tickets.retain(|t| seen.is_unread(t));
+    tickets.sort_by_key(|t| std::cmp::Reverse(t.meta.updated.unwrap_or(t.meta.created)));
+
+    if json {
+        let entries: Vec<_> = tickets
+            .iter()
+            .map(|t| output::InboxEntry {
+                schema: output::SCHEMA_VERSION,
+                id: t.id().to_string(),
+                title: t.title.clone(),
+                status: t.meta.status.to_string(),
+                priority: t.meta.priority,
+                changed: t.meta.updated.unwrap_or(t.meta.created),
+                last_seen: seen.seen(t),
+            })
+            .collect();
+        output::print(&entries)?;
+    } else if tickets.is_empty() {
+        println!("Nothing new.");
+    } else {
+        for t in &tickets {
+            let changed = t.meta.updated.unwrap_or(t.meta.created);
+            let when = match seen.seen(t) {
+                Some(_) => "changed",
+                None => "new to you",
+            };
+            println!(
+                "{} [{}] {} ({} {})",
+                t.id(),
+                t.meta.status,
+                t.title,
+                when,
+                changed.format("%Y-%m-%d %H:%M")
+            );
+        }
+    }
+
+    if mark_read {
+        seen.mark_all_read(Utc::now());
+        storage.save_local_file(inbox::STATE_FILE, &state.to_json()?)?;
+    }
+    Ok(())
+}
+
+fn cmd_edit(
+    storage: &Storage,
+    id: &str,
+    editor: Option<String>,
+    replace_notes: bool,
+) -> Result<()> {
+    use std::io::{IsTerminal, Read};
+
+    ensure_init(storage)?;
+
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+    let notes = Storage::notes_section(&ticket.body).filter(|_| !replace_notes);
+
+    let mut buf = String::new();
+    if editor.is_some() || std::io::stdin().is_terminal() {
+        let editor = editor::resolve(editor, &storage.config()?);
+        // Notes are put back afterwards, so they aren't offered for editing
+        let body = match notes {
+            Some(notes) => &ticket.body[..ticket.body.len() - notes.len()],
+            None => ticket.body.as_str(),
+        };
+        let current = format!("# {}\n\n{}", ticket.title, body.trim_end());
+        buf = editor::compose(&editor, &format!("edit-{}", ticket.id()), &current)?;
+    } else {
+        std::io::stdin().read_to_string(&mut buf)?;
+    }
+    let input = buf.trim();
+
+    if input.is_empty() {
+        return Err(TkError::validation("No input provided. Expected: # Title\\n[body]").into());
+    }
+
+    let (title, body) = Storage::extract_title(input);
+    if title == "Untitled" && !input.starts_with("# ") {
+        return Err(TkError::validation("No title found. First line must be: # Your Title").into());
+    }
+
+    check_title(&title, ticket.meta.ticket_type, &storage.config()?)?;
+    let body = if replace_notes {
+        body.to_string()
+    } else {
+        keep_notes(&ticket.body, body)?
+    };
+
+    ticket.title = title;
+    ticket.body = body;
+    storage.save(&ticket)?;
+    fire_hooks(storage, &ticket, "edited", Some(&ticket.body))?;
+
+    output::changed(format!("Updated {}", ticket.id()), &[ticket.id()]);
+    Ok(())
+}
+
+/// `new` as the body replacing `old`, with `old`'s notes put back after it.
+/// A `new` that already has every note (say, edited `tk show` output) is
+/// taken as is; one that has only some of them is refused.
+fn keep_notes(old: &str, new: &str) -> Result<String> {
+    let old_notes: Vec<String> = Storage::parse_notes(old).iter().map(Note::format).collect();
+    let new_notes: Vec<String> = Storage::parse_notes(new).iter().map(Note::format).collect();
+    if old_notes.iter().all(|n| new_notes.contains(n)) {
+        return Ok(new.to_string());
+    }
+    if !new_notes.is_empty() {
+        let dropped = old_notes.iter().filter(|n| !new_notes.contains(n)).count();
+        return Err(TkError::validation(format!(
+            "The new text drops or changes {} of the ticket's {} notes; keep them or pass --replace-notes",
+            dropped,
+            old_notes.len()
+        ))
+        .into());
+    }
+    let notes = match Storage::notes_section(old) {
+        Some(section) => section.trim_end().to_string(),
+        None => old_notes.join("\n\n"),
+    };
+    let new = new.trim_end();
+    if new.is_empty() {
+        return Ok(notes);
+    }
+    Ok(format!("{}\n\n{}", new, notes))
+}
+
+fn cmd_status(
+    storage: &Storage,
+    id: &str,
+    status_str: &str,
+    force: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+
+    let new_status: Status = status_str.parse()?;
+    if new_status == Status::Closed && !force {
+        check_closable(storage, &ticket, "tk status --force")?;
+    }
+    ticket.set_status(new_status);
+
+    storage.save(&ticket)?;
+    let archived = file_by_status(storage, &mut ticket)?;
+    fire_hooks(storage, &ticket, "status", None)?;
+    let unblocked = unblocked_by(storage, &ticket)?;
+
+    if json {
+        output::print(&output::StatusChange {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            status: new_status.to_string(),
+            unblocked,
+            archived,
+        })?;
+    } else {
+        let status = new_status.to_string();
+        output::changed(
+            format!(
+                "{} -> {}{}{}",
+                ticket.id(),
+                status,
+                unblocks(&unblocked),
+                moved(archived)
+            ),
+            &[ticket.id(), &status],
+        );
+    }
+    Ok(())
+}
+
+/// Apply `archive_on_close` after a status change: with `true`, a ticket
+/// just closed goes to the archive, and with any setting, a reopened one
+/// comes back out. Returns where it moved: into the archive (true) or out.
+fn file_by_status(storage: &Storage, ticket: &mut Ticket) -> Result<Option<bool>> {
+    let archive = match storage.config()?.archive_on_close {
+        config::ArchiveOnClose::Now(false) => return Ok(None),
+        _ if ticket.is_open() => false,
+        config::ArchiveOnClose::Now(true) => true,
+        // Left for `tk gc`
+        config::ArchiveOnClose::AfterDays(_) => return Ok(None),
+    };
+    if ticket.archived == archive || !storage.is_loose(ticket) {
+        return Ok(None);
+    }
+    storage.set_archived(ticket, archive)?;
+    Ok(Some(archive))
+}
+
+/// " (archived)" or " (out of the archive)" for a confirmation, or nothing
+fn moved(archived: Option<bool>) -> &'static str {
+    match archived {
+        Some(true) => " (archived)",
+        Some(false) => " (out of the archive)",
+        None => "",
+    }
+}
+
+/// Open tickets that were waiting on `ticket`, now closed, and on nothing
+/// else still open
+fn unblocked_by(storage: &Storage, ticket: &Ticket) -> Result<Vec<String>> {
+    if ticket.is_open() || storage.is_dry_run() {
+        return Ok(Vec::new());
+    }
+    let view = storage.view()?;
+    Ok(view
+        .dependents(ticket.id())
+        .into_iter()
+        .filter(|t| t.is_open() && view.open_deps(t).is_empty())
+        .map(|t| t.id().to_string())
+        .collect())
+}
+
+/// " (unblocks a, b)" for a confirmation, or nothing
+fn unblocks(ids: &[String]) -> String {
+    if ids.is_empty() {
+        String::new()
+    } else {
+        format!(" (unblocks {})", ids.join(", "))
+    }
+}
+
+fn cmd_start(storage: &Storage, id: &str, lease: Option<String>, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+    let lease = lease.as_deref().map(dates::lease).transpose()?;
+
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+
+    let started = ticket.meta.status != Status::InProgress;
+    ticket.set_status(Status::InProgress);
+    if let Some(lease) = lease {
+        ticket.meta.claimed_until = Some(Utc::now() + lease);
+        ticket.touch();
+    }
+    storage.save(&ticket)?;
+    let archived = file_by_status(storage, &mut ticket)?;
+    if started {
+        fire_hooks(storage, &ticket, "status", None)?;
+    }
+
+    let status = Status::InProgress.to_string();
+    if json {
+        output::print(&output::StatusChange {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            status,
+            unblocked: Vec::new(),
+            archived,
+        })?;
+    } else {
+        let prose = match ticket.meta.claimed_until {
+            Some(until) => format!(
+                "{} -> {} until {}",
+                ticket.id(),
+                status,
+                until.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+            ),
+            None => format!("{} -> {}", ticket.id(), status),
+        };
+        output::changed(
+            format!("{}{}", prose, moved(archived)),
+            &[ticket.id(), &status],
+        );
+    }
+    Ok(())
+}
+
+/// Refuse a title that breaks the configured title rule
+fn check_title(title: &str, ticket_type: TicketType, config: &config::Config) -> Result<()> {
+    match lint::title_problem(title, ticket_type, config) {
+        Some(problem) => {
+            let hint = lint::fix_title(title, ticket_type, config)
+                .map(|t| format!(" (try \"{}\")", t))
+                .unwrap_or_default();
+            Err(TkError::validation(format!("Invalid title: {}{}", problem, hint)).into())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Refuse to close a ticket whose required sections are missing or empty
+/// Fails if the close checks object to closing `ticket`; `override_with`
+/// names the flag that skips them on the calling command.
+fn check_closable(storage: &Storage, ticket: &Ticket, override_with: &str) -> Result<()> {
+    let config = storage.config()?;
+    let tickets = if config.close.require_children_closed {
+        storage.load_all(Scope::All)?
+    } else {
+        Vec::new()
+    };
+    let blockers = lint::close_blockers(ticket, &tickets, &config);
+    if blockers.is_empty() {
+        return Ok(());
+    }
+    Err(TkError::validation(format!(
+        "{} can't be closed yet: {}. Fix that or use {}",
+        ticket.id(),
+        blockers.join("; "),
+        override_with
+    ))
+    .into())
+}
+
+fn cmd_priority(storage: &Storage, id: &str, priority: u8, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    if priority > 4 {
+        return Err(TkError::validation("Priority must be 0-4").into());
+    }
+
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+
+    let from = ticket.meta.priority;
+    ticket.set_priority(priority);
+    storage.save(&ticket)?;
+    fire_hooks(storage, &ticket, "priority", None)?;
+    escalate(storage, &ticket, Some(from))?;
+
+    if json {
+        output::print(&output::FieldChange {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            field: "priority".to_string(),
+            from: Some(from.to_string()),
+            to: Some(priority.to_string()),
+        })?;
+    } else {
+        output::changed(
+            format!("{} -> P{}", ticket.id(), priority),
+            &[ticket.id(), &priority.to_string()],
+        );
+    }
+    Ok(())
+}
+
+fn cmd_assign(storage: &Storage, id: &str, assignee: Option<String>, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+
+    let from = ticket.meta.assignee.clone();
+    ticket.set_assignee(assignee.clone());
+    storage.save(&ticket)?;
+    fire_hooks(storage, &ticket, "assignee", None)?;
+
+    if json {
+        output::print(&output::FieldChange {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            field: "assignee".to_string(),
+            from,
+            to: assignee,
+        })?;
+    } else {
+        let prose = match &assignee {
+            Some(a) => format!("{} -> {}", ticket.id(), a),
+            None => format!("{} unassigned", ticket.id()),
+        };
+        output::changed(prose, &[ticket.id(), assignee.as_deref().unwrap_or("")]);
+    }
+    Ok(())
+}
+
+/// Reassign a ticket and leave a handoff note, saved and announced as one
+/// change
+fn cmd_handoff(
+    storage: &Storage,
+    id: &str,
+    assignee: String,
+    note: Option<String>,
+    reopen: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    let assignee = assignee.trim().to_string();
+    if assignee.is_empty() {
+        return Err(TkError::validation("Hand off to whom? The assignee is empty").into());
+    }
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+    let from = ticket.meta.assignee.clone();
+    if from.as_deref() == Some(assignee.as_str()) {
+        return Err(TkError::validation(format!(
+            "{} is already assigned to {}",
+            ticket.id(),
+            assignee
+        ))
+        .into());
+    }
+
+    let mut content = match &from {
+        Some(from) => format!("Handed off from @{} to @{}", from, assignee),
+        None => format!("Handed off to @{}", assignee),
+    };
+    if reopen && ticket.meta.status == Status::InProgress {
+        content.push_str(" (back to open)");
+    }
+    if let Some(text) = note.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        content.push_str("\n\n");
+        content.push_str(text);
+    }
+
+    ticket.set_assignee(Some(assignee.clone()));
+    if reopen && ticket.meta.status == Status::InProgress {
+        ticket.set_status(Status::Open);
+    }
+    let note = Note {
+        author: identity::current(),
+        ..Note::new(content)
+    };
+    ticket.add_note(&note);
+    storage.save(&ticket)?;
+    file_by_status(storage, &mut ticket)?;
+    fire_hooks(storage, &ticket, "handoff", Some(&note.content))?;
+
+    if json {
+        output::print(&output::Handoff {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            from,
+            to: assignee,
+            status: ticket.meta.status.to_string(),
+            note: Storage::parse_notes(&ticket.body).len(),
+        })?;
+    } else {
+        output::changed(
+            format!("{} handed off to {}", ticket.id(), assignee),
+            &[ticket.id(), &assignee],
+        );
+    }
+    Ok(())
+}
+
+fn cmd_watch_ticket(
+    storage: &Storage,
+    id: &str,
+    name: Option<String>,
+    remove: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+    let name = name.or_else(identity::current).ok_or_else(|| {
+        TkError::validation("Can't tell who you are; pass a name or set TK_AUTHOR")
+    })?;
+
+    let watching = ticket.meta.watchers.contains(&name);
+    let changed = if remove {
+        ticket.meta.watchers.retain(|w| *w != name);
+        watching
+    } else if !watching {
+        ticket.meta.watchers.push(name.clone());
+        true
+    } else {
+        false
+    };
+    if changed {
+        ticket.touch();
+        storage.save(&ticket)?;
+        fire_hooks(storage, &ticket, "watch", None)?;
+    }
+
+    if json {
+        output::print(&output::Watchers {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            watchers: ticket.meta.watchers.clone(),
+        })?;
+    } else {
+        let prose = if remove {
+            format!("{} no longer watches {}", name, ticket.id())
+        } else {
+            format!("{} watches {}", name, ticket.id())
+        };
+        output::changed(prose, &[ticket.id(), &ticket.meta.watchers.join(",")]);
+    }
+    Ok(())
+}
+
+fn cmd_close(
+    storage: &Storage,
+    id: &str,
+    resolution: Option<String>,
+    force: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+    if let Some(resolution) = resolution {
+        ticket.meta.resolution = Some(resolution.trim().to_string());
+    }
+
+    if !force {
+        check_closable(storage, &ticket, "tk close --force")?;
+    }
+
+    ticket.set_status(Status::Closed);
+
+    storage.save(&ticket)?;
+    let archived = file_by_status(storage, &mut ticket)?;
+    fire_hooks(storage, &ticket, "status", None)?;
+    let unblocked = unblocked_by(storage, &ticket)?;
+
+    if json {
+        output::print(&output::StatusChange {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            status: Status::Closed.to_string(),
+            unblocked,
+            archived,
+        })?;
+    } else {
+        output::changed(
+            format!(
+                "Closed {}{}{}",
+                ticket.id(),
+                unblocks(&unblocked),
+                moved(archived)
+            ),
+            &[ticket.id(), "closed"],
+        );
+    }
+    Ok(())
+}
+
+fn cmd_review_queue(storage: &Storage, include_archived: bool, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let tickets = storage.load_all(Scope::listing(include_archived))?;
+    let mut queue: Vec<&Ticket> = tickets
+        .iter()
+        .filter(|t| t.meta.status == Status::Closed && t.meta.reviewed.is_none())
+        .collect();
+    queue.sort_by_key(|t| (t.meta.closed, t.meta.priority));
+
+    if json {
+        let items: Vec<_> = queue
+            .iter()
+            .map(|t| output::ReviewItem {
+                schema: output::SCHEMA_VERSION,
+                id: t.id().to_string(),
+                title: t.title.clone(),
+                priority: t.meta.priority,
+                closed: t.meta.closed,
+                closed_by: t.closed_by().map(str::to_string),
+            })
+            .collect();
+        return output::print(&items);
+    }
+    if queue.is_empty() {
+        println!("Nothing to review.");
+    }
+    for t in queue {
+        let closed = match (t.closed_by(), t.meta.closed) {
+            (Some(by), Some(at)) => format!(" (closed by {} {})", by, at.format("%Y-%m-%d")),
+            (None, Some(at)) => format!(" (closed {})", at.format("%Y-%m-%d")),
+            (Some(by), None) => format!(" (closed by {})", by),
+            (None, None) => String::new(),
+        };
+        println!("{} [P{}] {}{}", t.id(), t.meta.priority, t.title, closed);
+    }
+    Ok(())
+}
+
+fn cmd_review_done(storage: &Storage, id: &str, force: bool, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+    let reviewer = identity::current()
+        .ok_or_else(|| TkError::validation("Can't tell who you are; set TK_AUTHOR"))?;
+
+    if ticket.meta.status != Status::Closed {
+        return Err(TkError::validation(format!(
+            "{} is {}; only closed tickets are reviewed",
+            ticket.id(),
+            ticket.meta.status
+        ))
+        .into());
+    }
+    if !force && ticket.closed_by() == Some(reviewer.as_str()) {
+        return Err(TkError::validation(format!(
+            "You closed {}; someone else should review it (or pass --force)",
+            ticket.id()
+        ))
+        .into());
+    }
+
+    ticket.review(reviewer.clone());
+    storage.save(&ticket)?;
+    fire_hooks(storage, &ticket, "reviewed", None)?;
+
+    let reviewed = ticket.meta.reviewed.unwrap_or_else(Utc::now);
+    if json {
+        output::print(&output::Reviewed {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            reviewer,
+            reviewed,
+        })?;
+    } else {
+        output::changed(
+            format!("{} reviewed by {}", ticket.id(), reviewer),
+            &[ticket.id(), &reviewer],
+        );
+    }
+    Ok(())
+}
+
+/// Block the ticket with `block`'s reason (if any), or unblock it for None
+fn cmd_block(storage: &Storage, id: &str, block: Option<Option<String>>, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+
+    let from = ticket.meta.blocked;
+    let to = block.is_some();
+    let reason = block.flatten();
+    if from != to || (to && ticket.meta.blocked_reason != reason) {
+        ticket.meta.blocked = to;
+        ticket.meta.blocked_reason = reason;
+        ticket.touch();
+        storage.save(&ticket)?;
+    }
+
+    if json {
+        output::print(&output::FieldChange {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            field: "blocked".to_string(),
+            from: Some(from.to_string()),
+            to: Some(to.to_string()),
+        })?;
+    } else {
+        let prose = match (&ticket.meta.blocked_reason, from, to) {
+            (_, false, false) => format!("{} isn't blocked", ticket.id()),
+            (_, true, false) => format!("Unblocked {}", ticket.id()),
+            (Some(reason), _, true) => format!("Blocked {}: {}", ticket.id(), reason),
+            (None, _, true) => format!("Blocked {}", ticket.id()),
+        };
+        output::changed(prose, &[ticket.id(), &to.to_string()]);
+    }
+    Ok(())
+}
+
+fn cmd_freeze(storage: &Storage, id: &str, locked: bool, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+
+    let from = ticket.meta.locked;
+    if from != locked {
+        ticket.meta.locked = locked;
+        ticket.touch();
+        storage.save_unchecked(&ticket)?;
+    }
+
+    if json {
+        output::print(&output::FieldChange {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            field: "locked".to_string(),
+            from: Some(from.to_string()),
+            to: Some(locked.to_string()),
+        })?;
+    } else {
+        let prose = match (from, locked) {
+            (true, true) => format!("{} is already frozen", ticket.id()),
+            (false, false) => format!("{} isn't frozen", ticket.id()),
+            (false, true) => format!("Froze {}", ticket.id()),
+            (true, false) => format!("Unfroze {}", ticket.id()),
+        };
+        output::changed(prose, &[ticket.id(), &locked.to_string()]);
+    }
+    Ok(())
+}
+
+fn cmd_delete(
+    storage: &Storage,
+    id: &str,
+    yes: bool,
+    orphans: Option<OrphanPolicy>,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    let ticket = find_ticket(storage, "Ticket", id)?;
+
+    // Tickets that would be left pointing at nothing
+    let view = storage.view()?;
+    let children = view.children(ticket.id());
+    let dependents = view.dependents(ticket.id());
+    let mut affected: Vec<Ticket> = children
+        .iter()
+        .chain(&dependents)
+        .map(|t| (*t).clone())
+        .collect();
+    affected.sort_by(|a, b| a.id().cmp(b.id()));
+    affected.dedup_by(|a, b| a.id() == b.id());
+    for t in std::iter::once(&ticket).chain(&affected) {
+        storage.check_unfrozen(t)?;
+    }
+
+    let policy = if affected.is_empty() {
+        None
+    } else {
+        let new_home = ticket
+            .meta
+            .parent
+            .clone()
+            .unwrap_or_else(|| "the top level".to_string());
+        eprintln!(
+            "Warning: {} has {} child(ren) and {} dependent(s): {}",
+            ticket.id(),
+            children.len(),
+            dependents.len(),
+            affected
+                .iter()
+                .map(|t| t.id())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        let policy = match orphans {
+            Some(p) => p,
+            None => {
+                let options = [
+                    format!("Move children to {}, drop deps on it", new_home),
+                    format!("Move children to {}, dependents inherit its deps", new_home),
+                    "Abort".to_string(),
+                ];
+                match prompt::choose(
+                    "What should happen to them?",
+                    &options,
+                    "--orphans drop|transfer|abort",
+                )? {
+                    0 => OrphanPolicy::Drop,
+                    1 => OrphanPolicy::Transfer,
+                    _ => OrphanPolicy::Abort,
+                }
+            }
+        };
+        if policy == OrphanPolicy::Abort {
+            anyhow::bail!("Aborted");
+        }
+        Some(policy)
+    };
+
+    prompt::confirm(&format!("Delete {} - {}?", ticket.id(), ticket.title), yes)?;
+
+    let mut updated = Vec::new();
+    if let Some(policy) = policy {
+        for t in &mut affected {
+            if t.meta.parent.as_deref() == Some(ticket.id()) {
+                t.meta.parent = ticket.meta.parent.clone();
+            }
+            if let Some(pos) = t.meta.deps.iter().position(|d| d == ticket.id()) {
+                t.meta.deps.remove(pos);
+                if policy == OrphanPolicy::Transfer {
+                    for dep in &ticket.meta.deps {
+                        if dep != t.id() && !t.meta.deps.contains(dep) {
+                            t.meta.deps.push(dep.clone());
+                        }
+                    }
+                }
+            }
+            t.touch();
+            storage.save(t)?;
+            updated.push(t.id().to_string());
+        }
+    }
+
+    storage.delete(&ticket)?;
+
+    if json {
+        output::print(&output::Deleted {
+            schema: output::SCHEMA_VERSION,
+            deleted: ticket.id().to_string(),
+            updated,
+        })?;
+    } else {
+        let mut prose = format!("Deleted {}", ticket.id());
+        for id in &updated {
+            prose.push_str(&format!("\n  Updated {}", id));
+        }
+        output::changed(prose, &[ticket.id()]);
+    }
+    Ok(())
+}
+
+fn cmd_dep(storage: &Storage, id: &str, dep_id: &str, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+
+    let dep = find_ticket(storage, "Dependency", dep_id)?;
+
+    if ticket.meta.deps.contains(&dep.id().to_string()) {
+        return Err(TkError::validation("Dependency already exists").into());
+    }
+
+    // The new dep closes a cycle if it already depends on this ticket
+    let view = storage.view()?;
+    if dep.id() == ticket.id() || graph::transitive_deps(&view, dep.id()).contains(ticket.id()) {
+        return Err(TkError::validation("Adding this dependency would create a cycle").into());
+    }
+    ticket.meta.deps.push(dep.id().to_string());
+
+    ticket.touch();
+    storage.save(&ticket)?;
+
+    if json {
+        output::print(&output::DepChange {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            dep: dep.id().to_string(),
+            removed: None,
+        })?;
+    } else {
+        output::changed(
+            format!("{} now depends on {}", ticket.id(), dep.id()),
+            &[ticket.id(), dep.id()],
+        );
+    }
+    Ok(())
+}
+
+fn cmd_undep(storage: &Storage, id: &str, dep_id: &str, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+
+    let dep = find_ticket(storage, "Dependency", dep_id)?;
+
+    let orig_len = ticket.meta.deps.len();
+    ticket.meta.deps.retain(|d| d != dep.id());
+
+    if ticket.meta.deps.len() == orig_len {
+        return Err(TkError::validation("Dependency not found").into());
+    }
+
+    ticket.touch();
+    storage.save(&ticket)?;
+
+    if json {
+        output::print(&output::DepChange {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            dep: dep.id().to_string(),
+            removed: Some(true),
+        })?;
+    } else {
+        output::changed(
+            format!("Removed dependency {} -> {}", ticket.id(), dep.id()),
+            &[ticket.id(), dep.id()],
+        );
+    }
+    Ok(())
+}
+
+fn cmd_adopt(
+    storage: &Storage,
+    id: &str,
+    new_parent: Option<&str>,
+    rename: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    let config = storage.config()?;
+    let mut tickets = storage.load_all(Scope::All)?;
+
+    let ticket_id = find_ticket(storage, "Ticket", id)?.id().to_string();
+    let parent_id = match new_parent {
+        Some(p) => Some(find_ticket(storage, "Parent", p)?.id().to_string()),
+        None => None,
+    };
+
+    // The new parent can't be the ticket itself or anything below it
+    if let Some(p) = &parent_id {
+        let view = storage.view()?;
+        if *p == ticket_id || graph::ancestors(&view, p).contains(&ticket_id) {
+            return Err(TkError::validation(format!(
+                "{} can't be moved under its own descendant {}",
+                ticket_id, p
+            ))
+            .into());
+        }
+    }
+
+    let index_of = |tickets: &[Ticket], id: &str| tickets.iter().position(|t| t.id() == id);
+    let old_parent_id = tickets[index_of(&tickets, &ticket_id).expect("resolved above")]
+        .meta
+        .parent
+        .clone();
+    if old_parent_id == parent_id {
+        return Err(TkError::validation(match &parent_id {
+            Some(p) => format!("{} is already a child of {}", ticket_id, p),
+            None => format!("{} is already a top-level ticket", ticket_id),
+        })
+        .into());
+    }
+
+    let mut changed: HashSet<usize> = HashSet::new();
+
+    // A parent that waited on this child hands the dep over to the new parent
+    let mut carry_dep = config.children_block_parent && parent_id.is_some();
+    if let Some(old) = old_parent_id.as_deref().and_then(|p| index_of(&tickets, p)) {
+        let deps = &mut tickets[old].meta.deps;
+        if let Some(pos) = deps.iter().position(|d| *d == ticket_id) {
+            deps.remove(pos);
+            carry_dep = parent_id.is_some();
+            changed.insert(old);
+        }
+    }
+    if carry_dep {
+        if let Some(new) = parent_id.as_deref().and_then(|p| index_of(&tickets, p)) {
+            if !tickets[new].meta.deps.contains(&ticket_id) {
+                tickets[new].meta.deps.push(ticket_id.clone());
+                changed.insert(new);
+            }
+        }
+    }
+
+    let i = index_of(&tickets, &ticket_id).expect("resolved above");
+    tickets[i].meta.parent = parent_id.clone();
+    changed.insert(i);
+
+    // Re-ID the ticket and every ticket in its ID namespace
+    let mut renamed: Vec<(String, String)> = Vec::new();
+    let mut superseded: Vec<Ticket> = Vec::new();
+    if rename {
+        let existing: Vec<String> = tickets.iter().map(|t| t.id().to_string()).collect();
+        let new_id = match &parent_id {
+            Some(p) => id::generate_child(p, &existing),
+            None => id::generate(&existing, config.id_scheme),
+        };
+        let namespace = format!("{}.", ticket_id);
+        for t in &tickets {
+            if t.id() == ticket_id {
+                renamed.push((ticket_id.clone(), new_id.clone()));
+            } else if let Some(suffix) = t.id().strip_prefix(&namespace) {
+                renamed.push((t.id().to_string(), format!("{}.{}", new_id, suffix)));
+            }
+        }
+
+        superseded = tickets
+            .iter()
+            .filter(|t| renamed.iter().any(|(from, _)| from == t.id()))
+            .cloned()
+            .collect();
+
+        let map: HashMap<&str, &str> = renamed
+            .iter()
+            .map(|(from, to)| (from.as_str(), to.as_str()))
+            .collect();
+        for (i, t) in tickets.iter_mut().enumerate() {
+            let mut touched = false;
+            if let Some(to) = map.get(t.id()) {
+                t.meta.id = to.to_string();
+                touched = true;
+            }
+            if let Some(to) = t.meta.parent.as_deref().and_then(|p| map.get(p)) {
+                t.meta.parent = Some(to.to_string());
+                touched = true;
+            }
+            for dep in t.meta.deps.iter_mut() {
+                if let Some(to) = map.get(dep.as_str()) {
+                    *dep = to.to_string();
+                    touched = true;
+                }
+            }
+            // Mentions in the body follow too, except in frozen tickets,
+            // which a stale mention shouldn't stop the move for
+            if touched || storage.can_change(t) {
+                if let Some(body) = id::rename_refs(&t.body, &map) {
+                    t.body = body;
+                    touched = true;
+                }
+            }
+            if touched {
+                changed.insert(i);
+            }
+        }
+    }
+
+    // Write renamed files before removing the old ones
+    let mut changed: Vec<usize> = changed.into_iter().collect();
+    changed.sort();
+    for &i in &changed {
+        storage.check_unfrozen(&tickets[i])?;
+    }
+    for &i in &changed {
+        tickets[i].touch();
+        let was = renamed
+            .iter()
+            .find(|(_, to)| to == tickets[i].id())
+            .and_then(|(from, _)| superseded.iter().find(|t| t.id() == from));
+        match was {
+            Some(old) => storage.replace(old, &tickets[i])?,
+            None => storage.save(&tickets[i])?,
+        }
+    }
+
+    let final_id = renamed
+        .iter()
+        .find(|(from, _)| *from == ticket_id)
+        .map_or(ticket_id.clone(), |(_, to)| to.clone());
+
+    if json {
+        output::print(&output::Adopted {
+            schema: output::SCHEMA_VERSION,
+            id: final_id,
+            parent: parent_id,
+            renamed: renamed
+                .into_iter()
+                .map(|(from, to)| output::Renamed { from, to })
+                .collect(),
+        })?;
+    } else {
+        let mut prose = match &parent_id {
+            Some(p) => format!("Moved {} under {}", ticket_id, p),
+            None => format!("Moved {} to the top level", ticket_id),
+        };
+        for (from, to) in &renamed {
+            prose.push_str(&format!("\n  Renamed {} -> {}", from, to));
+        }
+        output::changed(prose, &[&ticket_id, parent_id.as_deref().unwrap_or("")]);
+    }
+    Ok(())
+}
+
+fn cmd_ready(
+    storage: &Storage,
+    filter: &FilterArgs,
+    include_archived: bool,
+    sort: ReadySort,
+    by_due: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+    let filter = filter.build()?;
+
+    // Deps resolve against the whole store; only the listing is scoped
+    let tickets = storage.load_all(Scope::All)?;
+    let config = storage.config()?;
+    let ready = ready_tickets(&tickets, &config, &filter, include_archived);
+    let today = chrono::Local::now().date_naive();
+    let mut ranked: Vec<(&Ticket, Option<f64>)> = match sort {
+        ReadySort::Priority => ready.into_iter().map(|t| (t, None)).collect(),
+        ReadySort::Score => score::rank(ready, &config.score, today)
+            .into_iter()
+            .map(|(t, s)| (t, Some(s)))
+            .collect(),
+    };
+    // Critical tickets first (in each section), each group still in the
+    // order asked for
+    let critical = |t: &Ticket| config.escalate.is_critical(t.meta.priority);
+    ranked.sort_by_key(|(t, _)| !critical(t));
+    // Sections in deadline order, each still in the order asked for
+    let deadline = (by_due || config.ready.by_due)
+        .then_some(|t: &Ticket| t.deadline(today, config.ready.due_soon));
+    if let Some(deadline) = deadline {
+        ranked.sort_by_key(|(t, _)| deadline(t));
+    }
+
+    if json {
+        let items: Vec<_> = ranked
+            .iter()
+            .map(|(t, score)| output::TicketSummary {
+                score: *score,
+                deadline: deadline.map(|d| d(t).to_string()),
+                ..output::TicketSummary::from(*t)
+            })
+            .collect();
+        output::print(&items)?;
+    } else if ranked.is_empty() {
+        println!("No ready tickets.");
+    } else {
+        let mut section = None;
+        for (t, score) in ranked {
+            let mut line = match score {
+                Some(s) => format!("{} [P{}] {:>6.1} {}", t.id(), t.meta.priority, s, t.title),
+                None => format!("{} [P{}] {}", t.id(), t.meta.priority, t.title),
+            };
+            if let Some(deadline) = deadline {
+                let this = deadline(t);
+                if section != Some(this) {
+                    if section.is_some() {
+                        println!();
+                    }
+                    println!("{}:", this.heading());
+                    section = Some(this);
+                }
+                if let Some(due) = t.meta.due {
+                    line.push_str(&format!(" (due {})", due));
+                }
+                line.insert_str(0, "  ");
+            }
+            println!("{}", output::flag_critical(critical(t), line));
+        }
+    }
+    Ok(())
+}
+
+fn cmd_next(storage: &Storage, tag: Option<String>, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let tickets = storage.load_all(Scope::All)?;
+    let config = storage.config()?;
+    let ready = ready_tickets(&tickets, &config, &FilterSet::tagged(tag), false);
+    let today = chrono::Local::now().date_naive();
+    let (ticket, score) = score::rank(ready, &config.score, today)
+        .into_iter()
+        .next()
+        .ok_or_else(|| TkError::NotFound("No ready tickets".to_string()))?;
+
+    if json {
+        return output::print(&output::TicketSummary {
+            score: Some(score),
+            ..output::TicketSummary::from(ticket)
+        });
+    }
+    let line = format!(
+        "{} [P{}] {} (score {:.1})",
+        ticket.id(),
+        ticket.meta.priority,
+        ticket.title,
+        score
+    );
+    let critical = config.escalate.is_critical(ticket.meta.priority);
+    println!("{}", output::flag_critical(critical, line));
+    Ok(())
+}
+
+fn cmd_suggest(storage: &Storage, good_first: bool, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let tickets = storage.load_all(Scope::All)?;
+    let config = storage.config()?;
+    let rules = &config.suggest;
+    let candidates: Vec<&Ticket> = ready_tickets(&tickets, &config, &FilterSet::default(), false)
+        .into_iter()
+        .filter(|t| t.meta.assignee.is_none())
+        .filter(|t| {
+            !good_first
+                || (t.meta.tags.contains(&rules.good_first_tag)
+                    && t.meta.deps.is_empty()
+                    && t.meta.estimate.is_some_and(|e| e <= rules.max_estimate))
+        })
+        .collect();
+
+    if candidates.is_empty() {
+        return Err(TkError::NotFound(if good_first {
+            format!(
+                "No good first tickets: none are ready, unassigned, free of deps, tagged {} \
+                 and estimated at {} or less",
+                rules.good_first_tag, rules.max_estimate
+            )
+        } else {
+            "No ready, unassigned tickets to suggest".to_string()
+        })
+        .into());
+    }
+
+    let mut bytes = [0u8; 8];
+    getrandom::getrandom(&mut bytes).expect("failed to get random bytes");
+    let ticket = candidates[(u64::from_le_bytes(bytes) % candidates.len() as u64) as usize];
+    let description = match Storage::notes_section(&ticket.body) {
+        Some(notes) => &ticket.body[..ticket.body.len() - notes.len()],
+        None => &ticket.body,
+    };
+
+    if json {
+        return output::print(&output::Suggestion {
+            ticket: output::TicketRecord::from(ticket),
+            body: ticket.body.clone(),
+            candidates: candidates.len(),
+        });
+    }
+
+    println!("How about {} - {}?", ticket.id(), ticket.title);
+    let mut facts = vec![
+        ticket.meta.ticket_type.to_string(),
+        format!("P{}", ticket.meta.priority),
+    ];
+    if let Some(e) = ticket.meta.estimate {
+        facts.push(format!("estimate {}", e));
+    }
+    if !ticket.meta.tags.is_empty() {
+        facts.push(format!("tagged {}", ticket.meta.tags.join(", ")));
+    }
+    println!("  {}", facts.join(" · "));
+    let rendered = render_markdown(description);
+    if !rendered.is_empty() {
+        println!();
+        for line in rendered.lines() {
+            if line.is_empty() {
+                println!();
+            } else {
+                println!("  {}", line);
+            }
+        }
+    }
+    println!();
+    println!("Nothing blocks it. To take it: tk start {}", ticket.id());
+    if candidates.len() > 1 {
+        println!(
+            "Not this one? Run it again; {} others fit.",
+            candidates.len() - 1
+        );
+    }
+    Ok(())
+}
+
+/// Markdown made easier on the eye in a terminal: headings underlined,
+/// checklist boxes drawn, emphasis markers and link targets dropped
+fn render_markdown(text: &str) -> String {
+    let mut out = String::new();
+    let mut in_code = false;
+    for line in text.trim().lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            out.push_str(&format!("    {}\n", line));
+            continue;
+        }
+        let hashes = line.chars().take_while(|&c| c == '#').count();
+        if hashes > 0 && line[hashes..].starts_with(' ') {
+            let heading = line[hashes..].trim();
+            out.push_str(&format!(
+                "{}\n{}\n",
+                heading,
+                "-".repeat(heading.chars().count())
+            ));
+            continue;
+        }
+        let line = line
+            .replacen("- [ ] ", "☐ ", 1)
+            .replacen("- [x] ", "☑ ", 1)
+            .replacen("- [X] ", "☑ ", 1)
+            .replace("**", "")
+            .replace('`', "");
+        out.push_str(&strip_link_targets(&line));
+        out.push('\n');
+    }
+    out.trim_end().to_string()
+}
+
+/// `[text](url)` -> `text`
+fn strip_link_targets(line: &str) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(open) = rest.find('[') {
+        let Some(close) = rest[open..].find("](").map(|i| open + i) else {
+            break;
+        };
+        let Some(end) = rest[close..].find(')').map(|i| close + i) else {
+            break;
+        };
+        out.push_str(&rest[..open]);
+        out.push_str(&rest[open + 1..close]);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn cmd_claim(
+    storage: &Storage,
+    tag: Option<String>,
+    assignee: Option<String>,
+    lease: Option<String>,
+) -> Result<()> {
+    ensure_init(storage)?;
+    let lease = lease.as_deref().map(dates::lease).transpose()?;
+
+    let assignee = assignee.or_else(identity::current).ok_or_else(|| {
+        TkError::validation("Can't tell who you are; pass --assignee or set TK_AUTHOR")
+    })?;
+
+    // Held from choosing the ticket until it is saved, so two workers
+    // claiming at once get different tickets
+    let lock = storage.lock()?;
+    let tickets = storage.load_all(Scope::All)?;
+    let filter = FilterSet::tagged(tag.clone());
+    let mut ticket = ready_tickets(&tickets, &storage.config()?, &filter, false)
+        .into_iter()
+        .filter(|t| t.meta.status == Status::Open)
+        .find(|t| t.meta.assignee.as_ref().is_none_or(|a| *a == assignee))
+        .cloned()
+        .ok_or_else(|| {
+            TkError::NotFound(match &tag {
+                Some(tag) => format!("No ready ticket tagged {} to claim", tag),
+                None => "No ready ticket to claim".to_string(),
+            })
+        })?;
+
+    ticket.set_status(Status::InProgress);
+    if ticket.meta.assignee.is_none() {
+        ticket.set_assignee(Some(assignee));
+    }
+    ticket.meta.claimed_until = lease.map(|lease| Utc::now() + lease);
+    storage.save(&ticket)?;
+    file_by_status(storage, &mut ticket)?;
+    drop(lock);
+
+    fire_hooks(storage, &ticket, "status", None)?;
+    output::print(&output::Claimed {
+        ticket: output::TicketRecord::from(&ticket),
+        body: ticket.body.clone(),
+    })
+}
+
+fn cmd_reap(storage: &Storage, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    // Held so a ticket being claimed or renewed right now isn't reopened
+    // from a stale copy
+    let lock = storage.lock()?;
+    let now = Utc::now();
+    let mut reaped = Vec::new();
+    for mut ticket in storage.load_all(Scope::All)? {
+        let Some(until) = ticket
+            .meta
+            .claimed_until
+            .filter(|_| ticket.lease_expired(now))
+        else {
+            continue;
+        };
+        let holder = ticket.meta.assignee.clone();
+        let content = match &holder {
+            Some(holder) => format!("Lease held by {} expired; returned to open", holder),
+            None => "Lease expired; returned to open".to_string(),
+        };
+        ticket.set_status(Status::Open);
+        ticket.set_assignee(None);
+        ticket.add_note(&Note::new(content));
+        storage.save(&ticket)?;
+        file_by_status(storage, &mut ticket)?;
+        reaped.push((ticket, holder, until));
+    }
+    drop(lock);
+
+    for (ticket, _, _) in &reaped {
+        fire_hooks(storage, ticket, "status", None)?;
+    }
+
+    if json {
+        let entries: Vec<output::Reaped> = reaped
+            .iter()
+            .map(|(t, holder, until)| output::Reaped {
+                schema: output::SCHEMA_VERSION,
+                id: t.id().to_string(),
+                assignee: holder.clone(),
+                claimed_until: *until,
+            })
+            .collect();
+        return output::print(&entries);
+    }
+    for (ticket, holder, _) in &reaped {
+        let prose = match holder {
+            Some(holder) => format!("{} -> open (lease held by {} expired)", ticket.id(), holder),
+            None => format!("{} -> open (lease expired)", ticket.id()),
+        };
+        output::changed(prose, &[ticket.id(), holder.as_deref().unwrap_or("")]);
+    }
+    Ok(())
+}
+
+/// Open tickets with no open deps that pass the configured `ready` rules
+/// and `filter`, most urgent first
+fn ready_tickets<'a>(
+    tickets: &'a [Ticket],
+    config: &config::Config,
+    filter: &FilterSet,
+    include_archived: bool,
+) -> Vec<&'a Ticket> {
+    let today = chrono::Local::now().date_naive();
+
+    let mut ready: Vec<_> = tickets
+        .iter()
+        .filter(|t| include_archived || !t.archived)
+        .filter(|t| {
+            if !t.is_open() {
+                tracing::trace!("{} not ready: status is {}", t.id(), t.meta.status);
+            }
+            t.is_open() && !t.is_blocked_by(tickets)
+        })
+        .filter(|t| match unready_reason(t, tickets, &config.ready, today) {
+            Some(reason) => {
+                tracing::trace!("{} not ready: {}", t.id(), reason);
+                false
+            }
+            None => true,
+        })
+        .filter(|t| filter.matches(t))
+        .collect();
+
+    ready.sort_by_key(|t| t.meta.priority);
+    ready
+}
+
+/// Which of the configured `ready` conditions an open, unblocked ticket fails
+fn unready_reason(
+    ticket: &Ticket,
+    tickets: &[Ticket],
+    rules: &config::ReadyConfig,
+    today: chrono::NaiveDate,
+) -> Option<String> {
+    if ticket.meta.blocked {
+        return Some(match &ticket.meta.blocked_reason {
+            Some(reason) => format!("blocked: {}", reason),
+            None => "blocked".to_string(),
+        });
+    }
+    if rules.require_assignee && ticket.meta.assignee.is_none() {
+        return Some("no assignee".to_string());
+    }
+    if rules.require_estimate && ticket.meta.estimate.is_none() {
+        return Some("no estimate".to_string());
+    }
+    if rules.exclude_snoozed {
+        if let Some(until) = ticket.meta.snoozed_until.filter(|d| *d > today) {
+            return Some(format!("snoozed until {}", until));
+        }
+    }
+    if rules.require_parent_in_progress {
+        if let Some(parent) = &ticket.meta.parent {
+            let status = tickets
+                .iter()
+                .find(|t| t.id() == parent)
+                .map(|p| p.meta.status);
+            if status.is_some_and(|s| s != Status::InProgress) {
+                return Some(format!("parent {} is not in progress", parent));
+            }
+        }
+    }
+    None
+}
+
+fn cmd_snapshot(storage: &Storage, check: bool, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let config = storage.config()?;
+    // Deps resolve against the whole store; only live tickets are summarized
+    let tickets = storage.load_all(Scope::All)?;
+    let live: Vec<&Ticket> = tickets.iter().filter(|t| !t.archived).collect();
+    let ready = ready_tickets(&tickets, &config, &FilterSet::default(), false);
+    let blocked: Vec<snapshot::Blocked> = live
+        .iter()
+        .filter(|t| t.is_open() && (t.meta.blocked || t.is_blocked_by(&tickets)))
+        .map(|t| snapshot::Blocked {
+            ticket: t,
+            waiting_on: t
+                .meta
+                .deps
+                .iter()
+                .filter(|d| tickets.iter().any(|x| x.id() == *d && x.is_open()))
+                .cloned()
+                .collect(),
+        })
+        .collect();
+    let content = snapshot::render(&live, &ready, &blocked);
+
+    let path = storage.root_dir().join(snapshot::SUMMARY_FILE);
+    let changed = std::fs::read_to_string(&path).ok().as_deref() != Some(content.as_str());
+    if check && changed {
+        return Err(TkError::validation(format!(
+            "{} is out of date; run tk snapshot and commit it",
+            path.display()
+        ))
+        .into());
+    }
+    if changed && !check {
+        storage.save_root_file(snapshot::SUMMARY_FILE, &content)?;
+    }
+
+    let path = path.display().to_string();
+    if json {
+        output::print(&output::Snapshot {
+            schema: output::SCHEMA_VERSION,
+            path,
+            changed,
+        })?;
+    } else if check {
+        output::changed(format!("{} is up to date", path), &[&path]);
+    } else if changed {
+        output::changed(format!("Wrote {}", path), &[&path]);
+    } else {
+        output::changed(format!("{} is already up to date", path), &[&path]);
+    }
+    Ok(())
+}
+
+fn cmd_blocked(
+    storage: &Storage,
+    filter: &FilterArgs,
+    include_archived: bool,
+    external: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+    let filter = filter.build()?;
+
+    // Deps resolve against the whole store; only the listing is scoped
+    let view = storage.view()?;
+    let outside = |t: &Ticket| {
+        if external {
+            view.external_deps(t)
+                .iter()
+                .map(|d| d.to_string())
+                .collect()
+        } else {
+            Vec::new()
+        }
+    };
+    let manual = |t: &Ticket| external && t.meta.blocked;
+
+    let mut blocked: Vec<_> = view
+        .tickets()
+        .iter()
+        .filter(|t| include_archived || !t.archived)
+        .filter(|t| {
+            t.is_open() && !(view.open_deps(t).is_empty() && outside(t).is_empty() && !manual(t))
+        })
+        .filter(|t| filter.matches(t))
+        .collect();
+
+    blocked.sort_by_key(|t| t.meta.priority);
+
+    if json {
+        let items = blocked
+            .iter()
             .map(|t| {
-                serde_json::json!({
-                    "id": t.id(),
-                    "title": t.title,
-                    "status": t.meta.status.to_string(),
-                    "priority": t.meta.priority,
-                    "type": t.meta.ticket_type.to_string(),
+                Ok(output::BlockedTicket {
+                    ticket: output::TicketSummary::from(*t),
+                    blocked_by: view.open_deps(t).iter().map(|d| d.to_string()).collect(),
+                    blocks: storage.dependents(t.id())?,
+                    external: outside(t),
+                    manual: manual(t),
+                    reason: t.meta.blocked_reason.clone().filter(|_| manual(t)),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        output::print(&items)?;
+    } else if blocked.is_empty() {
+        println!("No blocked tickets.");
+    } else {
+        for t in blocked {
+            let mut reasons = Vec::new();
+            if manual(t) {
+                reasons.push(match &t.meta.blocked_reason {
+                    Some(reason) => format!("blocked: {}", reason),
+                    None => "blocked by hand".to_string(),
+                });
+            }
+            let open = view.open_deps(t);
+            if !open.is_empty() {
+                reasons.push(format!("blocked by: {}", open.join(", ")));
+            }
+            let outside = outside(t);
+            if !outside.is_empty() {
+                reasons.push(format!("not in this store: {}", outside.join(", ")));
+            }
+            let blocks = storage.dependents(t.id())?;
+            if !blocks.is_empty() {
+                reasons.push(format!("blocks: {}", blocks.join(", ")));
+            }
+            println!(
+                "{} [P{}] {} ({})",
+                t.id(),
+                t.meta.priority,
+                t.title,
+                reasons.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+fn cmd_why_blocked(storage: &Storage, id: &str, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let ticket = find_ticket(storage, "Ticket", id)?;
+    let view = storage.view()?;
+
+    let chains = graph::blocker_chains(&view, &ticket);
+
+    // A chain ending in a cycle has no leaf; its last ticket is still open
+    let mut leaves: Vec<&Ticket> = Vec::new();
+    for chain in &chains {
+        if let Some(leaf) = chain.last().and_then(|id| view.get(id)) {
+            if !leaves.iter().any(|l| l.id() == leaf.id()) {
+                leaves.push(leaf);
+            }
+        }
+    }
+    leaves.sort_by(|a, b| {
+        a.meta
+            .priority
+            .cmp(&b.meta.priority)
+            .then_with(|| a.id().cmp(b.id()))
+    });
+
+    if json {
+        output::print(&output::BlockerChains {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            chains,
+            leaves: leaves
+                .iter()
+                .map(|t| output::TicketSummary::from(*t))
+                .collect(),
+        })?;
+    } else if chains.is_empty() {
+        println!("{} is not blocked.", ticket.id());
+    } else {
+        println!("{} is blocked through:", ticket.id());
+        for chain in &chains {
+            println!("  {}", chain.join(" -> "));
+        }
+        println!();
+        println!("Do first:");
+        for t in leaves {
+            println!("  {} [P{}] {}", t.id(), t.meta.priority, t.title);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_plan(
+    storage: &Storage,
+    assignees: Option<String>,
+    parallel: Option<usize>,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    if parallel == Some(0) {
+        return Err(TkError::validation("--parallel must be at least 1").into());
+    }
+    let assignees: Vec<String> = assignees
+        .map(|a| {
+            a.split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let view = storage.view()?;
+    let plan = plan::plan(view.tickets(), parallel, &assignees);
+    let critical = graph::critical_path(&view);
+
+    if json {
+        return output::print(&output::Plan {
+            schema: output::SCHEMA_VERSION,
+            waves: plan
+                .waves
+                .iter()
+                .map(|wave| {
+                    wave.iter()
+                        .map(|p| output::PlanItem {
+                            id: p.ticket.id().to_string(),
+                            title: p.ticket.title.clone(),
+                            priority: p.ticket.meta.priority,
+                            status: p.ticket.meta.status.to_string(),
+                            assignee: p.assignee.clone(),
+                            after: p.after.clone(),
+                        })
+                        .collect()
+                })
+                .collect(),
+            unschedulable: plan
+                .unschedulable
+                .iter()
+                .map(|t| t.id().to_string())
+                .collect(),
+            critical_path: critical.iter().map(|t| t.id().to_string()).collect(),
+        });
+    }
+
+    if plan.waves.is_empty() && plan.unschedulable.is_empty() {
+        println!("No open tickets.");
+    }
+    for (i, wave) in plan.waves.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("Wave {}", i + 1);
+        for p in wave {
+            let who = p
+                .assignee
+                .as_ref()
+                .map(|a| format!(" ({})", a))
+                .unwrap_or_default();
+            let after = if p.after.is_empty() {
+                String::new()
+            } else {
+                format!(" after {}", p.after.join(", "))
+            };
+            println!(
+                "  {} [P{}] {}{}{}",
+                p.ticket.id(),
+                p.ticket.meta.priority,
+                p.ticket.title,
+                who,
+                after
+            );
+        }
+    }
+    // A single ticket is trivially its own longest chain
+    if critical.len() > 1 {
+        let ids: Vec<&str> = critical.iter().map(|t| t.id()).collect();
+        // Only real estimates add up; the weight of 1 an unestimated ticket
+        // gets for choosing the path isn't work anyone estimated
+        let total: u32 = critical.iter().filter_map(|t| t.meta.estimate).sum();
+        let unestimated = critical
+            .iter()
+            .filter(|t| t.meta.estimate.is_none())
+            .count();
+        let size = match unestimated {
+            0 => format!("total estimate {}", total),
+            n if n == critical.len() => "no estimates".to_string(),
+            n => format!("total estimate {}, {} unestimated", total, n),
+        };
+        println!();
+        println!("Critical path: {} ({})", ids.join(" -> "), size);
+    }
+    if !plan.unschedulable.is_empty() {
+        if !plan.waves.is_empty() {
+            println!();
+        }
+        println!("Unschedulable (dependency cycle or archived open dep):");
+        for t in &plan.unschedulable {
+            println!("  {} [P{}] {}", t.id(), t.meta.priority, t.title);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_plan_for(storage: &Storage, who: &str, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let tickets = storage.load_all(Scope::All)?;
+    let config = storage.config()?;
+    let ready = ready_tickets(&tickets, &config, &FilterSet::default(), false);
+    let queue = plan::queue(&tickets, &ready, who);
+
+    if json {
+        return output::print(&output::Queue {
+            schema: output::SCHEMA_VERSION,
+            assignee: who.to_string(),
+            queue: queue
+                .iter()
+                .map(|&(t, reason)| output::QueuedTicket {
+                    reason: reason.to_string(),
+                    ticket: output::TicketSummary::from(t),
+                })
+                .collect(),
+        });
+    }
+
+    if queue.is_empty() {
+        println!("Nothing queued for {}.", who);
+    }
+    let mut section = None;
+    for (t, reason) in queue {
+        if section != Some(reason) {
+            if section.is_some() {
+                println!();
+            }
+            println!("{}:", reason.heading());
+            section = Some(reason);
+        }
+        let tags = if reason == plan::Reason::Tagged {
+            format!(" [{}]", t.meta.tags.join(", "))
+        } else {
+            String::new()
+        };
+        println!("  {} [P{}] {}{}", t.id(), t.meta.priority, t.title, tags);
+    }
+    Ok(())
+}
+
+fn cmd_dep_cycle(storage: &Storage, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let view = storage.view()?;
+    let cycles = graph::cycles(&view);
+
+    if json {
+        let items: Vec<_> = cycles
+            .iter()
+            .map(|c| output::Cycle {
+                schema: output::SCHEMA_VERSION,
+                cycle: c.clone(),
+            })
+            .collect();
+        output::print(&items)?;
+    } else if cycles.is_empty() {
+        println!("No dependency cycles found.");
+    } else {
+        println!("Dependency cycles detected:");
+        for cycle in &cycles {
+            println!("  {} -> {}", cycle.join(" -> "), cycle[0]);
+        }
+    }
+
+    if !cycles.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn cmd_tree(
+    storage: &Storage,
+    id: Option<&str>,
+    full: bool,
+    include_archived: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    let view = storage.view()?;
+    let shown = |t: &Ticket| include_archived || !t.archived;
+    let tree = BlocksTree {
+        view: &view,
+        shown: &shown,
+        full,
+    };
+
+    if let Some(id) = id {
+        // Show tree for a single ticket (what it blocks)
+        let ticket = find_ticket(storage, "Ticket", id)?;
+
+        if json {
+            let mut node = tree.json(&ticket);
+            node.schema = Some(output::SCHEMA_VERSION);
+            output::print(&node)?;
+        } else {
+            println!("{}", tree.line(&ticket));
+            tree.print(&ticket, "");
+        }
+    } else {
+        // Show tree for root tickets (those with no deps)
+        let roots: Vec<_> = view
+            .tickets()
+            .iter()
+            .filter(|t| shown(t) && (full || t.is_open()))
+            .filter(|t| {
+                t.meta.deps.is_empty()
+                    || t.meta.deps.iter().all(|d| {
+                        view.get(d)
+                            .filter(|x| shown(x))
+                            .is_none_or(|x| !full && !x.is_open())
+                    })
+            })
+            .collect();
+
+        if json {
+            let trees: Vec<_> = roots
+                .iter()
+                .map(|t| {
+                    let mut node = tree.json(t);
+                    node.schema = Some(output::SCHEMA_VERSION);
+                    node
+                })
+                .collect();
+            output::print(&trees)?;
+        } else {
+            for ticket in roots {
+                println!("{}", tree.line(ticket));
+                tree.print(ticket, "");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `tk tree`: what each ticket blocks, found through the reverse-deps index
+struct BlocksTree<'a> {
+    view: &'a view::View,
+    /// Whether a ticket is in the listing's scope
+    shown: &'a dyn Fn(&Ticket) -> bool,
+    /// Include closed tickets
+    full: bool,
+}
+
+impl BlocksTree<'_> {
+    /// Tickets that depend on `ticket` and belong in the tree
+    fn blocked(&self, ticket: &Ticket) -> Vec<&Ticket> {
+        self.view
+            .dependents(ticket.id())
+            .into_iter()
+            .filter(|t| (self.shown)(t) && (self.full || t.is_open()))
+            .collect()
+    }
+
+    fn print(&self, ticket: &Ticket, prefix: &str) {
+        let blocked = self.blocked(ticket);
+        for (i, t) in blocked.iter().enumerate() {
+            let is_last = i == blocked.len() - 1;
+            let connector = if is_last { "└── " } else { "├── " };
+            println!("{}{}{}", prefix, connector, self.line(t));
+
+            let new_prefix = format!("{}{}   ", prefix, if is_last { " " } else { "│" });
+            self.print(t, &new_prefix);
+        }
+    }
+
+    /// `[ ] tk-a1b2 - Title`, with a parent's remaining estimate
+    fn line(&self, ticket: &Ticket) -> String {
+        let marker = if ticket.is_open() { " " } else { "x" };
+        let size = rollup_under(self.view, ticket)
+            .map(|sum| remaining(&sum))
+            .unwrap_or_default();
+        format!("[{}] {} - {}{}", marker, ticket.id(), ticket.title, size)
+    }
+
+    fn json(&self, ticket: &Ticket) -> output::TreeNode {
+        output::TreeNode {
+            schema: None,
+            id: ticket.id().to_string(),
+            title: ticket.title.clone(),
+            status: ticket.meta.status.to_string(),
+            blocks: self
+                .blocked(ticket)
+                .into_iter()
+                .map(|t| self.json(t))
+                .collect(),
+            rollup: rollup_under(self.view, ticket)
+                .as_ref()
+                .map(output::Rollup::from),
+        }
+    }
+}
+
+fn cmd_graph(
+    storage: &Storage,
+    full: bool,
+    include_archived: bool,
+    cluster_by: Option<dot::ClusterBy>,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    let tickets = storage.load_all(Scope::listing(include_archived))?;
+    let mut shown: Vec<&Ticket> = tickets.iter().filter(|t| full || t.is_open()).collect();
+    shown.sort_by_key(|t| t.id());
+
+    print!("{}", dot::render(&shown, cluster_by));
+    Ok(())
+}
+
+fn cmd_note(
+    storage: &Storage,
+    id: &str,
+    content: Option<String>,
+    editor: Option<String>,
+    author: Option<String>,
+    reply: Option<usize>,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    let author = match author {
+        Some(a) => Some(a.trim().to_string()),
+        None => identity::current(),
+    };
+    // The author sits inside the note's "[date author]" prefix
+    if let Some(a) = &author {
+        if a.is_empty() || a.contains([']', '\n']) {
+            return Err(TkError::validation(format!("Invalid note author: {:?}", a)).into());
+        }
+    }
+
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+
+    let number = Storage::parse_notes(&ticket.body).len() + 1;
+    if let Some(n) = reply {
+        if n == 0 || n >= number {
+            return Err(TkError::not_found("Note", &n.to_string()).into());
+        }
+    }
+
+    let content = if let Some(c) = content {
+        c
+    } else {
+        let editor = editor::resolve(editor, &storage.config()?);
+        let name = format!("note-{}", ticket.id());
+        let content = editor::compose(&editor, &name, "")?.trim().to_string();
+
+        if content.is_empty() {
+            return Err(TkError::validation("Empty note, aborting").into());
+        }
+        content
+    };
+
+    let note = Note {
+        author,
+        reply_to: reply,
+        ..Note::new(content)
+    };
+    ticket.add_note(&note);
+    storage.save(&ticket)?;
+    fire_hooks(storage, &ticket, "note", Some(&note.content))?;
+
+    if json {
+        output::print(&output::NoteAdded {
+            schema: output::SCHEMA_VERSION,
+            added: ticket.id().to_string(),
+            author: note.author,
+            number,
+            reply_to: reply,
+        })?;
+    } else {
+        let prose = match reply {
+            Some(n) => format!("Replied to note {} on {}", n, ticket.id()),
+            None => format!("Added note to {}", ticket.id()),
+        };
+        output::changed(prose, &[ticket.id(), &number.to_string()]);
+    }
+    Ok(())
+}
+
+fn cmd_notes(storage: &Storage, id: &str, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let ticket = find_ticket(storage, "Ticket", id)?;
+    let notes = Storage::parse_notes(&ticket.body);
+
+    if json {
+        output::print(&output::NoteEntry::list(notes))?;
+    } else if notes.is_empty() {
+        println!("No notes.");
+    } else {
+        print!("{}", note_thread(&notes));
+    }
+    Ok(())
+}
+
+/// Notes numbered for `note --reply`, each reply indented under the note it
+/// answers. Replies to a missing or later note are shown at the top level.
+fn note_thread(notes: &[Note]) -> String {
+    let parents: Vec<Option<usize>> = notes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| n.reply_to.filter(|&r| r >= 1 && r <= i).map(|r| r - 1))
+        .collect();
+    let mut out = String::new();
+    for root in (0..notes.len()).filter(|&i| parents[i].is_none()) {
+        write_note(&mut out, notes, &parents, root, 0);
+    }
+    out
+}
+
+fn write_note(out: &mut String, notes: &[Note], parents: &[Option<usize>], i: usize, depth: usize) {
+    let indent = "  ".repeat(depth);
+    let mut lines = notes[i].content.lines();
+    let first = lines.next().unwrap_or_default();
+    out.push_str(&format!(
+        "{}#{} {}{}\n",
+        indent,
+        i + 1,
+        notes[i].header(),
+        first
+    ));
+    for line in lines {
+        if !line.is_empty() {
+            out.push_str(&indent);
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    for child in (i + 1..notes.len()).filter(|&c| parents[c] == Some(i)) {
+        write_note(out, notes, parents, child, depth + 1);
+    }
+}
+
+fn cmd_section(
+    storage: &Storage,
+    id: &str,
+    action: SectionAction,
+    heading: &str,
+    text: Option<String>,
+    json: bool,
+) -> Result<()> {
+    use std::io::{IsTerminal, Read};
+
+    ensure_init(storage)?;
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+
+    if action == SectionAction::Get {
+        if text.is_some() {
+            return Err(TkError::validation("get takes no text").into());
+        }
+        let content = sections::content(&ticket.body, heading).ok_or_else(|| {
+            TkError::NotFound(format!("{} has no '{}' section", ticket.id(), heading))
+        })?;
+        if json {
+            return output::print(&output::Section {
+                schema: output::SCHEMA_VERSION,
+                id: ticket.id().to_string(),
+                heading: heading.to_string(),
+                content,
+            });
+        }
+        if !content.is_empty() {
+            println!("{}", content);
+        }
+        return Ok(());
+    }
+
+    let text = match text {
+        Some(text) => text,
+        None if std::io::stdin().is_terminal() => {
+            return Err(TkError::validation("Pass the text, or pipe it on stdin").into());
+        }
+        None => {
+            let mut buf = String::new();
+            std::io::stdin().read_to_string(&mut buf)?;
+            buf
+        }
+    };
+    ticket.body = match action {
+        SectionAction::Set => sections::set(&ticket.body, heading, &text),
+        _ => sections::append(&ticket.body, heading, &text),
+    };
+    ticket.touch();
+    storage.save(&ticket)?;
+    fire_hooks(storage, &ticket, "edited", Some(&ticket.body))?;
+
+    let content = sections::content(&ticket.body, heading).unwrap_or_default();
+    if json {
+        output::print(&output::Section {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            heading: heading.to_string(),
+            content,
+        })?;
+    } else {
+        output::changed(
+            format!("Updated '{}' in {}", heading, ticket.id()),
+            &[ticket.id(), heading],
+        );
+    }
+    Ok(())
+}
+
+fn cmd_summarize(storage: &Storage, id: &str, save: bool, json: bool) -> Result<()> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    ensure_init(storage)?;
+
+    let command = storage.config()?.summarize.map(|s| s.command).ok_or_else(|| {
+        TkError::validation(
+            "No summarizer configured. Add to .tickets/config.yaml:\n\n  summarize:\n    command: <shell command reading the ticket on stdin>",
+        )
+    })?;
+
+    let mut ticket = find_ticket(storage, "Ticket", id)?;
+
+    let input = format!("# {}\n\n{}\n", ticket.title, ticket.body);
+
+    tracing::debug!("running summarizer: {}", command);
+    let mut child = shell_command(&command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run summarizer: {}", command))?;
+    // Write from a thread so a summarizer that streams output before reading
+    // all of stdin can't deadlock on a full pipe
+    let mut stdin = child
+        .stdin
+        .take()
+        .context("Failed to open summarizer stdin")?;
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    writer.join().expect("summarizer stdin writer panicked")?;
+
+    if !output.status.success() {
+        anyhow::bail!("Summarizer exited with {}", output.status);
+    }
+    let summary = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if summary.is_empty() {
+        anyhow::bail!("Summarizer produced no output");
+    }
+
+    if save {
+        ticket.add_note(&Note::new(format!("Summary: {}", summary)));
+        storage.save(&ticket)?;
+    }
+
+    if json {
+        output::print(&output::Summary {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            summary,
+            saved: save,
+        })?;
+    } else {
+        println!("{}", summary);
+    }
+    Ok(())
+}
+
+/// Run the `hooks.on_change` commands for a change just saved. `text` is the
+/// new prose, if any, to find `@mentions` in.
+fn fire_hooks(storage: &Storage, ticket: &Ticket, action: &str, text: Option<&str>) -> Result<()> {
+    let commands = storage.config()?.hooks.on_change;
+    if commands.is_empty() {
+        return Ok(());
+    }
+    let payload = hooks::payload(ticket, action, text);
+    for command in &commands {
+        if storage.is_dry_run() {
+            eprintln!("Would run hook: {}", command);
+        } else {
+            hooks::run(shell_command(command), &payload);
+        }
+    }
+    Ok(())
+}
+
+/// Run the `escalate` commands and webhooks if `ticket` just became
+/// critical: created at the configured priority or above, or raised to it
+/// from below. `from` is the priority before the change.
+fn escalate(storage: &Storage, ticket: &Ticket, from: Option<u8>) -> Result<()> {
+    let rule = storage.config()?.escalate;
+    let critical = |p: u8| rule.is_critical(p);
+    if !critical(ticket.meta.priority) || from.is_some_and(critical) {
+        return Ok(());
+    }
+    if rule.run.is_empty() && rule.webhooks.is_empty() {
+        return Ok(());
+    }
+    tracing::info!("escalating {} at P{}", ticket.id(), ticket.meta.priority);
+    let payload = hooks::payload(ticket, "escalated", None);
+    for command in &rule.run {
+        if storage.is_dry_run() {
+            eprintln!("Would run escalation: {}", command);
+        } else {
+            hooks::run(shell_command(command), &payload);
+        }
+    }
+    for url in &rule.webhooks {
+        if storage.is_dry_run() {
+            eprintln!("Would POST escalation to {}", url);
+        } else {
+            hooks::post(url, &payload);
+        }
+    }
+    Ok(())
+}
+
+/// Run a configured command line through the platform shell
+fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.args(["/C", command]);
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.args(["-c", command]);
+        c
+    }
+}
+
+fn cmd_count(
+    storage: &Storage,
+    filter: &FilterArgs,
+    by: Option<CountBy>,
+    include_archived: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+    let filter = filter.build()?;
+
+    let view = storage.snapshot(Scope::listing(include_archived))?;
+    // Only tickets with the first -t tag can match, so start from those
+    let candidates = match filter.first_tag() {
+        Some(tag) => view.tagged(tag),
+        None => view.tickets().iter().collect(),
+    };
+    let matching: Vec<_> = candidates
+        .into_iter()
+        .filter(|t| filter.matches(t))
+        .collect();
+
+    let groups = by.map(|by| {
+        let mut groups: std::collections::BTreeMap<String, usize> = Default::default();
+        for t in &matching {
+            let keys = match by {
+                CountBy::Status => vec![t.meta.status.to_string()],
+                CountBy::Type => vec![t.meta.ticket_type.to_string()],
+                CountBy::Tag => t.meta.tags.clone(),
+                CountBy::Priority => vec![t.meta.priority.to_string()],
+            };
+            for key in keys {
+                *groups.entry(key).or_default() += 1;
+            }
+        }
+        groups
+    });
+
+    if json {
+        output::print(&output::Counts {
+            schema: output::SCHEMA_VERSION,
+            total: matching.len(),
+            by: groups,
+        })?;
+    } else if let Some(groups) = groups {
+        for (key, count) in groups {
+            println!("{}\t{}", key, count);
+        }
+    } else {
+        println!("{}", matching.len());
+    }
+    Ok(())
+}
+
+fn cmd_tags(
+    storage: &Storage,
+    suggest_merges: bool,
+    include_archived: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    let tickets = storage.load_all(Scope::listing(include_archived))?;
+    let counts = tags::counts(&tickets);
+
+    if suggest_merges {
+        let merges = tags::suggest_merges(&counts);
+        if json {
+            let items: Vec<_> = merges
+                .into_iter()
+                .map(|(into, from)| output::TagMerge {
+                    schema: output::SCHEMA_VERSION,
+                    into,
+                    from,
+                })
+                .collect();
+            output::print(&items)?;
+        } else if merges.is_empty() {
+            println!("No near-duplicate tags.");
+        } else {
+            for (into, from) in merges {
+                let from: Vec<_> = from
+                    .iter()
+                    .map(|t| format!("{} ({})", t, counts[t]))
+                    .collect();
+                println!("{} -> {} ({})", from.join(", "), into, counts[&into]);
+            }
+        }
+        return Ok(());
+    }
+
+    let mut sorted: Vec<_> = counts.into_iter().collect();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    if json {
+        let items: Vec<_> = sorted
+            .into_iter()
+            .map(|(tag, count)| output::TagCount {
+                schema: output::SCHEMA_VERSION,
+                tag,
+                count,
+            })
+            .collect();
+        output::print(&items)?;
+    } else if sorted.is_empty() {
+        println!("No tags.");
+    } else {
+        for (tag, count) in sorted {
+            println!("{}\t{}", tag, count);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_query(storage: &Storage, filter: Option<String>, include_archived: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let tickets = storage.load_all(Scope::listing(include_archived))?;
+
+    let items: Vec<_> = tickets.iter().map(output::TicketRecord::from).collect();
+
+    if let Some(filter) = filter {
+        // Pipe through jq if filter provided
+        tracing::debug!("running jq {:?}", filter);
+        let mut child = Command::new("jq")
+            .arg(&filter)
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .context("Failed to run jq. Is it installed?")?;
+
+        if let Some(stdin) = child.stdin.as_mut() {
+            use std::io::Write;
+            stdin.write_all(serde_json::to_string(&items)?.as_bytes())?;
+        }
+
+        child.wait()?;
+    } else {
+        output::print(&items)?;
+    }
+
+    Ok(())
+}
+
+fn cmd_find(storage: &Storage, external: &str, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let external: ExternalRef = external.parse()?;
+    let ticket = storage
+        .find_by_external(&external.system, &external.id, Scope::All)?
+        .ok_or_else(|| TkError::NotFound(format!("No ticket mapped to {}", external)))?;
+
+    if json {
+        output::print(&output::Found {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            title: ticket.title.clone(),
+            status: ticket.meta.status.to_string(),
+            external: ticket.meta.external.clone(),
+        })?;
+    } else {
+        println!("{} [{}] {}", ticket.id(), ticket.meta.status, ticket.title);
+    }
+    Ok(())
+}
+
+fn cmd_search(
+    storage: &Storage,
+    similar: &str,
+    limit: usize,
+    filter: &FilterArgs,
+    scope: Scope,
+    format: Format,
+) -> Result<()> {
+    ensure_init(storage)?;
+    let filter = filter.build()?;
+
+    // A single word that resolves to a ticket means "like this ticket";
+    // anything else is free text
+    let reference = if similar.split_whitespace().count() == 1 {
+        match storage.find_by_prefix(similar, Scope::All)?.as_slice() {
+            [ticket] => Some(ticket.clone()),
+            _ => None,
+        }
+    } else {
+        None
+    };
+    let query = match &reference {
+        Some(t) => format!("{} {}", t.title, t.body),
+        None => similar.to_string(),
+    };
+
+    let tickets: Vec<Ticket> = storage
+        .load_all(scope)?
+        .into_iter()
+        .filter(|t| reference.as_ref().is_none_or(|r| r.id() != t.id()))
+        .filter(|t| filter.matches(t))
+        .collect();
+    // Count the title twice: it's the densest summary of a ticket
+    let documents: Vec<String> = tickets
+        .iter()
+        .map(|t| format!("{} {} {}", t.title, t.title, t.body))
+        .collect();
+
+    let hits: Vec<(&Ticket, f64)> = similarity::rank(&documents, &query)
+        .into_iter()
+        .take(limit)
+        .map(|(i, score)| (&tickets[i], score))
+        .collect();
+
+    if format == Format::Json {
+        let hits: Vec<output::SearchHit> = hits
+            .iter()
+            .map(|(t, score)| output::SearchHit {
+                schema: output::SCHEMA_VERSION,
+                id: t.id().to_string(),
+                title: t.title.clone(),
+                status: t.meta.status.to_string(),
+                score: *score,
+            })
+            .collect();
+        return output::print(&hits);
+    }
+
+    for (t, score) in hits {
+        let line = format!("{} {:.2} [{}] {}", t.id(), score, t.meta.status, t.title);
+        if format == Format::Vimgrep {
+            println!("{}", vimgrep_line(storage, t.id(), None, &line));
+        } else {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_browse(storage: &Storage, id: &str, n: Option<usize>, all: bool, json: bool) -> Result<()> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    ensure_init(storage)?;
+
+    let ticket = find_ticket(storage, "Ticket", id)?;
+
+    let links = &ticket.meta.links;
+    if links.is_empty() {
+        anyhow::bail!("{} has no links", ticket.id());
+    }
+
+    let selected: Vec<&String> = if all {
+        links.iter().collect()
+    } else if let Some(n) = n {
+        let link = n
+            .checked_sub(1)
+            .and_then(|i| links.get(i))
+            .context(format!("No link {} (ticket has {})", n, links.len()))?;
+        vec![link]
+    } else if links.len() == 1 {
+        vec![&links[0]]
+    } else {
+        for (i, link) in links.iter().enumerate() {
+            eprintln!("{}) {}", i + 1, link);
+        }
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!("Multiple links; pass a number or --all");
+        }
+        eprint!("Open which? [1-{}, a=all] ", links.len());
+        std::io::stderr().flush()?;
+
+        let mut answer = String::new();
+        std::io::stdin().lock().read_line(&mut answer)?;
+        let answer = answer.trim();
+        if answer == "a" {
+            links.iter().collect()
+        } else {
+            let i: usize = answer.parse().context("Expected a number")?;
+            vec![links
+                .get(i.wrapping_sub(1))
+                .context(format!("No link {}", i))?]
+        }
+    };
+
+    for link in &selected {
+        open_in_browser(link)?;
+    }
+
+    if json {
+        output::print(&output::Browsed {
+            schema: output::SCHEMA_VERSION,
+            id: ticket.id().to_string(),
+            opened: selected.iter().map(|l| l.to_string()).collect(),
+        })?;
+    } else {
+        for link in &selected {
+            println!("Opened {}", link);
+        }
+    }
+    Ok(())
+}
+
+/// Open a URL with $BROWSER or the platform's default handler
+fn open_in_browser(url: &str) -> Result<()> {
+    tracing::debug!("opening {}", url);
+    let status = if let Ok(browser) = std::env::var("BROWSER") {
+        Command::new(browser).arg(url).status()
+    } else if cfg!(target_os = "macos") {
+        Command::new("open").arg(url).status()
+    } else if cfg!(windows) {
+        Command::new("cmd").args(["/C", "start", "", url]).status()
+    } else {
+        Command::new("xdg-open").arg(url).status()
+    }
+    .context("Failed to launch browser")?;
+
+    if !status.success() {
+        anyhow::bail!("Browser exited with error opening {}", url);
+    }
+    Ok(())
+}
+
+fn cmd_stats_quality(
+    storage: &Storage,
+    min: usize,
+    include_archived: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    let tickets = storage.load_all(Scope::listing(include_archived))?;
+    let rate = stats::reopen_rate(&tickets);
+    let flapping = stats::flapping(&tickets, min);
+
+    if json {
+        output::print(&output::QualityReport {
+            schema: output::SCHEMA_VERSION,
+            reopen_rate: rate,
+            flapping: flapping
+                .iter()
+                .map(|t| output::Flapping {
+                    id: t.id().to_string(),
+                    title: t.title.clone(),
+                    status: t.meta.status.to_string(),
+                    reopened_count: t.meta.reopened.len(),
+                    reopened: t.meta.reopened.clone(),
+                })
+                .collect(),
+        })?;
+        return Ok(());
+    }
+
+    println!("Reopen rate: {:.0}% of closed tickets", rate * 100.0);
+    if flapping.is_empty() {
+        println!("No tickets reopened {} or more times.", min);
+    } else {
+        println!();
+        for t in flapping {
+            let last = t
+                .meta
+                .reopened
+                .last()
+                .map(|d| d.format("%Y-%m-%d").to_string())
+                .unwrap_or_default();
+            println!(
+                "{} reopened {}x (last {}) [{}] {}",
+                t.id(),
+                t.meta.reopened.len(),
+                last,
+                t.meta.status,
+                t.title
+            );
+        }
+    }
+    Ok(())
+}
+
+fn cmd_stats_aging_wip(
+    storage: &Storage,
+    warn: u32,
+    stale: u32,
+    include_archived: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+    if warn > stale {
+        return Err(TkError::validation(format!(
+            "--warn {} is past --stale {}; nothing would warn",
+            warn, stale
+        ))
+        .into());
+    }
+
+    let tickets = storage.load_all(Scope::listing(include_archived))?;
+    let now = Utc::now();
+    let wip = stats::aging_wip(&tickets, now);
+    let level = |age: chrono::Duration| match age.num_days() {
+        d if d >= i64::from(stale) => "stale",
+        d if d >= i64::from(warn) => "warn",
+        _ => "ok",
+    };
+
+    if json {
+        let rows: Vec<output::AgingWip> = wip
+            .iter()
+            .map(|(t, since)| output::AgingWip {
+                id: t.id().to_string(),
+                title: t.title.clone(),
+                assignee: t.meta.assignee.clone(),
+                since: *since,
+                days: (now - *since).num_minutes() as f64 / 1440.0,
+                level: level(now - *since).to_string(),
+            })
+            .collect();
+        output::print(&rows)?;
+        return Ok(());
+    }
+
+    if wip.is_empty() {
+        println!("Nothing in progress.");
+        return Ok(());
+    }
+    for (t, since) in &wip {
+        let age = now - *since;
+        let line = format!(
+            "{:>8}  {}  {}{}",
+            stats::format_duration(age),
+            t.id(),
+            t.title,
+            t.meta
+                .assignee
+                .as_ref()
+                .map(|a| format!(" @{}", a))
+                .unwrap_or_default()
+        );
+        println!(
+            "{}",
+            match level(age) {
+                "stale" => output::paint(31, format!("{}  (stalled)", line)),
+                "warn" => output::paint(33, line),
+                _ => line,
+            }
+        );
+    }
+    Ok(())
+}
+
+fn cmd_stats_time_in_status(
+    storage: &Storage,
+    id: Option<&str>,
+    since: Option<chrono::NaiveDate>,
+    include_archived: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    let tickets = match id {
+        Some(id) => vec![find_ticket(storage, "Ticket", id)?],
+        None => storage.load_all(Scope::listing(include_archived))?,
+    };
+    let now = Utc::now();
+    let from = since.and_then(|d| {
+        d.and_time(chrono::NaiveTime::MIN)
+            .and_local_timezone(chrono::Local)
+            .earliest()
+    });
+    let mut spans: Vec<stats::Span> = tickets.iter().flat_map(|t| stats::spans(t, now)).collect();
+    if let Some(from) = from {
+        spans.retain(|s| s.end > from);
+        for span in &mut spans {
+            span.start = span.start.max(from.with_timezone(&Utc));
+        }
+    }
+    let states = stats::time_in_status(&spans);
+
+    let hours = |d: chrono::Duration| d.num_minutes() as f64 / 60.0;
+    if json {
+        output::print(&output::TimeInStatus {
+            schema: output::SCHEMA_VERSION,
+            states: states
+                .iter()
+                .map(|s| output::StateTime {
+                    state: s.state.to_string(),
+                    spans: s.spans,
+                    tickets: s.tickets,
+                    total_hours: hours(s.total),
+                    mean_hours: hours(s.mean),
+                    median_hours: hours(s.median),
+                    longest: s.longest.map(|l| l.ticket.id().to_string()),
                 })
+                .collect(),
+            spans: id.map(|_| {
+                spans
+                    .iter()
+                    .map(|s| output::StateSpan {
+                        state: s.state.to_string(),
+                        start: s.start,
+                        end: (!s.ongoing).then_some(s.end),
+                        hours: hours(s.duration()),
+                    })
+                    .collect()
+            }),
+        })?;
+        return Ok(());
+    }
+
+    if states.is_empty() {
+        println!("No time spent open, in progress or in review.");
+        return Ok(());
+    }
+    if let (Some(_), Some(t)) = (id, tickets.first()) {
+        println!("{} {}", t.id(), t.title);
+        for s in &spans {
+            println!(
+                "  {:<11}  {}  {:>8}{}",
+                s.state,
+                s.start
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M"),
+                stats::format_duration(s.duration()),
+                if s.ongoing { "  (so far)" } else { "" }
+            );
+        }
+        println!();
+    }
+    println!(
+        "{:<11}  {:>7}  {:>8}  {:>8}  {:>8}  LONGEST",
+        "STATE", "TICKETS", "MEAN", "MEDIAN", "TOTAL"
+    );
+    for s in &states {
+        println!(
+            "{:<11}  {:>7}  {:>8}  {:>8}  {:>8}  {}",
+            s.state,
+            s.tickets,
+            stats::format_duration(s.mean),
+            stats::format_duration(s.median),
+            stats::format_duration(s.total),
+            s.longest.map_or("", |l| l.ticket.id())
+        );
+    }
+    if id.is_none() {
+        if let Some(slowest) = states.iter().max_by_key(|s| s.mean) {
+            println!();
+            println!(
+                "Slowest: {} takes {} on average",
+                slowest.state,
+                stats::format_duration(slowest.mean)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn cmd_stats_remaining(
+    storage: &Storage,
+    milestone: Option<&str>,
+    include_archived: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    let tickets = storage.load_all(Scope::listing(include_archived))?;
+    // By milestone name, tickets without one last
+    let mut groups: BTreeMap<(bool, Option<&str>), Vec<&Ticket>> = BTreeMap::new();
+    for t in &tickets {
+        let m = t.meta.milestone.as_deref();
+        if milestone.is_none_or(|want| m == Some(want)) {
+            groups.entry((m.is_none(), m)).or_default().push(t);
+        }
+    }
+    if let (Some(want), true) = (milestone, groups.is_empty()) {
+        return Err(TkError::NotFound(format!("No tickets in milestone {}", want)).into());
+    }
+    let rows: Vec<(Option<&str>, graph::Rollup)> = groups
+        .into_iter()
+        .map(|((_, m), of)| (m, graph::rollup(&of)))
+        .collect();
+
+    if json {
+        let rows: Vec<_> = rows
+            .iter()
+            .map(|(m, sum)| output::Remaining {
+                milestone: m.map(str::to_string),
+                tickets: sum.tickets,
+                open: sum.open,
+                estimate: sum.estimate,
+                remaining: sum.remaining,
+                unestimated: sum.unestimated,
             })
             .collect();
-        println!("{}", serde_json::to_string(&items)?);
-    } else if filtered.is_empty() {
-        println!("No tickets found.");
-    } else {
-        for t in filtered {
-            let marker = match t.meta.status {
-                Status::Open => " ",
-                Status::Closed => "x",
+        return output::print(&rows);
+    }
+
+    if rows.is_empty() {
+        println!("No tickets.");
+        return Ok(());
+    }
+    println!(
+        "{:<16} {:>9} {:>9} {:>8} {:>12}",
+        "MILESTONE", "REMAINING", "ESTIMATE", "OPEN/ALL", "UNESTIMATED"
+    );
+    for (m, sum) in rows {
+        println!(
+            "{:<16} {:>9} {:>9} {:>8} {:>12}",
+            m.unwrap_or("(none)"),
+            sum.remaining,
+            sum.estimate,
+            format!("{}/{}", sum.open, sum.tickets),
+            sum.unestimated
+        );
+    }
+    Ok(())
+}
+
+fn cmd_stats_throughput(
+    storage: &Storage,
+    by: stats::Interval,
+    ticket_type: Option<&str>,
+    since: Option<chrono::NaiveDate>,
+    csv: bool,
+    json: bool,
+) -> Result<()> {
+    ensure_init(storage)?;
+
+    let ticket_type: Option<TicketType> = ticket_type.map(str::parse).transpose()?;
+    // Archived tickets too: closed tickets end up there, and leaving them
+    // out would undercount every interval but the latest
+    let mut tickets = storage.load_all(Scope::All)?;
+    tickets.retain(|t| ticket_type.is_none_or(|ty| t.meta.ticket_type == ty));
+    let today = chrono::Local::now().date_naive();
+    let from = since.unwrap_or_else(|| by.back(by.start(today), 11));
+    let intervals = stats::throughput(&tickets, by, from, today);
+
+    // Types with any tickets in the report, alphabetically
+    let mut types: Vec<TicketType> = Vec::new();
+    for t in intervals
+        .iter()
+        .flat_map(|i| i.created.iter().chain(&i.closed))
+    {
+        if !types.contains(&t.meta.ticket_type) {
+            types.push(t.meta.ticket_type);
+        }
+    }
+    types.sort_by_key(|ty| ty.to_string());
+    let count =
+        |of: &[&Ticket], ty: TicketType| of.iter().filter(|t| t.meta.ticket_type == ty).count();
+
+    if json {
+        output::print(&output::Throughput {
+            schema: output::SCHEMA_VERSION,
+            by: by.to_string(),
+            ticket_type: ticket_type.map(|ty| ty.to_string()),
+            intervals: intervals
+                .iter()
+                .map(|i| output::ThroughputInterval {
+                    start: i.start,
+                    label: by.label(i.start),
+                    created: i.created.len(),
+                    closed: i.closed.len(),
+                    by_type: types
+                        .iter()
+                        .map(|&ty| output::TypeThroughput {
+                            ticket_type: ty.to_string(),
+                            created: count(&i.created, ty),
+                            closed: count(&i.closed, ty),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        })?;
+        return Ok(());
+    }
+
+    if csv {
+        let mut header = vec![by.to_string(), "created".to_string(), "closed".to_string()];
+        for ty in &types {
+            header.push(format!("{}_created", ty));
+            header.push(format!("{}_closed", ty));
+        }
+        println!("{}", header.join(","));
+        for i in &intervals {
+            let mut row = vec![
+                by.label(i.start),
+                i.created.len().to_string(),
+                i.closed.len().to_string(),
+            ];
+            for &ty in &types {
+                row.push(count(&i.created, ty).to_string());
+                row.push(count(&i.closed, ty).to_string());
+            }
+            println!("{}", row.join(","));
+        }
+        return Ok(());
+    }
+
+    let heading = by.to_string().to_uppercase();
+    println!("{:<10}  {:>7}  {:>6}", heading, "CREATED", "CLOSED");
+    for i in &intervals {
+        println!(
+            "{:<10}  {:>7}  {:>6}",
+            by.label(i.start),
+            i.created.len(),
+            i.closed.len()
+        );
+    }
+    let created: Vec<usize> = intervals.iter().map(|i| i.created.len()).collect();
+    let closed: Vec<usize> = intervals.iter().map(|i| i.closed.len()).collect();
+    let max = created.iter().chain(&closed).copied().max().unwrap_or(0);
+    println!();
+    println!("created  {}", stats::sparkline(&created, max));
+    println!("closed   {}", stats::sparkline(&closed, max));
+
+    if ticket_type.is_none() && !types.is_empty() {
+        println!();
+        println!("{:<10}  {:>7}  {:>6}", "TYPE", "CREATED", "CLOSED");
+        for &ty in &types {
+            let created: usize = intervals.iter().map(|i| count(&i.created, ty)).sum();
+            let closed: usize = intervals.iter().map(|i| count(&i.closed, ty)).sum();
+            println!("{:<10}  {:>7}  {:>6}", ty.to_string(), created, closed);
+        }
+    }
+    Ok(())
+}
+
+/// Files `export ndjson` writes: live tickets by status, then the archive
+const EXPORT_PARTITIONS: [&str; 4] = ["open", "in-progress", "closed", "archived"];
+
+fn cmd_export_ndjson(storage: &Storage, out: &Path, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let mut tickets = storage.load_all(Scope::All)?;
+    tickets.sort_by(|a, b| a.id().cmp(b.id()));
+
+    let mut files = Vec::new();
+    for partition in EXPORT_PARTITIONS {
+        let mut content = String::new();
+        let mut count = 0;
+        for t in &tickets {
+            let part = if t.archived {
+                "archived".to_string()
+            } else {
+                t.meta.status.to_string()
+            };
+            if part != partition {
+                continue;
+            }
+            let record = output::ExportedTicket {
+                ticket: output::TicketRecord::from(t),
+                body: t.body.clone(),
+                notes: output::NoteEntry::list(Storage::parse_notes(&t.body)),
             };
-            println!("[{}] {} [P{}] {}", marker, t.id(), t.meta.priority, t.title);
+            content.push_str(&serde_json::to_string(&record)?);
+            content.push('\n');
+            count += 1;
+        }
+
+        // Every file is written, even empty, so loaders can rely on the set
+        let path = out.join(format!("{}.ndjson", partition));
+        if storage.is_dry_run() {
+            eprintln!("Would write {} ({} tickets)", path.display(), count);
+        } else {
+            std::fs::create_dir_all(out)?;
+            std::fs::write(&path, content)?;
         }
+        files.push((path.display().to_string(), count));
+    }
+
+    if json {
+        return output::print(&output::ExportResult {
+            schema: output::SCHEMA_VERSION,
+            files: files
+                .into_iter()
+                .map(|(path, tickets)| output::ExportFile { path, tickets })
+                .collect(),
+        });
+    }
+    for (path, count) in &files {
+        output::changed(
+            format!("Wrote {} tickets to {}", count, path),
+            &[path, &count.to_string()],
+        );
     }
     Ok(())
 }
 
-fn cmd_show(storage: &Storage, id: &str) -> Result<()> {
+fn cmd_export_cards(
+    storage: &Storage,
+    out: &Path,
+    filter: &FilterArgs,
+    include_archived: bool,
+    json: bool,
+) -> Result<()> {
     ensure_init(storage)?;
+    let filter = filter.build()?;
 
-    let ticket = storage
-        .find_by_prefix(id)?
-        .context(format!("Ticket '{}' not found", id))?;
-
-    let path = std::path::Path::new(".tickets").join(format!("{}.md", ticket.id()));
-    let content = std::fs::read_to_string(&path)
-        .or_else(|_| {
-            // Try with storage's tickets_dir if not in current dir
-            let alt_path = storage.ticket_path(ticket.id());
-            std::fs::read_to_string(alt_path)
-        })
-        .context("Failed to read ticket file")?;
+    let tickets = storage.load_all(Scope::listing(include_archived))?;
+    let mut matching: Vec<Ticket> = tickets.into_iter().filter(|t| filter.matches(t)).collect();
+    // Most urgent first, as they'd be pinned on a board
+    matching.sort_by(|a, b| (a.meta.priority, a.id()).cmp(&(b.meta.priority, b.id())));
+
+    let count = matching.len();
+    if storage.is_dry_run() {
+        eprintln!("Would write {} ({} tickets)", out.display(), count);
+    } else {
+        if let Some(dir) = out.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(out, html::cards(&matching))
+            .with_context(|| format!("Failed to write {}", out.display()))?;
+    }
 
-    print!("{}", content);
+    let path = out.display().to_string();
+    if json {
+        return output::print(&output::ExportResult {
+            schema: output::SCHEMA_VERSION,
+            files: vec![output::ExportFile {
+                path,
+                tickets: count,
+            }],
+        });
+    }
+    output::changed(
+        format!("Wrote {} cards to {}", count, path),
+        &[&path, &count.to_string()],
+    );
     Ok(())
 }
 
-fn cmd_edit(storage: &Storage, id: &str) -> Result<()> {
-    use std::io::Read;
+fn cmd_archive_compact(storage: &Storage, older_than: u32, json: bool) -> Result<()> {
+    use chrono::Datelike;
 
     ensure_init(storage)?;
 
-    let mut ticket = storage
-        .find_by_prefix(id)?
-        .context(format!("Ticket '{}' not found", id))?;
+    let lock = storage.lock()?;
+    let cutoff = Utc::now() - chrono::Months::new(older_than * 12);
+    let mut by_year: std::collections::BTreeMap<i32, Vec<Ticket>> = Default::default();
+    for ticket in storage.load_all(Scope::Archived)? {
+        if !storage.is_loose(&ticket) {
+            continue;
+        }
+        let m = &ticket.meta;
+        let last = m.closed.or(m.updated).unwrap_or(m.created);
+        if last <= cutoff {
+            by_year.entry(last.year()).or_default().push(ticket);
+        }
+    }
 
-    // Read title + body from stdin
-    let mut buf = String::new();
-    std::io::stdin().read_to_string(&mut buf)?;
-    let input = buf.trim();
+    let mut bundles = Vec::new();
+    let mut bundled: Vec<Vec<String>> = Vec::new();
+    for (year, tickets) in &by_year {
+        let path = storage.bundle(*year, tickets)?;
+        bundles.push(output::Bundle {
+            year: *year,
+            path: path.display().to_string(),
+            tickets: tickets.len(),
+        });
+        bundled.push(tickets.iter().map(|t| t.id().to_string()).collect());
+    }
+    drop(lock);
 
-    if input.is_empty() {
-        anyhow::bail!("No input provided. Expected: # Title\\n[body]");
+    if json {
+        return output::print(&output::ArchiveCompacted {
+            schema: output::SCHEMA_VERSION,
+            bundles,
+        });
+    }
+    if bundles.is_empty() {
+        output::changed_records(
+            format!(
+                "No archived tickets older than {} years outside bundles.",
+                older_than
+            ),
+            &[],
+        );
     }
+    for (b, ids) in bundles.iter().zip(&bundled) {
+        let records: Vec<Vec<&str>> = ids.iter().map(|id| vec![id.as_str(), &b.path]).collect();
+        output::changed_records(
+            format!(
+                "Bundled {} tickets from {} into {}",
+                b.tickets, b.year, b.path
+            ),
+            &records,
+        );
+    }
+    Ok(())
+}
 
-    let (title, body) = Storage::extract_title(input);
-    if title == "Untitled" && !input.starts_with("# ") {
-        anyhow::bail!("No title found. First line must be: # Your Title");
+fn cmd_gc(storage: &Storage, keep: Option<usize>, yes: bool, json: bool) -> Result<()> {
+    ensure_init(storage)?;
+
+    let config = storage.config()?;
+    let keep = keep.or(config.max_events).unwrap_or(100);
+    let now = Utc::now();
+    let mut tickets = storage.load_all(Scope::All)?;
+    tickets.sort_by(|a, b| a.id().cmp(b.id()));
+
+    let mut compacted = Vec::new();
+    let mut events_dropped = 0;
+    let mut archived = Vec::new();
+    let mut changes = Vec::new();
+    // Frozen tickets keep their history as it was signed off, and stay put
+    for mut ticket in tickets.into_iter().filter(|t| storage.can_change(t)) {
+        let dropped = ticket.compact_events(keep);
+        if dropped > 0 {
+            compacted.push(ticket.id().to_string());
+            events_dropped += dropped;
+        }
+        let archive = config.archive_on_close.due(&ticket, now);
+        if archive {
+            archived.push(ticket.id().to_string());
+        }
+        if archive || dropped > 0 {
+            changes.push((ticket, archive));
+        }
     }
 
-    ticket.title = title;
-    ticket.body = body.to_string();
-    storage.save(&ticket)?;
+    // Neither trimmed history nor archiving can be undone by tk
+    if !changes.is_empty() && !storage.is_dry_run() {
+        let mut question = String::new();
+        if events_dropped > 0 {
+            question = format!(
+                "Drop {} events from {} tickets",
+                events_dropped,
+                compacted.len()
+            );
+        }
+        if !archived.is_empty() {
+            question += if question.is_empty() {
+                "Archive"
+            } else {
+                " and archive"
+            };
+            question += &format!(" {} closed tickets", archived.len());
+        }
+        prompt::confirm(&format!("{}? This can't be undone.", question), yes)?;
+    }
+    for (mut ticket, archive) in changes {
+        if archive {
+            storage.set_archived(&mut ticket, true)?;
+        } else {
+            storage.save(&ticket)?;
+        }
+    }
 
-    println!("Updated {}", ticket.id());
+    if json {
+        output::print(&output::GcResult {
+            schema: output::SCHEMA_VERSION,
+            compacted,
+            events_dropped,
+            archived,
+        })?;
+        return Ok(());
+    }
+    if compacted.is_empty() && archived.is_empty() {
+        output::changed_records("Nothing to compact.", &[]);
+    }
+    if !compacted.is_empty() {
+        let records: Vec<Vec<&str>> = compacted.iter().map(|id| vec![id, "compacted"]).collect();
+        output::changed_records(
+            format!(
+                "Dropped {} events from {} tickets",
+                events_dropped,
+                compacted.len()
+            ),
+            &records,
+        );
+    }
+    if !archived.is_empty() {
+        let records: Vec<Vec<&str>> = archived.iter().map(|id| vec![id, "archived"]).collect();
+        output::changed_records(
+            format!(
+                "Archived {} closed tickets: {}",
+                archived.len(),
+                archived.join(", ")
+            ),
+            &records,
+        );
+    }
     Ok(())
 }
 
-fn cmd_status(storage: &Storage, id: &str, status_str: &str, json: bool) -> Result<()> {
+fn cmd_migrate(storage: &Storage, args: MigrateArgs, json: bool) -> Result<()> {
     ensure_init(storage)?;
 
-    let mut ticket = storage
-        .find_by_prefix(id)?
-        .context(format!("Ticket '{}' not found", id))?;
+    let config = storage.config()?;
+    let mut files: Vec<String> = Vec::new();
+    let mut settings = Vec::new();
+    if let Some(layout) = args.layout {
+        for (from, to) in storage.relayout(layout)? {
+            let id = to.file_stem().unwrap_or_default().to_string_lossy();
+            let to = to.display().to_string();
+            if !json {
+                output::changed(format!("Moved {} to {}", from.display(), to), &[&id, &to]);
+            }
+            files.push(to);
+        }
+        if config.layout != layout {
+            settings.push(("layout", layout.name()));
+        }
+    }
+    if let Some(format) = args.format {
+        for (path, ids) in storage.convert(format)? {
+            let path = path.display().to_string();
+            if !json {
+                // A bundle holds many tickets, and each gets its record
+                let records: Vec<Vec<&str>> =
+                    ids.iter().map(|id| vec![id.as_str(), &path]).collect();
+                output::changed_records(format!("Converted {}", path), &records);
+            }
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+        if config.frontmatter != format {
+            settings.push(("frontmatter", format.name()));
+        }
+    }
 
-    let new_status: Status = status_str.parse()?;
-    ticket.meta.status = new_status;
-    ticket.touch();
+    let mut slugged = Vec::new();
+    if args.slugs {
+        let mut taken = storage.taken_names()?;
+        let mut tickets = storage.load_all(Scope::All)?;
+        tickets.sort_by(|a, b| a.id().cmp(b.id()));
+        for mut ticket in tickets {
+            if ticket.meta.slug.is_some() || !storage.can_change(&ticket) {
+                continue;
+            }
+            let Some(slug) = id::slug(&ticket.title, &taken) else {
+                continue;
+            };
+            taken.push(slug.clone());
+            ticket.meta.slug = Some(slug.clone());
+            storage.save(&ticket)?;
+            if !json {
+                let path = storage.locate(ticket.id()).display().to_string();
+                output::changed(
+                    format!("{} is now {}", ticket.id(), slug),
+                    &[ticket.id(), &path, &slug],
+                );
+            }
+            slugged.push(ticket.id().to_string());
+        }
+    }
 
-    storage.save(&ticket)?;
+    // Saves from now on follow the new settings too
+    let config_changed = !settings.is_empty();
+    if config_changed {
+        let mut text = storage.config_text()?.unwrap_or_default();
+        for (key, value) in &settings {
+            text = with_config_key(&text, key, value);
+        }
+        let path = storage.write_config(&text, "tk migrate")?;
+        let path = path.display().to_string();
+        if !json {
+            let set: Vec<String> = settings
+                .iter()
+                .map(|(key, value)| format!("{}: {}", key, value))
+                .collect();
+            // Not a ticket, so the ID field is empty
+            output::changed(format!("Set {} in {}", set.join(", "), path), &["", &path]);
+        }
+        files.push(path);
+    }
 
     if json {
-        println!(r#"{{"id":"{}","status":"{}"}}"#, ticket.id(), new_status);
+        output::print(&output::Migrated {
+            schema: output::SCHEMA_VERSION,
+            format: args.format.map(|f| f.name().to_string()),
+            layout: args.layout.map(|l| l.name().to_string()),
+            files,
+            config_changed,
+            slugged,
+        })?;
+    } else if files.is_empty() && slugged.is_empty() {
+        println!("Nothing to migrate: the store already looks like that.");
+    }
+    Ok(())
+}
+
+/// `config` with the top-level `key` set to `value`: its line replaced, or
+/// one appended, so the rest of the file keeps its comments and layout
+fn with_config_key(config: &str, key: &str, value: &str) -> String {
+    let prefix = format!("{}:", key);
+    let line = format!("{} {}", prefix, value);
+    let mut found = false;
+    let mut lines: Vec<&str> = config
+        .lines()
+        .map(|l| {
+            if l.starts_with(&prefix) {
+                found = true;
+                line.as_str()
+            } else {
+                l
+            }
+        })
+        .collect();
+    if !found {
+        lines.push(&line);
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+fn cmd_lint(storage: &Storage, id: Option<&str>, fix: bool, format: Format) -> Result<()> {
+    ensure_init(storage)?;
+
+    let tickets = match id {
+        Some(id) => vec![find_ticket(storage, "Ticket", id)?],
+        None => {
+            let mut all = storage.load_all(Scope::Live)?;
+            all.sort_by(|a, b| a.id().cmp(b.id()));
+            all
+        }
+    };
+
+    let config = storage.config()?;
+    let known_ids: HashSet<String> = storage.all_ids(Scope::All)?.into_iter().collect();
+    let mut problems = Vec::new();
+    for mut ticket in tickets {
+        if fix && storage.can_change(&ticket) {
+            let mut changed = false;
+            if let Some(title) = lint::fix_title(&ticket.title, ticket.meta.ticket_type, &config) {
+                if format == Format::Text {
+                    println!("{}: {} -> {} (fixed)", ticket.id(), ticket.title, title);
+                }
+                ticket.title = title;
+                changed = true;
+            }
+            if let Some(body) = lint::fix_body(&ticket.body) {
+                if format == Format::Text {
+                    println!("{}: checklists and notes tidied (fixed)", ticket.id());
+                }
+                ticket.body = body;
+                changed = true;
+            }
+            if changed {
+                ticket.touch();
+                storage.save(&ticket)?;
+            }
+        }
+        problems.extend(lint::check(&ticket, &config, &known_ids));
+    }
+    let failed = !problems.is_empty();
+
+    if format == Format::Json {
+        output::print(&output::LintReport {
+            schema: output::SCHEMA_VERSION,
+            problems: problems
+                .into_iter()
+                .map(|p| output::LintProblem {
+                    id: p.id,
+                    rule: p.rule.to_string(),
+                    message: p.message,
+                    line: p.line,
+                })
+                .collect(),
+        })?;
+    } else if format == Format::Vimgrep {
+        for p in &problems {
+            let message = format!("[{}] {}", p.rule, p.message);
+            println!("{}", vimgrep_line(storage, &p.id, p.line, &message));
+        }
+    } else if problems.is_empty() {
+        println!("No problems found.");
     } else {
-        println!("{} -> {}", ticket.id(), new_status);
+        for p in &problems {
+            println!("{}: [{}] {}", p.id, p.rule, p.message);
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
     }
     Ok(())
 }
 
-fn cmd_close(storage: &Storage, id: &str, json: bool) -> Result<()> {
+fn cmd_gate(
+    storage: &Storage,
+    max_open_priority: Option<u8>,
+    forbid_cycles: bool,
+    forbid_dangling_deps: bool,
+    json: bool,
+) -> Result<()> {
     ensure_init(storage)?;
 
-    let mut ticket = storage
-        .find_by_prefix(id)?
-        .context(format!("Ticket '{}' not found", id))?;
+    if max_open_priority.is_none() && !forbid_cycles && !forbid_dangling_deps {
+        return Err(TkError::validation(
+            "No checks given. Use --max-open-priority, --forbid-cycles or --forbid-dangling-deps",
+        )
+        .into());
+    }
 
-    ticket.meta.status = Status::Closed;
-    ticket.meta.closed = Some(Utc::now());
-    ticket.touch();
+    let view = storage.view()?;
+    let tickets = view.tickets();
+    let mut failures = Vec::new();
+
+    if let Some(max) = max_open_priority {
+        for t in tickets.iter().filter(|t| !t.archived && t.is_open()) {
+            if t.meta.priority <= max {
+                failures.push(lint::Problem {
+                    id: t.id().to_string(),
+                    rule: "open-priority",
+                    message: format!(
+                        "P{} ticket is {}: {}",
+                        t.meta.priority, t.meta.status, t.title
+                    ),
+                    line: None,
+                });
+            }
+        }
+    }
 
-    storage.save(&ticket)?;
+    if forbid_cycles {
+        for cycle in graph::cycles(&view) {
+            failures.push(lint::Problem {
+                id: cycle[0].clone(),
+                rule: "cycle",
+                message: format!("{} -> {}", cycle.join(" -> "), cycle[0]),
+                line: None,
+            });
+        }
+    }
+
+    if forbid_dangling_deps {
+        let ids: HashSet<&str> = tickets.iter().map(|t| t.id()).collect();
+        for t in tickets {
+            for dep in t.meta.deps.iter().filter(|d| !ids.contains(d.as_str())) {
+                failures.push(lint::Problem {
+                    id: t.id().to_string(),
+                    rule: "dangling-dep",
+                    message: format!("depends on {}, which doesn't exist", dep),
+                    line: None,
+                });
+            }
+        }
+    }
+
+    let failed = !failures.is_empty();
 
     if json {
-        println!(r#"{{"id":"{}","status":"closed"}}"#, ticket.id());
+        output::print(&output::GateReport {
+            schema: output::SCHEMA_VERSION,
+            passed: !failed,
+            failures: failures
+                .into_iter()
+                .map(|p| output::LintProblem {
+                    id: p.id,
+                    rule: p.rule.to_string(),
+                    message: p.message,
+                    line: p.line,
+                })
+                .collect(),
+        })?;
+    } else if !failed {
+        println!("Gate passed.");
     } else {
-        println!("Closed {}", ticket.id());
+        for p in &failures {
+            println!("{}: [{}] {}", p.id, p.rule, p.message);
+        }
+        println!("Gate failed: {} problem(s).", failures.len());
+    }
+
+    if failed {
+        std::process::exit(1);
     }
     Ok(())
 }
 
-fn cmd_dep(storage: &Storage, id: &str, dep_id: &str, json: bool) -> Result<()> {
+fn cmd_lint_links(storage: &Storage, id: Option<&str>, reanchor: bool, json: bool) -> Result<()> {
     ensure_init(storage)?;
 
-    let mut ticket = storage
-        .find_by_prefix(id)?
-        .context(format!("Ticket '{}' not found", id))?;
+    let tickets = match id {
+        Some(id) => vec![find_ticket(storage, "Ticket", id)?],
+        None => {
+            let mut all = storage.load_all(Scope::Live)?;
+            all.sort_by(|a, b| a.id().cmp(b.id()));
+            all
+        }
+    };
 
-    let dep = storage
-        .find_by_prefix(dep_id)?
-        .context(format!("Dependency '{}' not found", dep_id))?;
+    let root = storage.root_dir();
+    let mut broken = Vec::new();
+    let mut moved = Vec::new();
 
-    if ticket.meta.deps.contains(&dep.id().to_string()) {
-        anyhow::bail!("Dependency already exists");
-    }
+    for mut ticket in tickets {
+        let refs = links::extract_refs(&ticket.body);
+        if refs.is_empty() {
+            continue;
+        }
 
-    // Add dep and check for cycles before saving
-    ticket.meta.deps.push(dep.id().to_string());
+        // Plain path:line refs were written against whatever was checked out
+        // when the ticket was last touched
+        let baseline = if reanchor && storage.can_change(&ticket) {
+            links::rev_at(&root, ticket.meta.updated.unwrap_or(ticket.meta.created))
+        } else {
+            None
+        };
+
+        let mut lines = HashMap::new();
+        for r in refs {
+            if let (Some(rev), None) = (&baseline, &r.rev) {
+                if let Some(new_line) = links::reanchor(&root, rev, &r.path, r.line) {
+                    if new_line != r.line {
+                        moved.push(output::ReanchoredLink {
+                            id: ticket.id().to_string(),
+                            from: r.raw.clone(),
+                            to: r.at_line(new_line),
+                        });
+                        lines.insert(r.raw, new_line);
+                        continue;
+                    }
+                }
+            }
 
-    let mut all_tickets: Vec<_> = storage
-        .load_all()?
-        .into_iter()
-        .filter(|t| t.id() != ticket.id())
-        .collect();
-    all_tickets.push(ticket.clone());
+            if let Some(problem) = links::check_ref(&root, &r) {
+                broken.push(output::BrokenLink {
+                    id: ticket.id().to_string(),
+                    reference: r.raw,
+                    problem,
+                });
+            }
+        }
 
-    if !find_cycles(&all_tickets).is_empty() {
-        anyhow::bail!("Adding this dependency would create a cycle");
+        if !lines.is_empty() {
+            ticket.body = links::rewrite(&ticket.body, &lines);
+            ticket.touch();
+            storage.save(&ticket)?;
+        }
     }
 
-    ticket.touch();
-    storage.save(&ticket)?;
+    let broken_count = broken.len();
+    let failed = broken_count > 0;
 
     if json {
-        println!(r#"{{"id":"{}","dep":"{}"}}"#, ticket.id(), dep.id());
+        output::print(&output::LinkReport {
+            schema: output::SCHEMA_VERSION,
+            broken,
+            reanchored: moved,
+        })?;
     } else {
-        println!("{} now depends on {}", ticket.id(), dep.id());
+        for m in &moved {
+            println!("{}: {} -> {} (re-anchored)", m.id, m.from, m.to);
+        }
+        if broken.is_empty() {
+            println!("No broken links found.");
+        } else {
+            for b in &broken {
+                println!("{}: {} - {}", b.id, b.reference, b.problem);
+            }
+        }
+    }
+
+    if failed {
+        return Err(TkError::Check(format!("{} broken links", broken_count)).into());
     }
     Ok(())
 }
 
-fn cmd_undep(storage: &Storage, id: &str, dep_id: &str, json: bool) -> Result<()> {
+fn cmd_daemon(storage: &Storage, metrics: Option<&str>) -> Result<()> {
     ensure_init(storage)?;
 
-    let mut ticket = storage
-        .find_by_prefix(id)?
-        .context(format!("Ticket '{}' not found", id))?;
-
-    let dep = storage
-        .find_by_prefix(dep_id)?
-        .context(format!("Dependency '{}' not found", dep_id))?;
-
-    let orig_len = ticket.meta.deps.len();
-    ticket.meta.deps.retain(|d| d != dep.id());
-
-    if ticket.meta.deps.len() == orig_len {
-        anyhow::bail!("Dependency not found");
-    }
-
-    ticket.touch();
-    storage.save(&ticket)?;
+    #[cfg(unix)]
+    return daemon::serve(storage, metrics);
 
-    if json {
-        println!(r#"{{"removed":true}}"#);
-    } else {
-        println!("Removed dependency {} -> {}", ticket.id(), dep.id());
+    #[cfg(not(unix))]
+    {
+        let _ = metrics;
+        anyhow::bail!("tk daemon needs unix domain sockets, which this platform lacks")
     }
-    Ok(())
 }
 
-fn cmd_ready(storage: &Storage, tag: Option<String>, json: bool) -> Result<()> {
+fn cmd_sync(
+    storage: &Storage,
+    provider: auth::Provider,
+    repo: Option<&str>,
+    full: bool,
+    report: bool,
+    on_conflict: Option<sync::ConflictPolicy>,
+    json: bool,
+) -> Result<()> {
     ensure_init(storage)?;
 
-    let tickets = storage.load_all()?;
-    let tags_filter: Vec<String> = tag
-        .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
-        .unwrap_or_default();
+    let config = storage.config()?;
+    let remote = sync::Remote::resolve(provider, repo, &storage.root_dir())?;
+    let key = remote.key();
+    let mut state = sync::SyncState::load(storage.tickets_dir())?;
+    let previous = match state.remotes.get(&key) {
+        Some(s) if !full => s.clone(),
+        _ => sync::RemoteState::default(),
+    };
 
-    let mut ready: Vec<_> = tickets
-        .iter()
-        .filter(|t| t.is_open() && !t.is_blocked_by(&tickets))
-        .filter(|t| {
-            tags_filter.is_empty() || tags_filter.iter().all(|tag| t.meta.tags.contains(tag))
-        })
-        .collect();
+    let token = auth::lookup(provider).map(|(token, _)| token);
+    if token.is_none() {
+        tracing::info!("no {} token; making unauthenticated requests", provider);
+    }
+    let fetched = sync::fetch(&remote, token.as_deref(), &previous)?;
+
+    let policy = on_conflict.unwrap_or(config.sync.conflicts);
+    let mut tickets = storage.load_all(Scope::All)?;
+    let mut created = Vec::new();
+    let mut updated = Vec::new();
+    let mut conflicts = Vec::new();
+    let mut entries: Vec<output::SyncReportEntry> = Vec::new();
+
+    for issue in &fetched.issues {
+        let number = issue.number.to_string();
+        let tracked = tickets.iter_mut().find(|t| {
+            t.meta
+                .external
+                .iter()
+                .any(|e| e.matches(provider.name(), &number))
+        });
+
+        let (outcome, id, reason) = match tracked {
+            Some(ticket) if !storage.can_change(ticket) => {
+                let changes = sync::update_ticket(&mut ticket.clone(), provider, issue);
+                let reason = if changes.is_empty() {
+                    "unchanged".to_string()
+                } else {
+                    format!("frozen; not applied: {}", changes.join(", "))
+                };
+                ("skipped", Some(ticket.id().to_string()), reason)
+            }
+            Some(ticket) => {
+                let local = sync::changed_locally(ticket, provider, issue);
+                let mut synced = ticket.clone();
+                let changes = sync::update_ticket(&mut synced, provider, issue).join(", ");
+                let id = Some(ticket.id().to_string());
+                let settled = local && !sync::changed_upstream(ticket, provider, issue);
+                let resolution = if changes.is_empty() || settled {
+                    None
+                } else if local {
+                    conflicts.push(ticket.id().to_string());
+                    Some(policy.resolve(ticket, issue))
+                } else {
+                    Some(sync::Resolution::TakeRemote)
+                };
+                match resolution {
+                    None if changes.is_empty() => ("skipped", id, "unchanged".to_string()),
+                    None => (
+                        "skipped",
+                        id,
+                        "changed here; nothing new upstream since last sync".to_string(),
+                    ),
+                    Some(sync::Resolution::TakeRemote) => {
+                        *ticket = synced;
+                        storage.save(ticket)?;
+                        let changes =
+                            format!("{}{}", changes, moved(file_by_status(storage, ticket)?));
+                        updated.push(ticket.id().to_string());
+                        if local {
+                            let reason = format!("changed here since last sync; took {}", changes);
+                            ("conflict", id, reason)
+                        } else {
+                            ("updated", id, changes)
+                        }
+                    }
+                    Some(sync::Resolution::KeepLocal) => {
+                        sync::mark_seen(ticket, provider, issue);
+                        storage.save(ticket)?;
+                        let reason =
+                            format!("changed here since last sync; kept it over {}", changes);
+                        ("conflict", id, reason)
+                    }
+                    Some(sync::Resolution::Manual) => {
+                        sync::flag_conflict(ticket, provider, issue, &changes);
+                        storage.save(ticket)?;
+                        let reason =
+                            format!("noted and tagged {}: {}", sync::CONFLICT_TAG, changes);
+                        ("conflict", id, reason)
+                    }
+                }
+            }
+            // Closed issues nobody tracked locally aren't worth a file
+            None if issue.closed => (
+                "skipped",
+                None,
+                "closed upstream and not tracked".to_string(),
+            ),
+            None => {
+                let existing: Vec<String> = tickets.iter().map(|t| t.id().to_string()).collect();
+                let id = id::generate(&existing, config.id_scheme);
+                let mut ticket = sync::new_ticket(id, provider, issue);
+                let taken: Vec<String> = tickets
+                    .iter()
+                    .flat_map(|t| t.names().map(str::to_string))
+                    .collect();
+                ticket.meta.slug = id::slug(&ticket.title, &taken);
+                storage.save(&ticket)?;
+                created.push(ticket.id().to_string());
+                let id = Some(ticket.id().to_string());
+                tickets.push(ticket);
+                ("created", id, "new open issue".to_string())
+            }
+        };
+        entries.push(output::SyncReportEntry {
+            outcome: outcome.to_string(),
+            id,
+            issue: issue.number,
+            reason,
+        });
+    }
 
-    ready.sort_by_key(|t| t.meta.priority);
+    state.remotes.insert(key.clone(), fetched.state);
+    storage.save_file(sync::STATE_FILE, &state.to_json()?)?;
 
     if json {
-        let items: Vec<_> = ready
-            .iter()
-            .map(|t| {
-                serde_json::json!({
-                    "id": t.id(),
-                    "title": t.title,
-                    "priority": t.meta.priority,
-                })
-            })
-            .collect();
-        println!("{}", serde_json::to_string(&items)?);
-    } else if ready.is_empty() {
-        println!("No ready tickets.");
+        return output::print(&output::SyncResult {
+            schema: output::SCHEMA_VERSION,
+            remote: key,
+            created,
+            updated,
+            conflicts,
+            not_modified: fetched.not_modified,
+            rate_limited_until: fetched.rate_limited_until,
+            report: report.then_some(entries),
+        });
+    }
+
+    if fetched.not_modified {
+        println!("{} is up to date", key);
     } else {
-        for t in ready {
-            println!("{} [P{}] {}", t.id(), t.meta.priority, t.title);
+        println!(
+            "Synced {}: {} created, {} updated, {} conflicts",
+            key,
+            created.len(),
+            updated.len(),
+            conflicts.len()
+        );
+    }
+    if report {
+        for e in &entries {
+            println!(
+                "{:<9} {:<10} #{:<6} {}",
+                e.outcome,
+                e.id.as_deref().unwrap_or("-"),
+                e.issue,
+                e.reason
+            );
         }
     }
+    if let Some(until) = fetched.rate_limited_until {
+        println!(
+            "Rate limited; run again after {}",
+            until.format("%Y-%m-%d %H:%M UTC")
+        );
+    }
     Ok(())
 }
 
-fn cmd_blocked(storage: &Storage, tag: Option<String>, json: bool) -> Result<()> {
+fn cmd_import_mbox(storage: &Storage, file: &Path, report: bool, json: bool) -> Result<()> {
     ensure_init(storage)?;
 
-    let tickets = storage.load_all()?;
-    let tags_filter: Vec<String> = tag
-        .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
-        .unwrap_or_default();
-
-    let mut blocked: Vec<_> = tickets
+    let content =
+        std::fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let messages = mail::split_mbox(&String::from_utf8_lossy(&content))
         .iter()
-        .filter(|t| t.is_open() && t.is_blocked_by(&tickets))
-        .filter(|t| {
-            tags_filter.is_empty() || tags_filter.iter().all(|tag| t.meta.tags.contains(tag))
-        })
+        .map(|raw| mail::parse(raw))
         .collect();
+    let result = import_messages(storage, messages)?;
+    print_import(result, report, json)
+}
 
-    blocked.sort_by_key(|t| t.meta.priority);
+fn cmd_ingest(storage: &Storage, maildir: &Path, report: bool, json: bool) -> Result<()> {
+    ensure_init(storage)?;
 
-    if json {
-        let items: Vec<_> = blocked
-            .iter()
-            .map(|t| {
-                let blocking: Vec<_> = t
-                    .meta
-                    .deps
-                    .iter()
-                    .filter(|d| {
-                        tickets
-                            .iter()
-                            .find(|x| x.id() == *d)
-                            .is_some_and(|x| x.is_open())
-                    })
-                    .collect();
-                serde_json::json!({
-                    "id": t.id(),
-                    "title": t.title,
-                    "blocked_by": blocking,
-                })
-            })
-            .collect();
-        println!("{}", serde_json::to_string(&items)?);
-    } else if blocked.is_empty() {
-        println!("No blocked tickets.");
-    } else {
-        for t in blocked {
-            let blocking: Vec<_> = t
-                .meta
-                .deps
-                .iter()
-                .filter(|d| {
-                    tickets
-                        .iter()
-                        .find(|x| x.id() == *d)
-                        .is_some_and(|x| x.is_open())
-                })
-                .cloned()
-                .collect();
-            println!(
-                "{} [P{}] {} (blocked by: {})",
-                t.id(),
-                t.meta.priority,
-                t.title,
-                blocking.join(", ")
-            );
+    let paths = mail::maildir_new(maildir)?;
+    let mut messages = Vec::new();
+    for path in &paths {
+        let raw =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        messages.push(mail::parse(&String::from_utf8_lossy(&raw)));
+    }
+    let result = import_messages(storage, messages)?;
+
+    for path in &paths {
+        if storage.is_dry_run() {
+            eprintln!("Would move {} to cur/", path.display());
+        } else {
+            mail::mark_seen(maildir, path)?;
         }
     }
-    Ok(())
+    print_import(result, report, json)
 }
 
-fn cmd_dep_cycle(storage: &Storage, json: bool) -> Result<()> {
-    ensure_init(storage)?;
+/// Oldest first, so a thread's first message makes the ticket and later
+/// ones note it. A message already on a ticket (by Message-ID, or by content
+/// for mail without one) is skipped.
+fn import_messages(
+    storage: &Storage,
+    mut messages: Vec<mail::Message>,
+) -> Result<output::ImportResult> {
+    let config = storage.config()?;
+    let mut tickets = storage.load_all(Scope::All)?;
+    let mut created = Vec::new();
+    let mut updated = Vec::new();
+    let mut skipped = 0;
+    let mut entries = Vec::new();
+
+    messages.sort_by_key(|m| m.date);
+    for msg in messages {
+        let on_ticket =
+            |t: &Ticket, id: &str| t.meta.external.iter().any(|e| e.matches(mail::SYSTEM, id));
+        let key = msg.key();
+        let seen = tickets.iter().find(|t| on_ticket(t, &key));
+        let thread = msg
+            .ancestors
+            .iter()
+            .find_map(|id| tickets.iter().position(|t| on_ticket(t, id)));
+
+        let (outcome, id, reason) = if let Some(ticket) = seen {
+            skipped += 1;
+            let id = Some(ticket.id().to_string());
+            ("skipped", id, "already imported".to_string())
+        } else if let Some(i) = thread {
+            let ticket = &mut tickets[i];
+            let id = Some(ticket.id().to_string());
+            let content = msg.reply_text();
+            if !storage.can_change(ticket) {
+                skipped += 1;
+                ("skipped", id, "reply to a frozen ticket".to_string())
+            } else if content.is_empty() {
+                skipped += 1;
+                (
+                    "skipped",
+                    id,
+                    "reply has nothing but quoted text".to_string(),
+                )
+            } else {
+                let note = Note {
+                    timestamp: msg.date.unwrap_or_else(Utc::now),
+                    author: msg.from.clone(),
+                    ..Note::new(content)
+                };
+                ticket.add_note(&note);
+                ticket.meta.external.push(mail::external(&key));
+                storage.save(ticket)?;
+                fire_hooks(storage, ticket, "note", Some(&note.content))?;
+                if !updated.iter().any(|u| Some(u) == id.as_ref()) {
+                    updated.push(ticket.id().to_string());
+                }
+                let reason = format!("reply from {}", msg.from.as_deref().unwrap_or("unknown"));
+                ("noted", id, reason)
+            }
+        } else {
+            let existing: Vec<String> = tickets.iter().map(|t| t.id().to_string()).collect();
+            let mut ticket = mail::new_ticket(id::generate(&existing, config.id_scheme), &msg);
+            let taken: Vec<String> = tickets
+                .iter()
+                .flat_map(|t| t.names().map(str::to_string))
+                .collect();
+            ticket.meta.slug = id::slug(&ticket.title, &taken);
+            storage.save(&ticket)?;
+            fire_hooks(storage, &ticket, "created", Some(&ticket.body))?;
+            escalate(storage, &ticket, None)?;
+            created.push(ticket.id().to_string());
+            let id = Some(ticket.id().to_string());
+            tickets.push(ticket);
+            ("created", id, "starts a thread".to_string())
+        };
+        entries.push(output::ImportReportEntry {
+            outcome: outcome.to_string(),
+            id,
+            message_id: msg.message_id,
+            reason,
+        });
+    }
 
-    let tickets = storage.load_all()?;
-    let cycles = find_cycles(&tickets);
+    Ok(output::ImportResult {
+        schema: output::SCHEMA_VERSION,
+        created,
+        updated,
+        skipped,
+        report: Some(entries),
+    })
+}
 
+fn print_import(mut result: output::ImportResult, report: bool, json: bool) -> Result<()> {
+    if !report {
+        result.report = None;
+    }
     if json {
-        println!("{}", serde_json::to_string(&cycles)?);
-    } else if cycles.is_empty() {
-        println!("No dependency cycles found.");
-    } else {
-        println!("Dependency cycles detected:");
-        for cycle in &cycles {
-            println!("  {} -> {}", cycle.join(" -> "), cycle[0]);
-        }
+        return output::print(&result);
     }
 
-    if !cycles.is_empty() {
-        std::process::exit(1);
+    println!(
+        "Imported mail: {} created, {} updated, {} skipped",
+        result.created.len(),
+        result.updated.len(),
+        result.skipped
+    );
+    for e in result.report.iter().flatten() {
+        println!(
+            "{:<8} {:<10} {:<30} {}",
+            e.outcome,
+            e.id.as_deref().unwrap_or("-"),
+            e.message_id.as_deref().unwrap_or("-"),
+            e.reason
+        );
     }
     Ok(())
 }
 
-/// Find all dependency cycles using DFS
-fn find_cycles(tickets: &[Ticket]) -> Vec<Vec<String>> {
-    let mut cycles = Vec::new();
-    let mut visited = HashSet::new();
-    let mut rec_stack = HashSet::new();
-    let mut path = Vec::new();
-
-    let ticket_map: HashMap<&str, &Ticket> = tickets.iter().map(|t| (t.id(), t)).collect();
+fn cmd_auth_login(storage: &Storage, provider: auth::Provider, json: bool) -> Result<()> {
+    let token = prompt::secret(&format!("{} token", provider))?;
 
-    for ticket in tickets {
-        if !visited.contains(ticket.id()) {
-            dfs_cycles(
-                ticket.id(),
-                &ticket_map,
-                &mut visited,
-                &mut rec_stack,
-                &mut path,
-                &mut cycles,
-            );
-        }
+    if storage.is_dry_run() {
+        eprintln!("Would store {} token in the OS keychain", provider);
+    } else {
+        auth::store(provider, &token)?;
     }
 
-    cycles
-}
-
-fn dfs_cycles(
-    id: &str,
-    tickets: &HashMap<&str, &Ticket>,
-    visited: &mut HashSet<String>,
-    rec_stack: &mut HashSet<String>,
-    path: &mut Vec<String>,
-    cycles: &mut Vec<Vec<String>>,
-) {
-    visited.insert(id.to_string());
-    rec_stack.insert(id.to_string());
-    path.push(id.to_string());
-
-    if let Some(ticket) = tickets.get(id) {
-        for dep in &ticket.meta.deps {
-            if !visited.contains(dep) {
-                dfs_cycles(dep, tickets, visited, rec_stack, path, cycles);
-            } else if rec_stack.contains(dep) {
-                // Found cycle - extract it
-                if let Some(start) = path.iter().position(|x| x == dep) {
-                    let cycle: Vec<String> = path[start..].to_vec();
-                    cycles.push(cycle);
-                }
-            }
-        }
+    if json {
+        output::print(&output::AuthChange {
+            schema: output::SCHEMA_VERSION,
+            provider: provider.to_string(),
+            stored: true,
+        })?;
+    } else {
+        println!("Stored {} token", provider);
     }
 
-    path.pop();
-    rec_stack.remove(id);
+    Ok(())
 }
 
-fn cmd_tree(storage: &Storage, id: Option<&str>, full: bool, json: bool) -> Result<()> {
-    ensure_init(storage)?;
-
-    let tickets = storage.load_all()?;
-
-    if let Some(id) = id {
-        // Show tree for a single ticket (what it blocks)
-        let ticket = storage
-            .find_by_prefix(id)?
-            .context(format!("Ticket '{}' not found", id))?;
-
-        if json {
-            let tree = build_blocks_json(&ticket, &tickets, full);
-            println!("{}", serde_json::to_string_pretty(&tree)?);
-        } else {
-            let marker = if ticket.is_open() { " " } else { "x" };
-            println!("[{}] {} - {}", marker, ticket.id(), ticket.title);
-            print_blocks_tree(&ticket, &tickets, "", full);
+fn cmd_auth_logout(storage: &Storage, provider: auth::Provider, json: bool) -> Result<()> {
+    let removed = if storage.is_dry_run() {
+        let stored = auth::is_stored(provider);
+        if stored {
+            eprintln!("Would remove {} token from the OS keychain", provider);
         }
+        stored
     } else {
-        // Show tree for root tickets (those with no deps)
-        let roots: Vec<_> = tickets
-            .iter()
-            .filter(|t| full || t.is_open())
-            .filter(|t| {
-                t.meta.deps.is_empty()
-                    || t.meta.deps.iter().all(|d| {
-                        tickets
-                            .iter()
-                            .find(|x| x.id() == d)
-                            .is_none_or(|x| !full && !x.is_open())
-                    })
-            })
-            .collect();
+        auth::remove(provider)?
+    };
 
-        let mut sorted = roots;
-        sorted.sort_by_key(|t| t.id());
+    if !removed {
+        return Err(TkError::not_found("Stored token", provider.name()).into());
+    }
 
-        if json {
-            let trees: Vec<_> = sorted
-                .iter()
-                .map(|t| build_blocks_json(t, &tickets, full))
-                .collect();
-            println!("{}", serde_json::to_string_pretty(&trees)?);
-        } else {
-            for ticket in sorted {
-                let marker = if ticket.is_open() { " " } else { "x" };
-                println!("[{}] {} - {}", marker, ticket.id(), ticket.title);
-                print_blocks_tree(ticket, &tickets, "", full);
-            }
-        }
+    if json {
+        output::print(&output::AuthChange {
+            schema: output::SCHEMA_VERSION,
+            provider: provider.to_string(),
+            stored: false,
+        })?;
+    } else {
+        println!("Removed {} token", provider);
     }
+
     Ok(())
 }
 
-/// Print tickets that are blocked by this ticket (depend on it)
-fn print_blocks_tree(ticket: &Ticket, all: &[Ticket], prefix: &str, full: bool) {
-    // Find tickets that have this ticket in their deps
-    let blocked_by_this: Vec<_> = all
+fn cmd_auth_status(json: bool) -> Result<()> {
+    let providers: Vec<output::ProviderAuth> = auth::Provider::ALL
         .iter()
-        .filter(|t| t.meta.deps.contains(&ticket.id().to_string()))
-        .filter(|t| full || t.is_open())
+        .map(|&p| output::ProviderAuth {
+            provider: p.to_string(),
+            source: auth::lookup(p).map(|(_, source)| source.to_string()),
+        })
         .collect();
 
-    for (i, blocked) in blocked_by_this.iter().enumerate() {
-        let is_last = i == blocked_by_this.len() - 1;
-        let connector = if is_last { "└── " } else { "├── " };
-        let marker = if blocked.is_open() { " " } else { "x" };
-
-        println!(
-            "{}{}[{}] {} - {}",
-            prefix,
-            connector,
-            marker,
-            blocked.id(),
-            blocked.title
-        );
+    if json {
+        return output::print(&output::AuthStatus {
+            schema: output::SCHEMA_VERSION,
+            providers,
+        });
+    }
 
-        let new_prefix = format!("{}{}   ", prefix, if is_last { " " } else { "│" });
-        print_blocks_tree(blocked, all, &new_prefix, full);
+    for p in providers {
+        match p.source {
+            Some(source) => println!("{:<8} {}", p.provider, source),
+            None => println!("{:<8} not configured", p.provider),
+        }
     }
+    Ok(())
 }
 
-fn build_blocks_json(ticket: &Ticket, all: &[Ticket], full: bool) -> serde_json::Value {
-    // Find tickets that have this ticket in their deps
-    let blocked: Vec<_> = all
-        .iter()
-        .filter(|t| t.meta.deps.contains(&ticket.id().to_string()))
-        .filter(|t| full || t.is_open())
-        .map(|t| build_blocks_json(t, all, full))
-        .collect();
-
-    serde_json::json!({
-        "id": ticket.id(),
-        "title": ticket.title,
-        "status": ticket.meta.status.to_string(),
-        "blocks": blocked,
-    })
+fn cmd_schema(name: Option<&str>) -> Result<()> {
+    let schemas = output::schemas();
+
+    match name {
+        Some(name) => {
+            let (_, schema) = schemas.iter().find(|(n, _)| *n == name).ok_or_else(|| {
+                let names: Vec<_> = schemas.iter().map(|(n, _)| *n).collect();
+                TkError::Validation(format!(
+                    "Unknown output '{}'. Use one of: {}",
+                    name,
+                    names.join(", ")
+                ))
+            })?;
+            output::print(schema)?;
+        }
+        None => {
+            let outputs: serde_json::Map<String, serde_json::Value> = schemas
+                .into_iter()
+                .map(|(n, schema)| Ok((n.to_string(), serde_json::to_value(schema)?)))
+                .collect::<Result<_>>()?;
+            output::print(&serde_json::json!({
+                "schema": output::SCHEMA_VERSION,
+                "outputs": outputs,
+            }))?;
+        }
+    }
+    Ok(())
 }
 
-fn cmd_note(storage: &Storage, id: &str, content: Option<String>, json: bool) -> Result<()> {
+fn cmd_doctor(storage: &Storage, verify: bool, rehash: bool, json: bool) -> Result<()> {
     ensure_init(storage)?;
 
-    let mut ticket = storage
-        .find_by_prefix(id)?
-        .context(format!("Ticket '{}' not found", id))?;
+    let recorded = storage.manifest()?;
+    if verify && !storage.keeps_manifest() && recorded.is_empty() {
+        return Err(TkError::validation(
+            "No .tickets/.manifest to verify against. Set manifest: true in config.yaml and run tk doctor --rehash",
+        )
+        .into());
+    }
+    if rehash && !storage.keeps_manifest() {
+        return Err(TkError::validation(
+            "The manifest is off; set manifest: true in config.yaml first",
+        )
+        .into());
+    }
 
-    let content = if let Some(c) = content {
-        c
-    } else {
-        // Open editor for note
-        let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
-        let temp = std::env::temp_dir().join(format!("tk-note-{}.md", ticket.id()));
-        std::fs::write(&temp, "")?;
+    let mut problems: Vec<output::DoctorProblem> = Vec::new();
+    let mut problem = |path: &Path, rule: &str, message: String| {
+        problems.push(output::DoctorProblem {
+            path: path.display().to_string(),
+            rule: rule.to_string(),
+            message,
+        })
+    };
 
-        let status = Command::new(&editor).arg(&temp).status()?;
-        if !status.success() {
-            anyhow::bail!("Editor exited with error");
+    let mut files = Vec::new();
+    let mut fresh = manifest::Manifest::default();
+    let mut seen: HashMap<String, PathBuf> = HashMap::new();
+    for (key, path) in storage.manifest_files()? {
+        let bytes = std::fs::read(&path)?;
+        let content = match String::from_utf8(bytes) {
+            Ok(content) => content,
+            Err(e) => {
+                problem(&path, "encoding", "isn't valid UTF-8".to_string());
+                String::from_utf8_lossy(e.as_bytes()).into_owned()
+            }
+        };
+        match storage.file_ids(&path, &content) {
+            Ok(ids) => {
+                fresh.record(&key, &content);
+                for id in ids {
+                    if let Some(other) = seen.get(&id) {
+                        problem(
+                            &path,
+                            "duplicate-id",
+                            format!("{} is also in {}", id, other.display()),
+                        );
+                    } else {
+                        seen.insert(id, path.clone());
+                    }
+                }
+            }
+            Err(e) => problem(&path, "parse", format!("{:#}", e)),
         }
+        files.push((key, content));
+    }
 
-        let content = std::fs::read_to_string(&temp)?.trim().to_string();
-        std::fs::remove_file(&temp)?;
-
-        if content.is_empty() {
-            anyhow::bail!("Empty note, aborting");
+    if verify {
+        for (key, mismatch) in recorded.verify(&files) {
+            let (rule, message) = match mismatch {
+                manifest::Mismatch::Changed => {
+                    ("changed", "changed outside tk since it was last saved")
+                }
+                manifest::Mismatch::Missing => {
+                    ("missing", "recorded in .manifest, but the file is gone")
+                }
+                manifest::Mismatch::Unrecorded => ("unrecorded", "not recorded in .manifest"),
+            };
+            problem(&storage.tickets_dir().join(key), rule, message.to_string());
         }
-        content
-    };
-
-    let note = Note::new(content);
-    let formatted = note.format();
-
-    // Append note to body
-    if !ticket.body.is_empty() && !ticket.body.ends_with('\n') {
-        ticket.body.push('\n');
-    }
-    if !ticket.body.is_empty() {
-        ticket.body.push('\n');
     }
-    ticket.body.push_str(&formatted);
-    ticket.touch();
+    problems.sort_by(|a, b| a.path.cmp(&b.path));
+    let failed = !problems.is_empty();
 
-    storage.save(&ticket)?;
+    let rehashed = if rehash {
+        Some(storage.write_manifest(&fresh)?.display().to_string())
+    } else {
+        None
+    };
 
     if json {
-        println!(r#"{{"added":"{}"}}"#, ticket.id());
+        output::print(&output::DoctorReport {
+            schema: output::SCHEMA_VERSION,
+            problems,
+            rehashed: rehashed.as_ref().map(|_| fresh.len()),
+        })?;
     } else {
-        println!("Added note to {}", ticket.id());
+        for p in &problems {
+            println!("{}: [{}] {}", p.path, p.rule, p.message);
+        }
+        match &rehashed {
+            Some(path) => output::changed(
+                format!("Recorded {} ticket files in {}", fresh.len(), path),
+                &[path],
+            ),
+            None if !failed => println!("No problems found."),
+            None => {}
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
     }
     Ok(())
 }
 
-fn cmd_query(storage: &Storage, filter: Option<String>) -> Result<()> {
-    ensure_init(storage)?;
-
-    let tickets = storage.load_all()?;
-
-    let items: Vec<_> = tickets
-        .iter()
-        .map(|t| {
-            serde_json::json!({
-                "id": t.id(),
-                "title": t.title,
-                "status": t.meta.status.to_string(),
-                "priority": t.meta.priority,
-                "type": t.meta.ticket_type.to_string(),
-                "deps": t.meta.deps,
-                "tags": t.meta.tags,
-                "created": t.meta.created,
-            })
-        })
-        .collect();
+fn cmd_rescue(storage: &Storage, file: &Path, in_place: bool, json: bool) -> Result<()> {
+    let bytes =
+        std::fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let content = String::from_utf8_lossy(&bytes);
+    let path = file.display().to_string();
 
-    let json_str = serde_json::to_string(&items)?;
+    if Storage::parse_ticket(&content).is_ok() {
+        if json {
+            output::print(&output::Rescue {
+                schema: output::SCHEMA_VERSION,
+                path,
+                written: None,
+                id: None,
+                assumptions: Vec::new(),
+            })?;
+        } else {
+            println!("{} parses; nothing to rescue.", path);
+        }
+        return Ok(());
+    }
 
-    if let Some(filter) = filter {
-        // Pipe through jq if filter provided
-        let mut child = Command::new("jq")
-            .arg(&filter)
-            .stdin(std::process::Stdio::piped())
-            .spawn()
-            .context("Failed to run jq. Is it installed?")?;
+    let name_id = file
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .filter(|s| id::looks_like_id(s))
+        .map(str::to_string);
+    let created = std::fs::metadata(file)
+        .and_then(|m| m.modified())
+        .map_or_else(|_| Utc::now(), chrono::DateTime::from);
+    let new_id = || -> Result<String> {
+        let existing = storage.all_ids(Scope::All)?;
+        Ok(id::generate(&existing, storage.config()?.id_scheme))
+    };
+    let mut rescued = rescue::rescue(&content, name_id, new_id, created)?;
+    if matches!(content, std::borrow::Cow::Owned(_)) {
+        rescued.assumptions.insert(
+            0,
+            "replaced bytes that aren't UTF-8 with U+FFFD".to_string(),
+        );
+    }
 
-        if let Some(stdin) = child.stdin.as_mut() {
-            use std::io::Write;
-            stdin.write_all(json_str.as_bytes())?;
-        }
+    let target = if in_place {
+        file.to_path_buf()
+    } else {
+        let mut name = file.as_os_str().to_owned();
+        name.push(".rescued");
+        PathBuf::from(name)
+    };
+    storage.save_at(&target, &rescued.ticket)?;
 
-        child.wait()?;
+    let written = target.display().to_string();
+    if json {
+        output::print(&output::Rescue {
+            schema: output::SCHEMA_VERSION,
+            path,
+            written: Some(written),
+            id: Some(rescued.ticket.id().to_string()),
+            assumptions: rescued.assumptions,
+        })?;
     } else {
-        println!("{}", json_str);
+        output::changed(
+            format!(
+                "Rescued {} from {} into {}",
+                rescued.ticket.id(),
+                path,
+                written
+            ),
+            &[rescued.ticket.id(), &written],
+        );
+        // On stderr, so --porcelain output stays one record
+        if !rescued.assumptions.is_empty() {
+            eprintln!("Assumed:");
+            for a in &rescued.assumptions {
+                eprintln!("  {}", a);
+            }
+        }
     }
-
     Ok(())
 }
@@ -0,0 +1,158 @@
+//! `.tickets/.manifest`: the SHA-256 of every ticket file as tk last wrote
+//! it (`manifest: true` in config.yaml), so `tk doctor --verify` can tell
+//! which files changed behind tk's back, whether by a hand edit or a sync
+//! that mangled them.
+//!
+//! One `<hash>  <path>` line per file, paths relative to `.tickets/` and
+//! sorted, the same shape as `sha256sum` output, so
+//! `cd .tickets && sha256sum -c .manifest` checks it without tk.
+
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+pub const MANIFEST_FILE: &str = ".manifest";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Manifest {
+    /// Hex digest by path relative to `.tickets/`
+    hashes: BTreeMap<String, String>,
+}
+
+/// How a ticket file differs from what the manifest recorded
+#[derive(Debug, PartialEq)]
+pub enum Mismatch {
+    /// Its content isn't what tk last wrote
+    Changed,
+    /// Recorded, but the file is gone
+    Missing,
+    /// The file is there, but was never recorded
+    Unrecorded,
+}
+
+impl Manifest {
+    /// Read a manifest, skipping lines that aren't `<hash>  <path>`
+    pub fn parse(text: &str) -> Self {
+        let hashes = text
+            .lines()
+            .filter_map(|line| {
+                let (hash, path) = line.split_once("  ")?;
+                Some((path.to_string(), hash.to_string()))
+            })
+            .collect();
+        Self { hashes }
+    }
+
+    pub fn render(&self) -> String {
+        self.hashes
+            .iter()
+            .map(|(path, hash)| format!("{}  {}\n", hash, path))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Record that `path` now holds `content`
+    pub fn record(&mut self, path: &str, content: &str) {
+        self.hashes.insert(path.to_string(), hash(content));
+    }
+
+    pub fn forget(&mut self, path: &str) {
+        self.hashes.remove(path);
+    }
+
+    /// Carry `from`'s recorded hash over to `to`, for a file tk moved
+    pub fn rename(&mut self, from: &str, to: &str) {
+        if let Some(hash) = self.hashes.remove(from) {
+            self.hashes.insert(to.to_string(), hash);
+        }
+    }
+
+    /// Every recorded path whose file isn't in `files` (path and content)
+    /// as recorded, and every file in `files` that isn't recorded, in path
+    /// order
+    pub fn verify(&self, files: &[(String, String)]) -> Vec<(String, Mismatch)> {
+        let mut mismatches: Vec<(String, Mismatch)> = Vec::new();
+        for (path, content) in files {
+            match self.hashes.get(path) {
+                Some(recorded) if *recorded == hash(content) => {}
+                Some(_) => mismatches.push((path.clone(), Mismatch::Changed)),
+                None => mismatches.push((path.clone(), Mismatch::Unrecorded)),
+            }
+        }
+        for path in self.hashes.keys() {
+            if !files.iter().any(|(p, _)| p == path) {
+                mismatches.push((path.clone(), Mismatch::Missing));
+            }
+        }
+        mismatches.sort_by(|a, b| a.0.cmp(&b.0));
+        mismatches
+    }
+}
+
+fn hash(content: &str) -> String {
+    hex::encode(Sha256::digest(content.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(entries: &[(&str, &str)]) -> Vec<(String, String)> {
+        entries
+            .iter()
+            .map(|(path, content)| (path.to_string(), content.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn verify_reports_changed_missing_and_unrecorded_files_in_path_order() {
+        let mut manifest = Manifest::default();
+        manifest.record("tk-c3.md", "same");
+        manifest.record("tk-b2.md", "before");
+        manifest.record("tk-a1.md", "gone");
+        let found = files(&[
+            ("tk-d4.md", "new"),
+            ("tk-c3.md", "same"),
+            ("tk-b2.md", "after"),
+        ]);
+        assert_eq!(
+            manifest.verify(&found),
+            vec![
+                ("tk-a1.md".to_string(), Mismatch::Missing),
+                ("tk-b2.md".to_string(), Mismatch::Changed),
+                ("tk-d4.md".to_string(), Mismatch::Unrecorded),
+            ]
+        );
+    }
+
+    #[test]
+    fn renamed_files_keep_their_hash() {
+        let mut manifest = Manifest::default();
+        manifest.record("tk-a1.md", "body");
+        manifest.rename("tk-a1.md", "archive/tk-a1.md");
+        assert!(manifest
+            .verify(&files(&[("archive/tk-a1.md", "body")]))
+            .is_empty());
+        manifest.forget("archive/tk-a1.md");
+        assert!(manifest.is_empty());
+    }
+
+    #[test]
+    fn render_is_sha256sum_shaped_and_parses_back() {
+        let mut manifest = Manifest::default();
+        manifest.record("tk-b2.md", "b");
+        manifest.record("tk-a1.md", "a");
+        let text = manifest.render();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("  tk-a1.md"));
+        assert_eq!(lines[0].split_once("  ").unwrap().0, hash("a"));
+        assert_eq!(Manifest::parse(&format!("junk\n{}", text)), manifest);
+    }
+}
@@ -0,0 +1,318 @@
+//! `tk merge-file`, the git merge driver `tk init --git` registers for
+//! ticket files. It merges a ticket's fields, title, text and notes one by
+//! one, so two clones that changed different parts of a ticket (or each
+//! added a note, or each archived different tickets into a bundle) merge
+//! cleanly. Whatever it can't settle goes to `git merge-file`, which
+//! leaves the usual conflict markers.
+
+use crate::storage::{FrontmatterFormat, Storage};
+use crate::types::{Frontmatter, Ticket};
+use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Merge the files git passes as %O, %A and %B, writing the result over
+/// `ours` (%A). `path` (%P) is the file's path in the repo, which tells an
+/// archive bundle from a ticket. Returns whether the merge was clean.
+pub fn merge_file(base: &Path, ours: &Path, theirs: &Path, path: &Path) -> Result<bool> {
+    let read =
+        |p: &Path| fs::read_to_string(p).with_context(|| format!("Failed to read {}", p.display()));
+    let (base_text, ours_text, theirs_text) = (read(base)?, read(ours)?, read(theirs)?);
+    let merged = if is_bundle(path) {
+        merge_bundle(&base_text, &ours_text, &theirs_text, path)
+    } else {
+        merge_ticket(&base_text, &ours_text, &theirs_text)
+    };
+    if let Some(text) = merged {
+        fs::write(ours, text).with_context(|| format!("Failed to write {}", ours.display()))?;
+        return Ok(true);
+    }
+    let status = Command::new("git")
+        .args([
+            "merge-file",
+            "-L",
+            "ours",
+            "-L",
+            "base",
+            "-L",
+            "theirs",
+            "--",
+        ])
+        .args([ours, base, theirs])
+        .status()
+        .context("Failed to run git merge-file")?;
+    Ok(status.success())
+}
+
+fn is_bundle(path: &Path) -> bool {
+    path.parent()
+        .is_some_and(|dir| dir.ends_with("archive/bundles"))
+}
+
+/// Three versions of a ticket file merged, or None if both sides changed
+/// the same thing differently (or a version doesn't parse)
+pub fn merge_ticket(base: &str, ours: &str, theirs: &str) -> Option<String> {
+    if let Some(text) = pick(&base, &ours, &theirs) {
+        return Some(text.to_string());
+    }
+    let format = FrontmatterFormat::detect(ours)?;
+    let parse = |text: &str| Storage::parse_ticket(text).ok();
+    let merged = merge(&parse(base)?, &parse(ours)?, &parse(theirs)?)?;
+    Storage::render_over(ours, &merged, format).ok()
+}
+
+/// Three versions of a year's archive bundle merged ticket by ticket
+fn merge_bundle(base: &str, ours: &str, theirs: &str, path: &Path) -> Option<String> {
+    if let Some(text) = pick(&base, &ours, &theirs) {
+        return Some(text.to_string());
+    }
+    let year: i32 = path.file_stem()?.to_str()?.parse().ok()?;
+    let format = Storage::bundle_sections(ours)
+        .chain(Storage::bundle_sections(theirs))
+        .find_map(FrontmatterFormat::detect)
+        .unwrap_or_default();
+    let parse = |text: &str| Storage::parse_whole_bundle(text, path).ok();
+    let (base, ours, theirs) = (parse(base)?, parse(ours)?, parse(theirs)?);
+    let find = |tickets: &'_ [Ticket], id: &str| tickets.iter().find(|t| t.id() == id).cloned();
+
+    let ids: BTreeSet<&str> = [&base, &ours, &theirs]
+        .into_iter()
+        .flatten()
+        .map(|t| t.id())
+        .collect();
+    let mut tickets = Vec::new();
+    for id in ids {
+        let merged = match (find(&base, id), find(&ours, id), find(&theirs, id)) {
+            (Some(b), Some(o), Some(t)) => Some(merge(&b, &o, &t)?),
+            (None, Some(o), Some(t)) => Some(same(&o, &t).then_some(o)?),
+            // Dropped on one side: fine if the other side left it alone
+            (Some(b), Some(kept), None) | (Some(b), None, Some(kept)) => {
+                same(&b, &kept).then_some(None)?
+            }
+            (None, Some(added), None) | (None, None, Some(added)) => Some(added),
+            (Some(_), None, None) | (None, None, None) => None,
+        };
+        tickets.extend(merged);
+    }
+    Storage::serialize_bundle(year, &tickets, format).ok()
+}
+
+fn same(a: &Ticket, b: &Ticket) -> bool {
+    let text = |t| Storage::serialize_ticket(t, FrontmatterFormat::Yaml).ok();
+    text(a) == text(b)
+}
+
+fn merge(base: &Ticket, ours: &Ticket, theirs: &Ticket) -> Option<Ticket> {
+    Some(Ticket {
+        meta: merge_meta(&base.meta, &ours.meta, &theirs.meta)?,
+        title: pick(&base.title, &ours.title, &theirs.title)?.clone(),
+        body: merge_body(&base.body, &ours.body, &theirs.body)?,
+        archived: ours.archived,
+        layout: ours.layout.clone(),
+    })
+}
+
+/// The side that changed `base`, or either if they agree; None if both
+/// changed it differently
+fn pick<'a, T: PartialEq>(base: &T, ours: &'a T, theirs: &'a T) -> Option<&'a T> {
+    if ours == theirs || theirs == base {
+        Some(ours)
+    } else if ours == base {
+        Some(theirs)
+    } else {
+        None
+    }
+}
+
+/// Frontmatter merged key by key. Lists (tags, deps, events, ...) changed
+/// on both sides keep both sides' additions and removals; so does
+/// `updated`, which takes the later time.
+fn merge_meta(base: &Frontmatter, ours: &Frontmatter, theirs: &Frontmatter) -> Option<Frontmatter> {
+    let mapping = |meta: &Frontmatter| match serde_yaml::to_value(meta) {
+        Ok(Value::Mapping(map)) => Some(map),
+        _ => None,
+    };
+    let (base, ours, theirs) = (mapping(base)?, mapping(ours)?, mapping(theirs)?);
+
+    let mut merged = Mapping::new();
+    for key in ours.keys().chain(theirs.keys()) {
+        if merged.contains_key(key) {
+            continue;
+        }
+        let (b, o, t) = (base.get(key), ours.get(key), theirs.get(key));
+        let value = match pick(&b, &o, &t) {
+            Some(value) => value.cloned(),
+            None if key.as_str() == Some("updated") => [o, t]
+                .into_iter()
+                .flatten()
+                .max_by_key(|v| v.as_str())
+                .cloned(),
+            None => Some(Value::Sequence(merge_sequence(key, b, o, t)?)),
+        };
+        if let Some(value) = value {
+            merged.insert(key.clone(), value);
+        }
+    }
+    serde_yaml::from_value(Value::Mapping(merged)).ok()
+}
+
+/// Both sides' changes to a list-valued key, or None if it isn't a list
+fn merge_sequence(
+    key: &Value,
+    base: Option<&Value>,
+    ours: Option<&Value>,
+    theirs: Option<&Value>,
+) -> Option<Vec<Value>> {
+    let items = |value: Option<&Value>| match value {
+        None => Some(Vec::new()),
+        Some(Value::Sequence(items)) => Some(items.clone()),
+        Some(_) => None,
+    };
+    let mut merged = merge_lists(&items(base)?, &items(ours)?, &items(theirs)?);
+    if key.as_str() == Some("events") {
+        merged.sort_by(|a, b| {
+            a.get("at")
+                .and_then(Value::as_str)
+                .cmp(&b.get("at").and_then(Value::as_str))
+        });
+    }
+    Some(merged)
+}
+
+/// `ours` with the items `theirs` added and without the ones it removed
+fn merge_lists<T: PartialEq + Clone>(base: &[T], ours: &[T], theirs: &[T]) -> Vec<T> {
+    let mut merged: Vec<T> = ours
+        .iter()
+        .filter(|item| theirs.contains(item) || !base.contains(item))
+        .cloned()
+        .collect();
+    merged.extend(
+        theirs
+            .iter()
+            .filter(|item| !base.contains(item) && !ours.contains(item))
+            .cloned(),
+    );
+    merged
+}
+
+/// A body merged as its text and its notes: the text as either side
+/// changed it, and every note either side added, in time order
+fn merge_body(base: &str, ours: &str, theirs: &str) -> Option<String> {
+    if let Some(body) = pick(&base, &ours, &theirs) {
+        return Some(body.to_string());
+    }
+    let (base_text, base_notes) = split_body(base);
+    let (ours_text, ours_notes) = split_body(ours);
+    let (theirs_text, theirs_notes) = split_body(theirs);
+    let text = pick(&base_text, &ours_text, &theirs_text)?;
+    let mut notes = merge_lists(&base_notes, &ours_notes, &theirs_notes);
+    // A note starts `[YYYY-MM-DD HH:MM`, so its text sorts by time
+    notes.sort_by(|a, b| a.get(..17).cmp(&b.get(..17)));
+
+    let mut body = text.to_string();
+    if !notes.is_empty() {
+        if !body.is_empty() {
+            body.push_str("\n\n");
+        }
+        body.push_str(&notes.join("\n\n"));
+        body.push('\n');
+    }
+    Some(body)
+}
+
+/// A body's text before its notes, and each note, if the notes come last
+/// (otherwise the whole body is text)
+fn split_body(body: &str) -> (&str, Vec<String>) {
+    match Storage::notes_section(body) {
+        Some(section) => {
+            let text = body[..body.len() - section.len()].trim_end();
+            let notes = Storage::split_notes(section)
+                .1
+                .into_iter()
+                .map(|lines| lines.join("\n").trim_end().to_string())
+                .collect();
+            (text, notes)
+        }
+        None => (body, Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "---\nid: tk-1\nstatus: open\ncreated: 2026-01-01T09:00:00Z\ntags:\n- ui\n---\n# Fix the menu\n\nIt flickers.\n\n[2026-01-02 10:00 ana] First look.\n";
+
+    #[test]
+    fn changes_to_different_fields_and_new_notes_all_land() {
+        let ours = BASE.replace("status: open", "status: in-progress").replace(
+            "First look.\n",
+            "First look.\n\n[2026-01-03 09:00 ana] On it.\n",
+        );
+        let theirs = BASE.replace("- ui\n", "- ui\n- urgent\n").replace(
+            "First look.\n",
+            "First look.\n\n[2026-01-02 12:00 bo] Seen on mobile too.\n",
+        );
+        let merged = Storage::parse_ticket(&merge_ticket(BASE, &ours, &theirs).unwrap()).unwrap();
+
+        assert_eq!(merged.meta.status.to_string(), "in-progress");
+        assert_eq!(merged.meta.tags, ["ui", "urgent"]);
+        let notes: Vec<String> = Storage::parse_notes(&merged.body)
+            .into_iter()
+            .map(|n| n.content)
+            .collect();
+        assert_eq!(notes, ["First look.", "Seen on mobile too.", "On it."]);
+        assert!(merged.body.starts_with("It flickers.\n\n"));
+    }
+
+    #[test]
+    fn removals_on_one_side_survive_additions_on_the_other() {
+        let ours = BASE.replace("- ui\n", "- ux\n");
+        let theirs = BASE.replace("- ui\n", "- ui\n- urgent\n");
+        let merged = Storage::parse_ticket(&merge_ticket(BASE, &ours, &theirs).unwrap()).unwrap();
+        assert_eq!(merged.meta.tags, ["ux", "urgent"]);
+    }
+
+    #[test]
+    fn the_same_thing_changed_two_ways_is_left_to_git() {
+        let ours = BASE.replace("status: open", "status: closed");
+        let theirs = BASE.replace("status: open", "status: in-progress");
+        assert_eq!(merge_ticket(BASE, &ours, &theirs), None);
+
+        let ours = BASE.replace("It flickers.", "It flickers on hover.");
+        let theirs = BASE.replace("It flickers.", "It flickers on scroll.");
+        assert_eq!(merge_ticket(BASE, &ours, &theirs), None);
+
+        assert_eq!(merge_ticket(BASE, "not a ticket", &theirs), None);
+    }
+
+    fn bundle(tickets: &[&str]) -> String {
+        let tickets: Vec<Ticket> = tickets
+            .iter()
+            .map(|id| Storage::parse_ticket(&BASE.replace("tk-1", id)).unwrap())
+            .collect();
+        Storage::serialize_bundle(2025, &tickets, FrontmatterFormat::Yaml).unwrap()
+    }
+
+    #[test]
+    fn bundles_keep_tickets_either_side_archived() {
+        let path = Path::new(".tickets/archive/bundles/2025.md");
+        assert!(is_bundle(path));
+        let base = bundle(&["tk-1", "tk-2"]);
+        let ours = bundle(&["tk-1", "tk-2", "tk-3"]);
+        let theirs = bundle(&["tk-2", "tk-4"]);
+        let merged = merge_bundle(&base, &ours, &theirs, path).unwrap();
+        let ids: Vec<String> = Storage::parse_bundle(&merged, path)
+            .iter()
+            .map(|t| t.id().to_string())
+            .collect();
+        assert_eq!(ids, ["tk-2", "tk-3", "tk-4"]);
+
+        // Changed on one side, dropped on the other: a real conflict
+        let ours = ours.replace("It flickers.", "It flickers badly.");
+        assert_eq!(merge_bundle(&base, &ours, &theirs, path), None);
+    }
+}
@@ -0,0 +1,160 @@
+//! Prometheus text exposition for `tk daemon --metrics`.
+//!
+//! Everything is a gauge computed from the current tickets on each scrape.
+//! Counts cover live tickets; archived ones are reported as a single total.
+
+use crate::types::{Status, Ticket};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Render all metrics for `tickets` (live and archived) as of `now`
+pub fn render(tickets: &[Ticket], now: DateTime<Utc>) -> String {
+    let live: Vec<&Ticket> = tickets.iter().filter(|t| !t.archived).collect();
+    let open: Vec<&Ticket> = live.iter().copied().filter(|t| t.is_open()).collect();
+
+    let mut out = String::new();
+
+    let mut by_status: BTreeMap<String, usize> = [Status::Open, Status::InProgress, Status::Closed]
+        .iter()
+        .map(|s| (s.to_string(), 0))
+        .collect();
+    for t in &live {
+        *by_status.entry(t.meta.status.to_string()).or_default() += 1;
+    }
+    gauge_family(
+        &mut out,
+        "tk_tickets",
+        "Tickets by status",
+        "status",
+        &by_status,
+    );
+
+    let mut by_priority = BTreeMap::new();
+    for t in &open {
+        *by_priority.entry(t.meta.priority.to_string()).or_default() += 1;
+    }
+    gauge_family(
+        &mut out,
+        "tk_open_tickets_by_priority",
+        "Open tickets by priority",
+        "priority",
+        &by_priority,
+    );
+
+    let mut by_type = BTreeMap::new();
+    for t in &open {
+        *by_type.entry(t.meta.ticket_type.to_string()).or_default() += 1;
+    }
+    gauge_family(
+        &mut out,
+        "tk_open_tickets_by_type",
+        "Open tickets by type",
+        "type",
+        &by_type,
+    );
+
+    let mut by_tag = BTreeMap::new();
+    for t in &open {
+        for tag in &t.meta.tags {
+            *by_tag.entry(tag.clone()).or_default() += 1;
+        }
+    }
+    gauge_family(
+        &mut out,
+        "tk_open_tickets_by_tag",
+        "Open tickets by tag",
+        "tag",
+        &by_tag,
+    );
+
+    let blocked = open
+        .iter()
+        .filter(|t| t.meta.blocked || t.is_blocked_by(tickets))
+        .count();
+    gauge(
+        &mut out,
+        "tk_blocked_tickets",
+        "Open tickets waiting on open deps or blocked with tk block",
+        blocked as f64,
+    );
+    gauge(
+        &mut out,
+        "tk_ready_tickets",
+        "Open tickets neither waiting on open deps nor blocked",
+        (open.len() - blocked) as f64,
+    );
+
+    let mean_age = if open.is_empty() {
+        0.0
+    } else {
+        open.iter()
+            .map(|t| (now - t.meta.created).num_seconds() as f64)
+            .sum::<f64>()
+            / open.len() as f64
+    };
+    gauge(
+        &mut out,
+        "tk_open_ticket_age_seconds_mean",
+        "Mean age of open tickets",
+        mean_age,
+    );
+
+    gauge(
+        &mut out,
+        "tk_archived_tickets",
+        "Tickets in .tickets/archive/",
+        (tickets.len() - live.len()) as f64,
+    );
+
+    out
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn gauge_family(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    values: &BTreeMap<String, usize>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    for (key, count) in values {
+        let _ = writeln!(
+            out,
+            "{}{{{}=\"{}\"}} {}",
+            name,
+            label,
+            escape_label(key),
+            count
+        );
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tickets_blocked_by_hand_are_not_ready() {
+        let mut held = Ticket::new("tk-0001".to_string(), "Held".to_string());
+        held.meta.blocked = true;
+        let free = Ticket::new("tk-0002".to_string(), "Free".to_string());
+        let out = render(&[held, free], Utc::now());
+        assert!(out.contains("\ntk_blocked_tickets 1\n"), "{}", out);
+        assert!(out.contains("\ntk_ready_tickets 1\n"), "{}", out);
+    }
+}
@@ -0,0 +1,119 @@
+/// A single ticket-to-be parsed from a Markdown outline
+#[derive(Debug, Clone)]
+pub struct OutlineItem {
+    pub title: String,
+    pub body: String,
+    /// Index of the enclosing item in the returned list
+    pub parent: Option<usize>,
+}
+
+/// Parse a Markdown outline into a flat list of items in document order.
+///
+/// Every `#` heading and every list item becomes an item. Nesting comes from
+/// heading levels and list indentation: list items belong to the heading they
+/// appear under, and indented list items belong to the item above them. Any
+/// other text is attached to the body of the most recent item.
+pub fn parse(input: &str) -> Vec<OutlineItem> {
+    let mut items: Vec<OutlineItem> = Vec::new();
+    // (depth, index into items) for the current chain of ancestors
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    // Indent widths of the currently open list levels
+    let mut list_indents: Vec<usize> = Vec::new();
+    let mut heading_depth = 0;
+    let mut in_fence = false;
+
+    for line in input.lines() {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+            append_body(&mut items, line);
+            continue;
+        }
+
+        let entry = if in_fence {
+            None
+        } else if let Some((level, title)) = parse_heading(trimmed) {
+            heading_depth = level;
+            list_indents.clear();
+            Some((level, title))
+        } else if let Some(title) = parse_list_item(trimmed) {
+            while list_indents.last().is_some_and(|&i| i > indent) {
+                list_indents.pop();
+            }
+            if list_indents.last() != Some(&indent) {
+                list_indents.push(indent);
+            }
+            Some((heading_depth + list_indents.len(), title))
+        } else {
+            None
+        };
+
+        let Some((depth, title)) = entry else {
+            append_body(&mut items, line.trim());
+            continue;
+        };
+
+        while stack.last().is_some_and(|&(d, _)| d >= depth) {
+            stack.pop();
+        }
+
+        items.push(OutlineItem {
+            title,
+            body: String::new(),
+            parent: stack.last().map(|&(_, i)| i),
+        });
+        stack.push((depth, items.len() - 1));
+    }
+
+    for item in &mut items {
+        item.body = item.body.trim().to_string();
+    }
+    items
+}
+
+fn parse_heading(line: &str) -> Option<(usize, String)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+    let title = line[level..].strip_prefix(' ')?.trim();
+    (!title.is_empty()).then(|| (level, title.to_string()))
+}
+
+fn parse_list_item(line: &str) -> Option<String> {
+    let rest = if let Some(rest) = line
+        .strip_prefix("- ")
+        .or_else(|| line.strip_prefix("* "))
+        .or_else(|| line.strip_prefix("+ "))
+    {
+        rest
+    } else {
+        // Ordered list: "1. item" or "1) item"
+        let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        line[digits..]
+            .strip_prefix(". ")
+            .or_else(|| line[digits..].strip_prefix(") "))?
+    };
+
+    // Drop task-list checkboxes
+    let rest = rest
+        .strip_prefix("[ ] ")
+        .or_else(|| rest.strip_prefix("[x] "))
+        .or_else(|| rest.strip_prefix("[X] "))
+        .unwrap_or(rest)
+        .trim();
+
+    (!rest.is_empty()).then(|| rest.to_string())
+}
+
+fn append_body(items: &mut [OutlineItem], line: &str) {
+    if let Some(item) = items.last_mut() {
+        item.body.push_str(line);
+        item.body.push('\n');
+    }
+}
@@ -0,0 +1,1186 @@
+//! Typed shapes for every `--json` output.
+//!
+//! Each top-level object carries `"schema": SCHEMA_VERSION`. Bump the version
+//! when a field is removed, renamed, or changes type; adding fields is not a
+//! breaking change. `tk schema` prints the JSON Schema for each shape.
+
+use crate::types::{Event, ExternalRef, Note, Ticket};
+use chrono::{DateTime, NaiveDate, Utc};
+use schemars::schema::RootSchema;
+use schemars::{schema_for, JsonSchema};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How commands that change tickets confirm it when not printing JSON
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Confirmations {
+    /// Sentences for people: "Created tk-a1b2 - Title"
+    #[default]
+    Prose,
+    /// Nothing; the exit code says it worked
+    Quiet,
+    /// `--porcelain`: the changed values, tab-separated, one record per line.
+    /// Fields are only ever added at the end.
+    Porcelain,
+}
+
+static CONFIRMATIONS: OnceLock<Confirmations> = OnceLock::new();
+
+/// Set once at startup from `-q`/`--porcelain`
+pub fn set_confirmations(mode: Confirmations) {
+    let _ = CONFIRMATIONS.set(mode);
+}
+
+/// Whether to color output: writing to a terminal and `NO_COLOR` isn't set
+fn color() -> bool {
+    static COLOR: OnceLock<bool> = OnceLock::new();
+    *COLOR.get_or_init(|| {
+        use std::io::IsTerminal;
+        std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+    })
+}
+
+/// `line` in the given ANSI color (31 red, 33 yellow) when coloring
+pub fn paint(code: u8, line: String) -> String {
+    if color() {
+        format!("\x1b[{}m{}\x1b[0m", code, line)
+    } else {
+        line
+    }
+}
+
+/// `text` as an OSC 8 hyperlink to `url`, which terminals that support it
+/// make clickable and the rest show as plain `text`
+pub fn hyperlink(url: &str, text: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+/// Show a critical ticket's line (`escalate.priority`, P0 unless set) in red
+pub fn flag_critical(critical: bool, line: String) -> String {
+    if critical {
+        paint(31, line)
+    } else {
+        line
+    }
+}
+
+/// Confirm a change: `prose` normally, `fields` joined by tabs under
+/// `--porcelain`, nothing under `--quiet`
+pub fn changed(prose: impl std::fmt::Display, fields: &[&str]) {
+    match CONFIRMATIONS.get().copied().unwrap_or_default() {
+        Confirmations::Prose => println!("{}", prose),
+        Confirmations::Quiet => {}
+        Confirmations::Porcelain => println!("{}", fields.join("\t")),
+    }
+}
+
+/// Confirm a change that touched several tickets: `prose` once normally,
+/// one line of tab-joined fields per record under `--porcelain`
+pub fn changed_records(prose: impl std::fmt::Display, records: &[Vec<&str>]) {
+    match CONFIRMATIONS.get().copied().unwrap_or_default() {
+        Confirmations::Prose => println!("{}", prose),
+        Confirmations::Quiet => {}
+        Confirmations::Porcelain => {
+            for fields in records {
+                println!("{}", fields.join("\t"));
+            }
+        }
+    }
+}
+
+/// How `--json` output is laid out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonStyle {
+    /// Indented on a terminal, one line when piped
+    #[default]
+    Auto,
+    /// `--pretty`
+    Pretty,
+    /// `--compact`
+    Compact,
+}
+
+static JSON_STYLE: OnceLock<JsonStyle> = OnceLock::new();
+
+/// Set once at startup from `--pretty`/`--compact`
+pub fn set_json_style(style: JsonStyle) {
+    let _ = JSON_STYLE.set(style);
+}
+
+/// Print a value as JSON, laid out as `--pretty`/`--compact` say
+pub fn print<T: Serialize>(value: &T) -> anyhow::Result<()> {
+    let pretty = match JSON_STYLE.get().copied().unwrap_or_default() {
+        JsonStyle::Pretty => true,
+        JsonStyle::Compact => false,
+        JsonStyle::Auto => {
+            use std::io::IsTerminal;
+            std::io::stdout().is_terminal()
+        }
+    };
+    if pretty {
+        println!("{}", serde_json::to_string_pretty(value)?);
+    } else {
+        println!("{}", serde_json::to_string(value)?);
+    }
+    Ok(())
+}
+
+/// `init`
+#[derive(Serialize, JsonSchema)]
+pub struct InitResult {
+    pub schema: u32,
+    /// "initialized" or "already_initialized"
+    pub status: String,
+    /// `--preset` written to config.yaml
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// IDs of the `--with-examples` tickets
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<String>,
+    /// Short hash of the `--git` commit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+/// `config export`
+#[derive(Serialize, JsonSchema)]
+pub struct ConfigExport {
+    pub schema: u32,
+    /// File written with `--output`
+    pub path: Option<String>,
+    /// config.yaml as it stands (empty when there is none)
+    pub yaml: String,
+}
+
+/// `config import`
+#[derive(Serialize, JsonSchema)]
+pub struct ConfigImported {
+    pub schema: u32,
+    pub path: String,
+    /// An existing config.yaml was overwritten
+    pub replaced: bool,
+}
+
+/// `create` (one object), `create --bulk` (an array), `draft` and `publish`
+#[derive(Serialize, JsonSchema)]
+pub struct Created {
+    pub schema: u32,
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slug: Option<String>,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// The ticket file that was written
+    pub path: String,
+}
+
+impl Created {
+    pub fn new(t: &Ticket, path: &Path) -> Self {
+        Self {
+            schema: SCHEMA_VERSION,
+            id: t.id().to_string(),
+            slug: t.meta.slug.clone(),
+            title: t.title.clone(),
+            parent: t.meta.parent.clone(),
+            path: path.display().to_string(),
+        }
+    }
+}
+
+/// `list` and `ready` entries
+#[derive(Serialize, JsonSchema)]
+pub struct TicketSummary {
+    pub schema: u32,
+    pub id: String,
+    pub title: String,
+    pub status: String,
+    pub priority: u8,
+    #[serde(rename = "type")]
+    pub ticket_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Weighted score (`ready --sort score` and `next`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// `ready --by-due`: overdue, due-soon, due-later or no-due-date
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<String>,
+}
+
+impl From<&Ticket> for TicketSummary {
+    fn from(t: &Ticket) -> Self {
+        Self {
+            schema: SCHEMA_VERSION,
+            id: t.id().to_string(),
+            title: t.title.clone(),
+            status: t.meta.status.to_string(),
+            priority: t.meta.priority,
+            ticket_type: t.meta.ticket_type.to_string(),
+            parent: t.meta.parent.clone(),
+            score: None,
+            deadline: None,
+        }
+    }
+}
+
+/// `claim`: the claimed ticket, body included
+#[derive(Serialize, JsonSchema)]
+pub struct Claimed {
+    #[serde(flatten)]
+    pub ticket: TicketRecord,
+    pub body: String,
+}
+
+/// `suggest`: the ticket picked, body included
+#[derive(Serialize, JsonSchema)]
+pub struct Suggestion {
+    #[serde(flatten)]
+    pub ticket: TicketRecord,
+    pub body: String,
+    /// How many tickets it was picked from
+    pub candidates: usize,
+}
+
+/// `reap`: tickets whose lease ran out
+#[derive(Serialize, JsonSchema)]
+pub struct Reaped {
+    pub schema: u32,
+    pub id: String,
+    /// Who held the lease
+    pub assignee: Option<String>,
+    pub claimed_until: DateTime<Utc>,
+}
+
+/// `review-queue`: closed tickets nobody has verified yet, oldest first
+#[derive(Serialize, JsonSchema)]
+pub struct ReviewItem {
+    pub schema: u32,
+    pub id: String,
+    pub title: String,
+    pub priority: u8,
+    pub closed: Option<DateTime<Utc>>,
+    /// Who closed it, if the history still says
+    pub closed_by: Option<String>,
+}
+
+/// `review done`
+#[derive(Serialize, JsonSchema)]
+pub struct Reviewed {
+    pub schema: u32,
+    pub id: String,
+    pub reviewer: String,
+    pub reviewed: DateTime<Utc>,
+}
+
+/// `list --tree` nodes: tickets nested under their parents
+#[derive(Serialize, JsonSchema)]
+pub struct ListNode {
+    #[serde(flatten)]
+    pub ticket: TicketSummary,
+    pub children: Vec<ListNode>,
+    /// Descendants left out because this ticket is closed (see --expand)
+    pub hidden: usize,
+    /// For a parent, the estimates of everything under it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollup: Option<Rollup>,
+}
+
+/// Estimates summed over a parent's descendants. A descendant with
+/// children of its own counts as them, not with its own estimate.
+#[derive(Serialize, JsonSchema)]
+pub struct Rollup {
+    /// Tickets counted
+    pub tickets: usize,
+    pub open: usize,
+    /// Estimates of every counted ticket, closed ones included
+    pub estimate: u32,
+    /// Estimates of the open ones
+    pub remaining: u32,
+    /// Open tickets without an estimate, so not in `remaining`
+    pub unestimated: usize,
+}
+
+impl From<&crate::graph::Rollup> for Rollup {
+    fn from(sum: &crate::graph::Rollup) -> Self {
+        Rollup {
+            tickets: sum.tickets,
+            open: sum.open,
+            estimate: sum.estimate,
+            remaining: sum.remaining,
+            unestimated: sum.unestimated,
+        }
+    }
+}
+
+/// `blocked` entries
+#[derive(Serialize, JsonSchema)]
+pub struct BlockedTicket {
+    #[serde(flatten)]
+    pub ticket: TicketSummary,
+    /// Open deps this ticket is waiting on
+    pub blocked_by: Vec<String>,
+    /// Tickets that depend on this one
+    pub blocks: Vec<String>,
+    /// With `--external`: deps with no ticket in this store
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub external: Vec<String>,
+    /// With `--external`: blocked by hand (`tk block`)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub manual: bool,
+    /// Why, when blocked by hand with a reason
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// `plan`
+#[derive(Serialize, JsonSchema)]
+pub struct Plan {
+    pub schema: u32,
+    /// Everything in a wave can proceed at once, after the waves before it
+    pub waves: Vec<Vec<PlanItem>>,
+    /// Open tickets stuck behind a cycle or an archived open dep
+    pub unschedulable: Vec<String>,
+    /// The chain of deps with the most estimated work (1 per unestimated
+    /// ticket), first to do first
+    pub critical_path: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct PlanItem {
+    pub id: String,
+    pub title: String,
+    pub priority: u8,
+    pub status: String,
+    pub assignee: Option<String>,
+    /// Open deps, all in earlier waves
+    pub after: Vec<String>,
+}
+
+/// `plan --for`
+#[derive(Serialize, JsonSchema)]
+pub struct Queue {
+    pub schema: u32,
+    pub assignee: String,
+    /// First to do first
+    pub queue: Vec<QueuedTicket>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct QueuedTicket {
+    /// in-progress, assigned (ready and theirs) or tagged (ready, unassigned,
+    /// sharing a tag with their tickets)
+    pub reason: String,
+    #[serde(flatten)]
+    pub ticket: TicketSummary,
+}
+
+/// `why-blocked`
+#[derive(Serialize, JsonSchema)]
+pub struct BlockerChains {
+    pub schema: u32,
+    pub id: String,
+    /// Paths of open deps from this ticket down to a leaf blocker; each
+    /// starts with the ticket itself
+    pub chains: Vec<Vec<String>>,
+    /// Open tickets at the end of the chains, with no open deps of their own
+    pub leaves: Vec<TicketSummary>,
+}
+
+/// `count`
+#[derive(Serialize, JsonSchema)]
+pub struct Counts {
+    pub schema: u32,
+    pub total: usize,
+    /// Count per value of the `--by` field; a ticket with several tags is
+    /// counted under each
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub by: Option<BTreeMap<String, usize>>,
+}
+
+/// `tags` entries
+#[derive(Serialize, JsonSchema)]
+pub struct TagCount {
+    pub schema: u32,
+    pub tag: String,
+    pub count: usize,
+}
+
+/// `tags --suggest-merges` entries
+#[derive(Serialize, JsonSchema)]
+pub struct TagMerge {
+    pub schema: u32,
+    /// The most used spelling
+    pub into: String,
+    /// Spellings that look like the same tag
+    pub from: Vec<String>,
+}
+
+/// `query` entries: the full record
+#[derive(Serialize, JsonSchema)]
+pub struct TicketRecord {
+    pub schema: u32,
+    pub id: String,
+    pub slug: Option<String>,
+    pub title: String,
+    pub status: String,
+    pub priority: u8,
+    #[serde(rename = "type")]
+    pub ticket_type: String,
+    pub deps: Vec<String>,
+    pub parent: Option<String>,
+    pub tags: Vec<String>,
+    pub links: Vec<String>,
+    pub external: Vec<ExternalRef>,
+    pub assignee: Option<String>,
+    pub claimed_until: Option<DateTime<Utc>>,
+    pub milestone: Option<String>,
+    pub estimate: Option<u32>,
+    pub snoozed_until: Option<NaiveDate>,
+    pub blocked: bool,
+    pub blocked_reason: Option<String>,
+    pub due: Option<NaiveDate>,
+    pub created: DateTime<Utc>,
+    pub updated: Option<DateTime<Utc>>,
+    pub closed: Option<DateTime<Utc>>,
+    pub resolution: Option<String>,
+    pub reviewed: Option<DateTime<Utc>>,
+    pub reviewer: Option<String>,
+    pub archived: bool,
+    pub reopened_count: usize,
+    pub reopened: Vec<DateTime<Utc>>,
+    pub events: Vec<Event>,
+    pub watchers: Vec<String>,
+    pub locked: bool,
+}
+
+impl From<&Ticket> for TicketRecord {
+    fn from(t: &Ticket) -> Self {
+        Self {
+            schema: SCHEMA_VERSION,
+            id: t.id().to_string(),
+            slug: t.meta.slug.clone(),
+            title: t.title.clone(),
+            status: t.meta.status.to_string(),
+            priority: t.meta.priority,
+            ticket_type: t.meta.ticket_type.to_string(),
+            deps: t.meta.deps.clone(),
+            parent: t.meta.parent.clone(),
+            tags: t.meta.tags.clone(),
+            links: t.meta.links.clone(),
+            external: t.meta.external.clone(),
+            assignee: t.meta.assignee.clone(),
+            claimed_until: t.meta.claimed_until,
+            milestone: t.meta.milestone.clone(),
+            estimate: t.meta.estimate,
+            snoozed_until: t.meta.snoozed_until,
+            blocked: t.meta.blocked,
+            blocked_reason: t.meta.blocked_reason.clone(),
+            due: t.meta.due,
+            created: t.meta.created,
+            updated: t.meta.updated,
+            closed: t.meta.closed,
+            resolution: t.meta.resolution.clone(),
+            reviewed: t.meta.reviewed,
+            reviewer: t.meta.reviewer.clone(),
+            archived: t.archived,
+            reopened_count: t.meta.reopened.len(),
+            reopened: t.meta.reopened.clone(),
+            events: t.meta.events.clone(),
+            watchers: t.meta.watchers.clone(),
+            locked: t.meta.locked,
+        }
+    }
+}
+
+/// What `hooks.on_change` commands read on stdin
+#[derive(Serialize, JsonSchema)]
+pub struct HookPayload {
+    pub schema: u32,
+    /// `created`, `edited`, `status`, `priority`, `assignee`, `handoff`,
+    /// `note`, `watch`, `reviewed`, or `escalated` for `escalate` commands and
+    /// webhooks
+    pub action: String,
+    pub id: String,
+    pub title: String,
+    pub status: String,
+    pub priority: u8,
+    /// Who made the change
+    pub by: Option<String>,
+    pub watchers: Vec<String>,
+    /// `@names` in the new text (the note, or the body on create and edit)
+    pub mentions: Vec<String>,
+    /// Watchers and mentions, without whoever made the change
+    pub notify: Vec<String>,
+}
+
+/// `handoff`
+#[derive(Serialize, JsonSchema)]
+pub struct Handoff {
+    pub schema: u32,
+    pub id: String,
+    /// The previous assignee
+    pub from: Option<String>,
+    pub to: String,
+    /// Status after the handoff (`open` with `--reopen`)
+    pub status: String,
+    /// Number of the handoff note
+    pub note: usize,
+}
+
+/// `watch-ticket`
+#[derive(Serialize, JsonSchema)]
+pub struct Watchers {
+    pub schema: u32,
+    pub id: String,
+    pub watchers: Vec<String>,
+}
+
+/// `status`, `close`, `reopen`
+#[derive(Serialize, JsonSchema)]
+pub struct StatusChange {
+    pub schema: u32,
+    pub id: String,
+    pub status: String,
+    /// On closing: open tickets that were waiting on this one and on
+    /// nothing else still open
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unblocked: Vec<String>,
+    /// With `archive_on_close`: true if the change moved the ticket into
+    /// `.tickets/archive/`, false if it moved it back out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archived: Option<bool>,
+}
+
+/// `priority`, `assign`, `freeze` and `unfreeze`
+#[derive(Serialize, JsonSchema)]
+pub struct FieldChange {
+    pub schema: u32,
+    pub id: String,
+    pub field: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// `stats quality`
+#[derive(Serialize, JsonSchema)]
+pub struct QualityReport {
+    pub schema: u32,
+    /// Share of tickets ever closed that were reopened, 0 to 1
+    pub reopen_rate: f64,
+    /// Tickets reopened at least `--min` times, most first
+    pub flapping: Vec<Flapping>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Flapping {
+    pub id: String,
+    pub title: String,
+    pub status: String,
+    pub reopened_count: usize,
+    pub reopened: Vec<DateTime<Utc>>,
+}
+
+/// `stats throughput`
+#[derive(Serialize, JsonSchema)]
+pub struct Throughput {
+    pub schema: u32,
+    /// `day`, `week` or `month`
+    pub by: String,
+    /// With `--type`: the only type counted
+    #[serde(rename = "type")]
+    pub ticket_type: Option<String>,
+    /// Oldest first
+    pub intervals: Vec<ThroughputInterval>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ThroughputInterval {
+    /// The interval's first day; weeks start on Monday
+    pub start: NaiveDate,
+    /// `2024-01-15`, or `2024-01` for a month
+    pub label: String,
+    pub created: usize,
+    pub closed: usize,
+    /// Counts per type, for types with any tickets in the report
+    pub by_type: Vec<TypeThroughput>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct TypeThroughput {
+    #[serde(rename = "type")]
+    pub ticket_type: String,
+    pub created: usize,
+    pub closed: usize,
+}
+
+/// `stats remaining`: one per milestone. A ticket with children in the
+/// same milestone counts as them, not with its own estimate.
+#[derive(Serialize, JsonSchema)]
+pub struct Remaining {
+    /// None for tickets without a milestone
+    pub milestone: Option<String>,
+    /// Tickets counted
+    pub tickets: usize,
+    pub open: usize,
+    /// Estimates of every counted ticket, closed ones included
+    pub estimate: u32,
+    /// Estimates of the open ones
+    pub remaining: u32,
+    /// Open tickets without an estimate, so not in `remaining`
+    pub unestimated: usize,
+}
+
+/// `stats time-in-status`
+#[derive(Serialize, JsonSchema)]
+pub struct TimeInStatus {
+    pub schema: u32,
+    /// open, in-progress and review, leaving out states nobody was in
+    pub states: Vec<StateTime>,
+    /// With `--id`: each stretch of time the ticket spent in a state
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spans: Option<Vec<StateSpan>>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct StateTime {
+    pub state: String,
+    /// Stretches of time spent in the state; a ticket can have several
+    pub spans: usize,
+    pub tickets: usize,
+    pub total_hours: f64,
+    pub mean_hours: f64,
+    pub median_hours: f64,
+    /// Ticket with the longest single stretch
+    pub longest: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct StateSpan {
+    pub state: String,
+    pub start: DateTime<Utc>,
+    /// None while the ticket is still in the state
+    pub end: Option<DateTime<Utc>>,
+    pub hours: f64,
+}
+
+/// `stats aging-wip`
+#[derive(Serialize, JsonSchema)]
+pub struct AgingWip {
+    pub id: String,
+    pub title: String,
+    pub assignee: Option<String>,
+    /// When the ticket last went in progress
+    pub since: DateTime<Utc>,
+    pub days: f64,
+    /// `ok`, `warn` (past `--warn`) or `stale` (past `--stale`)
+    pub level: String,
+}
+
+/// `snapshot`
+#[derive(Serialize, JsonSchema)]
+pub struct Snapshot {
+    pub schema: u32,
+    pub path: String,
+    /// The summary differed from the file (and, without `--check`, was
+    /// rewritten)
+    pub changed: bool,
+}
+
+/// `gc`
+#[derive(Serialize, JsonSchema)]
+pub struct GcResult {
+    pub schema: u32,
+    /// Tickets whose history was trimmed
+    pub compacted: Vec<String>,
+    /// Events dropped across all tickets
+    pub events_dropped: usize,
+    /// Closed tickets moved into `.tickets/archive/` (`archive_on_close`)
+    pub archived: Vec<String>,
+}
+
+/// `migrate`
+#[derive(Serialize, JsonSchema)]
+pub struct Migrated {
+    pub schema: u32,
+    /// `yaml` or `toml`, if `--format` was given
+    pub format: Option<String>,
+    /// `flat` or `sharded`, if `--layout` was given
+    pub layout: Option<String>,
+    /// Files moved or rewritten (ticket files and bundles, at their new
+    /// paths), then config.yaml if it changed
+    pub files: Vec<String>,
+    /// Whether config.yaml was changed to name the new format or layout
+    pub config_changed: bool,
+    /// Tickets given a slug (`--slugs`)
+    pub slugged: Vec<String>,
+}
+
+/// `dep` and `undep`
+#[derive(Serialize, JsonSchema)]
+pub struct DepChange {
+    pub schema: u32,
+    pub id: String,
+    pub dep: String,
+    /// Present (true) for `undep`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub removed: Option<bool>,
+}
+
+/// `adopt`
+#[derive(Serialize, JsonSchema)]
+pub struct Adopted {
+    pub schema: u32,
+    /// The ticket's ID after the move
+    pub id: String,
+    /// New parent; null when moved to the top level
+    pub parent: Option<String>,
+    /// Re-IDed tickets (with --rename): the ticket and its descendants
+    pub renamed: Vec<Renamed>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Renamed {
+    pub from: String,
+    pub to: String,
+}
+
+/// `notes` entries
+#[derive(Serialize, JsonSchema)]
+pub struct NoteEntry {
+    pub schema: u32,
+    /// 1-based position in the ticket, the number `note --reply` takes
+    pub number: usize,
+    /// Number of the note this replies to
+    pub reply_to: Option<usize>,
+    /// Written with minute precision
+    pub timestamp: DateTime<Utc>,
+    pub author: Option<String>,
+    pub content: String,
+}
+
+impl NoteEntry {
+    /// Entries for a ticket's notes, numbered as `note --reply` counts them
+    pub fn list(notes: Vec<Note>) -> Vec<Self> {
+        notes
+            .into_iter()
+            .enumerate()
+            .map(|(i, n)| Self {
+                schema: SCHEMA_VERSION,
+                number: i + 1,
+                reply_to: n.reply_to,
+                timestamp: n.timestamp,
+                author: n.author,
+                content: n.content,
+            })
+            .collect()
+    }
+}
+
+/// One line of `export ndjson`: the full ticket with its body and notes
+#[derive(Serialize, JsonSchema)]
+pub struct ExportedTicket {
+    #[serde(flatten)]
+    pub ticket: TicketRecord,
+    pub body: String,
+    pub notes: Vec<NoteEntry>,
+}
+
+/// `export ndjson`: the files written, one per partition
+#[derive(Serialize, JsonSchema)]
+pub struct ExportResult {
+    pub schema: u32,
+    pub files: Vec<ExportFile>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ExportFile {
+    pub path: String,
+    pub tickets: usize,
+}
+
+/// `inbox` entries
+#[derive(Serialize, JsonSchema)]
+pub struct InboxEntry {
+    pub schema: u32,
+    pub id: String,
+    pub title: String,
+    pub status: String,
+    pub priority: u8,
+    /// When the ticket last changed
+    pub changed: DateTime<Utc>,
+    /// When you last showed it or marked everything read; null if never
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+/// `note`
+#[derive(Serialize, JsonSchema)]
+pub struct NoteAdded {
+    pub schema: u32,
+    /// ID of the ticket the note was added to
+    pub added: String,
+    /// Who the note is attributed to
+    pub author: Option<String>,
+    /// The new note's number
+    pub number: usize,
+    /// Number of the note this replies to
+    pub reply_to: Option<usize>,
+}
+
+/// `section`: the section's text, after any change
+#[derive(Serialize, JsonSchema)]
+pub struct Section {
+    pub schema: u32,
+    pub id: String,
+    pub heading: String,
+    pub content: String,
+}
+
+/// `summarize`
+#[derive(Serialize, JsonSchema)]
+pub struct Summary {
+    pub schema: u32,
+    pub id: String,
+    pub summary: String,
+    /// Whether the summary was appended to the ticket as a note
+    pub saved: bool,
+}
+
+/// `delete`
+#[derive(Serialize, JsonSchema)]
+pub struct Deleted {
+    pub schema: u32,
+    pub deleted: String,
+    /// Children and dependents rewritten so nothing points at the deleted ticket
+    pub updated: Vec<String>,
+}
+
+/// `dep-cycle` entries
+#[derive(Serialize, JsonSchema)]
+pub struct Cycle {
+    pub schema: u32,
+    /// IDs along the cycle; the last one depends on the first
+    pub cycle: Vec<String>,
+}
+
+/// `tree` nodes. Only the root nodes carry `schema`.
+#[derive(Serialize, JsonSchema)]
+pub struct TreeNode {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema: Option<u32>,
+    pub id: String,
+    pub title: String,
+    pub status: String,
+    /// Tickets that depend on this one
+    pub blocks: Vec<TreeNode>,
+    /// For a parent, the estimates of everything under it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollup: Option<Rollup>,
+}
+
+/// `find`
+#[derive(Serialize, JsonSchema)]
+pub struct Found {
+    pub schema: u32,
+    pub id: String,
+    pub title: String,
+    pub status: String,
+    pub external: Vec<ExternalRef>,
+}
+
+/// `search` entries, best match first
+#[derive(Serialize, JsonSchema)]
+pub struct SearchHit {
+    pub schema: u32,
+    pub id: String,
+    pub title: String,
+    pub status: String,
+    /// Cosine similarity of TF-IDF vectors, 0 to 1
+    pub score: f64,
+}
+
+/// `browse`
+#[derive(Serialize, JsonSchema)]
+pub struct Browsed {
+    pub schema: u32,
+    pub id: String,
+    pub opened: Vec<String>,
+}
+
+/// `lint-links`
+#[derive(Serialize, JsonSchema)]
+pub struct LinkReport {
+    pub schema: u32,
+    pub broken: Vec<BrokenLink>,
+    pub reanchored: Vec<ReanchoredLink>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct BrokenLink {
+    pub id: String,
+    pub reference: String,
+    pub problem: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ReanchoredLink {
+    pub id: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// `lint`
+#[derive(Serialize, JsonSchema)]
+pub struct LintReport {
+    pub schema: u32,
+    pub problems: Vec<LintProblem>,
+}
+
+/// `gate`
+#[derive(Serialize, JsonSchema)]
+pub struct GateReport {
+    pub schema: u32,
+    pub passed: bool,
+    pub failures: Vec<LintProblem>,
+}
+
+/// `doctor`
+#[derive(Serialize, JsonSchema)]
+pub struct DoctorReport {
+    pub schema: u32,
+    pub problems: Vec<DoctorProblem>,
+    /// Files recorded in .tickets/.manifest, with `--rehash`
+    pub rehashed: Option<usize>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DoctorProblem {
+    pub path: String,
+    /// e.g. "parse", "duplicate-id", "changed"
+    pub rule: String,
+    pub message: String,
+}
+
+/// `rescue`
+#[derive(Serialize, JsonSchema)]
+pub struct Rescue {
+    pub schema: u32,
+    /// The broken file
+    pub path: String,
+    /// Where the salvaged ticket was written; None if the file parses
+    pub written: Option<String>,
+    /// The salvaged ticket's ID
+    pub id: Option<String>,
+    /// What was guessed, repaired or dropped
+    pub assumptions: Vec<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct LintProblem {
+    pub id: String,
+    /// Rule name, e.g. "required-sections"
+    pub rule: String,
+    pub message: String,
+    /// Line of the body (from 1) the problem is on, for problems on a line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+/// `sync`
+#[derive(Serialize, JsonSchema)]
+pub struct SyncResult {
+    pub schema: u32,
+    /// e.g. "github:owner/repo"
+    pub remote: String,
+    /// IDs of tickets created for newly seen open issues
+    pub created: Vec<String>,
+    /// IDs of tickets whose title or status changed
+    pub updated: Vec<String>,
+    /// IDs of tickets changed both upstream and here since their last
+    /// sync, however the conflict policy settled them
+    pub conflicts: Vec<String>,
+    /// The remote reported nothing changed since the last sync
+    pub not_modified: bool,
+    /// Set when the sync stopped early; run again after this time
+    pub rate_limited_until: Option<DateTime<Utc>>,
+    /// Every issue fetched and what became of it (`--report`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<Vec<SyncReportEntry>>,
+}
+
+/// One issue in `sync --report`
+#[derive(Serialize, JsonSchema)]
+pub struct SyncReportEntry {
+    /// `created`, `updated`, `skipped` or `conflict`
+    pub outcome: String,
+    /// The local ticket, if there is one
+    pub id: Option<String>,
+    /// Issue number upstream
+    pub issue: u64,
+    pub reason: String,
+}
+
+/// `import mbox` and `ingest --maildir`
+#[derive(Serialize, JsonSchema)]
+pub struct ImportResult {
+    pub schema: u32,
+    /// Tickets started by messages that begin a thread
+    pub created: Vec<String>,
+    /// Tickets that gained notes from replies
+    pub updated: Vec<String>,
+    /// Messages that were already imported
+    pub skipped: usize,
+    /// Every message and what became of it (`--report`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<Vec<ImportReportEntry>>,
+}
+
+/// One message in `import mbox --report` and `ingest --report`
+#[derive(Serialize, JsonSchema)]
+pub struct ImportReportEntry {
+    /// `created`, `noted` or `skipped`
+    pub outcome: String,
+    /// The ticket it became or was added to
+    pub id: Option<String>,
+    pub message_id: Option<String>,
+    pub reason: String,
+}
+
+/// `archive compact`
+#[derive(Serialize, JsonSchema)]
+pub struct ArchiveCompacted {
+    pub schema: u32,
+    /// Bundles written, oldest year first
+    pub bundles: Vec<Bundle>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct Bundle {
+    pub year: i32,
+    pub path: String,
+    /// Tickets newly rolled in
+    pub tickets: usize,
+}
+
+/// `auth login` and `auth logout`
+#[derive(Serialize, JsonSchema)]
+pub struct AuthChange {
+    pub schema: u32,
+    pub provider: String,
+    /// true after login; false after logout
+    pub stored: bool,
+}
+
+/// `auth status`
+#[derive(Serialize, JsonSchema)]
+pub struct AuthStatus {
+    pub schema: u32,
+    pub providers: Vec<ProviderAuth>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ProviderAuth {
+    pub provider: String,
+    /// "keychain" or "$VAR"; null when no token is configured
+    pub source: Option<String>,
+}
+
+/// Errors, written to stderr
+#[derive(Serialize, JsonSchema)]
+pub struct ErrorOutput {
+    pub schema: u32,
+    pub error: ErrorBody,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct ErrorBody {
+    /// usage, not_found, ambiguous_prefix, ambiguous_title, validation, parse_error, or error
+    pub code: String,
+    pub message: String,
+    /// Matching IDs for ambiguous_prefix and ambiguous_title
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidates: Option<Vec<String>>,
+}
+
+/// JSON Schema for every output, keyed by command name
+pub fn schemas() -> Vec<(&'static str, RootSchema)> {
+    vec![
+        ("init", schema_for!(InitResult)),
+        ("create", schema_for!(Created)),
+        ("draft", schema_for!(Created)),
+        ("publish", schema_for!(Created)),
+        ("create-bulk", schema_for!(Vec<Created>)),
+        ("list", schema_for!(Vec<TicketSummary>)),
+        ("list-tree", schema_for!(Vec<ListNode>)),
+        ("ready", schema_for!(Vec<TicketSummary>)),
+        ("next", schema_for!(TicketSummary)),
+        ("blocked", schema_for!(Vec<BlockedTicket>)),
+        ("why-blocked", schema_for!(BlockerChains)),
+        ("plan", schema_for!(Plan)),
+        ("plan-for", schema_for!(Queue)),
+        ("claim", schema_for!(Claimed)),
+        ("suggest", schema_for!(Suggestion)),
+        ("start", schema_for!(StatusChange)),
+        ("reap", schema_for!(Vec<Reaped>)),
+        ("query", schema_for!(Vec<TicketRecord>)),
+        ("count", schema_for!(Counts)),
+        ("tags", schema_for!(Vec<TagCount>)),
+        ("tags-merges", schema_for!(Vec<TagMerge>)),
+        ("status", schema_for!(StatusChange)),
+        ("close", schema_for!(StatusChange)),
+        ("reopen", schema_for!(StatusChange)),
+        ("priority", schema_for!(FieldChange)),
+        ("export-ndjson", schema_for!(ExportResult)),
+        ("export-cards", schema_for!(ExportResult)),
+        ("export-record", schema_for!(ExportedTicket)),
+        ("review-queue", schema_for!(Vec<ReviewItem>)),
+        ("review-done", schema_for!(Reviewed)),
+        ("freeze", schema_for!(FieldChange)),
+        ("unfreeze", schema_for!(FieldChange)),
+        ("assign", schema_for!(FieldChange)),
+        ("watch-ticket", schema_for!(Watchers)),
+        ("hook", schema_for!(HookPayload)),
+        ("gc", schema_for!(GcResult)),
+        ("migrate", schema_for!(Migrated)),
+        ("stats-quality", schema_for!(QualityReport)),
+        ("stats-time-in-status", schema_for!(TimeInStatus)),
+        ("stats-throughput", schema_for!(Throughput)),
+        ("stats-remaining", schema_for!(Vec<Remaining>)),
+        ("stats-aging-wip", schema_for!(Vec<AgingWip>)),
+        ("handoff", schema_for!(Handoff)),
+        ("dep", schema_for!(DepChange)),
+        ("undep", schema_for!(DepChange)),
+        ("adopt", schema_for!(Adopted)),
+        ("note", schema_for!(NoteAdded)),
+        ("notes", schema_for!(Vec<NoteEntry>)),
+        ("section", schema_for!(Section)),
+        ("inbox", schema_for!(Vec<InboxEntry>)),
+        ("summarize", schema_for!(Summary)),
+        ("delete", schema_for!(Deleted)),
+        ("dep-cycle", schema_for!(Vec<Cycle>)),
+        ("tree", schema_for!(TreeNode)),
+        ("tree-all", schema_for!(Vec<TreeNode>)),
+        ("find", schema_for!(Found)),
+        ("search", schema_for!(Vec<SearchHit>)),
+        ("browse", schema_for!(Browsed)),
+        ("lint", schema_for!(LintReport)),
+        ("lint-links", schema_for!(LinkReport)),
+        ("snapshot", schema_for!(Snapshot)),
+        ("gate", schema_for!(GateReport)),
+        ("doctor", schema_for!(DoctorReport)),
+        ("rescue", schema_for!(Rescue)),
+        ("sync", schema_for!(SyncResult)),
+        ("archive-compact", schema_for!(ArchiveCompacted)),
+        ("import-mbox", schema_for!(ImportResult)),
+        ("ingest", schema_for!(ImportResult)),
+        ("config-export", schema_for!(ConfigExport)),
+        ("config-import", schema_for!(ConfigImported)),
+        ("auth", schema_for!(AuthChange)),
+        ("auth-status", schema_for!(AuthStatus)),
+        ("error", schema_for!(ErrorOutput)),
+    ]
+}
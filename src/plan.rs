@@ -0,0 +1,217 @@
+//! Dependency-aware execution plan for `tk plan`.
+//!
+//! Open tickets are scheduled in waves: a ticket joins the first wave after
+//! all of its open deps, so everything in a wave can be worked on at once.
+//! Within a wave higher priority goes first, and with a `parallel` limit the
+//! tickets that don't fit wait for a later wave. Tickets whose deps can never
+//! be met (a cycle, or an open dep that is archived) are left unschedulable.
+
+use crate::types::{Status, Ticket};
+use std::collections::{HashMap, HashSet};
+
+pub struct Planned<'a> {
+    pub ticket: &'a Ticket,
+    /// The ticket's own assignee, or who the plan hands it to
+    pub assignee: Option<String>,
+    /// Open deps, all in earlier waves
+    pub after: Vec<String>,
+}
+
+pub struct Plan<'a> {
+    pub waves: Vec<Vec<Planned<'a>>>,
+    pub unschedulable: Vec<&'a Ticket>,
+}
+
+/// Plan the live open tickets among `tickets`. At most `parallel` tickets go
+/// in a wave (default: one per assignee, or no limit without assignees).
+/// Unassigned tickets are spread over `assignees`, fewest tickets first.
+pub fn plan<'a>(tickets: &'a [Ticket], parallel: Option<usize>, assignees: &[String]) -> Plan<'a> {
+    let open: HashMap<&str, &Ticket> = tickets
+        .iter()
+        .filter(|t| t.is_open())
+        .map(|t| (t.id(), t))
+        .collect();
+
+    let mut pending: Vec<(&Ticket, Vec<&str>)> = Vec::new();
+    let mut unschedulable = Vec::new();
+    for t in tickets.iter().filter(|t| t.is_open() && !t.archived) {
+        let deps: Vec<&str> = t
+            .meta
+            .deps
+            .iter()
+            .map(String::as_str)
+            .filter(|d| open.contains_key(d))
+            .collect();
+        if deps.iter().any(|d| open[d].archived) {
+            unschedulable.push(t);
+        } else {
+            pending.push((t, deps));
+        }
+    }
+
+    let limit = parallel
+        .or((!assignees.is_empty()).then_some(assignees.len()))
+        .unwrap_or(usize::MAX)
+        .max(1);
+    let mut done: HashSet<&str> = HashSet::new();
+    let mut waves = Vec::new();
+    loop {
+        let mut available: Vec<usize> = (0..pending.len())
+            .filter(|&i| pending[i].1.iter().all(|d| done.contains(d)))
+            .collect();
+        if available.is_empty() {
+            break;
+        }
+        available.sort_by_key(|&i| (pending[i].0.meta.priority, pending[i].0.meta.created));
+        available.truncate(limit);
+        available.sort_unstable_by(|a, b| b.cmp(a));
+
+        let mut wave: Vec<(&Ticket, Vec<&str>)> =
+            available.into_iter().map(|i| pending.remove(i)).collect();
+        wave.sort_by_key(|(t, _)| (t.meta.priority, t.meta.created));
+        done.extend(wave.iter().map(|(t, _)| t.id()));
+        waves.push(assign(wave, assignees));
+    }
+
+    unschedulable.extend(pending.into_iter().map(|(t, _)| t));
+    unschedulable.sort_by_key(|t| t.id());
+    Plan {
+        waves,
+        unschedulable,
+    }
+}
+
+fn assign<'a>(wave: Vec<(&'a Ticket, Vec<&str>)>, assignees: &[String]) -> Vec<Planned<'a>> {
+    let mut load: HashMap<&str, usize> = assignees.iter().map(|a| (a.as_str(), 0)).collect();
+    for (t, _) in &wave {
+        if let Some(count) = t.meta.assignee.as_deref().and_then(|a| load.get_mut(a)) {
+            *count += 1;
+        }
+    }
+
+    wave.into_iter()
+        .map(|(ticket, deps)| {
+            let assignee = ticket.meta.assignee.clone().or_else(|| {
+                let pick = assignees.iter().min_by_key(|a| load[a.as_str()])?;
+                *load.get_mut(pick.as_str())? += 1;
+                Some(pick.clone())
+            });
+            Planned {
+                ticket,
+                assignee,
+                after: deps.into_iter().map(str::to_string).collect(),
+            }
+        })
+        .collect()
+}
+
+/// Why a ticket is in someone's queue, in queue order
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// In progress and assigned to them
+    Started,
+    /// Ready and assigned to them
+    Assigned,
+    /// Ready, unassigned, and sharing a tag with work they've been assigned
+    Tagged,
+}
+
+impl Reason {
+    pub fn heading(self) -> &'static str {
+        match self {
+            Reason::Started => "In progress",
+            Reason::Assigned => "Assigned to you",
+            Reason::Tagged => "Unassigned, matching your tags",
+        }
+    }
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Reason::Started => "in-progress",
+            Reason::Assigned => "assigned",
+            Reason::Tagged => "tagged",
+        })
+    }
+}
+
+/// `who`'s queue: their in-progress tickets, then the `ready` tickets
+/// assigned to them, then unassigned `ready` ones tagged like any ticket
+/// ever assigned to them. In-progress tickets go by priority and the rest
+/// keep their order in `ready`; since ready tickets have no open deps,
+/// nothing in the queue waits on something after it.
+pub fn queue<'a>(
+    tickets: &'a [Ticket],
+    ready: &[&'a Ticket],
+    who: &str,
+) -> Vec<(&'a Ticket, Reason)> {
+    let mine = |t: &Ticket| t.meta.assignee.as_deref() == Some(who);
+    let tags: HashSet<&str> = tickets
+        .iter()
+        .filter(|t| mine(t))
+        .flat_map(|t| t.meta.tags.iter().map(String::as_str))
+        .collect();
+
+    let mut queue: Vec<_> = tickets
+        .iter()
+        .filter(|t| !t.archived && t.meta.status == Status::InProgress && mine(t))
+        .map(|t| (t, Reason::Started))
+        .collect();
+    queue.sort_by_key(|(t, _)| t.meta.priority);
+    let ready = ready
+        .iter()
+        .filter(|t| t.meta.status != Status::InProgress)
+        .filter_map(|&t| match &t.meta.assignee {
+            Some(_) if mine(t) => Some((t, Reason::Assigned)),
+            None if t.meta.tags.iter().any(|tag| tags.contains(tag.as_str())) => {
+                Some((t, Reason::Tagged))
+            }
+            _ => None,
+        });
+    let (assigned, tagged): (Vec<_>, Vec<_>) = ready.partition(|&(_, r)| r == Reason::Assigned);
+    queue.extend(assigned);
+    queue.extend(tagged);
+    queue
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticket(id: &str, status: Status, priority: u8, assignee: Option<&str>, tag: &str) -> Ticket {
+        let mut t = Ticket::new(id.to_string(), id.to_string());
+        t.meta.status = status;
+        t.meta.priority = priority;
+        t.meta.assignee = assignee.map(str::to_string);
+        t.meta.tags = vec![tag.to_string()];
+        t
+    }
+
+    #[test]
+    fn queue_puts_started_then_assigned_then_tagged_work() {
+        let tickets = vec![
+            ticket("tk-0001", Status::Open, 0, None, "ui"),
+            ticket("tk-0002", Status::InProgress, 2, Some("ana"), "ui"),
+            ticket("tk-0003", Status::Open, 1, Some("ana"), "api"),
+            ticket("tk-0004", Status::InProgress, 1, Some("ana"), "ui"),
+            ticket("tk-0005", Status::Open, 0, None, "docs"),
+            ticket("tk-0006", Status::Open, 0, Some("bo"), "ui"),
+        ];
+        // As `ready` would order them; tk-0004 is in progress, not ready
+        let ready: Vec<&Ticket> = [0, 4, 5, 2].iter().map(|&i| &tickets[i]).collect();
+        let queue: Vec<(&str, String)> = queue(&tickets, &ready, "ana")
+            .into_iter()
+            .map(|(t, reason)| (t.id(), reason.to_string()))
+            .collect();
+        assert_eq!(
+            queue,
+            [
+                ("tk-0004", "in-progress".to_string()),
+                ("tk-0002", "in-progress".to_string()),
+                ("tk-0003", "assigned".to_string()),
+                ("tk-0001", "tagged".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use crate::error::TkError;
+use anyhow::Result;
+use std::io::IsTerminal;
+
+/// Whether there's a person at a terminal to ask
+pub fn interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Ask before a destructive action.
+///
+/// `yes` (from `--yes`/`--force`) skips the question. Without a terminal to
+/// ask on, the action is refused rather than assumed.
+pub fn confirm(question: &str, yes: bool) -> Result<()> {
+    if yes {
+        return Ok(());
+    }
+
+    if !interactive() {
+        return Err(
+            TkError::validation(format!("{} Re-run with --yes to confirm.", question)).into(),
+        );
+    }
+
+    let confirmed = dialoguer::Confirm::new()
+        .with_prompt(question)
+        .default(false)
+        .interact()?;
+
+    if !confirmed {
+        anyhow::bail!("Aborted");
+    }
+    Ok(())
+}
+
+/// Read a secret: a hidden prompt on a terminal, otherwise the first line of
+/// stdin (`pass show github | tk auth login github`).
+pub fn secret(question: &str) -> Result<String> {
+    let secret = if interactive() {
+        dialoguer::Password::new()
+            .with_prompt(question)
+            .interact()?
+    } else {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        line
+    };
+
+    let secret = secret.trim().to_string();
+    if secret.is_empty() {
+        return Err(TkError::validation("No token given").into());
+    }
+    Ok(secret)
+}
+
+/// Ask the user to pick one of `options`; returns its index.
+///
+/// `flag` names the option that answers the question non-interactively and
+/// is suggested when there is no terminal to ask on.
+pub fn choose(question: &str, options: &[String], flag: &str) -> Result<usize> {
+    if !interactive() {
+        return Err(TkError::validation(format!("{} Re-run with {}.", question, flag)).into());
+    }
+
+    Ok(dialoguer::Select::new()
+        .with_prompt(question)
+        .items(options)
+        .default(0)
+        .interact()?)
+}
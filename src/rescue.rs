@@ -0,0 +1,335 @@
+//! `tk rescue`: salvage what can be read from a ticket file that doesn't
+//! parse. Frontmatter lines that don't parse are repaired where the fix is
+//! a safe guess (quoting a value, a missing space after `:`) and dropped
+//! where it isn't, keys whose values are of the wrong kind are dropped, and
+//! a missing ID or creation time is filled in. The body is kept as it is.
+//! Every guess is reported, for someone to check.
+
+use crate::storage::{FrontmatterFormat, Storage};
+use crate::types::{Frontmatter, Ticket};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde_yaml::{Mapping, Value};
+
+pub struct Rescued {
+    pub ticket: Ticket,
+    /// What was guessed, repaired or dropped: line repairs in file order,
+    /// then keys
+    pub assumptions: Vec<String>,
+}
+
+/// Salvage a ticket from `content`. A missing or broken ID becomes
+/// `name_id` (the ID in the file's name) if there is one, else `new_id()`;
+/// a missing creation time becomes `created`.
+pub fn rescue(
+    content: &str,
+    name_id: Option<String>,
+    new_id: impl FnOnce() -> Result<String>,
+    created: DateTime<Utc>,
+) -> Result<Rescued> {
+    let mut notes = Vec::new();
+    let (format, lines, rest) = split(content, &mut notes);
+    let mapping = salvage(format, &lines, &mut notes);
+
+    // Keep each key whose value a ticket can hold
+    let mut probe = Mapping::new();
+    probe.insert("id".into(), "tk-0000".into());
+    probe.insert("created".into(), created.to_rfc3339().into());
+    let mut meta = Mapping::new();
+    for (key, value) in mapping {
+        let mut one = probe.clone();
+        one.insert(key.clone(), value.clone());
+        match serde_yaml::from_value::<Frontmatter>(Value::Mapping(one)) {
+            Ok(_) => {
+                meta.insert(key, value);
+            }
+            Err(e) => notes.push(format!("dropped `{}`: {}", describe(&key), e)),
+        }
+    }
+
+    if !meta.contains_key("id") {
+        let id = match name_id {
+            Some(id) => {
+                notes.push(format!("no usable `id`; took {} from the file name", id));
+                id
+            }
+            None => {
+                let id = new_id()?;
+                notes.push(format!("no usable `id`; gave it the new ID {}", id));
+                id
+            }
+        };
+        meta.insert("id".into(), id.into());
+    }
+    if !meta.contains_key("created") {
+        let created = created.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        notes.push(format!(
+            "no usable `created`; used the file's modification time, {}",
+            created
+        ));
+        meta.insert("created".into(), created.into());
+    }
+
+    // Read back as a whole file, so the title and body come out as tk reads them
+    let meta: Frontmatter = serde_yaml::from_value(Value::Mapping(meta))?;
+    let content = format!("---\n{}---\n{}", serde_yaml::to_string(&meta)?, rest);
+    let ticket = Storage::parse_ticket(&content)?;
+    Ok(Rescued {
+        ticket,
+        assumptions: notes,
+    })
+}
+
+/// The frontmatter format, the frontmatter's lines with their line numbers
+/// in the file, and what follows it
+fn split<'a>(
+    content: &'a str,
+    notes: &mut Vec<String>,
+) -> (FrontmatterFormat, Vec<(usize, &'a str)>, String) {
+    let content = content.trim_start_matches('\u{feff}');
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let open = lines.iter().position(|l| !l.trim().is_empty());
+    let Some((open, format)) = open.and_then(|i| Some((i, FrontmatterFormat::detect(lines[i])?)))
+    else {
+        notes.push("no frontmatter; the whole file is the body".to_string());
+        return (FrontmatterFormat::Yaml, Vec::new(), content.to_string());
+    };
+    let fence = format.fence();
+
+    let mut meta = Vec::new();
+    let first = lines[open].trim().trim_start_matches(fence).trim();
+    if !first.is_empty() {
+        notes.push(format!(
+            "line {}: read `{}` after the opening {} as frontmatter",
+            open + 1,
+            first,
+            fence
+        ));
+        meta.push((open + 1, first));
+    }
+    let after = &lines[open + 1..];
+    if let Some(close) = after.iter().position(|l| l.trim_end() == fence) {
+        meta.extend(numbered(open + 2, &after[..close]));
+        return (format, meta, after[close + 1..].concat());
+    }
+
+    // No closing fence: the frontmatter ends at the first line that can't
+    // be part of it
+    let end = after.iter().position(|l| {
+        let l = l.trim();
+        l.is_empty() || l.starts_with(fence) || l.starts_with("# ")
+    });
+    let (fm, rest) = after.split_at(end.unwrap_or(after.len()));
+    let rest = match rest.first() {
+        Some(l) if l.trim().starts_with(fence) => &rest[1..],
+        _ => rest,
+    };
+    notes.push(match end {
+        Some(i) => format!(
+            "no closing {} line; took the frontmatter to end at line {}",
+            fence,
+            open + 2 + i
+        ),
+        None => format!(
+            "no closing {} line; took the rest of the file as frontmatter",
+            fence
+        ),
+    });
+    meta.extend(numbered(open + 2, fm));
+    (format, meta, rest.concat())
+}
+
+fn numbered<'a>(first: usize, lines: &[&'a str]) -> Vec<(usize, &'a str)> {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, l)| (first + i, l.trim_end_matches(['\n', '\r'])))
+        .collect()
+}
+
+/// The frontmatter entries that parse, repaired if need be, as YAML
+fn salvage(format: FrontmatterFormat, lines: &[(usize, &str)], notes: &mut Vec<String>) -> Mapping {
+    let mut kept = String::new();
+    for (line, entry) in entries(lines) {
+        if entry.trim().is_empty() {
+            continue;
+        }
+        let fixed = std::iter::once(entry.clone())
+            .chain(repairs(format, &entry))
+            .find(|text| parse(format, &format!("{}{}\n", kept, text)).is_ok());
+        match fixed {
+            Some(text) => {
+                if text != entry {
+                    notes.push(format!("line {}: read `{}` as `{}`", line, entry, text));
+                }
+                kept.push_str(&text);
+                kept.push('\n');
+            }
+            None => {
+                let why = parse(format, &format!("{}{}\n", kept, entry))
+                    .err()
+                    .unwrap_or_default();
+                notes.push(format!("line {}: dropped `{}` ({})", line, entry, why));
+            }
+        }
+    }
+    parse(format, &kept).unwrap_or_default()
+}
+
+/// The lines grouped into top-level entries: a key with the indented lines
+/// (or YAML list items) under it
+fn entries(lines: &[(usize, &str)]) -> Vec<(usize, String)> {
+    let mut entries: Vec<(usize, String)> = Vec::new();
+    for &(n, line) in lines {
+        let continues = line.starts_with([' ', '\t', '-', ']']) && !entries.is_empty();
+        match entries.last_mut().filter(|_| continues) {
+            Some((_, entry)) => {
+                entry.push('\n');
+                entry.push_str(line);
+            }
+            None => entries.push((n, line.to_string())),
+        }
+    }
+    entries
+}
+
+/// Guesses at what a broken entry meant, safest first
+fn repairs(format: FrontmatterFormat, entry: &str) -> Vec<String> {
+    let mut fixes = Vec::new();
+    if format == FrontmatterFormat::Yaml && entry.contains('\t') {
+        // YAML doesn't allow tabs for indentation
+        fixes.push(entry.replace('\t', "  "));
+    }
+    if entry.contains('\n') {
+        return fixes;
+    }
+    let (separator, joiner) = match format {
+        FrontmatterFormat::Yaml => (':', ": "),
+        FrontmatterFormat::Toml => ('=', " = "),
+    };
+    // A YAML-style `key: value` line in TOML frontmatter, or the reverse
+    let split = entry
+        .split_once(separator)
+        .or_else(|| entry.split_once([':', '=']));
+    let Some((key, value)) = split else {
+        return fixes;
+    };
+    let (key, value) = (key.trim(), value.trim());
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return fixes;
+    }
+    fixes.push(format!("{}{}{}", key, joiner, value));
+    if !value.is_empty() {
+        let quoted = match format {
+            FrontmatterFormat::Yaml => serde_yaml::to_string(value).unwrap_or_default(),
+            FrontmatterFormat::Toml => toml::Value::from(value).to_string(),
+        };
+        fixes.push(format!("{}{}{}", key, joiner, quoted.trim_end()));
+    }
+    fixes
+}
+
+/// `text` as a mapping, or why it isn't one
+fn parse(format: FrontmatterFormat, text: &str) -> std::result::Result<Mapping, String> {
+    // Positions would be in the salvaged text, not the file, so leave them out
+    let first_line = |e: String| {
+        let line = e.lines().next().unwrap_or_default();
+        line.split(" at line ").next().unwrap_or(line).to_string()
+    };
+    match format {
+        FrontmatterFormat::Yaml => {
+            match serde_yaml::from_str::<Option<Mapping>>(text)
+                .map_err(|e| first_line(e.to_string()))?
+            {
+                Some(mapping) => Ok(mapping),
+                None => Ok(Mapping::new()),
+            }
+        }
+        FrontmatterFormat::Toml => {
+            let table: toml::Table =
+                toml::from_str(text).map_err(|e| first_line(e.message().to_string()))?;
+            match toml_to_yaml(toml::Value::Table(table)) {
+                Value::Mapping(mapping) => Ok(mapping),
+                _ => Ok(Mapping::new()),
+            }
+        }
+    }
+}
+
+/// A TOML value as the YAML value it would be, dates as strings
+fn toml_to_yaml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => s.into(),
+        toml::Value::Integer(i) => i.into(),
+        toml::Value::Float(f) => f.into(),
+        toml::Value::Boolean(b) => b.into(),
+        toml::Value::Datetime(d) => d.to_string().into(),
+        toml::Value::Array(items) => Value::Sequence(items.into_iter().map(toml_to_yaml).collect()),
+        toml::Value::Table(table) => Value::Mapping(
+            table
+                .into_iter()
+                .map(|(k, v)| (k.into(), toml_to_yaml(v)))
+                .collect(),
+        ),
+    }
+}
+
+fn describe(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        other => serde_yaml::to_string(other)
+            .unwrap_or_default()
+            .trim_end()
+            .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rescued(content: &str) -> Rescued {
+        let created = "2024-01-15T10:30:00Z".parse().unwrap();
+        rescue(
+            content,
+            Some("tk-a1b2".to_string()),
+            || unreachable!(),
+            created,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn repairs_lines_and_keeps_the_body() {
+        let r = rescued(
+            "---\nid: tk-a1b2\nstatus:closed\ntitle: Fix: login\npriority: high\n---\n\nBody.\n",
+        );
+        assert_eq!(r.ticket.title, "Fix: login");
+        assert_eq!(r.ticket.meta.status, crate::types::Status::Closed);
+        assert_eq!(r.ticket.body, "Body.");
+        assert_eq!(r.assumptions.len(), 4, "{:?}", r.assumptions);
+        assert!(r.assumptions[2].starts_with("dropped `priority`"));
+    }
+
+    #[test]
+    fn fills_in_what_is_missing() {
+        let r = rescued("--- status: open\n\n# Title\n");
+        assert_eq!(r.ticket.id(), "tk-a1b2");
+        assert_eq!(r.ticket.title, "Title");
+        assert_eq!(
+            r.ticket.meta.created.to_rfc3339(),
+            "2024-01-15T10:30:00+00:00"
+        );
+
+        let r = rescued("Notes without frontmatter\n");
+        assert_eq!(r.ticket.body, "Notes without frontmatter");
+    }
+
+    #[test]
+    fn repairs_toml() {
+        let r =
+            rescued("+++\nid = tk-a1b2\npriority: 1\ncreated = 2024-01-15T10:30:00Z\n+++\n# T\n");
+        assert_eq!(r.ticket.meta.priority, 1);
+        assert_eq!(r.assumptions.len(), 2, "{:?}", r.assumptions);
+    }
+}
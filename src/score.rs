@@ -0,0 +1,39 @@
+//! Ranking tickets by the weighted score in `score:` of config.yaml, for
+//! `tk ready --sort score` and `tk next`.
+
+use crate::config::ScoreConfig;
+use crate::types::Ticket;
+use chrono::{Local, NaiveDate};
+
+/// The ticket's score on `today`: each configured weight times its factor
+pub fn score(ticket: &Ticket, weights: &ScoreConfig, today: NaiveDate) -> f64 {
+    let m = &ticket.meta;
+    let priority = 4u8.saturating_sub(m.priority) as f64;
+    let created = m.created.with_timezone(&Local).date_naive();
+    let age = (today - created).num_days().max(0) as f64;
+    // Zero until the window opens, then one more per day, and on past it
+    let due = m.due.map_or(0.0, |due| {
+        let left = (due - today).num_days();
+        (weights.due_window as i64 - left).max(0) as f64
+    });
+    let estimate = m.estimate.unwrap_or(0) as f64;
+
+    weights.priority * priority
+        + weights.age * age
+        + weights.due * due
+        + weights.estimate * estimate
+}
+
+/// Highest score first; ties keep their order
+pub fn rank<'a>(
+    tickets: Vec<&'a Ticket>,
+    weights: &ScoreConfig,
+    today: NaiveDate,
+) -> Vec<(&'a Ticket, f64)> {
+    let mut ranked: Vec<_> = tickets
+        .into_iter()
+        .map(|t| (t, score(t, weights, today)))
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
@@ -0,0 +1,152 @@
+//! `## Section`s of a ticket body: reading, replacing and extending one
+//! without disturbing the rest of the body or the notes after it.
+
+use crate::storage::Storage;
+use std::ops::Range;
+
+/// Text under a `##`-or-deeper heading named `name` (case-insensitive), up
+/// to the next heading or the notes. None if there is no such heading.
+pub fn content(body: &str, name: &str) -> Option<String> {
+    find(body, name).map(|range| body[range].trim_matches('\n').to_string())
+}
+
+/// `body` with the section `name` holding just `text`. A missing section is
+/// added after the last one, ahead of any notes.
+pub fn set(body: &str, name: &str, text: &str) -> String {
+    let text = text.trim_matches('\n').trim_end();
+    let (mut out, rest) = match find(body, name) {
+        Some(range) => {
+            let mut head = body[..range.start].to_string();
+            if !head.ends_with('\n') {
+                head.push('\n');
+            }
+            (head, &body[range.end..])
+        }
+        None => {
+            let stop = notes_start(body);
+            let mut head = body[..stop].trim_end().to_string();
+            if !head.is_empty() {
+                head.push_str("\n\n");
+            }
+            head.push_str(&format!("## {}\n", name.trim()));
+            (head, &body[stop..])
+        }
+    };
+    if !text.is_empty() {
+        out.push_str(text);
+        out.push('\n');
+    }
+    if !rest.is_empty() {
+        out.push('\n');
+        out.push_str(rest);
+    }
+    out
+}
+
+/// `body` with `text` added as the last lines of the section `name`
+pub fn append(body: &str, name: &str, text: &str) -> String {
+    let current = content(body, name).unwrap_or_default();
+    let current = current.trim_end();
+    if current.trim().is_empty() {
+        set(body, name, text)
+    } else {
+        set(
+            body,
+            name,
+            &format!("{}\n{}", current, text.trim_matches('\n')),
+        )
+    }
+}
+
+/// Byte range of the section's text: from after its heading line to the
+/// next heading or the notes
+fn find(body: &str, name: &str) -> Option<Range<usize>> {
+    let stop = notes_start(body);
+    let mut start = None;
+    let mut offset = 0;
+    for line in body[..stop].split_inclusive('\n') {
+        match (start, heading_text(line)) {
+            (None, Some(h)) if h.eq_ignore_ascii_case(name.trim()) => {
+                start = Some(offset + line.len());
+            }
+            (Some(s), Some(_)) => return Some(s..offset),
+            _ => {}
+        }
+        offset += line.len();
+    }
+    start.map(|s| s..stop)
+}
+
+/// Where the notes at the end of the body begin (its length if none)
+fn notes_start(body: &str) -> usize {
+    Storage::notes_section(body).map_or(body.len(), |notes| body.len() - notes.len())
+}
+
+/// The text of a `##`-or-deeper heading line
+fn heading_text(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let hashes = line.chars().take_while(|&c| c == '#').count();
+    if hashes < 2 {
+        return None;
+    }
+    line[hashes..].strip_prefix(' ').map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &str = "\
+Intro.
+
+## Test plan
+Run the suite.
+
+### Details
+Nothing yet.
+
+[2026-03-02 09:00 ana] Started on it
+";
+
+    #[test]
+    fn sections_end_at_the_next_heading_or_the_notes() {
+        assert_eq!(
+            content(BODY, "test PLAN").as_deref(),
+            Some("Run the suite.")
+        );
+        assert_eq!(content(BODY, "Details").as_deref(), Some("Nothing yet."));
+        assert_eq!(content(BODY, "Missing"), None);
+    }
+
+    #[test]
+    fn setting_and_appending_leave_the_notes_alone() {
+        let body = append(BODY, "Test plan", "Try it on staging.");
+        assert_eq!(
+            content(&body, "Test plan").as_deref(),
+            Some("Run the suite.\nTry it on staging.")
+        );
+        let body = set(&body, "Details", "Done.");
+        assert_eq!(
+            body,
+            "\
+Intro.
+
+## Test plan
+Run the suite.
+Try it on staging.
+
+### Details
+Done.
+
+[2026-03-02 09:00 ana] Started on it
+"
+        );
+    }
+
+    #[test]
+    fn a_missing_section_goes_before_the_notes() {
+        let body = set(BODY, "Rollout", "Behind a flag.");
+        assert!(body.contains("Nothing yet.\n\n## Rollout\nBehind a flag.\n\n[2026-03-02"));
+        assert_eq!(set("", "Rollout", ""), "## Rollout\n");
+    }
+}
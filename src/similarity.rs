@@ -0,0 +1,84 @@
+//! TF-IDF text similarity for `tk search`.
+//!
+//! Documents are bags of lowercased words; common English words and
+//! one-letter tokens are dropped. Scores are cosine similarities in [0, 1].
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "can", "do", "for", "from", "has",
+    "have", "if", "in", "into", "is", "it", "its", "not", "of", "on", "or", "should", "so", "that",
+    "the", "their", "then", "there", "this", "to", "was", "we", "when", "which", "will", "with",
+    "would", "you",
+];
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 1)
+        .map(|w| w.to_lowercase())
+        .filter(|w| !STOPWORDS.contains(&w.as_str()))
+        .collect()
+}
+
+/// Score every document against `query`. Returns `(index, score)` pairs with
+/// a positive score, best first.
+pub fn rank(documents: &[String], query: &str) -> Vec<(usize, f64)> {
+    let docs: Vec<Vec<String>> = documents.iter().map(|d| tokenize(d)).collect();
+
+    // Document frequency over the corpus plus the query, so query-only words
+    // get a weight instead of dividing by zero
+    let mut df: HashMap<&str, usize> = HashMap::new();
+    let query_tokens = tokenize(query);
+    for tokens in docs.iter().chain(std::iter::once(&query_tokens)) {
+        let unique: HashSet<&str> = tokens.iter().map(String::as_str).collect();
+        for t in unique {
+            *df.entry(t).or_default() += 1;
+        }
+    }
+    let n = (docs.len() + 1) as f64;
+    let idf = |t: &str| (n / df.get(t).copied().unwrap_or(1) as f64).ln() + 1.0;
+
+    let q = weigh(&query_tokens, &idf);
+    let q_norm = norm(&q);
+    if q_norm == 0.0 {
+        return Vec::new();
+    }
+
+    let mut scored: Vec<(usize, f64)> = docs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, tokens)| {
+            let d = weigh(tokens, &idf);
+            let d_norm = norm(&d);
+            if d_norm == 0.0 {
+                return None;
+            }
+            let dot: f64 = q
+                .iter()
+                .filter_map(|(t, w)| d.get(t).map(|dw| w * dw))
+                .sum();
+            let score = dot / (q_norm * d_norm);
+            (score > 0.0).then_some((i, score))
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored
+}
+
+/// Term frequency times inverse document frequency. Ordered, so sums over
+/// the terms come out the same to the last bit on every run.
+fn weigh<'a>(tokens: &'a [String], idf: &impl Fn(&str) -> f64) -> BTreeMap<&'a str, f64> {
+    let mut tf: BTreeMap<&str, f64> = BTreeMap::new();
+    for t in tokens {
+        *tf.entry(t.as_str()).or_default() += 1.0;
+    }
+    for (t, w) in tf.iter_mut() {
+        *w *= idf(t);
+    }
+    tf
+}
+
+fn norm(v: &BTreeMap<&str, f64>) -> f64 {
+    v.values().map(|w| w * w).sum::<f64>().sqrt()
+}
@@ -0,0 +1,126 @@
+//! `tk snapshot`: a Markdown summary of the tracker meant to be committed, so
+//! a PR's diff shows how it moved tickets.
+//!
+//! The output depends only on the tickets: no timestamps, and every list is
+//! sorted by priority then ID, so running it twice on the same tickets gives
+//! the same file byte for byte.
+
+use crate::types::{Status, Ticket};
+
+pub const SUMMARY_FILE: &str = "TICKETS_SUMMARY.md";
+
+/// An open ticket waiting on open deps, or blocked with `tk block`
+pub struct Blocked<'a> {
+    pub ticket: &'a Ticket,
+    pub waiting_on: Vec<String>,
+}
+
+/// The summary of the live `tickets`, given which of them are ready and
+/// which blocked
+pub fn render(tickets: &[&Ticket], ready: &[&Ticket], blocked: &[Blocked]) -> String {
+    let mut out = String::from("# Tickets\n\n");
+    out.push_str("Written by `tk snapshot`; run it again instead of editing this file.\n\n");
+
+    out.push_str("## Counts\n\n| Status | Tickets |\n|--------|---------|\n");
+    for status in [Status::Open, Status::InProgress, Status::Closed] {
+        let n = tickets.iter().filter(|t| t.meta.status == status).count();
+        out.push_str(&format!("| {} | {} |\n", status, n));
+    }
+    out.push_str(&format!("| total | {} |\n", tickets.len()));
+
+    let mut priorities: Vec<u8> = tickets
+        .iter()
+        .filter(|t| t.is_open())
+        .map(|t| t.meta.priority)
+        .collect();
+    priorities.sort_unstable();
+    priorities.dedup();
+    if !priorities.is_empty() {
+        out.push_str("\n| Priority | Not closed |\n|----------|------------|\n");
+        for p in priorities {
+            let n = tickets
+                .iter()
+                .filter(|t| t.is_open() && t.meta.priority == p)
+                .count();
+            out.push_str(&format!("| P{} | {} |\n", p, n));
+        }
+    }
+
+    let mut ready = ready.to_vec();
+    ready.sort_by(|a, b| order(a, b));
+    out.push_str(&format!("\n## Ready ({})\n\n", ready.len()));
+    if ready.is_empty() {
+        out.push_str("Nothing is ready.\n");
+    }
+    for t in ready {
+        out.push_str(&format!("- {}\n", line(t)));
+    }
+
+    let mut blocked: Vec<&Blocked> = blocked.iter().collect();
+    blocked.sort_by(|a, b| order(a.ticket, b.ticket));
+    out.push_str(&format!("\n## Blocked ({})\n\n", blocked.len()));
+    if blocked.is_empty() {
+        out.push_str("Nothing is blocked.\n");
+    }
+    for b in blocked {
+        let mut why = Vec::new();
+        if b.ticket.meta.blocked {
+            why.push(match &b.ticket.meta.blocked_reason {
+                Some(reason) => format!("blocked: {}", reason),
+                None => "blocked".to_string(),
+            });
+        }
+        if !b.waiting_on.is_empty() {
+            let mut deps = b.waiting_on.clone();
+            deps.sort();
+            let deps: Vec<String> = deps.iter().map(|d| format!("`{}`", d)).collect();
+            why.push(format!("waiting on {}", deps.join(", ")));
+        }
+        out.push_str(&format!("- {}, {}\n", line(b.ticket), why.join("; ")));
+    }
+    out
+}
+
+fn order(a: &Ticket, b: &Ticket) -> std::cmp::Ordering {
+    a.meta
+        .priority
+        .cmp(&b.meta.priority)
+        .then_with(|| a.id().cmp(b.id()))
+}
+
+fn line(t: &Ticket) -> String {
+    let status = match t.meta.status {
+        Status::InProgress => " (in progress)",
+        _ => "",
+    };
+    format!("`{}` [P{}] {}{}", t.id(), t.meta.priority, t.title, status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tickets_blocked_by_hand_are_listed_with_their_reason() {
+        let dep = Ticket::new("tk-0001".to_string(), "Dep".to_string());
+        let mut waiting = Ticket::new("tk-0002".to_string(), "Waiting".to_string());
+        waiting.meta.deps = vec!["tk-0001".to_string()];
+        let mut held = Ticket::new("tk-0003".to_string(), "Held".to_string());
+        held.meta.blocked = true;
+        held.meta.blocked_reason = Some("vendor patch".to_string());
+        let blocked = [
+            Blocked {
+                ticket: &held,
+                waiting_on: Vec::new(),
+            },
+            Blocked {
+                ticket: &waiting,
+                waiting_on: vec!["tk-0001".to_string()],
+            },
+        ];
+        let out = render(&[&dep, &waiting, &held], &[&dep], &blocked);
+        assert!(out.contains("## Blocked (2)\n"), "{}", out);
+        assert!(out.contains(", waiting on `tk-0001`\n"), "{}", out);
+        assert!(out.contains(", blocked: vendor patch\n"), "{}", out);
+    }
+}
@@ -0,0 +1,480 @@
+//! Reports for `tk stats`, computed from ticket fields and history.
+
+use crate::types::{EventAction, Status, Ticket};
+use chrono::{DateTime, Datelike, Duration, Local, Months, NaiveDate, Utc};
+
+/// Tickets that were reopened at least `min` times, most reopened first
+pub fn flapping(tickets: &[Ticket], min: usize) -> Vec<&Ticket> {
+    let mut flapping: Vec<&Ticket> = tickets
+        .iter()
+        .filter(|t| t.meta.reopened.len() >= min.max(1))
+        .collect();
+    flapping.sort_by(|a, b| {
+        b.meta
+            .reopened
+            .len()
+            .cmp(&a.meta.reopened.len())
+            .then_with(|| a.id().cmp(b.id()))
+    });
+    flapping
+}
+
+/// Share of tickets that were ever closed which were later reopened
+pub fn reopen_rate(tickets: &[Ticket]) -> f64 {
+    let ever_closed = tickets
+        .iter()
+        .filter(|t| !t.is_open() || !t.meta.reopened.is_empty())
+        .count();
+    if ever_closed == 0 {
+        return 0.0;
+    }
+    let reopened = tickets
+        .iter()
+        .filter(|t| !t.meta.reopened.is_empty())
+        .count();
+    reopened as f64 / ever_closed as f64
+}
+
+/// A stretch of time a ticket spent in one state. `review` is the time from
+/// closing to `tk review done`.
+pub struct Span<'a> {
+    pub ticket: &'a Ticket,
+    pub state: &'static str,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Still in that state at `now`
+    pub ongoing: bool,
+}
+
+impl Span<'_> {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+/// States reported on, in workflow order
+pub const STATES: [&str; 3] = ["open", "in-progress", "review"];
+
+/// The ticket's time in each state, oldest first, rebuilt from the status
+/// changes in its history. Time closed (other than waiting for review)
+/// isn't counted. A ticket with no status changes recorded has been in its
+/// current status all along (it may have been created in progress).
+pub fn spans(ticket: &Ticket, now: DateTime<Utc>) -> Vec<Span<'_>> {
+    let mut spans = Vec::new();
+    let mut reviews = Vec::new();
+    let mut start = ticket.meta.created;
+    let mut closed_at = None;
+    let changed = ticket
+        .meta
+        .events
+        .iter()
+        .any(|e| e.action == EventAction::Status);
+    let mut state = if changed {
+        Status::Open
+    } else {
+        ticket.meta.status
+    };
+    let mut push = |state: Status, start, end, ongoing| {
+        if let Some(name) = state_name(state) {
+            if end > start {
+                spans.push(Span {
+                    ticket,
+                    state: name,
+                    start,
+                    end,
+                    ongoing,
+                });
+            }
+        }
+    };
+    for event in &ticket.meta.events {
+        match event.action {
+            EventAction::Status => {
+                // `from` is right even when older events were compacted away
+                let from = event.from.as_deref().and_then(|s| s.parse().ok());
+                push(from.unwrap_or(state), start, event.at, false);
+                state = event
+                    .to
+                    .as_deref()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(state);
+                start = event.at;
+                closed_at = (state == Status::Closed).then_some(event.at);
+            }
+            EventAction::Reviewed => {
+                if let Some(closed) = closed_at.take() {
+                    if event.at > closed {
+                        reviews.push(Span {
+                            ticket,
+                            state: "review",
+                            start: closed,
+                            end: event.at,
+                            ongoing: false,
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    push(state, start, now, true);
+    spans.append(&mut reviews);
+    spans.sort_by_key(|s| s.start);
+    spans
+}
+
+/// In-progress tickets with how long they've been in progress this time,
+/// longest first
+pub fn aging_wip(tickets: &[Ticket], now: DateTime<Utc>) -> Vec<(&Ticket, DateTime<Utc>)> {
+    let mut wip: Vec<(&Ticket, DateTime<Utc>)> = tickets
+        .iter()
+        .filter(|t| t.meta.status == Status::InProgress)
+        .map(|t| {
+            let since = spans(t, now)
+                .into_iter()
+                .rfind(|s| s.ongoing)
+                .map_or(t.meta.created, |s| s.start);
+            (t, since)
+        })
+        .collect();
+    wip.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.id().cmp(b.0.id())));
+    wip
+}
+
+fn state_name(status: Status) -> Option<&'static str> {
+    match status {
+        Status::Open => Some("open"),
+        Status::InProgress => Some("in-progress"),
+        Status::Closed => None,
+    }
+}
+
+/// How long tickets stay in one state
+pub struct StateTime<'a> {
+    pub state: &'static str,
+    pub spans: usize,
+    pub tickets: usize,
+    pub total: Duration,
+    pub mean: Duration,
+    pub median: Duration,
+    pub longest: Option<&'a Span<'a>>,
+}
+
+/// Time in each of `STATES` over `spans`; states nobody was in are left out
+pub fn time_in_status<'a>(spans: &'a [Span<'a>]) -> Vec<StateTime<'a>> {
+    STATES
+        .iter()
+        .filter_map(|&state| {
+            let of_state: Vec<&Span> = spans.iter().filter(|s| s.state == state).collect();
+            if of_state.is_empty() {
+                return None;
+            }
+            let mut durations: Vec<Duration> = of_state.iter().map(|s| s.duration()).collect();
+            durations.sort();
+            let total: Duration = durations.iter().sum();
+            let mut tickets: Vec<&str> = of_state.iter().map(|s| s.ticket.id()).collect();
+            tickets.dedup();
+            Some(StateTime {
+                state,
+                spans: of_state.len(),
+                tickets: tickets.len(),
+                total,
+                mean: total / of_state.len() as i32,
+                median: durations[durations.len() / 2],
+                longest: of_state.into_iter().max_by_key(|s| s.duration()),
+            })
+        })
+        .collect()
+}
+
+/// `3d 4h`, `5h 10m`, `12m`
+pub fn format_duration(d: Duration) -> String {
+    let minutes = d.num_minutes().max(0);
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+/// What `tk stats throughput` counts per
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Interval {
+    Day,
+    /// Monday to Sunday
+    Week,
+    Month,
+}
+
+impl Interval {
+    /// The first day of the interval `date` is in
+    pub fn start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Interval::Day => date,
+            Interval::Week => date - Duration::days(date.weekday().num_days_from_monday().into()),
+            Interval::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    /// The first day of the interval after the one starting on `start`
+    pub fn next(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Interval::Day => start + Duration::days(1),
+            Interval::Week => start + Duration::days(7),
+            Interval::Month => start + Months::new(1),
+        }
+    }
+
+    /// The first day of the interval `n` before the one starting on `start`
+    pub fn back(self, start: NaiveDate, n: u32) -> NaiveDate {
+        match self {
+            Interval::Day => start - Duration::days(n.into()),
+            Interval::Week => start - Duration::weeks(n.into()),
+            Interval::Month => start - Months::new(n),
+        }
+    }
+
+    /// `2024-01-15` (the first day), or `2024-01` for a month
+    pub fn label(self, start: NaiveDate) -> String {
+        match self {
+            Interval::Month => start.format("%Y-%m").to_string(),
+            _ => start.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Interval::Day => write!(f, "day"),
+            Interval::Week => write!(f, "week"),
+            Interval::Month => write!(f, "month"),
+        }
+    }
+}
+
+/// The tickets created and closed in one interval
+pub struct Throughput<'a> {
+    pub start: NaiveDate,
+    pub created: Vec<&'a Ticket>,
+    pub closed: Vec<&'a Ticket>,
+}
+
+/// Tickets created and closed in each interval from the one `from` is in
+/// through the one `to` is in, oldest first, by local date. A ticket closed
+/// more than once counts when it was last closed.
+pub fn throughput(
+    tickets: &[Ticket],
+    by: Interval,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Vec<Throughput<'_>> {
+    let mut starts = Vec::new();
+    let mut start = by.start(from);
+    while start <= to {
+        starts.push(start);
+        start = by.next(start);
+    }
+    let slot = |at: DateTime<Utc>| {
+        let start = by.start(at.with_timezone(&Local).date_naive());
+        starts.binary_search(&start).ok()
+    };
+    let mut intervals: Vec<Throughput> = starts
+        .iter()
+        .map(|&start| Throughput {
+            start,
+            created: Vec::new(),
+            closed: Vec::new(),
+        })
+        .collect();
+    for t in tickets {
+        let created = slot(t.meta.created);
+        let closed = t.meta.closed.filter(|_| !t.is_open()).and_then(slot);
+        if let Some(i) = created {
+            intervals[i].created.push(t);
+        }
+        if let Some(i) = closed {
+            intervals[i].closed.push(t);
+        }
+    }
+    intervals
+}
+
+/// `values` as one ASCII character each, from `_` for nothing up to `#`
+/// for `max`, so two series scaled to the same `max` can be compared
+pub fn sparkline(values: &[usize], max: usize) -> String {
+    const LEVELS: &[u8] = b"_.:-=+*#";
+    values
+        .iter()
+        .map(|&v| match v {
+            0 => '_',
+            _ => {
+                let top = LEVELS.len() - 1;
+                let level = (v * top).div_ceil(max.max(1)).clamp(1, top);
+                LEVELS[level] as char
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Event;
+
+    /// A ticket created and (if `closed`) closed at noon UTC on these days
+    fn ticket(id: &str, created: &str, closed: Option<&str>) -> Ticket {
+        let at = |day: &str| {
+            format!("{}T12:00:00Z", day)
+                .parse::<DateTime<Utc>>()
+                .unwrap()
+        };
+        let mut t = Ticket::new(id.to_string(), id.to_string());
+        t.meta.created = at(created);
+        if let Some(day) = closed {
+            t.meta.status = Status::Closed;
+            t.meta.closed = Some(at(day));
+        }
+        t
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn at(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn status_change(when: &str, from: Option<&str>, to: &str) -> Event {
+        Event {
+            at: at(when),
+            by: None,
+            action: EventAction::Status,
+            from: from.map(str::to_string),
+            to: Some(to.to_string()),
+        }
+    }
+
+    fn states<'a>(spans: &[Span<'a>]) -> Vec<(&'a str, DateTime<Utc>, DateTime<Utc>)> {
+        spans.iter().map(|s| (s.state, s.start, s.end)).collect()
+    }
+
+    #[test]
+    fn spans_follow_status_changes_and_count_review_waits() {
+        let mut t = ticket("tk-0001", "2026-03-02", Some("2026-03-05"));
+        t.meta.events = vec![
+            status_change("2026-03-03T12:00:00Z", None, "in-progress"),
+            status_change("2026-03-05T12:00:00Z", Some("in-progress"), "closed"),
+            Event {
+                action: EventAction::Reviewed,
+                to: None,
+                ..status_change("2026-03-06T12:00:00Z", None, "")
+            },
+        ];
+        let now = at("2026-03-10T12:00:00Z");
+        assert_eq!(
+            states(&spans(&t, now)),
+            vec![
+                (
+                    "open",
+                    at("2026-03-02T12:00:00Z"),
+                    at("2026-03-03T12:00:00Z")
+                ),
+                (
+                    "in-progress",
+                    at("2026-03-03T12:00:00Z"),
+                    at("2026-03-05T12:00:00Z")
+                ),
+                (
+                    "review",
+                    at("2026-03-05T12:00:00Z"),
+                    at("2026-03-06T12:00:00Z")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn without_status_changes_a_ticket_spent_its_life_in_its_status() {
+        let mut t = ticket("tk-0001", "2026-03-02", None);
+        t.meta.status = Status::InProgress;
+        let now = at("2026-03-04T12:00:00Z");
+        let spans = spans(&t, now);
+        assert_eq!(
+            states(&spans),
+            vec![("in-progress", at("2026-03-02T12:00:00Z"), now)]
+        );
+        assert!(spans[0].ongoing);
+        assert_eq!(
+            aging_wip(std::slice::from_ref(&t), now)[0].1,
+            at("2026-03-02T12:00:00Z")
+        );
+    }
+
+    #[test]
+    fn throughput_counts_per_interval() {
+        // Closed, then reopened: its old close time doesn't count
+        let mut reopened = ticket("tk-0004", "2026-03-10", Some("2026-03-10"));
+        reopened.meta.status = Status::Open;
+        let tickets = vec![
+            ticket("tk-0001", "2026-03-02", Some("2026-03-11")),
+            ticket("tk-0002", "2026-03-04", None),
+            ticket("tk-0003", "2026-02-20", Some("2026-03-03")),
+            reopened,
+        ];
+        let weeks = throughput(
+            &tickets,
+            Interval::Week,
+            date("2026-03-04"),
+            date("2026-03-15"),
+        );
+        let counts: Vec<(NaiveDate, usize, usize)> = weeks
+            .iter()
+            .map(|w| (w.start, w.created.len(), w.closed.len()))
+            .collect();
+        assert_eq!(
+            counts,
+            [(date("2026-03-02"), 2, 1), (date("2026-03-09"), 1, 1)]
+        );
+
+        let months = throughput(
+            &tickets,
+            Interval::Month,
+            date("2026-02-10"),
+            date("2026-03-15"),
+        );
+        assert_eq!(months[0].start, date("2026-02-01"));
+        assert_eq!(months[0].created.len(), 1);
+        assert_eq!(months[1].created.len(), 3);
+        assert_eq!(months[1].closed.len(), 2);
+    }
+
+    #[test]
+    fn tickets_closed_by_a_status_change_count_as_closed() {
+        // What `tk status <id> closed` does
+        let mut closed = Ticket::new("tk-0001".to_string(), "A".to_string());
+        closed.set_status(Status::Closed);
+        let mut reopened = Ticket::new("tk-0002".to_string(), "B".to_string());
+        reopened.set_status(Status::Closed);
+        reopened.set_status(Status::Open);
+        assert_eq!(reopened.meta.closed, None);
+
+        let today = Local::now().date_naive();
+        let tickets = [closed, reopened];
+        let days = throughput(&tickets, Interval::Day, today, today);
+        let ids: Vec<&str> = days[0].closed.iter().map(|t| t.id()).collect();
+        assert_eq!(ids, ["tk-0001"]);
+    }
+
+    #[test]
+    fn sparklines_share_one_scale() {
+        assert_eq!(sparkline(&[0, 1, 4, 7], 7), "_.=#");
+        assert_eq!(sparkline(&[1, 1], 100), "..");
+        assert_eq!(sparkline(&[3], 0), "#");
+        assert_eq!(sparkline(&[], 5), "");
+    }
+}
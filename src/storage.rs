@@ -1,18 +1,223 @@
-use crate::types::{Frontmatter, Ticket};
+use crate::config::{Config, CONFIG_FILE};
+#[cfg(unix)]
+use crate::daemon;
+use crate::diff;
+use crate::error::TkError;
+use crate::frontmatter;
+use crate::id;
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::types::{Frontmatter, Layout, Note, Ticket, TitleStyle, REPLY_PREFIX};
+use crate::view::View;
 use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
 
 const TICKETS_DIR: &str = ".tickets";
+const ARCHIVE_DIR: &str = "archive";
+/// Old archived tickets rolled into one Markdown file per year, inside the
+/// archive (`tk archive compact`)
+const BUNDLES_DIR: &str = "bundles";
+/// Starts each ticket in a bundle; an HTML comment so the bundle still
+/// renders as Markdown
+const BUNDLE_MARKER: &str = "<!-- tk-bundle: ";
+/// Unpublished tickets, one subdirectory per author (`tk draft`)
+const DRAFTS_DIR: &str = "drafts";
+/// Per-clone state that isn't part of the project, ignored by git
+const LOCAL_DIR: &str = ".local";
+const LOCK_FILE: &str = ".lock";
+/// Held while `.manifest` is read, changed and written back. A lock of its
+/// own, since writes happen both with and without the store lock held.
+const MANIFEST_LOCK: &str = ".manifest.lock";
+/// Notes for people browsing `.tickets/`; not a ticket
+const README_FILE: &str = "README.md";
+/// How long `Storage::lock` waits for another tk to let go
+const LOCK_WAIT: Duration = Duration::from_secs(10);
+/// How long a read waits for another tk's change to finish before going
+/// ahead anyway; changes take milliseconds, so a lock held longer than
+/// this is more likely left behind than in use
+const READ_WAIT: Duration = Duration::from_secs(1);
+/// A lock this old was left behind by a tk that died, and is taken over
+const LOCK_STALE: Duration = Duration::from_secs(60);
+
+/// How `.tickets/` and its archive hold ticket files (`layout` in
+/// config.yaml). Files are found in either place whatever the setting.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreLayout {
+    /// Every ticket directly in the directory
+    #[default]
+    Flat,
+    /// Each ticket in a two-character subdirectory (`ab/tk-ab12.md`), for
+    /// stores with tens of thousands of tickets
+    Sharded,
+}
+
+impl StoreLayout {
+    pub fn name(self) -> &'static str {
+        match self {
+            StoreLayout::Flat => "flat",
+            StoreLayout::Sharded => "sharded",
+        }
+    }
+}
+
+/// How ticket files write their frontmatter (`frontmatter` in config.yaml).
+/// Files in either format are read whatever the setting.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum FrontmatterFormat {
+    /// YAML between `---` lines
+    #[default]
+    Yaml,
+    /// TOML between `+++` lines, as Hugo and Zola write it
+    Toml,
+}
+
+impl FrontmatterFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            FrontmatterFormat::Yaml => "yaml",
+            FrontmatterFormat::Toml => "toml",
+        }
+    }
+
+    /// The line that opens and closes the frontmatter
+    pub fn fence(self) -> &'static str {
+        match self {
+            FrontmatterFormat::Yaml => "---",
+            FrontmatterFormat::Toml => "+++",
+        }
+    }
+
+    /// The format of a ticket file's content, from its opening fence
+    pub fn detect(content: &str) -> Option<Self> {
+        [FrontmatterFormat::Yaml, FrontmatterFormat::Toml]
+            .into_iter()
+            .find(|f| content.trim_start().starts_with(f.fence()))
+    }
+}
+
+/// The store-wide lock from `Storage::lock`, released when dropped
+pub struct StoreLock {
+    path: Option<PathBuf>,
+}
+
+impl Drop for StoreLock {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Which part of the store an operation sees.
+///
+/// Archived tickets live in `.tickets/archive/`, the oldest perhaps rolled
+/// into yearly bundles in `.tickets/archive/bundles/`. They are hidden from
+/// listings by default but still resolve by ID and still reserve their IDs.
+///
+/// Drafts in `.tickets/drafts/<user>/` are in no scope: only the draft
+/// methods below see them, until `tk publish` moves them into the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Live,
+    Archived,
+    All,
+}
+
+impl Scope {
+    /// Scope for listing commands with an `--include-archived` flag
+    pub fn listing(include_archived: bool) -> Self {
+        if include_archived {
+            Scope::All
+        } else {
+            Scope::Live
+        }
+    }
+
+    fn includes_live(self) -> bool {
+        matches!(self, Scope::Live | Scope::All)
+    }
+
+    fn includes_archived(self) -> bool {
+        matches!(self, Scope::Archived | Scope::All)
+    }
+}
 
 pub struct Storage {
     tickets_dir: PathBuf,
+    dry_run: bool,
+    force: bool,
+    /// `layout` from config.yaml, read on first use
+    layout: OnceLock<StoreLayout>,
+    /// `manifest` from config.yaml, read on first use
+    keeps_manifest: OnceLock<bool>,
+    /// `--strict`, else `strict` from config.yaml, read on first use
+    strict: OnceLock<bool>,
+    /// Every ticket, indexed, from the first `view()` until tk next writes
+    view: Mutex<Option<Arc<View>>>,
 }
 
 impl Storage {
     pub fn new() -> Self {
         let tickets_dir = Self::find_tickets_dir();
-        Self { tickets_dir }
+        Self {
+            tickets_dir,
+            dry_run: false,
+            force: false,
+            layout: OnceLock::new(),
+            keeps_manifest: OnceLock::new(),
+            strict: OnceLock::new(),
+            view: Mutex::new(None),
+        }
+    }
+
+    /// A store at `tickets_dir`, for tests
+    #[cfg(test)]
+    pub fn in_dir(tickets_dir: PathBuf) -> Self {
+        Self {
+            tickets_dir,
+            ..Self::new()
+        }
+    }
+
+    /// In dry-run mode writes are described on stderr instead of performed
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// In strict mode a ticket file that doesn't parse is an error rather
+    /// than a warning, so scripts never act on a partial set of tickets
+    pub fn with_strict(self, strict: bool) -> Self {
+        if strict {
+            let _ = self.strict.set(true);
+        }
+        self
+    }
+
+    pub fn is_strict(&self) -> bool {
+        *self
+            .strict
+            .get_or_init(|| self.config().map(|c| c.strict).unwrap_or_default())
+    }
+
+    /// With force (`--ignore-frozen`), frozen tickets can be saved and deleted like any other
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
     }
 
     /// Find .tickets directory by searching current and parent directories.
@@ -20,6 +225,7 @@ impl Storage {
     fn find_tickets_dir() -> PathBuf {
         // Check TICKETS_DIR env var first
         if let Ok(dir) = std::env::var("TICKETS_DIR") {
+            tracing::debug!("using TICKETS_DIR={}", dir);
             return PathBuf::from(dir);
         }
 
@@ -28,6 +234,7 @@ impl Storage {
             loop {
                 let candidate = current.join(TICKETS_DIR);
                 if candidate.is_dir() {
+                    tracing::debug!("found {}", candidate.display());
                     return candidate;
                 }
                 if !current.pop() {
@@ -42,7 +249,11 @@ impl Storage {
 
     pub fn init(&self) -> Result<()> {
         if !self.tickets_dir.exists() {
-            fs::create_dir_all(&self.tickets_dir)?;
+            if self.dry_run {
+                eprintln!("Would create {}/", self.tickets_dir.display());
+            } else {
+                fs::create_dir_all(&self.tickets_dir)?;
+            }
         }
         Ok(())
     }
@@ -51,164 +262,2165 @@ impl Storage {
         self.tickets_dir.exists()
     }
 
-    pub fn ticket_path(&self, id: &str) -> PathBuf {
-        self.tickets_dir.join(format!("{}.md", id))
+    /// Directory containing .tickets/ (the project root)
+    pub fn root_dir(&self) -> PathBuf {
+        match self.tickets_dir.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    }
+
+    pub fn tickets_dir(&self) -> &Path {
+        &self.tickets_dir
+    }
+
+    /// Where `tk daemon` listens
+    #[cfg(unix)]
+    pub fn socket_path(&self) -> PathBuf {
+        self.tickets_dir.join(daemon::SOCKET_NAME)
     }
 
-    /// Parse a markdown file with YAML frontmatter into a Ticket
-    fn parse_ticket(content: &str) -> Result<Ticket> {
-        let content = content.trim();
+    /// Settings from .tickets/config.yaml
+    pub fn config(&self) -> Result<Config> {
+        Config::load(&self.tickets_dir)
+    }
+
+    /// Whether a file in .tickets/ or the archive holds a ticket
+    pub fn is_ticket_file(path: &Path) -> bool {
+        path.extension().is_some_and(|e| e == "md")
+            && path.file_name().is_none_or(|name| name != README_FILE)
+    }
 
-        // Must start with ---
-        if !content.starts_with("---") {
-            anyhow::bail!("Invalid ticket format: missing YAML frontmatter");
+    /// Write .tickets/README.md unless there already is one. Returns
+    /// whether it was written.
+    pub fn write_readme(&self, text: &str) -> Result<bool> {
+        let path = self.tickets_dir.join(README_FILE);
+        if path.exists() {
+            return Ok(false);
         }
+        self.write(&path, text)?;
+        Ok(true)
+    }
 
-        // Find the closing ---
-        let rest = &content[3..];
-        let end = rest
-            .find("\n---")
-            .context("Invalid ticket format: unclosed frontmatter")?;
+    /// The text of .tickets/config.yaml, None if there isn't one
+    pub fn config_text(&self) -> Result<Option<String>> {
+        match fs::read_to_string(self.tickets_dir.join(CONFIG_FILE)) {
+            Ok(text) => Ok(Some(text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
 
-        let yaml_str = &rest[..end].trim();
-        let body_start = end + 4; // skip \n---
-        let body = if body_start < rest.len() {
-            rest[body_start..].trim()
-        } else {
-            ""
+    /// Replace .tickets/config.yaml with `yaml`, refusing it unless it
+    /// parses as a config
+    pub fn write_config(&self, yaml: &str, origin: &str) -> Result<PathBuf> {
+        Config::parse(yaml, origin)?;
+        let path = self.tickets_dir.join(CONFIG_FILE);
+        self.write(&path, yaml)?;
+        Ok(path)
+    }
+
+    pub fn archive_dir(&self) -> PathBuf {
+        self.tickets_dir.join(ARCHIVE_DIR)
+    }
+
+    pub fn bundles_dir(&self) -> PathBuf {
+        self.archive_dir().join(BUNDLES_DIR)
+    }
+
+    /// Path of an archived ticket's own file (it may be in a bundle instead)
+    fn archived_path(&self, id: &str) -> PathBuf {
+        self.path_in(&self.archive_dir(), id)
+    }
+
+    /// Where `user`'s drafts live
+    pub fn drafts_dir(&self, user: &str) -> PathBuf {
+        self.tickets_dir.join(DRAFTS_DIR).join(user)
+    }
+
+    pub fn local_dir(&self) -> PathBuf {
+        self.tickets_dir.join(LOCAL_DIR)
+    }
+
+    /// Path of a live ticket file
+    pub fn ticket_path(&self, id: &str) -> PathBuf {
+        self.path_in(&self.tickets_dir, id)
+    }
+
+    /// Where `id`'s file is in `dir`: wherever it already is, flat or in its
+    /// shard, else where the configured layout puts it
+    fn path_in(&self, dir: &Path, id: &str) -> PathBuf {
+        let layout = self.layout();
+        let preferred = Self::placed(dir, id, layout);
+        if preferred.exists() {
+            return preferred;
+        }
+        let other = match layout {
+            StoreLayout::Flat => Self::placed(dir, id, StoreLayout::Sharded),
+            StoreLayout::Sharded => Self::placed(dir, id, StoreLayout::Flat),
         };
+        if other.exists() {
+            other
+        } else {
+            preferred
+        }
+    }
+
+    /// The configured layout new ticket files go in
+    pub fn layout(&self) -> StoreLayout {
+        *self
+            .layout
+            .get_or_init(|| self.config().map(|c| c.layout).unwrap_or_default())
+    }
+
+    /// Where `layout` puts `id`'s file in `dir`
+    fn placed(dir: &Path, id: &str, layout: StoreLayout) -> PathBuf {
+        let name = format!("{}.md", id);
+        match layout {
+            StoreLayout::Flat => dir.join(name),
+            StoreLayout::Sharded => dir.join(id::shard(id)).join(name),
+        }
+    }
+
+    /// The ticket files in `dir` and its shard subdirectories, in name order
+    pub fn ticket_files(dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        if !dir.exists() {
+            return Ok(files);
+        }
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if Self::is_shard_dir(&path) {
+                for entry in fs::read_dir(&path)? {
+                    let path = entry?.path();
+                    if Self::is_ticket_file(&path) {
+                        files.push(path);
+                    }
+                }
+            } else if Self::is_ticket_file(&path) {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
 
-        // Parse YAML frontmatter
-        let meta: Frontmatter =
-            serde_yaml::from_str(yaml_str).context("Failed to parse YAML frontmatter")?;
+    /// A two-character directory like the sharded layout makes
+    fn is_shard_dir(path: &Path) -> bool {
+        path.is_dir()
+            && path.file_name().and_then(|n| n.to_str()).is_some_and(|n| {
+                n.len() == 2
+                    && n.bytes()
+                        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+            })
+    }
+
+    /// Path of an existing ticket file, checking the archive if it isn't live
+    pub fn locate(&self, id: &str) -> PathBuf {
+        let archived = self.archived_path(id);
+        if !self.ticket_path(id).exists() && archived.exists() {
+            archived
+        } else {
+            self.ticket_path(id)
+        }
+    }
 
-        // Extract title from first markdown heading
-        let (title, body) = Self::extract_title(body);
+    /// Parse a markdown file with YAML or TOML frontmatter into a Ticket
+    pub fn parse_ticket(content: &str) -> Result<Ticket> {
+        let (format, meta_str, rest) = Self::split_frontmatter(content)?;
+        // Blank lines in place of what comes before the frontmatter, so the
+        // line numbers in parse errors are the file's
+        let start = meta_str.as_ptr() as usize - content.as_ptr() as usize;
+        let meta_str = "\n".repeat(content[..start].matches('\n').count()) + meta_str;
+        let mut meta: Frontmatter = match format {
+            FrontmatterFormat::Yaml => serde_yaml::from_str(&meta_str)
+                .map_err(|e| TkError::Parse(format!("Failed to parse YAML frontmatter: {}", e)))?,
+            FrontmatterFormat::Toml => toml::from_str(&meta_str)
+                .map_err(|e| TkError::Parse(format!("Failed to parse TOML frontmatter: {}", e)))?,
+        };
 
+        let meta_title = meta.title.take();
+        let (title, body, layout) = Self::split_title(rest, meta_title);
         Ok(Ticket {
             meta,
             title,
-            body: body.to_string(),
+            body,
+            archived: false,
+            layout,
+        })
+    }
+
+    /// A ticket file's frontmatter format, frontmatter text (without its
+    /// fences) and everything after the closing fence's line. Each fence is
+    /// a line of its own; the text between them is kept as is, since the
+    /// whitespace in a YAML block scalar is part of its value.
+    fn split_frontmatter(content: &str) -> Result<(FrontmatterFormat, &str, &str)> {
+        // Editors on Windows may start the file with a byte order mark
+        let content = content.trim_start_matches('\u{feff}').trim_start();
+
+        // Must start with --- (YAML) or +++ (TOML)
+        let format = FrontmatterFormat::detect(content)
+            .ok_or_else(|| TkError::Parse("Invalid ticket format: missing frontmatter".into()))?;
+        let fence = format.fence();
+
+        let (opening, text) = content.split_once('\n').unwrap_or((content, ""));
+        if opening.trim_end() != fence {
+            return Err(TkError::Parse(format!(
+                "Invalid ticket format: the opening {} must be on a line of its own",
+                fence
+            ))
+            .into());
+        }
+
+        // Find the closing fence
+        let mut end = 0;
+        for line in text.split_inclusive('\n') {
+            if line.trim_end() == fence {
+                return Ok((format, &text[..end], &text[end + line.len()..]));
+            }
+            end += line.len();
+        }
+        Err(TkError::Parse(format!(
+            "Invalid ticket format: unclosed frontmatter (no closing {} line)",
+            fence
+        ))
+        .into())
+    }
+
+    /// The title, body and layout of what follows the frontmatter. The title
+    /// is `meta_title` (from a `title:` key) if given, else a `# ` or setext
+    /// `===` heading at the top, else "Untitled" with the body starting
+    /// right away. The body is the text between the title's blank lines and
+    /// the trailing whitespace; the layout keeps both.
+    fn split_title(rest: &str, meta_title: Option<String>) -> (String, String, Layout) {
+        let untitled = |style| Layout {
+            before_title: String::new(),
+            title: Some(style),
+            heading: String::new(),
+            tail: rest.to_string(),
+        };
+        let (before_title, after) = Self::blank_lines(rest);
+        let (title, layout) = match (meta_title, Self::heading(after)) {
+            (Some(title), _) => (title, untitled(TitleStyle::Frontmatter)),
+            (None, Some((title, style, tail))) => (
+                title,
+                Layout {
+                    before_title: before_title.to_string(),
+                    title: Some(style),
+                    heading: after[..after.len() - tail.len()].to_string(),
+                    tail: tail.to_string(),
+                },
+            ),
+            (None, None) => ("Untitled".to_string(), untitled(TitleStyle::Missing)),
+        };
+        let (_, body, _) = Self::split_tail(&layout.tail);
+        (title, body.to_string(), layout)
+    }
+
+    /// The title of a `# Title` line or a setext heading (`Title` over a
+    /// line of `=`) at the start of `text`, how it was written, and the text
+    /// after it
+    fn heading(text: &str) -> Option<(String, TitleStyle, &str)> {
+        let (line, rest) = text.split_once('\n').unwrap_or((text, ""));
+        if let Some(title) = line.trim().strip_prefix("# ") {
+            return Some((title.trim().to_string(), TitleStyle::Heading, rest));
+        }
+        let (underline, after) = rest.split_once('\n').unwrap_or((rest, ""));
+        let marker = underline.trim();
+        let setext = !marker.is_empty()
+            && marker.chars().all(|c| c == '=')
+            && !line.trim().is_empty()
+            && !line.starts_with("    ");
+        setext.then(|| {
+            let style = TitleStyle::Setext(underline.to_string());
+            (line.trim().to_string(), style, after)
         })
     }
 
-    /// Extract title from first # heading, return (title, remaining body)
+    /// A tail's leading blank lines, body and trailing whitespace
+    fn split_tail(tail: &str) -> (&str, &str, &str) {
+        let (gap, rest) = Self::blank_lines(tail);
+        let body = rest.trim_end();
+        (gap, body, &rest[body.len()..])
+    }
+
+    /// The whitespace-only lines `text` starts with, and the rest
+    fn blank_lines(text: &str) -> (&str, &str) {
+        let mut end = 0;
+        for line in text.split_inclusive('\n') {
+            if !line.ends_with('\n') || !line.trim().is_empty() {
+                break;
+            }
+            end += line.len();
+        }
+        text.split_at(end)
+    }
+
+    /// Extract title from a `# ` or setext heading on the first non-blank
+    /// line, return (title, remaining body)
     pub fn extract_title(body: &str) -> (String, &str) {
+        let (_, text) = Self::blank_lines(body);
+        match Self::heading(text) {
+            Some((title, _, rest)) => (title, rest.trim_start_matches('\n')),
+            None => ("Untitled".to_string(), body),
+        }
+    }
+
+    /// Notes in a ticket body, as `Ticket::add_note` writes them:
+    /// `[YYYY-MM-DD HH:MM author] text`. A note runs until the next note or
+    /// heading, so multi-line notes come back whole. `anonymous` (how a note
+    /// without an author is written) reads back as no author, and a leading
+    /// `re #N: ` as a reply to note N.
+    pub fn parse_notes(body: &str) -> Vec<Note> {
+        Self::split_notes(body)
+            .1
+            .into_iter()
+            .filter_map(|lines| {
+                let mut note = Self::parse_note_line(lines[0])?;
+                for line in &lines[1..] {
+                    note.content.push('\n');
+                    note.content.push_str(line);
+                }
+                note.content.truncate(note.content.trim_end().len());
+                Some(note)
+            })
+            .collect()
+    }
+
+    /// A body's lines split the way `parse_notes` reads them: the lines
+    /// outside any note, and each note's lines from its `[timestamp author]`
+    /// line up to the next note or heading
+    pub fn split_notes(body: &str) -> (Vec<&str>, Vec<Vec<&str>>) {
+        let mut text = Vec::new();
+        let mut notes: Vec<Vec<&str>> = Vec::new();
+        let mut in_note = false;
         for line in body.lines() {
-            let trimmed = line.trim();
-            if let Some(stripped) = trimmed.strip_prefix("# ") {
-                let title = stripped.trim().to_string();
-                // Find where this line ends and return the rest
-                if let Some(pos) = body.find(line) {
-                    let after = pos + line.len();
-                    let rest = body[after..].trim_start_matches('\n');
-                    return (title, rest);
-                }
-            } else if !trimmed.is_empty() {
-                // Non-empty, non-heading line - no title found
-                break;
+            if Self::is_note_line(line) {
+                notes.push(vec![line]);
+                in_note = true;
+            } else if Self::is_heading(line) || !in_note {
+                in_note = false;
+                text.push(line);
+            } else {
+                notes.last_mut().expect("in a note").push(line);
+            }
+        }
+        (text, notes)
+    }
+
+    /// Whether a line starts a note: `[YYYY-MM-DD HH:MM author] text`
+    pub fn is_note_line(line: &str) -> bool {
+        Self::parse_note_line(line).is_some()
+    }
+
+    /// The end of a body from its first note on, if only notes follow
+    pub fn notes_section(body: &str) -> Option<&str> {
+        let mut offset = 0;
+        for line in body.split_inclusive('\n') {
+            if Self::parse_note_line(line.trim_end()).is_some() {
+                let section = &body[offset..];
+                return (!section.lines().any(Self::is_heading)).then_some(section);
+            }
+            offset += line.len();
+        }
+        None
+    }
+
+    /// The line of a ticket file (from 1) its body starts on, or None if the
+    /// file doesn't parse
+    pub fn body_line(content: &str) -> Option<usize> {
+        let ticket = Self::parse_ticket(content).ok()?;
+        let tail = ticket.layout.tail.as_str();
+        if !content.ends_with(tail) {
+            return None;
+        }
+        let start = content.len() - tail.len();
+        let (gap, _, _) = Self::split_tail(tail);
+        Some(content[..start + gap.len()].matches('\n').count() + 1)
+    }
+
+    /// Whether a line is a Markdown ATX heading (`# Title`, `## Section`)
+    pub fn is_heading(line: &str) -> bool {
+        let hashes = line.chars().take_while(|&c| c == '#').count();
+        hashes > 0 && line[hashes..].starts_with(' ')
+    }
+
+    fn parse_note_line(line: &str) -> Option<Note> {
+        let (header, content) = line.strip_prefix('[')?.split_once(']')?;
+        let timestamp = NaiveDateTime::parse_from_str(header.get(..16)?, "%Y-%m-%d %H:%M")
+            .ok()?
+            .and_utc();
+        let author = header[16..].trim();
+        let content = content.strip_prefix(' ').unwrap_or(content);
+        let reply = content.strip_prefix(REPLY_PREFIX).and_then(|rest| {
+            let (n, text) = rest.split_once(':')?;
+            Some((n.parse().ok()?, text.strip_prefix(' ').unwrap_or(text)))
+        });
+        let (reply_to, content) = match reply {
+            Some((n, text)) => (Some(n), text),
+            None => (None, content),
+        };
+        Some(Note {
+            timestamp,
+            author: (!author.is_empty() && author != "anonymous").then(|| author.to_string()),
+            content: content.to_string(),
+            reply_to,
+        })
+    }
+
+    /// Serialize a Ticket to markdown with frontmatter in `format`
+    pub fn serialize_ticket(ticket: &Ticket, format: FrontmatterFormat) -> Result<String> {
+        let meta = Self::file_meta(ticket);
+        let meta = match format {
+            FrontmatterFormat::Yaml => serde_yaml::to_string(&meta)?,
+            FrontmatterFormat::Toml => toml::to_string(&meta)?,
+        };
+        Ok(Self::assemble(ticket, format, meta))
+    }
+
+    /// The frontmatter as the file has it: with the title, if that's where
+    /// the file keeps it
+    fn file_meta(ticket: &Ticket) -> Cow<'_, Frontmatter> {
+        if ticket.layout.title == Some(TitleStyle::Frontmatter) {
+            let mut meta = ticket.meta.clone();
+            meta.title = Some(ticket.title.clone());
+            Cow::Owned(meta)
+        } else {
+            Cow::Borrowed(&ticket.meta)
+        }
+    }
+
+    /// `ticket` as the new content of `path`. If `path` already has YAML
+    /// frontmatter and YAML is what's written, its unchanged keys keep
+    /// their text, so the diff shows only what changed.
+    fn render(path: &Path, ticket: &Ticket, format: FrontmatterFormat) -> Result<String> {
+        let old = fs::read_to_string(path).unwrap_or_default();
+        Self::render_over(&old, ticket, format)
+    }
+
+    /// `ticket` as the new version of the file text `old`, keeping the text
+    /// of unchanged YAML keys the way `render` does
+    pub fn render_over(old: &str, ticket: &Ticket, format: FrontmatterFormat) -> Result<String> {
+        if format == FrontmatterFormat::Yaml {
+            let patched = Self::split_frontmatter(old)
+                .ok()
+                .filter(|(old_format, _, _)| *old_format == format)
+                .and_then(|(_, meta, _)| frontmatter::patch(meta, &Self::file_meta(ticket)));
+            if let Some(meta) = patched {
+                return Ok(Self::assemble(ticket, format, meta));
             }
         }
-        ("Untitled".to_string(), body)
+        Self::serialize_ticket(ticket, format)
     }
 
-    /// Serialize a Ticket to markdown with YAML frontmatter
-    fn serialize_ticket(ticket: &Ticket) -> Result<String> {
-        let yaml = serde_yaml::to_string(&ticket.meta)?;
-        let mut content = format!("---\n{}---\n\n# {}\n", yaml, ticket.title);
+    fn assemble(ticket: &Ticket, format: FrontmatterFormat, mut meta: String) -> String {
+        if !meta.ends_with('\n') {
+            meta.push('\n');
+        }
+        let fence = format.fence();
+        let layout = &ticket.layout;
+        let mut content = format!("{}\n{}{}\n", fence, meta, fence);
+        match &layout.title {
+            Some(TitleStyle::Frontmatter) => {}
+            Some(TitleStyle::Missing) if ticket.title == "Untitled" => {}
+            // An unchanged title goes back exactly as it was read
+            Some(TitleStyle::Heading | TitleStyle::Setext(_))
+                if Self::heading(&layout.heading).is_some_and(|(t, _, _)| t == ticket.title) =>
+            {
+                content.push_str(&layout.before_title);
+                content.push_str(&layout.heading);
+            }
+            Some(TitleStyle::Heading) => {
+                content.push_str(&layout.before_title);
+                content.push_str(&format!("# {}\n", ticket.title));
+            }
+            Some(TitleStyle::Setext(underline)) => {
+                content.push_str(&layout.before_title);
+                content.push_str(&format!("{}\n{}\n", ticket.title, underline));
+            }
+            // New, or newly titled: tk's usual heading
+            Some(TitleStyle::Missing) | None => {
+                content.push_str(&format!("\n# {}\n", ticket.title));
+            }
+        }
 
-        if !ticket.body.is_empty() {
-            content.push('\n');
+        // An unchanged body goes back exactly as it was read; a changed one
+        // keeps the blank lines above it
+        let (gap, body, _) = Self::split_tail(&layout.tail);
+        if body == ticket.body {
+            content.push_str(&layout.tail);
+        } else if !ticket.body.is_empty() {
+            // A title read from a file's last line has no newline of its own
+            if !content.ends_with('\n') {
+                content.push('\n');
+            }
+            content.push_str(if body.is_empty() { "\n" } else { gap });
             content.push_str(&ticket.body);
             if !ticket.body.ends_with('\n') {
                 content.push('\n');
             }
         }
-
-        Ok(content)
+        content
     }
 
     /// Load a single ticket by ID
     #[allow(dead_code)]
     pub fn load(&self, id: &str) -> Result<Option<Ticket>> {
-        let path = self.ticket_path(id);
+        let path = self.locate(id);
         if !path.exists() {
             return Ok(None);
         }
         let content = fs::read_to_string(&path)?;
-        Ok(Some(Self::parse_ticket(&content)?))
+        let mut ticket = Self::parse_ticket(&content)?;
+        ticket.archived = path.starts_with(self.archive_dir());
+        Ok(Some(ticket))
     }
 
-    /// Load all tickets
-    pub fn load_all(&self) -> Result<Vec<Ticket>> {
-        self.load_from_dir(&self.tickets_dir)
+    /// Load all tickets in scope, from `tk daemon` if one is running. The
+    /// daemon skips files that don't parse, so strict mode reads them here.
+    ///
+    /// Tickets come in ID order, a live one before an archived one with the
+    /// same ID, whatever order the directories or the daemon list them in;
+    /// every listing sorts stably from here, so its ties fall back to ID.
+    pub fn load_all(&self, scope: Scope) -> Result<Vec<Ticket>> {
+        let mut tickets = self.load_unordered(scope)?;
+        tickets.sort_by(|a, b| a.id().cmp(b.id()).then(a.archived.cmp(&b.archived)));
+        Ok(tickets)
     }
 
-    fn load_from_dir(&self, dir: &Path) -> Result<Vec<Ticket>> {
+    fn load_unordered(&self, scope: Scope) -> Result<Vec<Ticket>> {
+        #[cfg(unix)]
+        if !self.is_strict() {
+            if let Some(tickets) = daemon::query(&self.socket_path(), scope) {
+                return Ok(tickets);
+            }
+        }
+
         let mut tickets = Vec::new();
+        if scope.includes_live() {
+            tickets.extend(self.load_from_dir(&self.tickets_dir)?);
+        }
+        if scope.includes_archived() {
+            let mut archived = self.load_from_dir(&self.archive_dir())?;
+            for t in &mut archived {
+                t.archived = true;
+            }
+            tickets.extend(archived);
+            for (_, bundled) in self.load_bundles()? {
+                tickets.extend(bundled);
+            }
+        }
+        Ok(tickets)
+    }
 
-        if !dir.exists() {
-            return Ok(tickets);
+    /// The tickets in `scope`, indexed for lookups. Reading doesn't take the
+    /// store lock, so it works in a read-only checkout and can't be held up
+    /// for long by a lock left behind. Instead, a read that overlaps another
+    /// tk holding the lock (a `tk claim` or `tk handoff` may be halfway
+    /// through) is done again once it lets go, for up to [`READ_WAIT`]. A daemon answers from its own index,
+    /// which it refreshes in one go.
+    pub fn snapshot(&self, scope: Scope) -> Result<View> {
+        let deadline = Instant::now() + READ_WAIT;
+        loop {
+            let before = self.lock_taken();
+            let tickets = self.load_all(scope)?;
+            if before.is_none() && self.lock_taken().is_none() || Instant::now() > deadline {
+                return Ok(View::new(tickets));
+            }
+            std::thread::sleep(Duration::from_millis(50));
         }
+    }
 
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
+    /// When another tk took the store lock, if it holds it and isn't stale
+    fn lock_taken(&self) -> Option<SystemTime> {
+        let taken = fs::metadata(self.tickets_dir.join(LOCK_FILE))
+            .and_then(|m| m.modified())
+            .ok()?;
+        let stale = taken.elapsed().is_ok_and(|age| age > LOCK_STALE);
+        (!stale).then_some(taken)
+    }
+
+    /// Every ticket, live and archived, indexed as by `snapshot`. Loaded
+    /// once and shared until a ticket file is written or removed, so
+    /// commands that look up what depends on what don't reload or rescan
+    /// the store for each lookup.
+    pub fn view(&self) -> Result<Arc<View>> {
+        let mut cached = self.view.lock().unwrap();
+        if let Some(view) = cached.as_ref() {
+            return Ok(Arc::clone(view));
+        }
+        let view = Arc::new(self.snapshot(Scope::All)?);
+        *cached = Some(Arc::clone(&view));
+        Ok(view)
+    }
+
+    /// IDs of the tickets that list `id` in their deps, from `view`'s
+    /// reverse-deps index
+    pub fn dependents(&self, id: &str) -> Result<Vec<String>> {
+        Ok(self
+            .view()?
+            .dependents(id)
+            .iter()
+            .map(|t| t.id().to_string())
+            .collect())
+    }
 
+    /// Drop the cached `view` after changing a ticket file
+    fn forget_view(&self) {
+        *self.view.lock().unwrap() = None;
+    }
+
+    /// Every bundle, with its (archived) tickets
+    fn load_bundles(&self) -> Result<Vec<(PathBuf, Vec<Ticket>)>> {
+        let dir = self.bundles_dir();
+        let mut bundles = Vec::new();
+        if !dir.exists() {
+            return Ok(bundles);
+        }
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
             if path.extension().is_some_and(|e| e == "md") {
                 let content = fs::read_to_string(&path)?;
-                match Self::parse_ticket(&content) {
-                    Ok(ticket) => tickets.push(ticket),
-                    Err(e) => {
-                        eprintln!("Warning: Failed to parse {}: {}", path.display(), e);
-                    }
+                let tickets = Self::parse_bundle(&content, &path);
+                if self.is_strict() && content.matches(BUNDLE_MARKER).count() != tickets.len() {
+                    return Err(TkError::Parse(format!(
+                        "{} holds a ticket that doesn't parse",
+                        path.display()
+                    ))
+                    .into());
+                }
+                tracing::debug!("loaded {} tickets from {}", tickets.len(), path.display());
+                bundles.push((path, tickets));
+            }
+        }
+        bundles.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(bundles)
+    }
+
+    /// The tickets in a bundle file; ones that fail to parse are warned
+    /// about and skipped, as loose files are
+    pub fn parse_bundle(content: &str, path: &Path) -> Vec<Ticket> {
+        let mut tickets = Vec::new();
+        for text in Self::bundle_sections(content) {
+            match Self::parse_ticket(text) {
+                Ok(mut ticket) => {
+                    ticket.archived = true;
+                    tickets.push(ticket);
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Warning: Failed to parse a ticket in {}: {}",
+                        path.display(),
+                        e
+                    );
                 }
             }
         }
+        tickets
+    }
 
+    /// A bundle's tickets, to write back: `parse_bundle` skips tickets it
+    /// can't read, and rewriting the bundle without them would lose them
+    pub fn parse_whole_bundle(content: &str, path: &Path) -> Result<Vec<Ticket>> {
+        let tickets = Self::parse_bundle(content, path);
+        if content.matches(BUNDLE_MARKER).count() != tickets.len() {
+            return Err(TkError::Parse(format!(
+                "{} holds a ticket that doesn't parse; fix it before changing the bundle",
+                path.display()
+            ))
+            .into());
+        }
         Ok(tickets)
     }
 
-    /// Save a ticket
-    pub fn save(&self, ticket: &Ticket) -> Result<()> {
-        let path = self.ticket_path(ticket.id());
-        let content = Self::serialize_ticket(ticket)?;
-        fs::write(&path, content)?;
-        Ok(())
+    /// The text of each ticket in a bundle, without its marker line
+    pub fn bundle_sections(content: &str) -> impl Iterator<Item = &str> {
+        let separator = format!("\n{}", BUNDLE_MARKER);
+        let mut sections: Vec<&str> = content.split(separator.as_str()).collect();
+        // Whatever precedes the first marker is the bundle's own header
+        if !content.starts_with(BUNDLE_MARKER) && !sections.is_empty() {
+            sections.remove(0);
+        }
+        sections.into_iter().map(|section| {
+            let section = section.strip_prefix(BUNDLE_MARKER).unwrap_or(section);
+            section.split_once('\n').map_or("", |(_, rest)| rest)
+        })
     }
 
-    /// Find a ticket by ID prefix
-    pub fn find_by_prefix(&self, prefix: &str) -> Result<Option<Ticket>> {
-        let tickets = self.load_all()?;
+    /// The IDs a ticket file or bundle at `path` holds, failing with a
+    /// parse error if any of its tickets don't parse
+    pub fn file_ids(&self, path: &Path, content: &str) -> Result<Vec<String>> {
+        if !path.starts_with(self.bundles_dir()) {
+            return Ok(vec![Self::parse_ticket(content)?.id().to_string()]);
+        }
+        Self::bundle_sections(content)
+            .enumerate()
+            .map(|(i, text)| {
+                Self::parse_ticket(text)
+                    .map(|t| t.id().to_string())
+                    .with_context(|| format!("ticket {} in the bundle", i + 1))
+            })
+            .collect()
+    }
 
-        // Exact match first
-        if let Some(ticket) = tickets.iter().find(|t| t.id() == prefix) {
-            return Ok(Some(ticket.clone()));
+    pub fn serialize_bundle(
+        year: i32,
+        tickets: &[Ticket],
+        format: FrontmatterFormat,
+    ) -> Result<String> {
+        let mut content = format!(
+            "<!-- Archived tickets from {}, bundled by tk archive compact. -->\n",
+            year
+        );
+        for ticket in tickets {
+            content.push_str(&format!("\n{}{} -->\n", BUNDLE_MARKER, ticket.id()));
+            content.push_str(&Self::serialize_ticket(ticket, format)?);
         }
+        Ok(content)
+    }
 
-        // Prefix match
-        let matches: Vec<_> = tickets
-            .iter()
-            .filter(|t| t.id().starts_with(prefix))
-            .collect();
+    /// Roll archived tickets into `year`'s bundle, merging with what's
+    /// already there, and remove their own files. Returns the bundle's path.
+    pub fn bundle(&self, year: i32, tickets: &[Ticket]) -> Result<PathBuf> {
+        let path = self.bundles_dir().join(format!("{}.md", year));
+        let mut bundled = match fs::read_to_string(&path) {
+            Ok(content) => Self::parse_whole_bundle(&content, &path)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(
+                    anyhow::Error::new(e).context(format!("Failed to read {}", path.display()))
+                )
+            }
+        };
+        bundled.retain(|b| !tickets.iter().any(|t| t.id() == b.id()));
+        bundled.extend(tickets.iter().cloned());
+        bundled.sort_by(|a, b| a.id().cmp(b.id()));
 
-        match matches.len() {
-            0 => Ok(None),
-            1 => Ok(Some(matches[0].clone())),
-            _ => anyhow::bail!(
-                "Ambiguous prefix '{}': matches {} tickets. Use full ID.",
-                prefix,
-                matches.len()
-            ),
+        if !self.dry_run {
+            fs::create_dir_all(self.bundles_dir())?;
+        }
+        let content = Self::serialize_bundle(year, &bundled, self.config()?.frontmatter)?;
+        if self.dry_run {
+            eprintln!("Would write {} ({} tickets)", path.display(), bundled.len());
+        } else {
+            fs::write(&path, &content)?;
+            self.forget_view();
+            self.track(&path, Some(&content))?;
+        }
+        for ticket in tickets {
+            let own = self.archived_path(ticket.id());
+            if self.dry_run {
+                eprintln!("Would delete {}", own.display());
+            } else if own.exists() {
+                fs::remove_file(&own)?;
+                self.track(&own, None)?;
+            }
         }
+        Ok(path)
     }
 
-    /// Get all existing ticket IDs
-    pub fn all_ids(&self) -> Result<Vec<String>> {
-        Ok(self
-            .load_all()?
-            .iter()
-            .map(|t| t.id().to_string())
-            .collect())
+    /// Replace (or with `None`, drop) a bundled ticket in its bundle. Returns
+    /// false if no bundle holds it.
+    fn rebundle(&self, id: &str, replacement: Option<&Ticket>) -> Result<bool> {
+        for (path, mut tickets) in self.load_bundles()? {
+            let Some(pos) = tickets.iter().position(|t| t.id() == id) else {
+                continue;
+            };
+            Self::parse_whole_bundle(&fs::read_to_string(&path)?, &path)?;
+            match replacement {
+                Some(t) => tickets[pos] = t.clone(),
+                None => {
+                    tickets.remove(pos);
+                }
+            }
+            if tickets.is_empty() {
+                if self.dry_run {
+                    eprintln!("Would delete {}", path.display());
+                } else {
+                    fs::remove_file(&path)?;
+                    self.forget_view();
+                    self.track(&path, None)?;
+                }
+                return Ok(true);
+            }
+            let format = self.config()?.frontmatter;
+            let content = Self::serialize_bundle(Self::bundle_year(&path), &tickets, format)?;
+            self.write(&path, &content)?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Move every live and archived ticket file to where `layout` puts it,
+    /// checking first that none would land on an existing file. Returns
+    /// each move, from and to.
+    pub fn relayout(&self, layout: StoreLayout) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let mut moves = Vec::new();
+        for dir in [self.tickets_dir.clone(), self.archive_dir()] {
+            for path in Self::ticket_files(&dir)? {
+                let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+                    continue;
+                };
+                let target = Self::placed(&dir, id, layout);
+                if target != path {
+                    moves.push((path, target));
+                }
+            }
+        }
+        if let Some((from, to)) = moves.iter().find(|(_, to)| to.exists()) {
+            return Err(TkError::validation(format!(
+                "Can't move {} to {}: there is already a file there",
+                from.display(),
+                to.display()
+            ))
+            .into());
+        }
+
+        for (from, to) in &moves {
+            if self.dry_run {
+                eprintln!("Would move {} to {}", from.display(), to.display());
+                continue;
+            }
+            tracing::debug!("moving {} to {}", from.display(), to.display());
+            if let Some(dir) = to.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::rename(from, to)?;
+            Self::remove_empty_shard(from);
+            self.forget_view();
+        }
+        if !self.dry_run {
+            self.update_manifest(|manifest| {
+                for (from, to) in &moves {
+                    if let (Some(from), Some(to)) = (self.manifest_key(from), self.manifest_key(to))
+                    {
+                        manifest.rename(&from, &to);
+                    }
+                }
+            })?;
+        }
+        Ok(moves)
+    }
+
+    fn bundle_year(path: &Path) -> i32 {
+        path.file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// Rewrite every ticket file (live, archived, bundled and drafts) whose
+    /// frontmatter isn't in `format` yet, changing nothing else; frozen
+    /// tickets too. Every file is parsed before any is written, so a broken
+    /// one stops the conversion before it starts. Returns the files changed,
+    /// each with the IDs of the tickets in it.
+    pub fn convert(&self, format: FrontmatterFormat) -> Result<Vec<(PathBuf, Vec<String>)>> {
+        let mut dirs = vec![self.tickets_dir.clone(), self.archive_dir()];
+        let drafts = self.tickets_dir.join(DRAFTS_DIR);
+        if drafts.exists() {
+            for entry in fs::read_dir(&drafts)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                }
+            }
+        }
+
+        let mut rewrites = Vec::new();
+        for dir in &dirs {
+            for path in Self::ticket_files(dir)? {
+                let content = fs::read_to_string(&path)?;
+                if FrontmatterFormat::detect(&content) == Some(format) {
+                    continue;
+                }
+                let ticket = Self::parse_ticket(&content)
+                    .with_context(|| format!("Failed to parse {}", path.display()))?;
+                let content = Self::serialize_ticket(&ticket, format)?;
+                rewrites.push((path, content, vec![ticket.id().to_string()]));
+            }
+        }
+        for (path, _) in self.load_bundles()? {
+            let old = fs::read_to_string(&path)?;
+            let tickets = Self::parse_whole_bundle(&old, &path)?;
+            let content = Self::serialize_bundle(Self::bundle_year(&path), &tickets, format)?;
+            if content != old {
+                let ids = tickets.iter().map(|t| t.id().to_string()).collect();
+                rewrites.push((path, content, ids));
+            }
+        }
+
+        // One manifest update for the lot, not one per file
+        for (path, content, _) in &rewrites {
+            self.write_untracked(path, content)?;
+        }
+        if !self.dry_run {
+            self.update_manifest(|manifest| {
+                for (path, content, _) in &rewrites {
+                    if let Some(key) = self.manifest_key(path) {
+                        manifest.record(&key, content);
+                    }
+                }
+            })?;
+        }
+        Ok(rewrites
+            .into_iter()
+            .map(|(path, _, ids)| (path, ids))
+            .collect())
+    }
+
+    /// Whether an archived ticket has its own file, rather than living in a
+    /// bundle
+    pub fn is_loose(&self, ticket: &Ticket) -> bool {
+        !ticket.archived || self.archived_path(ticket.id()).exists()
+    }
+
+    fn load_from_dir(&self, dir: &Path) -> Result<Vec<Ticket>> {
+        let mut tickets = Vec::new();
+        let files = Self::ticket_files(dir)?;
+        for path in self.without_stray_copies(dir, files)? {
+            let content = fs::read_to_string(&path)?;
+            match Self::parse_ticket(&content) {
+                Ok(ticket) => {
+                    tracing::trace!("loaded {}", path.display());
+                    tickets.push(ticket);
+                }
+                Err(e) => {
+                    let message = format!(
+                        "Failed to parse {}: {} (`tk rescue {}` salvages what it can)",
+                        path.display(),
+                        e,
+                        path.display()
+                    );
+                    if self.is_strict() {
+                        return Err(TkError::Parse(message).into());
+                    }
+                    eprintln!("Warning: {}", message);
+                }
+            }
+        }
+
+        tracing::debug!("loaded {} tickets from {}", tickets.len(), dir.display());
+        Ok(tickets)
+    }
+
+    /// `files` less any ticket file that is also in the other layout's
+    /// place, warned about or (in strict mode) refused as `stray_copies`
+    fn without_stray_copies(&self, dir: &Path, files: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+        let stray = Self::stray_copies(dir, &files, self.layout());
+        for (kept, path) in &stray {
+            let message = format!(
+                "{} is also at {}; tk uses the first, so remove the other",
+                kept.display(),
+                path.display()
+            );
+            if self.is_strict() {
+                return Err(TkError::validation(message).into());
+            }
+            eprintln!("Warning: {}", message);
+        }
+        Ok(files
+            .into_iter()
+            .filter(|p| !stray.iter().any(|(_, s)| s == p))
+            .collect())
+    }
+
+    /// Ticket files among `files` that are a second copy of a ticket, flat
+    /// and in its shard (after a move cut short, or a merge of branches
+    /// using different layouts), each with the copy that counts: the one
+    /// where `layout` puts the ticket, which reads and writes go to
+    pub fn stray_copies(
+        dir: &Path,
+        files: &[PathBuf],
+        layout: StoreLayout,
+    ) -> Vec<(PathBuf, PathBuf)> {
+        let mut by_id: HashMap<&str, Vec<&PathBuf>> = HashMap::new();
+        for path in files {
+            if let Some(id) = path.file_stem().and_then(|s| s.to_str()) {
+                by_id.entry(id).or_default().push(path);
+            }
+        }
+        let mut stray = Vec::new();
+        for (id, paths) in by_id.into_iter().filter(|(_, paths)| paths.len() > 1) {
+            let placed = Self::placed(dir, id, layout);
+            let kept = paths.iter().find(|p| ***p == placed).unwrap_or(&paths[0]);
+            for path in paths.iter().filter(|p| *p != kept) {
+                stray.push(((*kept).clone(), (*path).clone()));
+            }
+        }
+        stray.sort();
+        stray
+    }
+
+    /// Save a ticket in place (archived tickets stay archived). Frozen
+    /// tickets are refused unless forced.
+    pub fn save(&self, ticket: &Ticket) -> Result<()> {
+        self.check_unfrozen(ticket)?;
+        self.save_unchecked(ticket)
+    }
+
+    /// Save a ticket even if it is frozen, for `tk freeze` itself
+    pub fn save_unchecked(&self, ticket: &Ticket) -> Result<()> {
+        self.save_replacing(ticket, None)
+    }
+
+    /// Save `ticket`, which is `old` under a new ID, then remove `old`
+    pub fn replace(&self, old: &Ticket, ticket: &Ticket) -> Result<()> {
+        self.check_unfrozen(old)?;
+        self.check_unfrozen(ticket)?;
+        self.save_replacing(ticket, Some(old.id()))?;
+        self.delete(old)
+    }
+
+    /// Save a ticket; `replacing` is the ID it's taking over from, whose
+    /// slug it may keep
+    fn save_replacing(&self, ticket: &Ticket, replacing: Option<&str>) -> Result<()> {
+        let path = if ticket.archived {
+            self.archived_path(ticket.id())
+        } else {
+            self.ticket_path(ticket.id())
+        };
+        let config = self.config()?;
+        let over_cap = config
+            .max_events
+            .is_some_and(|max| ticket.meta.events.len() > max);
+        let title = Self::title_style(ticket, &config);
+        let adjusted;
+        let ticket = if config.lowercase_tags || over_cap || title.is_some() {
+            let mut ticket = ticket.clone();
+            if config.lowercase_tags {
+                ticket.lowercase_tags();
+            }
+            if let Some(max) = config.max_events {
+                ticket.compact_events(max);
+            }
+            if let Some(style) = title {
+                ticket.layout.set_title_style(style);
+            }
+            adjusted = ticket;
+            &adjusted
+        } else {
+            ticket
+        };
+        // A bundled ticket is changed where it is
+        if ticket.archived && !path.exists() && self.rebundle(ticket.id(), Some(ticket))? {
+            return Ok(());
+        }
+        if path.exists() {
+            return self.write(&path, &Self::render(&path, ticket, config.frontmatter)?);
+        }
+        let view = self.view()?;
+        Self::check_slug(&view, ticket, replacing)?;
+        self.write(&path, &Self::render(&path, ticket, config.frontmatter)?)?;
+        // A new ticket leaves every other ticket as it was, so the view
+        // only gains it: saving many in a row doesn't reload the store
+        if replacing.is_none() && !self.dry_run {
+            let mut view = Arc::unwrap_or_clone(view);
+            view.insert(ticket.clone());
+            *self.view.lock().unwrap() = Some(Arc::new(view));
+        }
+        Ok(())
+    }
+
+    /// Where the project's `title` setting says to put `ticket`'s title, if
+    /// its file doesn't settle that: it's new, or was untitled until now
+    fn title_style(ticket: &Ticket, config: &Config) -> Option<TitleStyle> {
+        match ticket.layout.title {
+            None => Some(config.title.style()),
+            Some(TitleStyle::Missing) if ticket.title != "Untitled" => Some(config.title.style()),
+            _ => None,
+        }
+    }
+
+    /// `user`'s drafts
+    pub fn load_drafts(&self, user: &str) -> Result<Vec<Ticket>> {
+        self.load_from_dir(&self.drafts_dir(user))
+    }
+
+    /// `user`'s drafts an ID prefix could mean
+    pub fn find_draft(&self, user: &str, prefix: &str) -> Result<Vec<Ticket>> {
+        Ok(Self::match_prefix(self.load_drafts(user)?, prefix))
+    }
+
+    /// Save a ticket as one of `user`'s drafts
+    pub fn save_draft(&self, user: &str, ticket: &Ticket) -> Result<()> {
+        let dir = self.drafts_dir(user);
+        if !self.dry_run && !dir.exists() {
+            fs::create_dir_all(&dir)?;
+        }
+        self.save_at(&dir.join(format!("{}.md", ticket.id())), ticket)
+    }
+
+    /// Save `ticket` to `path` rather than where its ID says, for drafts
+    /// and `tk rescue`
+    pub fn save_at(&self, path: &Path, ticket: &Ticket) -> Result<()> {
+        let config = self.config()?;
+        let mut ticket = Cow::Borrowed(ticket);
+        if let Some(style) = Self::title_style(&ticket, &config) {
+            ticket.to_mut().layout.set_title_style(style);
+        }
+        self.write(path, &Self::render(path, &ticket, config.frontmatter)?)
+    }
+
+    /// Remove one of `user`'s drafts
+    pub fn delete_draft(&self, user: &str, id: &str) -> Result<()> {
+        let path = self.drafts_dir(user).join(format!("{}.md", id));
+        if self.dry_run {
+            eprintln!("Would delete {}", path.display());
+            return Ok(());
+        }
+        tracing::debug!("deleting {}", path.display());
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    /// Whether `save` and `delete` accept this ticket
+    pub fn can_change(&self, ticket: &Ticket) -> bool {
+        !ticket.meta.locked || self.force
+    }
+
+    /// Fail as `save` would on a frozen ticket, for commands that change
+    /// several tickets and must check them all before writing any
+    pub fn check_unfrozen(&self, ticket: &Ticket) -> Result<()> {
+        if !self.can_change(ticket) {
+            return Err(TkError::validation(format!(
+                "{} is frozen; pass --ignore-frozen to change it anyway",
+                ticket.id()
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Take the store-wide lock, so a read-decide-write sequence (like
+    /// `tk claim`) can't interleave with another tk doing the same. Creating
+    /// `.tickets/.lock` either succeeds or fails atomically on every platform.
+    pub fn lock(&self) -> Result<StoreLock> {
+        if self.dry_run {
+            return Ok(StoreLock { path: None });
+        }
+        self.lock_file(LOCK_FILE)
+    }
+
+    /// Create `.tickets/<name>` as a lock, waiting for (or taking over a
+    /// stale) one another tk holds
+    fn lock_file(&self, name: &str) -> Result<StoreLock> {
+        let path = self.tickets_dir.join(name);
+        let deadline = Instant::now() + LOCK_WAIT;
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(_) => {
+                    tracing::debug!("locked {}", path.display());
+                    return Ok(StoreLock { path: Some(path) });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if Self::is_stale(&path) {
+                        Self::clear_stale_lock(&path);
+                    } else if Instant::now() > deadline {
+                        return Err(TkError::validation(format!(
+                            "{} is held by another tk; delete it if none is running",
+                            path.display()
+                        ))
+                        .into());
+                    } else {
+                        std::thread::sleep(Duration::from_millis(50));
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn is_stale(path: &Path) -> bool {
+        fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.elapsed().ok())
+            .is_some_and(|age| age > LOCK_STALE)
+    }
+
+    /// Get a stale lock out of the way. Removing it outright could remove a
+    /// fresh lock another tk took after clearing the same stale one, so it
+    /// is first renamed to a name of this process's own, which only one tk
+    /// can do, and checked again there. A live lock grabbed by mistake is
+    /// put back unless the lock has been taken again meanwhile.
+    fn clear_stale_lock(path: &Path) {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        let aside = path.with_extension(format!("stale-{}-{}", std::process::id(), nanos));
+        if fs::rename(path, &aside).is_err() {
+            // Another tk moved it first
+            return;
+        }
+        if Self::is_stale(&aside) {
+            tracing::warn!("took over stale lock {}", path.display());
+        } else {
+            let _ = fs::hard_link(&aside, path);
+        }
+        let _ = fs::remove_file(&aside);
+    }
+
+    /// Write a bookkeeping file (not a ticket) inside .tickets/
+    pub fn save_file(&self, name: &str, content: &str) -> Result<()> {
+        self.write(&self.tickets_dir.join(name), content)
+    }
+
+    /// Write a file at the project root, next to .tickets/
+    pub fn save_root_file(&self, name: &str, content: &str) -> Result<()> {
+        self.write(&self.root_dir().join(name), content)
+    }
+
+    /// Write a file inside .tickets/.local/, creating it with a .gitignore
+    /// that keeps it out of commits
+    pub fn save_local_file(&self, name: &str, content: &str) -> Result<()> {
+        let dir = self.local_dir();
+        if !self.dry_run && !dir.exists() {
+            fs::create_dir_all(&dir)?;
+            fs::write(dir.join(".gitignore"), "*\n")?;
+        }
+        self.write(&dir.join(name), content)
+    }
+
+    fn write(&self, path: &Path, content: &str) -> Result<()> {
+        self.write_untracked(path, content)?;
+        if !self.dry_run {
+            self.track(path, Some(content))?;
+        }
+        Ok(())
+    }
+
+    /// `write` without updating the manifest, for callers that update it
+    /// once for many files
+    fn write_untracked(&self, path: &Path, content: &str) -> Result<()> {
+        if self.dry_run {
+            Self::describe_write(path, content);
+            return Ok(());
+        }
+        self.forget_view();
+        tracing::debug!("writing {}", path.display());
+        if let Some(dir) = path.parent().filter(|d| !d.exists()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    fn describe_write(path: &Path, content: &str) {
+        match fs::read_to_string(path) {
+            Ok(old) if old == content => {
+                eprintln!("Would leave {} unchanged", path.display());
+            }
+            Ok(old) => {
+                eprintln!("Would update {}:", path.display());
+                for line in diff::changed_lines(&old, content) {
+                    eprintln!("  {}", line);
+                }
+            }
+            Err(_) => {
+                eprintln!("Would create {}:", path.display());
+                for line in content.lines() {
+                    eprintln!("  + {}", line);
+                }
+            }
+        }
+    }
+
+    /// Move a ticket's file into `.tickets/archive/` (`archived`) or back
+    /// out, saving it on the way. Tickets in a bundle stay there.
+    pub fn set_archived(&self, ticket: &mut Ticket, archived: bool) -> Result<()> {
+        if ticket.archived == archived || !self.is_loose(ticket) {
+            return Ok(());
+        }
+        let from = if ticket.archived {
+            self.archived_path(ticket.id())
+        } else {
+            self.ticket_path(ticket.id())
+        };
+        ticket.archived = archived;
+        self.save(ticket)?;
+        self.remove(&from)
+    }
+
+    /// Remove a ticket file (frozen tickets only when forced)
+    pub fn delete(&self, ticket: &Ticket) -> Result<()> {
+        self.check_unfrozen(ticket)?;
+        let path = if ticket.archived {
+            self.archived_path(ticket.id())
+        } else {
+            self.ticket_path(ticket.id())
+        };
+        if ticket.archived && !path.exists() && self.rebundle(ticket.id(), None)? {
+            return Ok(());
+        }
+        self.remove(&path)
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        if self.dry_run {
+            eprintln!("Would delete {}", path.display());
+            return Ok(());
+        }
+        tracing::debug!("deleting {}", path.display());
+        fs::remove_file(path)?;
+        Self::remove_empty_shard(path);
+        self.forget_view();
+        self.track(path, None)
+    }
+
+    /// Whether config.yaml turns on `.tickets/.manifest`
+    pub fn keeps_manifest(&self) -> bool {
+        *self
+            .keeps_manifest
+            .get_or_init(|| self.config().map(|c| c.manifest).unwrap_or_default())
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        self.tickets_dir.join(MANIFEST_FILE)
+    }
+
+    /// The recorded hashes; empty if there's no manifest yet
+    pub fn manifest(&self) -> Result<Manifest> {
+        match fs::read_to_string(self.manifest_path()) {
+            Ok(text) => Ok(Manifest::parse(&text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Manifest::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replace the manifest with `manifest`. Returns its path.
+    pub fn write_manifest(&self, manifest: &Manifest) -> Result<PathBuf> {
+        let path = self.manifest_path();
+        if self.dry_run {
+            self.write_untracked(&path, &manifest.render())?;
+        } else {
+            let _lock = self.lock_file(MANIFEST_LOCK)?;
+            self.replace_manifest(manifest)?;
+        }
+        Ok(path)
+    }
+
+    /// Every file the manifest covers (live, archived and bundled tickets,
+    /// not drafts), by the path it's recorded under
+    pub fn manifest_files(&self) -> Result<Vec<(String, PathBuf)>> {
+        let mut paths = Self::ticket_files(&self.tickets_dir)?;
+        paths.extend(Self::ticket_files(&self.archive_dir())?);
+        paths.extend(Self::ticket_files(&self.bundles_dir())?);
+        Ok(paths
+            .into_iter()
+            .filter_map(|path| Some((self.manifest_key(&path)?, path)))
+            .collect())
+    }
+
+    /// What the manifest calls `path`: its path relative to .tickets/ with
+    /// `/` separators, or None if the manifest doesn't cover it
+    fn manifest_key(&self, path: &Path) -> Option<String> {
+        if !Self::is_ticket_file(path) {
+            return None;
+        }
+        let parts = path
+            .strip_prefix(&self.tickets_dir)
+            .ok()?
+            .iter()
+            .map(|part| part.to_str())
+            .collect::<Option<Vec<&str>>>()?;
+        match parts.first() {
+            Some(&DRAFTS_DIR) | Some(&LOCAL_DIR) | None => None,
+            Some(_) => Some(parts.join("/")),
+        }
+    }
+
+    /// Record in the manifest, if it's kept, that tk wrote `content` to
+    /// `path`, or with `None` removed it
+    fn track(&self, path: &Path, content: Option<&str>) -> Result<()> {
+        let Some(key) = self.manifest_key(path) else {
+            return Ok(());
+        };
+        self.update_manifest(|manifest| match content {
+            Some(content) => manifest.record(&key, content),
+            None => manifest.forget(&key),
+        })
+    }
+
+    /// Read, change and write back the manifest, holding its lock so two
+    /// tk writing at once can't drop each other's entries
+    fn update_manifest(&self, change: impl FnOnce(&mut Manifest)) -> Result<()> {
+        if !self.keeps_manifest() {
+            return Ok(());
+        }
+        let _lock = self.lock_file(MANIFEST_LOCK)?;
+        let mut manifest = self.manifest()?;
+        change(&mut manifest);
+        self.replace_manifest(&manifest)
+    }
+
+    /// Write `.manifest` beside it and rename it into place, so a crash
+    /// mid-write leaves the old one whole. The caller holds its lock.
+    fn replace_manifest(&self, manifest: &Manifest) -> Result<()> {
+        let path = self.manifest_path();
+        let temp = path.with_extension(format!("tmp-{}", std::process::id()));
+        fs::write(&temp, manifest.render())?;
+        fs::rename(&temp, &path)?;
+        Ok(())
+    }
+
+    /// Remove the shard directory `path` was in if that left it empty
+    fn remove_empty_shard(path: &Path) {
+        if let Some(dir) = path.parent().filter(|d| Self::is_shard_dir(d)) {
+            // Fails, harmlessly, while other tickets are in it
+            let _ = fs::remove_dir(dir);
+        }
+    }
+
+    /// Every ticket an ID prefix (or, for ULID IDs, a suffix) could mean,
+    /// ordered by ID. An exact ID means only that ticket.
+    pub fn find_by_prefix(&self, prefix: &str, scope: Scope) -> Result<Vec<Ticket>> {
+        Ok(Self::match_prefix(self.load_all(scope)?, prefix))
+    }
+
+    fn match_prefix(tickets: Vec<Ticket>, prefix: &str) -> Vec<Ticket> {
+        // Exact match first, by ID or slug
+        if let Some(ticket) = tickets.iter().find(|t| t.id() == prefix) {
+            return vec![ticket.clone()];
+        }
+        if let Some(ticket) = tickets
+            .iter()
+            .find(|t| t.meta.slug.as_deref() == Some(prefix))
+        {
+            return vec![ticket.clone()];
+        }
+
+        // Prefix match
+        let matches: Vec<_> = tickets
+            .iter()
+            .filter(|t| t.id().starts_with(prefix))
+            .collect();
+
+        // ULIDs made around the same time share a long timestamp prefix, so
+        // their random tail is the handier thing to type: `3xyz` for a root,
+        // `3xyz.1` for its first child. Only the root ULID is matched against
+        // the tail, so `12.1` can't pick out every `.12.1` grandchild.
+        let (tail, path) = prefix.split_at(prefix.find('.').unwrap_or(prefix.len()));
+        let matches = if matches.is_empty() && tail.len() >= 4 {
+            tickets
+                .iter()
+                .filter(|t| {
+                    let (root, rest) = t.id().split_at(t.id().find('.').unwrap_or(t.id().len()));
+                    id::is_ulid(root) && root.ends_with(tail) && rest == path
+                })
+                .collect()
+        } else {
+            matches
+        };
+
+        tracing::debug!("prefix {:?} matched {} tickets", prefix, matches.len());
+        let mut matches: Vec<Ticket> = matches.into_iter().cloned().collect();
+        matches.sort_by(|a, b| a.id().cmp(b.id()));
+        matches
+    }
+
+    /// Tickets whose title contains `text`, ignoring case, ordered by ID.
+    /// A title that is exactly `text` means only that ticket.
+    pub fn find_by_title(&self, text: &str, scope: Scope) -> Result<Vec<Ticket>> {
+        Ok(Self::match_title(self.load_all(scope)?, text))
+    }
+
+    /// `user`'s drafts whose title contains `text`, as `find_by_title`
+    pub fn find_draft_by_title(&self, user: &str, text: &str) -> Result<Vec<Ticket>> {
+        Ok(Self::match_title(self.load_drafts(user)?, text))
+    }
+
+    fn match_title(tickets: Vec<Ticket>, text: &str) -> Vec<Ticket> {
+        let text = text.to_lowercase();
+        let mut matches: Vec<Ticket> = tickets
+            .into_iter()
+            .filter(|t| t.title.to_lowercase().contains(&text))
+            .collect();
+        if let Some(i) = matches.iter().position(|t| t.title.to_lowercase() == text) {
+            return vec![matches.swap_remove(i)];
+        }
+        matches.sort_by(|a, b| a.id().cmp(b.id()));
+        matches
+    }
+
+    /// Find the ticket mapped to an issue in an external system
+    pub fn find_by_external(&self, system: &str, id: &str, scope: Scope) -> Result<Option<Ticket>> {
+        Ok(self
+            .load_all(scope)?
+            .into_iter()
+            .find(|t| t.meta.external.iter().any(|e| e.matches(system, id))))
+    }
+
+    /// Every ID and slug in the store, which a new slug must not be
+    pub fn taken_names(&self) -> Result<Vec<String>> {
+        Ok(self
+            .load_all(Scope::All)?
+            .iter()
+            .flat_map(|t| t.names().map(str::to_string))
+            .collect())
+    }
+
+    /// Refuse a new ticket whose slug another ticket already goes by
+    /// (`replacing` aside)
+    fn check_slug(view: &View, ticket: &Ticket, replacing: Option<&str>) -> Result<()> {
+        let Some(slug) = &ticket.meta.slug else {
+            return Ok(());
+        };
+        if let Some(other) = view
+            .named(slug)
+            .into_iter()
+            .find(|t| t.id() != ticket.id() && Some(t.id()) != replacing)
+        {
+            return Err(TkError::validation(format!(
+                "Slug '{}' is already taken by {}",
+                slug,
+                other.id()
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Get existing ticket IDs in scope. ID generation must use `Scope::All`
+    /// so new IDs never collide with archived ones.
+    pub fn all_ids(&self, scope: Scope) -> Result<Vec<String>> {
+        Ok(self
+            .load_all(scope)?
+            .iter()
+            .map(|t| t.id().to_string())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty store in a fresh temporary directory
+    fn scratch(name: &str) -> Storage {
+        let dir = std::env::temp_dir().join(format!("tk-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Storage::in_dir(dir)
+    }
+
+    #[test]
+    fn blocked_by_hand_round_trips() {
+        let storage = scratch("blocked");
+        let mut ticket = Ticket::new("tk-a1b2".to_string(), "A".to_string());
+        ticket.meta.blocked = true;
+        ticket.meta.blocked_reason = Some("waiting on vendor".to_string());
+        storage.save(&ticket).unwrap();
+        let content = fs::read_to_string(storage.ticket_path("tk-a1b2")).unwrap();
+        assert!(content.contains("blocked: true\nblocked_reason: waiting on vendor\n"));
+
+        let loaded = &storage.load_all(Scope::Live).unwrap()[0];
+        assert!(loaded.meta.blocked);
+        assert_eq!(
+            loaded.meta.blocked_reason.as_deref(),
+            Some("waiting on vendor")
+        );
+        let unblocked = Storage::parse_ticket(&content.replace("blocked: true\n", "")).unwrap();
+        assert!(!unblocked.meta.blocked);
+    }
+
+    #[test]
+    fn titles_match_any_case_and_exact_titles_win() {
+        let storage = scratch("titles");
+        for (id, title) in [("tk-a1b2", "Login times out"), ("tk-c3d4", "Login page")] {
+            storage
+                .save(&Ticket::new(id.to_string(), title.to_string()))
+                .unwrap();
+        }
+        let ids = |text| -> Vec<String> {
+            storage
+                .find_by_title(text, Scope::All)
+                .unwrap()
+                .iter()
+                .map(|t| t.id().to_string())
+                .collect()
+        };
+        assert_eq!(ids("LOGIN"), ["tk-a1b2", "tk-c3d4"]);
+        assert_eq!(ids("login page"), ["tk-c3d4"]);
+        assert_eq!(ids("times"), ["tk-a1b2"]);
+        assert!(ids("logout").is_empty());
+
+        let matches = storage.find_by_title("login", Scope::All).unwrap();
+        let err = TkError::ambiguous_title("login", &matches);
+        assert_eq!((err.code(), err.exit_code()), ("ambiguous_title", 4));
+    }
+
+    /// `scratch` with `layout` set in its config
+    fn scratch_in(name: &str, layout: &str) -> Storage {
+        let storage = scratch(name);
+        fs::write(
+            storage.tickets_dir().join(CONFIG_FILE),
+            format!("layout: {}\n", layout),
+        )
+        .unwrap();
+        storage
+    }
+
+    #[test]
+    fn concurrent_writes_keep_every_manifest_entry() {
+        let storage = scratch("manifest");
+        let dir = storage.tickets_dir().to_path_buf();
+        fs::write(dir.join(CONFIG_FILE), "manifest: true\n").unwrap();
+        let writers: Vec<_> = (0..8)
+            .map(|n| {
+                let dir = dir.clone();
+                std::thread::spawn(move || {
+                    let storage = Storage::in_dir(dir);
+                    for i in 0..4 {
+                        let id = format!("tk-{}{}00", n, i);
+                        storage.save(&Ticket::new(id, "T".to_string())).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        let manifest = storage.manifest().unwrap();
+        assert_eq!(manifest.len(), 32);
+        assert!(!dir.join(MANIFEST_LOCK).exists());
+
+        // Rewritten whole, it is renamed into place with nothing left over
+        storage.write_manifest(&manifest).unwrap();
+        assert_eq!(storage.manifest().unwrap(), manifest);
+        let leftovers = fs::read_dir(&dir)
+            .unwrap()
+            .filter(|e| {
+                let name = e.as_ref().unwrap().file_name();
+                name.to_string_lossy().starts_with(".manifest.")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn sharded_stores_file_tickets_by_their_random_part() {
+        let storage = scratch_in("sharded", "sharded");
+        let dir = storage.tickets_dir().to_path_buf();
+        let child = Ticket::new("tk-a1b2.1".to_string(), "Child".to_string());
+        storage.save(&child).unwrap();
+        assert_eq!(
+            storage.ticket_path("tk-a1b2.1"),
+            dir.join("a1/tk-a1b2.1.md")
+        );
+        assert!(dir.join("a1/tk-a1b2.1.md").exists());
+        assert_eq!(
+            storage.ticket_path("tk-01hq3k5v7w9x2y4z6a8b0c1d12"),
+            dir.join("12/tk-01hq3k5v7w9x2y4z6a8b0c1d12.md")
+        );
+
+        // A file still in the flat place is found and saved where it is
+        let flat = Ticket::new("tk-c3d4".to_string(), "Flat".to_string());
+        fs::write(
+            dir.join("tk-c3d4.md"),
+            Storage::serialize_ticket(&flat, FrontmatterFormat::Yaml).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(storage.ticket_path("tk-c3d4"), dir.join("tk-c3d4.md"));
+        storage.save(&flat).unwrap();
+        assert!(!dir.join("c3").exists());
+        assert_eq!(
+            storage.all_ids(Scope::All).unwrap(),
+            ["tk-a1b2.1", "tk-c3d4"]
+        );
+    }
+
+    #[test]
+    fn relayout_moves_every_file_and_clears_empty_shards() {
+        let storage = scratch_in("relayout", "flat");
+        let dir = storage.tickets_dir().to_path_buf();
+        for id in ["tk-a1b2", "tk-a1b2.1", "tk-c3d4"] {
+            storage
+                .save(&Ticket::new(id.to_string(), id.to_string()))
+                .unwrap();
+        }
+        let mut done = Ticket::new("tk-e5f6".to_string(), "Done".to_string());
+        done.archived = true;
+        storage.save(&done).unwrap();
+
+        let moves = storage.relayout(StoreLayout::Sharded).unwrap();
+        assert_eq!(moves.len(), 4);
+        assert!(dir.join("a1/tk-a1b2.md").exists());
+        assert!(dir.join("a1/tk-a1b2.1.md").exists());
+        assert!(dir.join("c3/tk-c3d4.md").exists());
+        assert!(storage.archive_dir().join("e5/tk-e5f6.md").exists());
+        assert!(storage.relayout(StoreLayout::Sharded).unwrap().is_empty());
+
+        storage.relayout(StoreLayout::Flat).unwrap();
+        assert!(dir.join("tk-a1b2.1.md").exists());
+        for shard in ["a1", "c3"] {
+            assert!(!dir.join(shard).exists(), "{} left behind", shard);
+        }
+        assert!(!storage.archive_dir().join("e5").exists());
+        assert_eq!(storage.all_ids(Scope::All).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn a_ticket_both_flat_and_sharded_is_loaded_once() {
+        let storage = scratch_in("stray", "sharded");
+        let dir = storage.tickets_dir().to_path_buf();
+        let ticket = Ticket::new("tk-a1b2".to_string(), "Sharded".to_string());
+        storage.save(&ticket).unwrap();
+        let stale = Ticket::new("tk-a1b2".to_string(), "Flat".to_string());
+        fs::write(
+            dir.join("tk-a1b2.md"),
+            Storage::serialize_ticket(&stale, FrontmatterFormat::Yaml).unwrap(),
+        )
+        .unwrap();
+
+        let files = Storage::ticket_files(&dir).unwrap();
+        assert_eq!(
+            Storage::stray_copies(&dir, &files, StoreLayout::Sharded),
+            [(dir.join("a1/tk-a1b2.md"), dir.join("tk-a1b2.md"))]
+        );
+        let tickets = storage.load_all(Scope::Live).unwrap();
+        assert_eq!(tickets.len(), 1);
+        assert_eq!(tickets[0].title, "Sharded");
+
+        let err = storage.relayout(StoreLayout::Flat).unwrap_err().to_string();
+        assert!(err.contains("already a file there"), "{}", err);
+        let strict = Storage::in_dir(dir).with_strict(true);
+        assert!(strict.load_all(Scope::Live).is_err());
+    }
+
+    #[test]
+    fn body_line_skips_frontmatter_title_and_gap() {
+        let yaml = "---\nid: tk-a1b2\ncreated: 2026-03-02T09:00:00Z\n---\n\n# T\n\n\nBody\n";
+        assert_eq!(Storage::body_line(yaml), Some(9));
+        let titled = "---\nid: tk-a1b2\ntitle: T\ncreated: 2026-03-02T09:00:00Z\n---\nBody\n";
+        assert_eq!(Storage::body_line(titled), Some(6));
+        assert_eq!(Storage::body_line("no frontmatter"), None);
+    }
+
+    #[test]
+    fn strict_mode_refuses_a_store_with_a_broken_ticket() {
+        let storage = scratch("strict");
+        let dir = storage.tickets_dir().to_path_buf();
+        storage
+            .save(&Ticket::new("tk-a1b2".to_string(), "Fine".to_string()))
+            .unwrap();
+        fs::write(
+            dir.join("tk-c3d4.md"),
+            "---\nid: [unclosed\n---\n# Broken\n",
+        )
+        .unwrap();
+
+        assert_eq!(storage.load_all(Scope::Live).unwrap().len(), 1);
+
+        let err = Storage::in_dir(dir.clone())
+            .with_strict(true)
+            .load_all(Scope::Live)
+            .unwrap_err();
+        assert!(err.to_string().contains("tk-c3d4.md"), "{}", err);
+        assert!(matches!(
+            err.downcast_ref::<TkError>(),
+            Some(TkError::Parse(_))
+        ));
+
+        fs::write(dir.join(CONFIG_FILE), "strict: true\n").unwrap();
+        assert!(Storage::in_dir(dir).load_all(Scope::Live).is_err());
+    }
+
+    #[test]
+    fn ulid_tails_match_the_root_only() {
+        let root = "tk-01hq3k5v7w9x2y4z6a8b0c1d12";
+        let tickets: Vec<Ticket> = [
+            root.to_string(),
+            format!("{}.1", root),
+            format!("{}.12.1", root),
+            "tk-01hq3k5v7w9x2y4z6a8b0c1e12".to_string(),
+        ]
+        .into_iter()
+        .map(|id| Ticket::new(id, "T".to_string()))
+        .collect();
+        let ids = |prefix: &str| -> Vec<String> {
+            Storage::match_prefix(tickets.clone(), prefix)
+                .iter()
+                .map(|t| t.id().to_string())
+                .collect()
+        };
+        assert_eq!(ids("1d12"), [root]);
+        assert_eq!(ids("1d12.1"), [format!("{}.1", root)]);
+        assert_eq!(ids("c1d12.12.1"), [format!("{}.12.1", root)]);
+        // Once matched any grandchild .12.1 of a root ending in 2
+        assert!(ids("2.12.1").is_empty());
+        assert!(ids("d12").is_empty(), "too short for a tail");
+        assert_eq!(ids("1e12").len(), 1);
+    }
+
+    #[test]
+    fn stale_locks_are_taken_over_and_live_ones_put_back() {
+        let storage = scratch("stale-lock");
+        let lock = storage.tickets_dir().join(LOCK_FILE);
+        let leftovers = || {
+            fs::read_dir(storage.tickets_dir())
+                .unwrap()
+                .filter_map(|e| e.ok())
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .filter(|n| n.starts_with(".lock."))
+                .count()
+        };
+
+        fs::File::create(&lock)
+            .unwrap()
+            .set_modified(SystemTime::now() - LOCK_STALE * 2)
+            .unwrap();
+        let held = storage.lock().unwrap();
+        assert!(!Storage::is_stale(&lock), "the lock is ours now");
+        assert_eq!(leftovers(), 0);
+        drop(held);
+        assert!(!lock.exists());
+
+        // A tk that saw the old lock as stale, racing one that already
+        // replaced it, must leave the new lock where it is
+        fs::File::create(&lock).unwrap();
+        Storage::clear_stale_lock(&lock);
+        assert!(lock.exists());
+        assert_eq!(leftovers(), 0);
+    }
+
+    #[test]
+    fn reads_skip_a_stale_lock_and_wait_out_a_live_one() {
+        let storage = scratch("snapshot-lock");
+        let lock = storage.tickets_dir().join(LOCK_FILE);
+        let ticket = Ticket::new("tk-a1b2".to_string(), "A".to_string());
+        storage.save(&ticket).unwrap();
+
+        let stale = SystemTime::now() - LOCK_STALE * 2;
+        fs::File::create(&lock)
+            .unwrap()
+            .set_modified(stale)
+            .unwrap();
+        let started = Instant::now();
+        assert_eq!(storage.snapshot(Scope::Live).unwrap().tickets().len(), 1);
+        assert!(started.elapsed() < READ_WAIT);
+        assert!(
+            lock.exists(),
+            "a read must not take over or remove the lock"
+        );
+
+        fs::File::create(&lock).unwrap();
+        let held = lock.clone();
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            fs::remove_file(held).unwrap();
+        });
+        let started = Instant::now();
+        assert_eq!(storage.snapshot(Scope::Live).unwrap().tickets().len(), 1);
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        writer.join().unwrap();
+    }
+
+    const META: &str = "---\nid: tk-a1b2\nstatus: open\ncreated: 2024-01-15T10:30:00Z\ntype: feat\npriority: 2\n---\n";
+
+    /// Parse `after` (what follows the frontmatter) and write it back
+    fn round_trip(after: &str) -> String {
+        let content = format!("{}{}", META, after);
+        let ticket = Storage::parse_ticket(&content).unwrap();
+        let written = Storage::serialize_ticket(&ticket, FrontmatterFormat::Yaml).unwrap();
+        written.strip_prefix(META).unwrap().to_string()
+    }
+
+    #[test]
+    fn usual_layout_round_trips() {
+        for after in ["\n# Title\n", "\n# Title\n\nBody.\n"] {
+            assert_eq!(round_trip(after), after);
+        }
+    }
+
+    #[test]
+    fn blank_lines_and_trailing_whitespace_round_trip() {
+        for after in [
+            "# Title\nBody right under the title\n",
+            "\n\n\n# Title\n\n\n\nBody.\n",
+            "\n# Title\n\nBody.\n\n\n",
+            "\n# Title\n\nNo final newline",
+            "\n# Title\n\nTrailing spaces   \n  \n",
+            "\n# Title\n\n    indented code first\n\nthen text\n",
+        ] {
+            assert_eq!(round_trip(after), after);
+        }
+    }
+
+    #[test]
+    fn untitled_files_get_no_title_line() {
+        for after in [
+            "```sh\nmake test\n```\n",
+            "\n    indented code first\n",
+            "Just text, no heading.\n",
+            "",
+        ] {
+            assert_eq!(round_trip(after), after);
+        }
+        let content = format!("{}\n```sh\nmake test\n```\n", META);
+        let ticket = Storage::parse_ticket(&content).unwrap();
+        assert_eq!(ticket.title, "Untitled");
+        assert_eq!(ticket.body, "```sh\nmake test\n```");
+    }
+
+    #[test]
+    fn setext_titles_round_trip() {
+        for after in [
+            "\nLogin fails\n===========\n\nBody.\n",
+            "Login fails\n==\nBody.\n",
+        ] {
+            assert_eq!(round_trip(after), after);
+        }
+        let content = format!("{}Login fails\n===\n\nBody.\n", META);
+        let ticket = Storage::parse_ticket(&content).unwrap();
+        assert_eq!(ticket.title, "Login fails");
+        assert_eq!(ticket.body, "Body.");
+        // A line of `-` under text is a level-2 heading, so part of the body
+        let content = format!("{}Section\n---\n", META);
+        assert_eq!(Storage::parse_ticket(&content).unwrap().title, "Untitled");
+    }
+
+    #[test]
+    fn frontmatter_titles_stay_in_the_frontmatter() {
+        let content = "---\nid: tk-a1b2\ntitle: Login fails\ncreated: 2024-01-15T10:30:00Z\n---\n\n# Steps\n\n1. Log in\n";
+        let mut ticket = Storage::parse_ticket(content).unwrap();
+        assert_eq!(ticket.title, "Login fails");
+        assert_eq!(ticket.body, "# Steps\n\n1. Log in");
+        assert_eq!(ticket.meta.title, None);
+
+        ticket.title = "Login fails on Safari".to_string();
+        let written = Storage::serialize_ticket(&ticket, FrontmatterFormat::Yaml).unwrap();
+        assert!(written.contains("title: Login fails on Safari\n"));
+        assert!(written.ends_with("---\n\n# Steps\n\n1. Log in\n"));
+    }
+
+    #[test]
+    fn body_excludes_surrounding_whitespace() {
+        let content = format!("{}\n# Title\n\n\n  code\n\ntext\n\n\n", META);
+        let ticket = Storage::parse_ticket(&content).unwrap();
+        assert_eq!(ticket.title, "Title");
+        assert_eq!(ticket.body, "  code\n\ntext");
+    }
+
+    #[test]
+    fn changed_body_keeps_the_gap_above_it() {
+        let content = format!("{}\n# Title\nBody.\n\n\n", META);
+        let mut ticket = Storage::parse_ticket(&content).unwrap();
+        ticket.body.push_str("\nMore.");
+        let written = Storage::serialize_ticket(&ticket, FrontmatterFormat::Yaml).unwrap();
+        assert_eq!(
+            written.strip_prefix(META).unwrap(),
+            "\n# Title\nBody.\nMore.\n"
+        );
+    }
+
+    #[test]
+    fn body_added_to_empty_ticket_gets_usual_layout() {
+        let mut ticket = Storage::parse_ticket(&format!("{}\n# Title\n", META)).unwrap();
+        ticket.body = "Body.".to_string();
+        let written = Storage::serialize_ticket(&ticket, FrontmatterFormat::Yaml).unwrap();
+        assert_eq!(written.strip_prefix(META).unwrap(), "\n# Title\n\nBody.\n");
+
+        let mut untitled = Storage::parse_ticket(&format!("{}Text.\n", META)).unwrap();
+        untitled.title = "Named".to_string();
+        let written = Storage::serialize_ticket(&untitled, FrontmatterFormat::Yaml).unwrap();
+        assert_eq!(written.strip_prefix(META).unwrap(), "\n# Named\nText.\n");
+    }
+
+    #[test]
+    fn frontmatter_whitespace_is_kept() {
+        let mut ticket = Ticket::new("tk-a1b2".to_string(), "Title".to_string());
+        ticket.meta.tags = vec!["two\nlines\n".to_string()];
+        let written = Storage::serialize_ticket(&ticket, FrontmatterFormat::Yaml).unwrap();
+        let read = Storage::parse_ticket(&written).unwrap();
+        assert_eq!(read.meta.tags, ticket.meta.tags);
+    }
+
+    #[test]
+    fn fences_are_whole_lines() {
+        let err = Storage::parse_ticket("--- id: tk-a1b2\n---\n").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("opening --- must be on a line of its own"));
+        // A longer line of dashes is a thematic break, not the closing fence
+        let content = format!("{}\n----\n", META.trim_end_matches("---\n"));
+        let err = Storage::parse_ticket(&content).unwrap_err();
+        assert!(err.to_string().contains("unclosed frontmatter"));
+    }
+
+    #[test]
+    fn parse_errors_give_file_line_numbers() {
+        let err = Storage::parse_ticket("\n---\nid: tk-a1b2\n  status: open\n---\n").unwrap_err();
+        assert!(err.to_string().contains("line 4"), "{}", err);
+    }
+
+    #[test]
+    fn byte_order_mark_is_skipped() {
+        let ticket = Storage::parse_ticket(&format!("\u{feff}{}\n# Title\n", META)).unwrap();
+        assert_eq!(ticket.title, "Title");
+    }
+
+    mod props {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// A title as `tk create` takes it: one line, no surrounding spaces
+        fn title() -> impl Strategy<Value = String> {
+            "[^\r\n]{1,40}".prop_filter("untrimmed", |t| t.trim() == t)
+        }
+
+        /// A body as tk keeps it: no blank lines above, no whitespace after
+        fn body() -> impl Strategy<Value = String> {
+            prop::collection::vec("[^\r\n]{0,30}", 0..6)
+                .prop_map(|lines| lines.join("\n"))
+                .prop_map(|b| Storage::blank_lines(&b).1.trim_end().to_string())
+        }
+
+        fn text() -> impl Strategy<Value = String> {
+            prop::option::of("[^\r]{0,30}").prop_map(Option::unwrap_or_default)
+        }
+
+        fn format() -> impl Strategy<Value = FrontmatterFormat> {
+            prop_oneof![Just(FrontmatterFormat::Yaml), Just(FrontmatterFormat::Toml)]
+        }
+
+        fn ticket() -> impl Strategy<Value = Ticket> {
+            (
+                title(),
+                body(),
+                text(),
+                text(),
+                prop::collection::vec("[^\r]{1,12}", 0..3),
+                any::<bool>(),
+            )
+                .prop_map(|(title, body, assignee, milestone, tags, in_meta)| {
+                    let mut t = Ticket::new("tk-a1b2".to_string(), title);
+                    t.body = body;
+                    t.meta.assignee = Some(assignee).filter(|a| !a.is_empty());
+                    t.meta.milestone = Some(milestone).filter(|m| !m.is_empty());
+                    t.meta.tags = tags;
+                    if in_meta {
+                        t.layout.set_title_style(TitleStyle::Frontmatter);
+                    }
+                    t
+                })
+        }
+
+        proptest! {
+            #[test]
+            fn tickets_round_trip(t in ticket(), format in format()) {
+                let written = Storage::serialize_ticket(&t, format).unwrap();
+                let read = Storage::parse_ticket(&written).unwrap();
+                prop_assert_eq!(&read.title, &t.title);
+                prop_assert_eq!(&read.body, &t.body);
+                prop_assert_eq!(&read.meta.assignee, &t.meta.assignee);
+                prop_assert_eq!(&read.meta.milestone, &t.meta.milestone);
+                prop_assert_eq!(&read.meta.tags, &t.meta.tags);
+                // And writing what was read changes nothing
+                let rewritten = Storage::serialize_ticket(&read, format).unwrap();
+                prop_assert_eq!(rewritten, written);
+            }
+
+            #[test]
+            fn files_read_back_byte_for_byte(after in "[ -~\n\t=#]{0,80}") {
+                prop_assert_eq!(round_trip(&after), after);
+            }
+
+            #[test]
+            fn parsing_anything_never_panics(content in "\\PC{0,200}") {
+                let _ = Storage::parse_ticket(&content);
+                let _ = Storage::extract_title(&content);
+            }
+
+            #[test]
+            fn parsing_near_tickets_never_panics(
+                fence in "(---|\\+\\+\\+)",
+                meta in "[ -~\n]{0,60}",
+                after in "[ -~\n]{0,60}",
+            ) {
+                let content = format!("{}\n{}\n{}\n{}", fence, meta, fence, after);
+                let _ = Storage::parse_ticket(&content);
+            }
+
+            #[test]
+            fn extract_title_reads_headings(t in title(), b in body(), gap in "\n{0,3}") {
+                let input = format!("{}# {}\n{}{}", gap, t, gap, b);
+                let (title, body) = Storage::extract_title(&input);
+                prop_assert_eq!(title, t.trim_start_matches("# ").trim());
+                prop_assert_eq!(body, b.as_str());
+            }
+        }
+    }
+
+    #[test]
+    fn bundles_with_a_broken_ticket_are_not_rewritten() {
+        let storage = scratch("broken-bundle");
+        let good = |id: &str| {
+            let mut t = Ticket::new(id.to_string(), format!("Ticket {}", id));
+            t.meta.status = crate::types::Status::Closed;
+            t.archived = true;
+            t
+        };
+        storage
+            .bundle(2020, &[good("tk-aaaa"), good("tk-bbbb")])
+            .unwrap();
+        let path = storage.bundles_dir().join("2020.md");
+        let broken = fs::read_to_string(&path)
+            .unwrap()
+            .replacen("id: tk-bbbb", "id: [tk-bbbb", 1);
+        fs::write(&path, &broken).unwrap();
+
+        assert!(storage.bundle(2020, &[good("tk-cccc")]).is_err());
+        assert!(storage.delete(&good("tk-aaaa")).is_err());
+        assert!(storage.convert(FrontmatterFormat::Toml).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), broken);
+    }
+
+    #[test]
+    fn replacing_a_ticket_keeps_its_slug() {
+        let storage = scratch("replace");
+        let mut old = Ticket::new("tk-aaaa".to_string(), "Login".to_string());
+        old.meta.slug = Some("login".to_string());
+        storage.save(&old).unwrap();
+
+        let mut moved = old.clone();
+        moved.meta.id = "tk-bbbb.1".to_string();
+        let mut other = moved.clone();
+        other.meta.id = "tk-cccc".to_string();
+        assert!(storage.save(&other).is_err(), "slug taken by tk-aaaa");
+
+        storage.replace(&old, &moved).unwrap();
+        let ids: Vec<String> = storage.all_ids(Scope::All).unwrap();
+        assert_eq!(ids, ["tk-bbbb.1"]);
+    }
+
+    #[test]
+    fn new_tickets_can_not_take_a_name_already_in_use() {
+        let storage = scratch("slugs");
+        let named = |id: &str, slug: &str| {
+            let mut t = Ticket::new(id.to_string(), id.to_string());
+            t.meta.slug = Some(slug.to_string());
+            t
+        };
+        // Each save adds to the cached view rather than reloading the
+        // store, so a slug saved a moment ago must already count
+        storage.save(&named("tk-aaaa", "login")).unwrap();
+        storage.save(&named("tk-bbbb", "signup")).unwrap();
+        let err = storage.save(&named("tk-cccc", "signup")).unwrap_err();
+        assert!(
+            err.to_string().contains("already taken by tk-bbbb"),
+            "{}",
+            err
+        );
+        assert!(storage.save(&named("tk-cccc", "tk-aaaa")).is_err());
+
+        // Resaving a ticket under its own slug is fine
+        storage.save(&named("tk-aaaa", "login")).unwrap();
+        let view = storage.view().unwrap();
+        assert_eq!(view.tickets().len(), 2);
+        assert_eq!(view.named("signup")[0].id(), "tk-bbbb");
     }
 }
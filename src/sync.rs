@@ -0,0 +1,649 @@
+//! Pull issues from GitHub/GitLab.
+//!
+//! Each remote keeps an `updated_at` cursor and the ETag of its last response
+//! in `.tickets/.sync-state.json`, so a sync only fetches issues changed since
+//! the previous run and an unchanged repo costs a single conditional request
+//! (which GitHub doesn't count against the rate limit). When the rate limit
+//! runs out mid-sync, the cursor is saved at the last issue received and the
+//! next run picks up from there.
+//!
+//! An issue that changed upstream while its ticket was also edited here is a
+//! conflict, settled by the `sync.conflicts` policy in config.yaml.
+
+use crate::auth::Provider;
+use crate::error::TkError;
+use crate::storage::Storage;
+use crate::types::{EventAction, ExternalRef, Note, Status, Ticket};
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const STATE_FILE: &str = ".sync-state.json";
+
+/// Tag `manual` conflicts leave on a ticket until someone settles them
+pub const CONFLICT_TAG: &str = "conflict";
+
+const PER_PAGE: u32 = 100;
+
+/// Where issues come from, e.g. github.com `owner/repo`
+#[derive(Debug, Clone)]
+pub struct Remote {
+    pub provider: Provider,
+    pub host: String,
+    pub repo: String,
+}
+
+impl Remote {
+    /// Work out the remote from `--repo` or the `origin` git remote
+    pub fn resolve(provider: Provider, repo: Option<&str>, root: &Path) -> Result<Self> {
+        let default_host = match provider {
+            Provider::Github => "github.com",
+            Provider::Gitlab => "gitlab.com",
+            Provider::Linear => {
+                return Err(TkError::validation("tk sync supports github and gitlab").into())
+            }
+        };
+
+        if let Some(repo) = repo {
+            return Ok(Remote {
+                provider,
+                host: default_host.to_string(),
+                repo: repo.trim_matches('/').to_string(),
+            });
+        }
+
+        let output = Command::new("git")
+            .current_dir(root)
+            .args(["remote", "get-url", "origin"])
+            .output()
+            .context("Failed to run git")?;
+        let url = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        parse_remote_url(&url)
+            .map(|(host, repo)| Remote {
+                provider,
+                host,
+                repo,
+            })
+            .ok_or_else(|| {
+                TkError::validation(format!(
+                    "Can't tell the {} repo from the origin remote; pass --repo owner/name",
+                    provider
+                ))
+                .into()
+            })
+    }
+
+    /// Key in the state file, e.g. `github:owner/repo`
+    pub fn key(&self) -> String {
+        format!("{}:{}", self.provider, self.repo)
+    }
+}
+
+/// Accepts `git@host:owner/repo.git`, `https://host/owner/repo(.git)` and
+/// `ssh://git@host/owner/repo.git`
+fn parse_remote_url(url: &str) -> Option<(String, String)> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .or_else(|| url.strip_prefix("ssh://"))
+        .unwrap_or(url);
+    let rest = rest.split_once('@').map_or(rest, |(_, r)| r);
+    let (host, path) = rest.split_once([':', '/'])?;
+    let repo = path.trim_end_matches('/').trim_end_matches(".git");
+    (!host.is_empty() && repo.contains('/')).then(|| (host.to_string(), repo.to_string()))
+}
+
+/// Persisted per-remote progress
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RemoteState {
+    /// Newest `updated_at` seen; the next sync asks for issues since then
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<DateTime<Utc>>,
+    /// ETag of the first page fetched with `cursor`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    #[serde(default)]
+    pub remotes: BTreeMap<String, RemoteState>,
+}
+
+impl SyncState {
+    pub fn path(tickets_dir: &Path) -> PathBuf {
+        tickets_dir.join(STATE_FILE)
+    }
+
+    pub fn load(tickets_dir: &Path) -> Result<Self> {
+        let path = Self::path(tickets_dir);
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| TkError::Parse(format!("{}: {}", path.display(), e)).into()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+}
+
+/// An issue as the importer sees it, whatever the provider
+#[derive(Debug, Clone)]
+pub struct RemoteIssue {
+    pub number: u64,
+    pub title: String,
+    pub body: String,
+    pub closed: bool,
+    pub labels: Vec<String>,
+    pub url: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What a fetch produced
+#[derive(Debug, Default)]
+pub struct Fetched {
+    /// Oldest update first
+    pub issues: Vec<RemoteIssue>,
+    /// Progress to persist for the next run
+    pub state: RemoteState,
+    /// The server answered 304: nothing changed
+    pub not_modified: bool,
+    /// Stopped early; resume after this time
+    pub rate_limited_until: Option<DateTime<Utc>>,
+}
+
+/// Fetch issues updated since `state.cursor`
+pub fn fetch(remote: &Remote, token: Option<&str>, state: &RemoteState) -> Result<Fetched> {
+    match remote.provider {
+        Provider::Github => fetch_github(remote, token, state),
+        Provider::Gitlab => fetch_gitlab(remote, token, state),
+        Provider::Linear => unreachable!("rejected by Remote::resolve"),
+    }
+}
+
+#[derive(Deserialize)]
+struct GithubIssue {
+    number: u64,
+    title: String,
+    body: Option<String>,
+    state: String,
+    labels: Vec<GithubLabel>,
+    html_url: String,
+    updated_at: DateTime<Utc>,
+    /// Present when the "issue" is really a pull request
+    pull_request: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct GithubLabel {
+    name: String,
+}
+
+fn fetch_github(remote: &Remote, token: Option<&str>, state: &RemoteState) -> Result<Fetched> {
+    let api = if remote.host == "github.com" {
+        "https://api.github.com".to_string()
+    } else {
+        format!("https://{}/api/v3", remote.host)
+    };
+    let mut url = format!(
+        "{}/repos/{}/issues?state=all&sort=updated&direction=asc&per_page={}",
+        api, remote.repo, PER_PAGE
+    );
+    if let Some(cursor) = state.cursor {
+        url.push_str(&format!(
+            "&since={}",
+            cursor.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ));
+    }
+
+    let mut fetched = Fetched {
+        state: state.clone(),
+        ..Default::default()
+    };
+    let mut first = true;
+    let mut next = Some(url);
+
+    while let Some(url) = next.take() {
+        let mut req = ureq::get(&url)
+            .set("Accept", "application/vnd.github+json")
+            .set("User-Agent", concat!("tk/", env!("CARGO_PKG_VERSION")));
+        if let Some(token) = token {
+            req = req.set("Authorization", &format!("Bearer {}", token));
+        }
+        if first {
+            if let Some(etag) = &state.etag {
+                req = req.set("If-None-Match", etag);
+            }
+        }
+
+        tracing::debug!("GET {}", url);
+        let resp = match call(req, "x-ratelimit-reset")? {
+            Response::Ok(resp) => resp,
+            Response::RateLimited(until) => {
+                fetched.rate_limited_until = Some(until);
+                break;
+            }
+        };
+
+        if resp.status() == 304 {
+            fetched.not_modified = true;
+            break;
+        }
+        if first {
+            fetched.state.etag = resp.header("etag").map(str::to_string);
+            first = false;
+        }
+
+        next = resp.header("link").and_then(next_link);
+        let remaining = header_u64(&resp, "x-ratelimit-remaining");
+        let reset = header_u64(&resp, "x-ratelimit-reset");
+
+        let page: Vec<GithubIssue> = resp.into_json()?;
+        for issue in page.into_iter().filter(|i| i.pull_request.is_none()) {
+            fetched.issues.push(RemoteIssue {
+                number: issue.number,
+                title: issue.title,
+                body: issue.body.unwrap_or_default(),
+                closed: issue.state == "closed",
+                labels: issue.labels.into_iter().map(|l| l.name).collect(),
+                url: issue.html_url,
+                updated_at: issue.updated_at,
+            });
+        }
+
+        if next.is_some() && remaining == Some(0) {
+            fetched.rate_limited_until = reset.and_then(epoch);
+            break;
+        }
+    }
+
+    advance_cursor(&mut fetched);
+    Ok(fetched)
+}
+
+#[derive(Deserialize)]
+struct GitlabIssue {
+    iid: u64,
+    title: String,
+    description: Option<String>,
+    state: String,
+    labels: Vec<String>,
+    web_url: String,
+    updated_at: DateTime<Utc>,
+}
+
+fn fetch_gitlab(remote: &Remote, token: Option<&str>, state: &RemoteState) -> Result<Fetched> {
+    let project = remote.repo.replace('/', "%2F");
+    let base = format!(
+        "https://{}/api/v4/projects/{}/issues?scope=all&order_by=updated_at&sort=asc&per_page={}",
+        remote.host, project, PER_PAGE
+    );
+    let base = match state.cursor {
+        Some(cursor) => format!(
+            "{}&updated_after={}",
+            base,
+            cursor.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ),
+        None => base,
+    };
+
+    let mut fetched = Fetched {
+        state: state.clone(),
+        ..Default::default()
+    };
+    let mut page = Some("1".to_string());
+
+    while let Some(n) = page.take() {
+        let url = format!("{}&page={}", base, n);
+        let mut req = ureq::get(&url).set("User-Agent", concat!("tk/", env!("CARGO_PKG_VERSION")));
+        if let Some(token) = token {
+            req = req.set("PRIVATE-TOKEN", token);
+        }
+        if n == "1" {
+            if let Some(etag) = &state.etag {
+                req = req.set("If-None-Match", etag);
+            }
+        }
+
+        tracing::debug!("GET {}", url);
+        let resp = match call(req, "ratelimit-reset")? {
+            Response::Ok(resp) => resp,
+            Response::RateLimited(until) => {
+                fetched.rate_limited_until = Some(until);
+                break;
+            }
+        };
+
+        if resp.status() == 304 {
+            fetched.not_modified = true;
+            break;
+        }
+        if n == "1" {
+            fetched.state.etag = resp.header("etag").map(str::to_string);
+        }
+
+        page = resp
+            .header("x-next-page")
+            .filter(|p| !p.is_empty())
+            .map(str::to_string);
+        let remaining = header_u64(&resp, "ratelimit-remaining");
+        let reset = header_u64(&resp, "ratelimit-reset");
+
+        let issues: Vec<GitlabIssue> = resp.into_json()?;
+        for issue in issues {
+            fetched.issues.push(RemoteIssue {
+                number: issue.iid,
+                title: issue.title,
+                body: issue.description.unwrap_or_default(),
+                closed: issue.state == "closed",
+                labels: issue.labels,
+                url: issue.web_url,
+                updated_at: issue.updated_at,
+            });
+        }
+
+        if page.is_some() && remaining == Some(0) {
+            fetched.rate_limited_until = reset.and_then(epoch);
+            break;
+        }
+    }
+
+    advance_cursor(&mut fetched);
+    Ok(fetched)
+}
+
+/// Move the cursor to the newest issue received. A new cursor means a new
+/// URL, so an ETag saved for the old one is useless; keep it only when the
+/// cursor stays put.
+fn advance_cursor(fetched: &mut Fetched) {
+    let newest = fetched.issues.iter().map(|i| i.updated_at).max();
+    if let Some(newest) = newest {
+        if fetched.state.cursor != Some(newest) {
+            fetched.state.cursor = Some(newest);
+            fetched.state.etag = None;
+        }
+    }
+    if fetched.rate_limited_until.is_some() {
+        fetched.state.etag = None;
+    }
+}
+
+enum Response {
+    Ok(Box<ureq::Response>),
+    RateLimited(DateTime<Utc>),
+}
+
+/// Make a request, turning rate-limit refusals into `RateLimited`
+fn call(req: ureq::Request, reset_header: &str) -> Result<Response> {
+    match req.call() {
+        Ok(resp) => Ok(Response::Ok(Box::new(resp))),
+        Err(ureq::Error::Status(403 | 429, resp))
+            if resp.header("retry-after").is_some()
+                || header_u64(&resp, "x-ratelimit-remaining") == Some(0)
+                || header_u64(&resp, "ratelimit-remaining") == Some(0) =>
+        {
+            let until = match header_u64(&resp, "retry-after") {
+                Some(secs) => Utc::now() + chrono::Duration::seconds(secs as i64),
+                None => header_u64(&resp, reset_header)
+                    .and_then(epoch)
+                    .unwrap_or_else(Utc::now),
+            };
+            Ok(Response::RateLimited(until))
+        }
+        Err(ureq::Error::Status(404, _)) => {
+            Err(TkError::NotFound("Repository not found (or token lacks access)".into()).into())
+        }
+        Err(ureq::Error::Status(code, resp)) => {
+            let body = resp.into_string().unwrap_or_default();
+            anyhow::bail!("HTTP {}: {}", code, body.trim())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn header_u64(resp: &ureq::Response, name: &str) -> Option<u64> {
+    resp.header(name)?.trim().parse().ok()
+}
+
+fn epoch(secs: u64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(secs as i64, 0).single()
+}
+
+/// Pull the `rel="next"` URL out of a GitHub `Link` header
+fn next_link(header: &str) -> Option<String> {
+    header.split(',').find_map(|part| {
+        let (url, rel) = part.split_once(';')?;
+        rel.contains("rel=\"next\"").then(|| {
+            url.trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        })
+    })
+}
+
+/// What to do when an issue changed upstream and its ticket changed here
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// Keep the ticket as it is here
+    LocalWins,
+    /// Take the upstream title and state
+    #[default]
+    RemoteWins,
+    /// Take whichever side changed last
+    NewestWins,
+    /// Change nothing; add a note listing the upstream changes and tag the
+    /// ticket `conflict`
+    Manual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    TakeRemote,
+    KeepLocal,
+    Manual,
+}
+
+impl ConflictPolicy {
+    pub fn resolve(self, ticket: &Ticket, issue: &RemoteIssue) -> Resolution {
+        match self {
+            ConflictPolicy::LocalWins => Resolution::KeepLocal,
+            ConflictPolicy::RemoteWins => Resolution::TakeRemote,
+            ConflictPolicy::NewestWins if ticket.meta.updated > Some(issue.updated_at) => {
+                Resolution::KeepLocal
+            }
+            ConflictPolicy::NewestWins => Resolution::TakeRemote,
+            ConflictPolicy::Manual => Resolution::Manual,
+        }
+    }
+}
+
+/// Start tracking an issue locally
+pub fn new_ticket(id: String, provider: Provider, issue: &RemoteIssue) -> Ticket {
+    let mut ticket = Ticket::new(id, issue.title.clone());
+    ticket.body = issue.body.trim().replace("\r\n", "\n");
+    ticket.meta.tags = issue.labels.clone();
+    ticket.meta.external = vec![ExternalRef {
+        system: provider.name().to_string(),
+        id: issue.number.to_string(),
+        url: Some(issue.url.clone()),
+        synced: Some(ticket.meta.created),
+    }];
+    ticket.record(EventAction::Created);
+    ticket
+}
+
+/// Whether the ticket was changed here since it was last synced with the
+/// issue. Unknown (never stamped by a sync) counts as unchanged.
+pub fn changed_locally(ticket: &Ticket, provider: Provider, issue: &RemoteIssue) -> bool {
+    let synced = external_ref(ticket, provider, issue).and_then(|e| e.synced);
+    matches!((ticket.meta.updated, synced), (Some(u), Some(s)) if u > s)
+}
+
+/// Whether the issue changed since the ticket was last synced with it.
+/// Unknown counts as changed.
+pub fn changed_upstream(ticket: &Ticket, provider: Provider, issue: &RemoteIssue) -> bool {
+    let synced = external_ref(ticket, provider, issue).and_then(|e| e.synced);
+    synced.is_none_or(|s| issue.updated_at > s)
+}
+
+/// Record the issue as seen without taking anything from it, so a conflict
+/// that was settled in the ticket's favour (or flagged for a person) doesn't
+/// come up again until one side changes.
+pub fn mark_seen(ticket: &mut Ticket, provider: Provider, issue: &RemoteIssue) {
+    let number = issue.number.to_string();
+    let seen = ticket.meta.updated.max(Some(issue.updated_at));
+    if let Some(e) = ticket
+        .meta
+        .external
+        .iter_mut()
+        .find(|e| e.matches(provider.name(), &number))
+    {
+        e.synced = e.synced.max(seen);
+    }
+}
+
+/// The `manual` policy: note the upstream changes on the ticket and tag it
+/// [`CONFLICT_TAG`]. A note already there for the same changes isn't added
+/// twice.
+pub fn flag_conflict(ticket: &mut Ticket, provider: Provider, issue: &RemoteIssue, changes: &str) {
+    let text = format!(
+        "Sync conflict with {}#{}: it changed upstream ({}) after this ticket changed here. \
+         Settle it by hand, then remove the {} tag.",
+        provider.name(),
+        issue.number,
+        changes,
+        CONFLICT_TAG
+    );
+    if !Storage::parse_notes(&ticket.body)
+        .iter()
+        .any(|n| n.content.trim() == text)
+    {
+        ticket.add_note(&Note::new(text));
+    }
+    if !ticket.meta.tags.iter().any(|t| t == CONFLICT_TAG) {
+        ticket.meta.tags.push(CONFLICT_TAG.to_string());
+    }
+    mark_seen(ticket, provider, issue);
+}
+
+fn external_ref<'a>(
+    ticket: &'a Ticket,
+    provider: Provider,
+    issue: &RemoteIssue,
+) -> Option<&'a ExternalRef> {
+    let number = issue.number.to_string();
+    ticket
+        .meta
+        .external
+        .iter()
+        .find(|e| e.matches(provider.name(), &number))
+}
+
+/// Bring a tracked ticket's title and open/closed state in line with the
+/// issue. Local body, deps and tags are left alone. Returns what changed,
+/// in words.
+pub fn update_ticket(ticket: &mut Ticket, provider: Provider, issue: &RemoteIssue) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    if ticket.title != issue.title {
+        changes.push(format!("title \"{}\" -> \"{}\"", ticket.title, issue.title));
+        ticket.title = issue.title.clone();
+    }
+
+    if issue.closed && ticket.is_open() {
+        ticket.set_status(Status::Closed);
+        ticket.meta.closed = Some(issue.updated_at);
+        changes.push("closed upstream".to_string());
+    } else if !issue.closed && !ticket.is_open() {
+        ticket.set_status(Status::Open);
+        changes.push("reopened upstream".to_string());
+    }
+
+    if !changes.is_empty() {
+        ticket.touch();
+        let number = issue.number.to_string();
+        let synced = ticket.meta.updated;
+        if let Some(e) = ticket
+            .meta
+            .external
+            .iter_mut()
+            .find(|e| e.matches(provider.name(), &number))
+        {
+            e.synced = synced;
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn issue(updated_at: DateTime<Utc>) -> RemoteIssue {
+        RemoteIssue {
+            number: 7,
+            title: "Upstream title".to_string(),
+            body: String::new(),
+            closed: false,
+            labels: vec![],
+            url: "https://github.com/o/r/issues/7".to_string(),
+            updated_at,
+        }
+    }
+
+    /// A ticket tracking issue 7, synced an hour ago and edited here since
+    fn edited_here() -> Ticket {
+        let hour_ago = Utc::now() - Duration::hours(1);
+        let mut ticket = new_ticket("t-1".to_string(), Provider::Github, &issue(hour_ago));
+        ticket.meta.external[0].synced = Some(hour_ago);
+        ticket.title = "Local title".to_string();
+        ticket.touch();
+        ticket
+    }
+
+    #[test]
+    fn kept_conflicts_are_settled_until_a_side_changes() {
+        let upstream = issue(Utc::now() - Duration::minutes(30));
+        let mut ticket = edited_here();
+        assert!(changed_locally(&ticket, Provider::Github, &upstream));
+        assert!(changed_upstream(&ticket, Provider::Github, &upstream));
+
+        mark_seen(&mut ticket, Provider::Github, &upstream);
+        assert!(!changed_locally(&ticket, Provider::Github, &upstream));
+        assert!(!changed_upstream(&ticket, Provider::Github, &upstream));
+
+        let later = issue(Utc::now() + Duration::minutes(1));
+        assert!(changed_upstream(&ticket, Provider::Github, &later));
+    }
+
+    #[test]
+    fn manual_conflicts_are_noted_once() {
+        let upstream = issue(Utc::now() - Duration::minutes(30));
+        let mut ticket = edited_here();
+        flag_conflict(&mut ticket, Provider::Github, &upstream, "title");
+        flag_conflict(&mut ticket, Provider::Github, &upstream, "title");
+        assert_eq!(Storage::parse_notes(&ticket.body).len(), 1);
+        assert_eq!(ticket.meta.tags, vec![CONFLICT_TAG.to_string()]);
+        assert!(!changed_upstream(&ticket, Provider::Github, &upstream));
+        assert_eq!(ticket.title, "Local title");
+    }
+
+    #[test]
+    fn unstamped_tickets_count_as_changed_upstream() {
+        let upstream = issue(Utc::now());
+        let mut ticket = edited_here();
+        ticket.meta.external[0].synced = None;
+        assert!(changed_upstream(&ticket, Provider::Github, &upstream));
+        assert!(!changed_locally(&ticket, Provider::Github, &upstream));
+    }
+}
@@ -0,0 +1,54 @@
+//! Tag bookkeeping for `tk tags`: usage counts and near-duplicate spellings.
+
+use crate::types::Ticket;
+use std::collections::{BTreeMap, HashMap};
+
+/// How many tickets carry each tag
+pub fn counts(tickets: &[Ticket]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for t in tickets {
+        for tag in &t.meta.tags {
+            *counts.entry(tag.clone()).or_default() += 1;
+        }
+    }
+    counts
+}
+
+/// Groups of tags that differ only in case, punctuation or a plural `s`,
+/// as `(most used spelling, the others)`. Ties go to a lowercase spelling,
+/// then the alphabetically first.
+pub fn suggest_merges(counts: &BTreeMap<String, usize>) -> Vec<(String, Vec<String>)> {
+    let mut groups: HashMap<String, Vec<&String>> = HashMap::new();
+    for tag in counts.keys() {
+        groups.entry(merge_key(tag)).or_default().push(tag);
+    }
+
+    let mut merges: Vec<(String, Vec<String>)> = groups
+        .into_values()
+        .filter(|g| g.len() > 1)
+        .map(|mut g| {
+            // Keys arrive sorted, so a stable sort keeps remaining ties alphabetical
+            g.sort_by_key(|t| {
+                let lowercase = !t.chars().any(char::is_uppercase);
+                (std::cmp::Reverse(counts[*t]), !lowercase)
+            });
+            let into = g[0].clone();
+            (into, g[1..].iter().map(|t| t.to_string()).collect())
+        })
+        .collect();
+    merges.sort();
+    merges
+}
+
+/// `Back-End`, `back_end` and `backends` all become `backend`
+fn merge_key(tag: &str) -> String {
+    let key: String = tag
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    match key.strip_suffix('s') {
+        Some(singular) if singular.len() >= 3 => singular.to_string(),
+        _ => key,
+    }
+}
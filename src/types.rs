@@ -1,4 +1,7 @@
-use chrono::{DateTime, Utc};
+use crate::error::TkError;
+use crate::identity;
+use chrono::{DateTime, NaiveDate, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 /// Ticket status
@@ -7,6 +10,8 @@ use serde::{Deserialize, Serialize};
 pub enum Status {
     #[default]
     Open,
+    #[serde(rename = "in-progress")]
+    InProgress,
     Closed,
 }
 
@@ -14,6 +19,7 @@ impl std::fmt::Display for Status {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Status::Open => write!(f, "open"),
+            Status::InProgress => write!(f, "in-progress"),
             Status::Closed => write!(f, "closed"),
         }
     }
@@ -24,15 +30,20 @@ impl std::str::FromStr for Status {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
-            "open" | "in-progress" | "in_progress" | "inprogress" | "started" => Ok(Status::Open),
+            "open" => Ok(Status::Open),
+            "in-progress" | "in_progress" | "inprogress" | "started" => Ok(Status::InProgress),
             "closed" | "done" | "archived" => Ok(Status::Closed),
-            _ => anyhow::bail!("Invalid status: {}. Use: open, closed", s),
+            _ => Err(TkError::Validation(format!(
+                "Invalid status: {}. Use: open, in-progress, closed",
+                s
+            ))
+            .into()),
         }
     }
 }
 
 /// Ticket type (aligned with conventional commits)
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum TicketType {
     #[default]
@@ -72,10 +83,58 @@ impl std::str::FromStr for TicketType {
             // Legacy aliases
             "task" => Ok(TicketType::Feat),
             "epic" => Ok(TicketType::Feat),
-            _ => anyhow::bail!(
+            _ => Err(TkError::Validation(format!(
                 "Invalid type: {}. Use: feat, fix, chore, docs, refactor, test",
                 s
-            ),
+            ))
+            .into()),
+        }
+    }
+}
+
+/// A ticket's identity in another system (GitHub, GitLab, Linear, ...)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct ExternalRef {
+    pub system: String,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// When `tk sync` last brought the ticket in line with the issue, or
+    /// last settled a conflict with it; local changes after this can
+    /// conflict with upstream ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synced: Option<DateTime<Utc>>,
+}
+
+impl ExternalRef {
+    pub fn matches(&self, system: &str, id: &str) -> bool {
+        self.system.eq_ignore_ascii_case(system) && self.id == id
+    }
+}
+
+impl std::fmt::Display for ExternalRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}#{}", self.system, self.id)
+    }
+}
+
+impl std::str::FromStr for ExternalRef {
+    type Err = anyhow::Error;
+
+    /// Parse "system#id", e.g. "github#123"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('#') {
+            Some((system, id)) if !system.is_empty() && !id.is_empty() => Ok(ExternalRef {
+                system: system.to_lowercase(),
+                id: id.to_string(),
+                url: None,
+                synced: None,
+            }),
+            _ => Err(TkError::Validation(format!(
+                "Invalid external reference: {}. Use: system#id",
+                s
+            ))
+            .into()),
         }
     }
 }
@@ -84,31 +143,210 @@ impl std::str::FromStr for TicketType {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Frontmatter {
     pub id: String,
+    /// Readable alias for the ID (`login-timeout`), unique in the store
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slug: Option<String>,
+    /// Only set while reading or writing a file that keeps its title here
+    /// rather than in a heading; tk itself uses `Ticket::title`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
     #[serde(default)]
     pub status: Status,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub deps: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
     pub created: DateTime<Utc>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub updated: Option<DateTime<Utc>>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub closed: Option<DateTime<Utc>>,
+    /// How it ended (`fixed`, `wontfix`, `duplicate of tk-a1b2`), from
+    /// `tk close --resolution`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<String>,
+    /// When a second person verified the closed ticket (`tk review done`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reviewed: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reviewer: Option<String>,
     #[serde(default, rename = "type")]
     pub ticket_type: TicketType,
     #[serde(default)]
     pub priority: u8,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub assignee: Option<String>,
+    /// End of the assignee's lease on an in-progress ticket (`tk claim
+    /// --lease`); `tk reap` reopens the ticket once it has passed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claimed_until: Option<DateTime<Utc>>,
+    /// Release or goal this ticket is scheduled for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub milestone: Option<String>,
+    /// Size in whatever unit the team estimates in (points, hours)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimate: Option<u32>,
+    /// Not ready before this date (when `ready.exclude_snoozed` is set)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snoozed_until: Option<NaiveDate>,
+    /// Held up by something deps can't express, set by `tk block`: never
+    /// ready, and listed by `tk blocked --external`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub blocked: bool,
+    /// What it's waiting for (`tk block --reason`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocked_reason: Option<String>,
+    /// Date the ticket should be done by; weighs in `tk ready --sort score`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due: Option<NaiveDate>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub tags: Vec<String>,
+    /// External resources: design docs, upstream issues, CI runs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<String>,
+    /// Mappings to issues in external trackers, used by importers/exporters
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub external: Vec<ExternalRef>,
+    /// When the ticket went from closed back to open, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reopened: Vec<DateTime<Utc>>,
+    /// Who did what to the ticket, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<Event>,
+    /// People hooks should tell about changes (`tk watch-ticket`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub watchers: Vec<String>,
+    /// Frozen by `tk freeze`: tk refuses to change it without `--force`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub locked: bool,
 }
 
-/// A complete ticket (frontmatter + body)
-#[derive(Debug, Clone)]
+/// One entry in a ticket's history
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Event {
+    pub at: DateTime<Utc>,
+    /// git user.name or login of whoever made the change
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub by: Option<String>,
+    pub action: EventAction,
+    /// Old value, for changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// New value, for changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EventAction {
+    Created,
+    Status,
+    Priority,
+    Assignee,
+    Note,
+    Reviewed,
+}
+
+impl std::fmt::Display for EventAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventAction::Created => write!(f, "created"),
+            EventAction::Status => write!(f, "status"),
+            EventAction::Priority => write!(f, "priority"),
+            EventAction::Assignee => write!(f, "assignee"),
+            EventAction::Note => write!(f, "note"),
+            EventAction::Reviewed => write!(f, "reviewed"),
+        }
+    }
+}
+
+/// A complete ticket (frontmatter + body). Serialized only between `tk daemon`
+/// and its clients; files on disk are written from `meta` by `Storage`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ticket {
     pub meta: Frontmatter,
     pub title: String,
     pub body: String,
+    /// Loaded from .tickets/archive/ (not stored in the file)
+    pub archived: bool,
+    /// The file's whitespace around the title and body
+    #[serde(default)]
+    pub layout: Layout,
+}
+
+/// How a ticket file was laid out around its title and body, as read by
+/// `Storage::parse_ticket`, so saving writes the same bytes back
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Layout {
+    /// Blank lines between the frontmatter and a title heading
+    pub before_title: String,
+    /// How the file gives the title; None for a ticket that isn't from a
+    /// file yet, which gets the project's `title` setting when saved
+    pub title: Option<TitleStyle>,
+    /// The title heading's lines as read, written back as they were while
+    /// the title is unchanged
+    #[serde(default)]
+    pub heading: String,
+    /// Everything after the title heading (or the frontmatter, without one)
+    /// as read: blank lines, body and trailing whitespace
+    pub tail: String,
+}
+
+impl Layout {
+    /// Put the title where `style` says, a heading going after a blank line
+    pub fn set_title_style(&mut self, style: TitleStyle) {
+        self.title = Some(style);
+        self.before_title = "\n".to_string();
+        self.heading.clear();
+    }
+}
+
+/// How close a ticket is to its due date, in the order `tk ready --by-due`
+/// lists them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Deadline {
+    Overdue,
+    Soon,
+    Later,
+    /// No due date
+    Undated,
+}
+
+impl Deadline {
+    /// The heading `tk ready --by-due` gives its tickets
+    pub fn heading(self) -> &'static str {
+        match self {
+            Deadline::Overdue => "Overdue",
+            Deadline::Soon => "Due soon",
+            Deadline::Later => "Due later",
+            Deadline::Undated => "No due date",
+        }
+    }
+}
+
+impl std::fmt::Display for Deadline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Deadline::Overdue => write!(f, "overdue"),
+            Deadline::Soon => write!(f, "due-soon"),
+            Deadline::Later => write!(f, "due-later"),
+            Deadline::Undated => write!(f, "no-due-date"),
+        }
+    }
+}
+
+/// Where a ticket file keeps its title
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TitleStyle {
+    /// A `# Title` line
+    Heading,
+    /// A `Title` line underlined with `=`, the underline as written
+    Setext(String),
+    /// A `title:` key in the frontmatter
+    Frontmatter,
+    /// Nowhere: the ticket is "Untitled" until it's given a title
+    Missing,
 }
 
 impl Ticket {
@@ -116,18 +354,39 @@ impl Ticket {
         Self {
             meta: Frontmatter {
                 id,
+                slug: None,
+                title: None,
                 status: Status::Open,
                 deps: vec![],
+                parent: None,
                 created: Utc::now(),
                 updated: None,
                 closed: None,
+                resolution: None,
+                reviewed: None,
+                reviewer: None,
                 ticket_type: TicketType::Feat,
                 priority: 2,
                 assignee: None,
+                claimed_until: None,
+                milestone: None,
+                estimate: None,
+                snoozed_until: None,
+                blocked: false,
+                blocked_reason: None,
+                due: None,
                 tags: vec![],
+                links: vec![],
+                external: vec![],
+                reopened: vec![],
+                events: vec![],
+                watchers: vec![],
+                locked: false,
             },
             title,
             body: String::new(),
+            archived: false,
+            layout: Layout::default(),
         }
     }
 
@@ -135,24 +394,180 @@ impl Ticket {
         &self.meta.id
     }
 
+    /// What the ticket can be referred to by: its ID and its slug
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.id()).chain(self.meta.slug.as_deref())
+    }
+
+    /// Not closed: open or in progress
     pub fn is_open(&self) -> bool {
-        self.meta.status == Status::Open
+        self.meta.status != Status::Closed
+    }
+
+    /// Where `due` puts the ticket on `today`, `soon` being how many days
+    /// ahead count as due soon
+    pub fn deadline(&self, today: NaiveDate, soon: u32) -> Deadline {
+        match self.meta.due.map(|due| (due - today).num_days()) {
+            None => Deadline::Undated,
+            Some(left) if left < 0 => Deadline::Overdue,
+            Some(left) if left <= soon.into() => Deadline::Soon,
+            Some(_) => Deadline::Later,
+        }
+    }
+
+    /// In progress with a lease that ran out before `now`
+    pub fn lease_expired(&self, now: DateTime<Utc>) -> bool {
+        self.meta.status == Status::InProgress && self.meta.claimed_until.is_some_and(|u| u <= now)
     }
 
     pub fn is_blocked_by(&self, tickets: &[Ticket]) -> bool {
         for dep_id in &self.meta.deps {
-            if let Some(dep) = tickets.iter().find(|t| t.id() == dep_id) {
-                if dep.is_open() {
+            match tickets.iter().find(|t| t.id() == dep_id) {
+                Some(dep) if dep.is_open() => {
+                    tracing::debug!("{} blocked by open dep {}", self.id(), dep_id);
                     return true;
                 }
+                Some(_) => {}
+                None => {
+                    tracing::debug!("{}: dep {} not found, ignoring", self.id(), dep_id);
+                }
             }
         }
         false
     }
 
+    /// Append a history entry by the current user
+    pub fn record(&mut self, action: EventAction) {
+        self.record_change(action, None, None);
+    }
+
+    fn record_change(&mut self, action: EventAction, from: Option<String>, to: Option<String>) {
+        self.meta.events.push(Event {
+            at: Utc::now(),
+            by: identity::current(),
+            action,
+            from,
+            to,
+        });
+    }
+
+    /// Change status, recording who did it and counting reopens. Closing
+    /// stamps `closed`, and reopening clears it.
+    pub fn set_status(&mut self, status: Status) {
+        if self.meta.status != Status::Closed && status == Status::Closed {
+            self.meta.closed = Some(Utc::now());
+        }
+        if self.meta.status == Status::Closed && status != Status::Closed {
+            self.meta.closed = None;
+            self.meta.reopened.push(Utc::now());
+            self.meta.resolution = None;
+            // A reopened ticket needs reviewing again once it's closed
+            self.meta.reviewed = None;
+            self.meta.reviewer = None;
+        }
+        if self.meta.status != status {
+            let from = self.meta.status.to_string();
+            self.record_change(EventAction::Status, Some(from), Some(status.to_string()));
+        }
+        if status != Status::InProgress {
+            self.meta.claimed_until = None;
+        }
+        self.meta.status = status;
+        self.touch();
+    }
+
+    /// Who closed the ticket last, if the history still says
+    pub fn closed_by(&self) -> Option<&str> {
+        self.meta
+            .events
+            .iter()
+            .rev()
+            .find(|e| e.action == EventAction::Status && e.to.as_deref() == Some("closed"))
+            .and_then(|e| e.by.as_deref())
+    }
+
+    /// Record that `reviewer` verified the closed ticket
+    pub fn review(&mut self, reviewer: String) {
+        self.meta.reviewed = Some(Utc::now());
+        self.meta.reviewer = Some(reviewer);
+        self.record(EventAction::Reviewed);
+        self.touch();
+    }
+
+    /// Change priority, recording who did it
+    pub fn set_priority(&mut self, priority: u8) {
+        if self.meta.priority != priority {
+            let from = self.meta.priority.to_string();
+            self.record_change(
+                EventAction::Priority,
+                Some(from),
+                Some(priority.to_string()),
+            );
+        }
+        self.meta.priority = priority;
+        self.touch();
+    }
+
+    /// Change (or clear) the assignee, recording who did it
+    pub fn set_assignee(&mut self, assignee: Option<String>) {
+        if self.meta.assignee != assignee {
+            let from = self.meta.assignee.clone();
+            self.record_change(EventAction::Assignee, from, assignee.clone());
+            // The lease was the old assignee's; `tk reap` mustn't take the
+            // ticket back from the new one when it runs out
+            self.meta.claimed_until = None;
+        }
+        self.meta.assignee = assignee;
+        self.touch();
+    }
+
+    /// Drop the oldest events beyond `keep`, always keeping the `created`
+    /// entry. Returns how many were dropped.
+    pub fn compact_events(&mut self, keep: usize) -> usize {
+        let events = &mut self.meta.events;
+        if events.len() <= keep {
+            return 0;
+        }
+        let created = (events[0].action == EventAction::Created).then(|| events[0].clone());
+        let tail = keep.saturating_sub(created.is_some() as usize);
+        let dropped = events.len() - tail - created.is_some() as usize;
+        let rest = events.split_off(events.len() - tail);
+        *events = created.into_iter().chain(rest).collect();
+        dropped
+    }
+
+    /// Lowercase every tag, dropping ones that become duplicates
+    pub fn lowercase_tags(&mut self) {
+        let mut seen = std::collections::HashSet::new();
+        self.meta.tags = std::mem::take(&mut self.meta.tags)
+            .into_iter()
+            .map(|t| t.to_lowercase())
+            .filter(|t| seen.insert(t.clone()))
+            .collect();
+    }
+
     pub fn touch(&mut self) {
         self.meta.updated = Some(Utc::now());
     }
+
+    /// Append a note to the end of the body
+    pub fn add_note(&mut self, note: &Note) {
+        if !self.body.is_empty() && !self.body.ends_with('\n') {
+            self.body.push('\n');
+        }
+        if !self.body.is_empty() {
+            self.body.push('\n');
+        }
+        self.body.push_str(&note.format());
+        self.meta.events.push(Event {
+            at: note.timestamp,
+            by: note.author.clone(),
+            action: EventAction::Note,
+            from: None,
+            to: None,
+        });
+        self.touch();
+    }
 }
 
 /// A timestamped note (appended to body)
@@ -161,24 +576,54 @@ pub struct Note {
     pub timestamp: DateTime<Utc>,
     pub author: Option<String>,
     pub content: String,
+    /// Number (1-based, in body order) of the note this answers
+    pub reply_to: Option<usize>,
 }
 
 impl Note {
     pub fn new(content: String) -> Self {
         Self {
             timestamp: Utc::now(),
-            author: std::env::var("USER").ok(),
+            author: identity::current(),
             content,
+            reply_to: None,
         }
     }
 
+    /// The line written to the body: `[date author] text`, with `re #N: `
+    /// before the text of a reply
     pub fn format(&self) -> String {
+        let reply = self
+            .reply_to
+            .map_or(String::new(), |n| format!("{}{}: ", REPLY_PREFIX, n));
+        format!("{}{}{}", self.header(), reply, self.content)
+    }
+
+    /// `[date author] `, as shown before the text
+    pub fn header(&self) -> String {
         let author = self.author.as_deref().unwrap_or("anonymous");
-        format!(
-            "[{} {}] {}",
-            self.timestamp.format("%Y-%m-%d %H:%M"),
-            author,
-            self.content
-        )
+        format!("[{} {}] ", self.timestamp.format("%Y-%m-%d %H:%M"), author)
+    }
+}
+
+/// Marks a note as a reply in the body: `[date author] re #3: text`
+pub const REPLY_PREFIX: &str = "re #";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_new_assignee_does_not_inherit_the_lease() {
+        let mut ticket = Ticket::new("tk-a1b2".to_string(), "A".to_string());
+        ticket.set_status(Status::InProgress);
+        ticket.set_assignee(Some("ana".to_string()));
+        ticket.meta.claimed_until = Some(Utc::now() - chrono::Duration::hours(1));
+        ticket.set_assignee(Some("ana".to_string()));
+        assert!(ticket.lease_expired(Utc::now()));
+
+        ticket.set_assignee(Some("bo".to_string()));
+        assert_eq!(ticket.meta.claimed_until, None);
+        assert!(!ticket.lease_expired(Utc::now()));
     }
 }
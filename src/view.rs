@@ -0,0 +1,193 @@
+//! A read-only set of tickets indexed for lookups (`Storage::snapshot`), so
+//! code that needs a ticket by ID, the tickets with a tag, a ticket's
+//! children or the tickets depending on it doesn't scan every ticket for
+//! each lookup.
+
+use crate::types::Ticket;
+use std::collections::HashMap;
+
+#[derive(Clone)]
+pub struct View {
+    /// In ID order; the indexes below hold positions in it
+    tickets: Vec<Ticket>,
+    by_id: HashMap<String, usize>,
+    by_slug: HashMap<String, usize>,
+    by_tag: HashMap<String, Vec<usize>>,
+    children: HashMap<String, Vec<usize>>,
+    /// Dep ID -> the tickets listing it in `deps`
+    dependents: HashMap<String, Vec<usize>>,
+}
+
+impl View {
+    pub fn new(mut tickets: Vec<Ticket>) -> Self {
+        tickets.sort_by(|a, b| a.id().cmp(b.id()));
+        let mut view = Self {
+            tickets: Vec::new(),
+            by_id: HashMap::new(),
+            by_slug: HashMap::new(),
+            by_tag: HashMap::new(),
+            children: HashMap::new(),
+            dependents: HashMap::new(),
+        };
+        for (i, t) in tickets.iter().enumerate() {
+            view.index(t, i);
+        }
+        view.tickets = tickets;
+        view
+    }
+
+    /// Add a ticket that isn't in the view yet, in its place in ID order,
+    /// without rebuilding the indexes
+    pub fn insert(&mut self, ticket: Ticket) {
+        let pos = self.tickets.partition_point(|t| t.id() < ticket.id());
+        let shift = |i: &mut usize| *i += usize::from(*i >= pos);
+        self.by_id.values_mut().for_each(shift);
+        self.by_slug.values_mut().for_each(shift);
+        for index in [&mut self.by_tag, &mut self.children, &mut self.dependents] {
+            index.values_mut().flatten().for_each(shift);
+        }
+        self.index(&ticket, pos);
+        self.tickets.insert(pos, ticket);
+    }
+
+    /// Enter `t`, at position `i`, in the indexes; each list stays in order
+    fn index(&mut self, t: &Ticket, i: usize) {
+        let add = |list: &mut Vec<usize>| list.insert(list.partition_point(|&j| j < i), i);
+        // A live ticket and an archived one with the same ID: keep the first
+        self.by_id.entry(t.id().to_string()).or_insert(i);
+        if let Some(slug) = &t.meta.slug {
+            self.by_slug.entry(slug.clone()).or_insert(i);
+        }
+        for tag in &t.meta.tags {
+            add(self.by_tag.entry(tag.clone()).or_default());
+        }
+        if let Some(parent) = &t.meta.parent {
+            add(self.children.entry(parent.clone()).or_default());
+        }
+        for dep in &t.meta.deps {
+            add(self.dependents.entry(dep.clone()).or_default());
+        }
+    }
+
+    /// Every ticket, in ID order
+    pub fn tickets(&self) -> &[Ticket] {
+        &self.tickets
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Ticket> {
+        self.by_id.get(id).map(|&i| &self.tickets[i])
+    }
+
+    /// Tickets going by `name`, as their ID or their slug
+    pub fn named(&self, name: &str) -> Vec<&Ticket> {
+        [self.by_id.get(name), self.by_slug.get(name)]
+            .into_iter()
+            .flatten()
+            .map(|&i| &self.tickets[i])
+            .collect()
+    }
+
+    /// Tickets tagged `tag`, in ID order
+    pub fn tagged(&self, tag: &str) -> Vec<&Ticket> {
+        self.lookup(&self.by_tag, tag)
+    }
+
+    /// Tickets whose parent is `id`, in ID order
+    pub fn children(&self, id: &str) -> Vec<&Ticket> {
+        self.lookup(&self.children, id)
+    }
+
+    /// Tickets that depend on `id`, in ID order
+    pub fn dependents(&self, id: &str) -> Vec<&Ticket> {
+        self.lookup(&self.dependents, id)
+    }
+
+    /// `t`'s deps that are still open; deps that don't exist don't count
+    pub fn open_deps<'a>(&self, t: &'a Ticket) -> Vec<&'a str> {
+        t.meta
+            .deps
+            .iter()
+            .filter(|d| self.get(d).is_some_and(|dep| dep.is_open()))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// `t`'s deps with no ticket here: in another repository, or deleted
+    pub fn external_deps<'a>(&self, t: &'a Ticket) -> Vec<&'a str> {
+        t.meta
+            .deps
+            .iter()
+            .filter(|d| self.get(d).is_none())
+            .map(String::as_str)
+            .collect()
+    }
+
+    fn lookup(&self, index: &HashMap<String, Vec<usize>>, key: &str) -> Vec<&Ticket> {
+        index
+            .get(key)
+            .into_iter()
+            .flatten()
+            .map(|&i| &self.tickets[i])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Status;
+
+    fn ticket(id: &str, deps: &[&str]) -> Ticket {
+        let mut t = Ticket::new(id.to_string(), id.to_string());
+        t.meta.deps = deps.iter().map(|d| d.to_string()).collect();
+        t
+    }
+
+    #[test]
+    fn deps_are_open_closed_or_outside_the_store() {
+        let mut done = ticket("tk-0002", &[]);
+        done.meta.status = Status::Closed;
+        let view = View::new(vec![
+            ticket("tk-0001", &["tk-0002", "tk-0003", "other-9f9f"]),
+            done,
+            ticket("tk-0003", &[]),
+        ]);
+        let t = view.get("tk-0001").unwrap();
+        assert_eq!(view.open_deps(t), ["tk-0003"]);
+        assert_eq!(view.external_deps(t), ["other-9f9f"]);
+        assert_eq!(view.dependents("tk-0003")[0].id(), "tk-0001");
+    }
+
+    #[test]
+    fn inserting_keeps_id_order_and_every_index() {
+        let mut slugged = ticket("tk-0004", &["tk-0001"]);
+        slugged.meta.slug = Some("login".to_string());
+        let built = View::new(vec![
+            ticket("tk-0001", &[]),
+            ticket("tk-0003", &["tk-0001"]),
+            slugged.clone(),
+        ]);
+        let mut view = View::new(vec![
+            ticket("tk-0001", &[]),
+            ticket("tk-0003", &["tk-0001"]),
+        ]);
+        view.insert(slugged);
+        let mut parent = ticket("tk-0002", &["tk-0001"]);
+        parent.meta.parent = Some("tk-0003".to_string());
+        view.insert(parent);
+
+        let ids = |ts: Vec<&Ticket>| ts.iter().map(|t| t.id().to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            ids(view.tickets().iter().collect()),
+            ["tk-0001", "tk-0002", "tk-0003", "tk-0004"]
+        );
+        assert_eq!(
+            ids(view.dependents("tk-0001")),
+            ["tk-0002", "tk-0003", "tk-0004"]
+        );
+        assert_eq!(ids(view.children("tk-0003")), ["tk-0002"]);
+        assert_eq!(view.get("tk-0003").unwrap().id(), "tk-0003");
+        assert_eq!(ids(view.named("login")), ids(built.named("login")));
+        assert_eq!(ids(view.named("tk-0004")), ["tk-0004"]);
+    }
+}